@@ -23,21 +23,21 @@ async function startWorker() {
   return new Worker(URL.createObjectURL(workerBlob));
 }
 
-async function payToll(tollHal, stamp) {
-  const payment = {
-    toll: tollHal.toll,
-    value: stamp
-  };
-  const payLink = tollHal._links.pay;
-  const response = await fetch(payLink, {
-    method: "POST",
-    headers: { "Content-Type": "application/json" },
-    body: JSON.stringify(payment)
-  });
-  const visa = await response.json();
-  document.cookie = `${visa.header_name}=${visa.token}; path=/`;
-  window.location.href = `http://${visa._links.origin_url}`;
-
+function payToll(tollHal, stamp) {
+  // Posting a form lets tollkeeper set the visa cookie and redirect back to the page
+  const form = document.createElement('form');
+  form.method = 'POST';
+  form.action = tollHal._links.pay;
+  const fields = { toll: JSON.stringify(tollHal.toll), value: stamp };
+  for (const [name, value] of Object.entries(fields)) {
+    const input = document.createElement('input');
+    input.type = 'hidden';
+    input.name = name;
+    input.value = value;
+    form.appendChild(input);
+  }
+  document.body.appendChild(form);
+  form.submit();
 }
 
 main();
@@ -0,0 +1,2 @@
+// Posting the visa keeps it out of urls, logs and Referer headers
+document.getElementById('handoff').submit();
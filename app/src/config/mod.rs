@@ -2,7 +2,7 @@ use indexmap::IndexMap;
 use serde::Deserialize;
 use tollkeeper::signatures::InMemorySecretKeyProvider;
 
-use crate::proxy;
+use crate::{http, payment, proxy};
 
 #[cfg(test)]
 mod tests;
@@ -19,7 +19,26 @@ pub struct Config {
 
 impl Config {
     pub fn from_toml(toml: &str) -> Result<Self, toml::de::Error> {
-        toml::from_str(toml)
+        let config: Self = toml::from_str(toml)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Rejects settings the API cannot be served with or browsers would silently ignore
+    fn validate(&self) -> Result<(), toml::de::Error> {
+        if !self.api.is_valid() {
+            let message = "api: base_url is required unless single_port is set";
+            return Err(serde::de::Error::custom(message));
+        }
+        for (id, gate) in &self.gates {
+            if gate.visa_cookie.as_ref().is_some_and(|c| !c.is_valid()) {
+                let message = format!(
+                    "visa_cookie of gate {id}: same_site = \"None\" requires secure = true"
+                );
+                return Err(serde::de::Error::custom(message));
+            }
+        }
+        Ok(())
     }
 
     pub fn server(&self) -> Server {
@@ -50,10 +69,10 @@ impl Config {
     }
 
     pub fn create_url_resolver(&self) -> proxy::UrlResolverImpl {
-        let mappings: indexmap::IndexMap<url::Url, url::Url> = self
+        let mappings: indexmap::IndexMap<url::Url, proxy::upstream::Target> = self
             .gates
             .iter()
-            .map(|(_, g)| {
+            .map(|(id, g)| {
                 let mut public_host = g.destination.clone();
                 public_host.set_path("");
                 let mut internal_host = g
@@ -61,17 +80,73 @@ impl Config {
                     .clone()
                     .unwrap_or(public_host.clone());
                 internal_host.set_path("");
-                (public_host, internal_host)
+                let mut target = proxy::upstream::Target::new(internal_host);
+                if let Some(forwarded_headers) = g.forwarded_headers {
+                    target.set_forwarded_headers(forwarded_headers.to_entity());
+                }
+                target.set_verified_header(g.verified_header.unwrap_or(false));
+                if let Some(host_header) = g.host_header {
+                    target.set_host_header(host_header.to_entity());
+                }
+                if let Some(upstream_tls) = &g.upstream_tls {
+                    let tls = proxy::upstream::TlsConnector::new(upstream_tls.to_entity())
+                        .unwrap_or_else(|e| panic!("Invalid upstream_tls of gate {id}: {e}"));
+                    target.set_tls(tls);
+                }
+                (public_host, target)
             })
             .collect();
         proxy::UrlResolverImpl::new(mappings)
     }
+
+    /// Attributes of the visa cookie, by gate id
+    pub fn create_visa_cookies(&self) -> payment::visa_cookie::VisaCookies {
+        let cookies = self
+            .gates
+            .iter()
+            .map(|(id, g)| {
+                let cookie = g.visa_cookie.clone().unwrap_or_default().to_entity();
+                (id.clone(), cookie)
+            })
+            .collect();
+        let date_provider = Box::new(tollkeeper::util::DateTimeProviderImpl);
+        payment::visa_cookie::VisaCookies::new(cookies, date_provider)
+    }
+
+    /// Creates the TLS setup of the proxy listener, selecting the certificate of a gate by its
+    /// destination host. Returns [None] if neither the proxy nor any gate has a certificate
+    pub fn create_proxy_tls(
+        &self,
+    ) -> Result<Option<http::server::tls::TlsAcceptor>, http::server::tls::TlsError> {
+        let default = self.server().tls.unwrap_or_default().proxy;
+        let gates: Vec<(&str, &Certificate)> = self
+            .gates
+            .values()
+            .filter_map(|g| Some((g.destination.host_str()?, g.tls.as_ref()?)))
+            .collect();
+        if default.is_none() && gates.is_empty() {
+            return Ok(None);
+        }
+        let mut certificates =
+            http::server::tls::CertificateStore::new(default.map(|c| c.to_entity()))?;
+        for (host, certificate) in gates {
+            certificates.add_server_name(host, certificate.to_entity())?;
+        }
+        http::server::tls::TlsAcceptor::new(certificates).map(Some)
+    }
 }
 
 #[derive(Deserialize, Debug, PartialEq, Eq, Clone)]
 pub struct Server {
     pub proxy_port: Option<usize>,
     pub api_port: Option<usize>,
+    pub keep_alive: Option<KeepAlive>,
+    pub max_connections: Option<usize>,
+    pub max_connections_per_ip: Option<usize>,
+    pub backlog_policy: Option<BacklogPolicy>,
+    pub timeouts: Option<Timeouts>,
+    pub tls: Option<Tls>,
+    pub proxy_protocol: Option<ProxyProtocol>,
 }
 impl Server {
     pub const PROXY_PORT_DEFAULT: usize = 8000;
@@ -84,20 +159,335 @@ impl Server {
     pub fn api_port(&self) -> usize {
         self.api_port.unwrap_or(Self::API_PORT_DEFAULT)
     }
+
+    pub fn connection_settings(&self) -> http::server::ConnectionSettings {
+        let keep_alive = self.keep_alive.clone().unwrap_or_default();
+        let timeouts = self.timeouts.clone().unwrap_or_default();
+        http::server::ConnectionSettings {
+            idle_timeout: keep_alive.idle_timeout(),
+            max_requests: keep_alive.max_requests(),
+            header_read_timeout: timeouts.header_read(),
+            body_read_timeout: timeouts.body_read(),
+        }
+    }
+
+    pub fn upstream_timeouts(&self) -> proxy::upstream::UpstreamTimeouts {
+        let timeouts = self.timeouts.clone().unwrap_or_default();
+        proxy::upstream::UpstreamTimeouts {
+            connect: timeouts.upstream_connect(),
+            read: timeouts.upstream_read(),
+        }
+    }
+
+    /// Time open connections get to finish after a shutdown signal
+    pub fn shutdown_timeout(&self) -> std::time::Duration {
+        self.timeouts.clone().unwrap_or_default().shutdown()
+    }
+
+    pub fn connection_limits(&self) -> http::server::limits::ConnectionLimits {
+        let defaults = http::server::limits::ConnectionLimits::default();
+        let max_connections = self.max_connections.unwrap_or(defaults.max_connections);
+        if max_connections == 0 {
+            panic!("Invalid max_connections: At least one connection is required");
+        }
+        http::server::limits::ConnectionLimits {
+            max_connections,
+            max_connections_per_ip: self.max_connections_per_ip,
+            backlog_policy: self
+                .backlog_policy
+                .map(|p| p.to_entity())
+                .unwrap_or(defaults.backlog_policy),
+        }
+    }
+
+    /// Creates the TLS setup of the API listener. Returns [None] if it has no certificate
+    pub fn create_api_tls(
+        &self,
+    ) -> Result<Option<http::server::tls::TlsAcceptor>, http::server::tls::TlsError> {
+        let Some(certificate) = self.tls.clone().unwrap_or_default().api else {
+            return Ok(None);
+        };
+        let certificates = http::server::tls::CertificateStore::new(Some(certificate.to_entity()))?;
+        http::server::tls::TlsAcceptor::new(certificates).map(Some)
+    }
+
+    /// PROXY protocol setup of the proxy listener. Returns [None] if it trusts no peer
+    pub fn proxy_listener_proxy_protocol(
+        &self,
+    ) -> Option<http::server::proxy_protocol::ProxyProtocol> {
+        let trusted_sources = self.proxy_protocol.clone().unwrap_or_default().proxy;
+        ProxyProtocol::to_entity(trusted_sources)
+    }
+
+    /// PROXY protocol setup of the API listener. Returns [None] if it trusts no peer
+    pub fn api_listener_proxy_protocol(
+        &self,
+    ) -> Option<http::server::proxy_protocol::ProxyProtocol> {
+        let trusted_sources = self.proxy_protocol.clone().unwrap_or_default().api;
+        ProxyProtocol::to_entity(trusted_sources)
+    }
 }
 impl Default for Server {
     fn default() -> Self {
         Self {
             proxy_port: Some(Self::PROXY_PORT_DEFAULT),
             api_port: Some(Self::API_PORT_DEFAULT),
+            keep_alive: None,
+            max_connections: None,
+            max_connections_per_ip: None,
+            backlog_policy: None,
+            timeouts: None,
+            tls: None,
+            proxy_protocol: None,
         }
     }
 }
 
+/// Certificates of the listeners. Plain HTTP is served if a listener has none
+#[derive(Deserialize, Debug, Default, PartialEq, Eq, Clone)]
+pub struct Tls {
+    pub proxy: Option<Certificate>,
+    pub api: Option<Certificate>,
+}
+
+/// Peers (CIDRs) allowed to send a PROXY protocol header, per listener
+#[derive(Deserialize, Debug, Default, PartialEq, Eq, Clone)]
+pub struct ProxyProtocol {
+    pub proxy: Option<Vec<ipnet::IpNet>>,
+    pub api: Option<Vec<ipnet::IpNet>>,
+}
+impl ProxyProtocol {
+    fn to_entity(
+        trusted_sources: Option<Vec<ipnet::IpNet>>,
+    ) -> Option<http::server::proxy_protocol::ProxyProtocol> {
+        let trusted_sources = trusted_sources.filter(|s| !s.is_empty())?;
+        Some(http::server::proxy_protocol::ProxyProtocol::new(
+            trusted_sources,
+        ))
+    }
+}
+
+/// PEM files of a certificate chain and its private key. Reloaded when the files change
+#[derive(Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct Certificate {
+    pub certificate: std::path::PathBuf,
+    pub key: std::path::PathBuf,
+}
+impl Certificate {
+    fn to_entity(&self) -> http::server::tls::CertificateFiles {
+        http::server::tls::CertificateFiles::new(&self.certificate, &self.key)
+    }
+}
+
+/// Handling of connections while all workers are busy
+#[derive(Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+pub enum BacklogPolicy {
+    Reject,
+    Queue(usize),
+}
+impl BacklogPolicy {
+    fn to_entity(self) -> http::server::limits::BacklogPolicy {
+        match self {
+            BacklogPolicy::Reject => http::server::limits::BacklogPolicy::Reject,
+            BacklogPolicy::Queue(size) => http::server::limits::BacklogPolicy::Queue(size),
+        }
+    }
+}
+
+/// Limits for persistent client connections
+#[derive(Deserialize, Debug, Default, PartialEq, Eq, Clone)]
+pub struct KeepAlive {
+    pub idle_timeout: Option<String>,
+    pub max_requests: Option<usize>,
+}
+impl KeepAlive {
+    pub fn idle_timeout(&self) -> std::time::Duration {
+        parse_timeout(
+            "keep_alive.idle_timeout",
+            &self.idle_timeout,
+            http::server::ConnectionSettings::IDLE_TIMEOUT_DEFAULT,
+        )
+    }
+
+    pub fn max_requests(&self) -> usize {
+        self.max_requests
+            .unwrap_or(http::server::ConnectionSettings::MAX_REQUESTS_DEFAULT)
+    }
+}
+
+/// Timeouts protecting the server from clients and upstreams that send data too slowly
+#[derive(Deserialize, Debug, Default, PartialEq, Eq, Clone)]
+pub struct Timeouts {
+    pub header_read: Option<String>,
+    pub body_read: Option<String>,
+    pub upstream_connect: Option<String>,
+    pub upstream_read: Option<String>,
+    pub shutdown: Option<String>,
+}
+impl Timeouts {
+    pub fn header_read(&self) -> std::time::Duration {
+        parse_timeout(
+            "timeouts.header_read",
+            &self.header_read,
+            http::server::ConnectionSettings::HEADER_READ_TIMEOUT_DEFAULT,
+        )
+    }
+
+    pub fn body_read(&self) -> std::time::Duration {
+        parse_timeout(
+            "timeouts.body_read",
+            &self.body_read,
+            http::server::ConnectionSettings::BODY_READ_TIMEOUT_DEFAULT,
+        )
+    }
+
+    pub fn upstream_connect(&self) -> std::time::Duration {
+        parse_timeout(
+            "timeouts.upstream_connect",
+            &self.upstream_connect,
+            proxy::upstream::UpstreamTimeouts::CONNECT_DEFAULT,
+        )
+    }
+
+    pub fn upstream_read(&self) -> std::time::Duration {
+        parse_timeout(
+            "timeouts.upstream_read",
+            &self.upstream_read,
+            proxy::upstream::UpstreamTimeouts::READ_DEFAULT,
+        )
+    }
+
+    pub fn shutdown(&self) -> std::time::Duration {
+        parse_timeout(
+            "timeouts.shutdown",
+            &self.shutdown,
+            http::server::Server::SHUTDOWN_TIMEOUT_DEFAULT,
+        )
+    }
+}
+
+/// Parses a non-zero timeout, panicking on invalid values
+fn parse_timeout(
+    name: &str,
+    value: &Option<String>,
+    default: std::time::Duration,
+) -> std::time::Duration {
+    match value {
+        Some(timeout) => parse_duration(timeout)
+            .and_then(|d| d.to_std().ok())
+            .filter(|d| !d.is_zero())
+            .unwrap_or_else(|| panic!("Invalid {name}: {timeout}")),
+        None => default,
+    }
+}
+
+/// Parses durations like `500ms`, `30s`, `5m`, `1h` or `7d`
+fn parse_duration(value: &str) -> Option<chrono::Duration> {
+    if let Some(time) = value.strip_suffix("ms") {
+        return Some(chrono::Duration::milliseconds(time.parse().ok()?));
+    }
+    let format = value.chars().last()?;
+    let time = value[..value.len() - format.len_utf8()]
+        .parse::<i64>()
+        .ok()?;
+    match format {
+        's' => Some(chrono::Duration::seconds(time)),
+        'm' => Some(chrono::Duration::minutes(time)),
+        'h' => Some(chrono::Duration::hours(time)),
+        'd' => Some(chrono::Duration::days(time)),
+        _ => None,
+    }
+}
+
 #[derive(Deserialize, Debug, PartialEq, Eq, Clone)]
 pub struct Api {
-    pub base_url: url::Url,
+    pub base_url: Option<url::Url>,
+    pub single_port: Option<bool>,
     pub real_ip_header: Option<String>,
+    pub trusted_proxies: Option<Vec<ipnet::IpNet>>,
+    pub real_ip_fallback: Option<RealIpFallback>,
+}
+impl Api {
+    /// Path reserved on every gate for the visa hand-off, and the API in single-port mode
+    pub const RESERVED_PATH: &str = "/.tollkeeper/";
+
+    /// Returns `true` if the API is served by the proxy listener instead of its own port
+    pub fn single_port(&self) -> bool {
+        self.single_port.unwrap_or(false)
+    }
+
+    /// Returns `false` if neither `single_port` nor `base_url` are set
+    pub fn is_valid(&self) -> bool {
+        self.single_port() || self.base_url.is_some()
+    }
+
+    /// Path all API endpoints and assets are served under
+    pub fn path(&self) -> &str {
+        match self.single_port() {
+            true => Self::RESERVED_PATH,
+            false => "/",
+        }
+    }
+
+    /// Url clients reach the API at. Has no origin in single-port mode, so links stay on the
+    /// gate the client is visiting
+    pub fn public_url(&self) -> String {
+        match (self.single_port(), &self.base_url) {
+            (false, Some(base_url)) => base_url.to_string(),
+            _ => Self::RESERVED_PATH.into(),
+        }
+    }
+
+    /// Resolves the client IP from the `real_ip_header`. Returns [None] if there is no header
+    pub fn real_ip_resolver(&self) -> Option<http::server::real_ip::RealIpResolver> {
+        let header = self.real_ip_header.as_ref()?;
+        Some(self.ip_resolver(header))
+    }
+
+    /// Resolves the client of forward auth requests from `X-Forwarded-For`, as the reverse
+    /// proxy asking is never the client. Returns [None] if the `real_ip_header` already
+    /// resolves the client of every API request
+    pub fn forward_auth_ip_resolver(&self) -> Option<http::server::real_ip::RealIpResolver> {
+        match self.real_ip_header {
+            Some(_) => None,
+            None => Some(self.ip_resolver("X-Forwarded-For")),
+        }
+    }
+
+    /// Networks of the reverse proxies in front of tollkeeper, private networks by default
+    pub fn trusted_proxies(&self) -> Vec<ipnet::IpNet> {
+        match &self.trusted_proxies {
+            Some(trusted_proxies) => trusted_proxies.clone(),
+            None => http::server::real_ip::RealIpResolver::TRUSTED_PROXIES_DEFAULT
+                .iter()
+                .map(|n| n.parse().unwrap())
+                .collect(),
+        }
+    }
+
+    fn ip_resolver(&self, header: &str) -> http::server::real_ip::RealIpResolver {
+        let mut resolver = http::server::real_ip::RealIpResolver::new(header);
+        resolver.set_trusted_proxies(self.trusted_proxies());
+        if let Some(fallback) = self.real_ip_fallback {
+            resolver.set_fallback(fallback.to_entity());
+        }
+        resolver
+    }
+}
+
+/// Handling of requests without trustworthy client IP
+#[derive(Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+pub enum RealIpFallback {
+    PeerAddress,
+    Reject,
+}
+impl RealIpFallback {
+    fn to_entity(self) -> http::server::real_ip::RealIpFallback {
+        match self {
+            RealIpFallback::PeerAddress => http::server::real_ip::RealIpFallback::PeerAddress,
+            RealIpFallback::Reject => http::server::real_ip::RealIpFallback::Reject,
+        }
+    }
 }
 
 #[derive(Deserialize, Debug, PartialEq, Eq, Clone)]
@@ -119,6 +509,12 @@ impl SecretKeyProvider {
 struct Gate {
     destination: url::Url,
     internal_destination: Option<url::Url>,
+    forwarded_headers: Option<ForwardedHeaders>,
+    verified_header: Option<bool>,
+    host_header: Option<HostHeader>,
+    tls: Option<Certificate>,
+    upstream_tls: Option<UpstreamTls>,
+    visa_cookie: Option<VisaCookie>,
     orders: Vec<Ref<Order>>,
 }
 
@@ -140,7 +536,7 @@ impl Gate {
             .collect();
         let destination = tollkeeper::descriptions::Destination::new(
             self.destination.host().unwrap().to_string(),
-            self.destination.port().unwrap_or(80),
+            self.destination.port_or_known_default().unwrap_or(80),
             self.destination.path(),
         );
         let gate = tollkeeper::Gate::with_id(id, destination, orders).unwrap();
@@ -148,6 +544,103 @@ impl Gate {
     }
 }
 
+/// Headers telling the upstream about the client
+#[derive(Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+enum ForwardedHeaders {
+    None,
+    XForwarded,
+    Forwarded,
+}
+impl ForwardedHeaders {
+    fn to_entity(self) -> proxy::forwarding::ForwardedHeaders {
+        match self {
+            ForwardedHeaders::None => proxy::forwarding::ForwardedHeaders::None,
+            ForwardedHeaders::XForwarded => proxy::forwarding::ForwardedHeaders::XForwarded,
+            ForwardedHeaders::Forwarded => proxy::forwarding::ForwardedHeaders::Forwarded,
+        }
+    }
+}
+
+/// Verification of `https` internal destinations
+#[derive(Deserialize, Debug, PartialEq, Eq, Clone)]
+struct UpstreamTls {
+    ca_bundle: Option<std::path::PathBuf>,
+    server_name: Option<String>,
+    skip_verify: Option<bool>,
+}
+impl UpstreamTls {
+    fn to_entity(&self) -> proxy::upstream::TlsSettings {
+        proxy::upstream::TlsSettings {
+            ca_bundle: self.ca_bundle.clone(),
+            server_name: self.server_name.clone(),
+            skip_verify: self.skip_verify.unwrap_or(false),
+        }
+    }
+}
+
+/// Attributes of the cookie storing the visa
+#[derive(Deserialize, Debug, Default, PartialEq, Eq, Clone)]
+struct VisaCookie {
+    domain: Option<String>,
+    secure: Option<bool>,
+    http_only: Option<bool>,
+    same_site: Option<SameSite>,
+}
+impl VisaCookie {
+    /// Browsers drop `SameSite=None` cookies without `Secure`
+    fn is_valid(&self) -> bool {
+        self.same_site != Some(SameSite::None) || self.secure == Some(true)
+    }
+
+    fn to_entity(&self) -> payment::visa_cookie::VisaCookie {
+        let mut cookie = payment::visa_cookie::VisaCookie::default();
+        if let Some(domain) = &self.domain {
+            cookie.set_domain(domain);
+        }
+        if let Some(secure) = self.secure {
+            cookie.set_secure(secure);
+        }
+        if let Some(http_only) = self.http_only {
+            cookie.set_http_only(http_only);
+        }
+        if let Some(same_site) = self.same_site {
+            cookie.set_same_site(same_site.to_entity());
+        }
+        cookie
+    }
+}
+
+#[derive(Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+enum SameSite {
+    Strict,
+    Lax,
+    None,
+}
+impl SameSite {
+    fn to_entity(self) -> payment::visa_cookie::SameSite {
+        match self {
+            SameSite::Strict => payment::visa_cookie::SameSite::Strict,
+            SameSite::Lax => payment::visa_cookie::SameSite::Lax,
+            SameSite::None => payment::visa_cookie::SameSite::None,
+        }
+    }
+}
+
+/// `Host` header the upstream receives
+#[derive(Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+enum HostHeader {
+    Preserve,
+    Rewrite,
+}
+impl HostHeader {
+    fn to_entity(self) -> proxy::rewriting::HostHeader {
+        match self {
+            HostHeader::Preserve => proxy::rewriting::HostHeader::Preserve,
+            HostHeader::Rewrite => proxy::rewriting::HostHeader::Rewrite,
+        }
+    }
+}
+
 #[derive(Deserialize, Debug, PartialEq, Eq, Clone)]
 #[serde(untagged)]
 enum Ref<T> {
@@ -288,17 +781,8 @@ impl HashcashDeclaration {
     }
 
     fn expiry(&self) -> chrono::Duration {
-        let end = self.expiry.len() - 1;
-        let time = &self.expiry[0..end];
-        let format = self.expiry.chars().last().unwrap();
-        let time = time.parse::<i64>().unwrap();
-        match format {
-            's' => chrono::Duration::seconds(time),
-            'm' => chrono::Duration::minutes(time),
-            'h' => chrono::Duration::hours(time),
-            'd' => chrono::Duration::days(time),
-            _ => panic!("Unexpected time format: {format}"),
-        }
+        parse_duration(&self.expiry)
+            .unwrap_or_else(|| panic!("Unexpected time format: {}", self.expiry))
     }
 }
 #[derive(Deserialize, Debug, Default, PartialEq, Eq, Clone)]
@@ -5,10 +5,25 @@ use tollkeeper::AccessPolicy;
 use crate::{
     config::{
         Api, Config, Declaration, Description, DoubleSpentDatabase, Gate, HashcashDeclaration,
-        Order, Ref, SecretKeyProvider, Server, StubDescription,
+        KeepAlive, Order, Ref, SecretKeyProvider, Server, StubDescription, Timeouts,
+    },
+    http::{
+        self,
+        server::{
+            limits,
+            proxy_protocol::ProxyProtocol,
+            real_ip::{RealIpFallback, RealIpResolver},
+            ConnectionSettings,
+        },
+    },
+    payment::visa_cookie,
+    proxy::{
+        forwarding, rewriting,
+        upstream::{Target, TlsConnector, TlsSettings, UpstreamTimeouts},
+        UrlResolver,
     },
-    proxy::UrlResolver,
 };
+use test_case::test_case;
 
 #[test]
 pub fn config_should_be_deserializable_from_toml() {
@@ -43,8 +58,11 @@ toll_declaration = { Hashcash = { expiry = "1h", difficulty = 4}}
     let config: Config = toml::from_str(toml).unwrap();
     // Assert
     let api = Api {
-        base_url: url("http://localhost:9100/"),
+        base_url: Some(url("http://localhost:9100/")),
+        single_port: None,
         real_ip_header: Some("X-Real-Ip".to_string()),
+        trusted_proxies: None,
+        real_ip_fallback: None,
     };
     let mut gates = IndexMap::new();
     gates.insert(
@@ -52,6 +70,12 @@ toll_declaration = { Hashcash = { expiry = "1h", difficulty = 4}}
         Gate {
             destination: url("http://example.com:80/"),
             internal_destination: None,
+            forwarded_headers: None,
+            verified_header: None,
+            host_header: None,
+            tls: None,
+            upstream_tls: None,
+            visa_cookie: None,
             orders: vec![Ref::Id("hash_cash_order".into())],
         },
     );
@@ -60,6 +84,12 @@ toll_declaration = { Hashcash = { expiry = "1h", difficulty = 4}}
         Gate {
             destination: url("http://localhost:80/"),
             internal_destination: None,
+            forwarded_headers: None,
+            verified_header: None,
+            host_header: None,
+            tls: None,
+            upstream_tls: None,
+            visa_cookie: None,
             orders: vec![Ref::Id("hash_cash_order".into())],
         },
     );
@@ -80,6 +110,13 @@ toll_declaration = { Hashcash = { expiry = "1h", difficulty = 4}}
     let server = Server {
         proxy_port: Some(9000),
         api_port: None,
+        keep_alive: None,
+        max_connections: None,
+        max_connections_per_ip: None,
+        backlog_policy: None,
+        timeouts: None,
+        tls: None,
+        proxy_protocol: None,
     };
     let expected_config = Config {
         server: Some(server),
@@ -100,8 +137,11 @@ fn url(s: &str) -> url::Url {
 pub fn create_tollkeeper_should_create_a_new_tollkeeper_instance_with_given_config() {
     // Arrange
     let api = Api {
-        base_url: url("http://localhost:9100/"),
+        base_url: Some(url("http://localhost:9100/")),
+        single_port: None,
         real_ip_header: None,
+        trusted_proxies: None,
+        real_ip_fallback: None,
     };
 
     let mut gates = IndexMap::new();
@@ -110,6 +150,12 @@ pub fn create_tollkeeper_should_create_a_new_tollkeeper_instance_with_given_conf
         Gate {
             destination: url("http://example.com:80/"),
             internal_destination: None,
+            forwarded_headers: None,
+            verified_header: None,
+            host_header: None,
+            tls: None,
+            upstream_tls: None,
+            visa_cookie: None,
             orders: vec![Ref::Id("hash_cash_order".into())],
         },
     );
@@ -118,6 +164,12 @@ pub fn create_tollkeeper_should_create_a_new_tollkeeper_instance_with_given_conf
         Gate {
             destination: url("http://localhost:80/"),
             internal_destination: None,
+            forwarded_headers: None,
+            verified_header: None,
+            host_header: None,
+            tls: None,
+            upstream_tls: None,
+            visa_cookie: None,
             orders: vec![Ref::Id("hash_cash_order".into())],
         },
     );
@@ -139,6 +191,13 @@ pub fn create_tollkeeper_should_create_a_new_tollkeeper_instance_with_given_conf
     let server = Server {
         proxy_port: Some(9000),
         api_port: Some(9100),
+        keep_alive: None,
+        max_connections: None,
+        max_connections_per_ip: None,
+        backlog_policy: None,
+        timeouts: None,
+        tls: None,
+        proxy_protocol: None,
     };
     let config = Config {
         server: Some(server),
@@ -161,8 +220,11 @@ pub fn create_tollkeeper_should_create_a_new_tollkeeper_instance_with_given_conf
 pub fn create_url_resolver_should_return_url_mappings() {
     // Arrange
     let api = Api {
-        base_url: url("http://localhost:9100/"),
+        base_url: Some(url("http://localhost:9100/")),
+        single_port: None,
         real_ip_header: None,
+        trusted_proxies: None,
+        real_ip_fallback: None,
     };
 
     let mut gates = IndexMap::new();
@@ -172,6 +234,12 @@ pub fn create_url_resolver_should_return_url_mappings() {
         Gate {
             destination: url("http://example.com:80/"),
             internal_destination: Some(expected_internal_url.clone()),
+            forwarded_headers: None,
+            verified_header: None,
+            host_header: None,
+            tls: None,
+            upstream_tls: None,
+            visa_cookie: None,
             orders: vec![Ref::Id("hash_cash_order".into())],
         },
     );
@@ -180,6 +248,13 @@ pub fn create_url_resolver_should_return_url_mappings() {
     let server = Server {
         proxy_port: Some(9000),
         api_port: Some(9100),
+        keep_alive: None,
+        max_connections: None,
+        max_connections_per_ip: None,
+        backlog_policy: None,
+        timeouts: None,
+        tls: None,
+        proxy_protocol: None,
     };
     let config = Config {
         server: Some(server),
@@ -191,7 +266,589 @@ pub fn create_url_resolver_should_return_url_mappings() {
     };
     // Act
     let url_resolver = config.create_url_resolver();
-    let url = url_resolver.resolve(&url("http://example.com:80/"));
+    let target = url_resolver.resolve(&url("http://example.com:80/"));
+    // Assert
+    assert_eq!(Some(Target::new(expected_internal_url)), target);
+}
+
+#[test_case("None", forwarding::ForwardedHeaders::None ; "none")]
+#[test_case("XForwarded", forwarding::ForwardedHeaders::XForwarded ; "x-forwarded")]
+#[test_case("Forwarded", forwarding::ForwardedHeaders::Forwarded ; "forwarded")]
+pub fn create_url_resolver_should_set_forwarded_headers_of_gate(
+    forwarded_headers: &str,
+    expected_forwarded_headers: forwarding::ForwardedHeaders,
+) {
+    // Arrange
+    let toml = format!(
+        r#"
+secret_key_provider = {{ InMemory = "verysecretkey" }}
+
+[api]
+base_url = "http://localhost:9100/"
+
+[gates.gate]
+destination = "http://example.com/"
+forwarded_headers = "{forwarded_headers}"
+orders = []
+"#
+    );
+    let config: Config = toml::from_str(&toml).unwrap();
+    // Act
+    let url_resolver = config.create_url_resolver();
+    let target = url_resolver
+        .resolve(&url("http://example.com:80/"))
+        .unwrap();
+    // Assert
+    assert_eq!(expected_forwarded_headers, target.forwarded_headers());
+}
+
+#[test]
+pub fn create_url_resolver_should_enable_verified_header_of_gate() {
+    // Arrange
+    let toml = r#"
+secret_key_provider = { InMemory = "verysecretkey" }
+
+[api]
+base_url = "http://localhost:9100/"
+
+[gates.gate]
+destination = "http://example.com/"
+verified_header = true
+orders = []
+"#;
+    let config: Config = toml::from_str(toml).unwrap();
+    // Act
+    let url_resolver = config.create_url_resolver();
+    let target = url_resolver
+        .resolve(&url("http://example.com:80/"))
+        .unwrap();
+    // Assert
+    assert!(target.verified_header(), "X-Keeper-Verified is not enabled");
+}
+
+#[test_case("Preserve", rewriting::HostHeader::Preserve ; "preserve")]
+#[test_case("Rewrite", rewriting::HostHeader::Rewrite ; "rewrite")]
+pub fn create_url_resolver_should_set_host_header_of_gate(
+    host_header: &str,
+    expected_host_header: rewriting::HostHeader,
+) {
+    // Arrange
+    let toml = format!(
+        r#"
+secret_key_provider = {{ InMemory = "verysecretkey" }}
+
+[api]
+base_url = "http://localhost:9100/"
+
+[gates.gate]
+destination = "http://example.com/"
+internal_destination = "http://internal.example.com:9000/"
+host_header = "{host_header}"
+orders = []
+"#
+    );
+    let config: Config = toml::from_str(&toml).unwrap();
+    // Act
+    let url_resolver = config.create_url_resolver();
+    let target = url_resolver
+        .resolve(&url("http://example.com:80/"))
+        .unwrap();
+    // Assert
+    assert_eq!(expected_host_header, target.host_header());
+}
+
+#[test_case("", Some(TlsSettings::default()) ; "https without settings")]
+#[test_case(
+    r#"upstream_tls = { server_name = "internal", skip_verify = true }"#,
+    Some(TlsSettings { ca_bundle: None, server_name: Some("internal".into()), skip_verify: true })
+    ; "https with settings"
+)]
+pub fn create_url_resolver_should_connect_to_https_destinations_over_tls(
+    upstream_tls: &str,
+    expected_settings: Option<TlsSettings>,
+) {
+    // Arrange
+    let toml = format!(
+        r#"
+secret_key_provider = {{ InMemory = "verysecretkey" }}
+
+[api]
+base_url = "http://localhost:9100/"
+
+[gates.gate]
+destination = "http://example.com/"
+internal_destination = "https://internal.example.com/"
+{upstream_tls}
+orders = []
+"#
+    );
+    let config: Config = toml::from_str(&toml).unwrap();
+    // Act
+    let url_resolver = config.create_url_resolver();
+    let target = url_resolver
+        .resolve(&url("http://example.com:80/"))
+        .unwrap();
+    // Assert
+    let expected_tls = expected_settings.map(|s| TlsConnector::new(s).unwrap());
+    assert_eq!(expected_tls.as_ref(), target.tls());
+}
+
+#[test]
+#[should_panic]
+pub fn create_url_resolver_should_panic_on_missing_ca_bundle() {
+    // Arrange
+    let toml = r#"
+secret_key_provider = { InMemory = "verysecretkey" }
+
+[api]
+base_url = "http://localhost:9100/"
+
+[gates.gate]
+destination = "http://example.com/"
+internal_destination = "https://internal.example.com/"
+upstream_tls = { ca_bundle = "/does/not/exist.pem" }
+orders = []
+"#;
+    let config: Config = toml::from_str(toml).unwrap();
+    // Act
+    let _ = config.create_url_resolver();
+    // Assert
+}
+
+#[test]
+pub fn create_visa_cookies_should_read_cookie_of_gate() {
+    // Arrange
+    let toml = r#"
+secret_key_provider = { InMemory = "verysecretkey" }
+
+[api]
+base_url = "http://localhost:9100/"
+
+[gates.gate]
+destination = "http://example.com/"
+visa_cookie = { domain = "example.com", secure = true, http_only = false, same_site = "Strict" }
+orders = []
+
+[gates.default_gate]
+destination = "http://localhost/"
+orders = []
+"#;
+    let config: Config = toml::from_str(toml).unwrap();
+    // Act
+    let visa_cookies = config.create_visa_cookies();
+    // Assert
+    let mut gate_cookie = visa_cookie::VisaCookie::default();
+    gate_cookie.set_domain("example.com");
+    gate_cookie.set_secure(true);
+    gate_cookie.set_http_only(false);
+    gate_cookie.set_same_site(visa_cookie::SameSite::Strict);
+    assert_eq!(gate_cookie, visa_cookies.get("gate"));
+    assert_eq!(
+        visa_cookie::VisaCookie::default(),
+        visa_cookies.get("default_gate")
+    );
+}
+
+#[test_case("same_site = \"None\", secure = true", true ; "secure")]
+#[test_case("same_site = \"None\", secure = false", false ; "insecure")]
+#[test_case("same_site = \"None\"", false ; "secure by request")]
+#[test_case("same_site = \"Lax\"", true ; "lax")]
+pub fn from_toml_should_require_secure_for_same_site_none(visa_cookie: &str, is_ok: bool) {
+    // Arrange
+    let toml = format!(
+        r#"
+secret_key_provider = {{ InMemory = "verysecretkey" }}
+
+[api]
+base_url = "http://localhost:9100/"
+
+[gates.gate]
+destination = "http://example.com/"
+visa_cookie = {{ {visa_cookie} }}
+orders = []
+"#
+    );
+    // Act
+    let config = Config::from_toml(&toml);
+    // Assert
+    assert_eq!(is_ok, config.is_ok(), "{config:?}");
+}
+
+fn tls_config(server_tls: &str, gate_tls: &str) -> Config {
+    let certs = concat!(env!("CARGO_MANIFEST_DIR"), "/src/http/server/tests/certs");
+    let toml = format!(
+        r#"
+secret_key_provider = {{ InMemory = "verysecretkey" }}
+
+[server.tls]
+{server_tls}
+
+[api]
+base_url = "http://localhost:9100/"
+
+[gates.gate]
+destination = "http://example.com/"
+{gate_tls}
+orders = []
+"#
+    )
+    .replace("$CERTS", certs);
+    toml::from_str(&toml).unwrap()
+}
+
+#[test]
+pub fn create_proxy_tls_should_return_none_without_certificates() {
+    // Arrange
+    let config = tls_config("", "");
+    // Act
+    let tls = config.create_proxy_tls().unwrap();
+    // Assert
+    assert!(tls.is_none(), "TLS is enabled without certificates");
+}
+
+#[test_case(r#"proxy = { certificate = "$CERTS/localhost.pem", key = "$CERTS/localhost.key" }"#, "" ; "proxy certificate")]
+#[test_case("", r#"tls = { certificate = "$CERTS/example.com.pem", key = "$CERTS/example.com.key" }"#; "gate certificate")]
+pub fn create_proxy_tls_should_enable_tls_with_any_certificate(server_tls: &str, gate_tls: &str) {
+    // Arrange
+    let config = tls_config(server_tls, gate_tls);
+    // Act
+    let tls = config.create_proxy_tls().unwrap();
+    // Assert
+    assert!(tls.is_some(), "TLS is not enabled");
+}
+
+#[test]
+pub fn create_proxy_tls_should_fail_for_missing_certificate_files() {
+    // Arrange
+    let gate_tls = r#"tls = { certificate = "$CERTS/missing.pem", key = "$CERTS/missing.key" }"#;
+    let config = tls_config("", gate_tls);
+    // Act
+    let tls = config.create_proxy_tls();
+    // Assert
+    assert!(tls.is_err(), "Missing certificate was accepted");
+}
+
+#[test]
+pub fn create_api_tls_should_only_use_api_certificate() {
+    // Arrange
+    let server_tls =
+        r#"proxy = { certificate = "$CERTS/localhost.pem", key = "$CERTS/localhost.key" }"#;
+    let api_tls = r#"api = { certificate = "$CERTS/localhost.pem", key = "$CERTS/localhost.key" }"#;
+    let proxy_only = tls_config(server_tls, "");
+    let api_only = tls_config(api_tls, "");
+    // Act
+    let proxy_only_tls = proxy_only.server().create_api_tls().unwrap();
+    let api_only_tls = api_only.server().create_api_tls().unwrap();
+    // Assert
+    assert!(proxy_only_tls.is_none(), "API uses proxy certificate");
+    assert!(api_only_tls.is_some(), "API does not use its certificate");
+}
+
+#[test]
+pub fn real_ip_resolver_should_use_trusted_proxies_and_fallback() {
+    // Arrange
+    let toml = r#"
+base_url = "http://localhost:9100/"
+real_ip_header = "X-Forwarded-For"
+trusted_proxies = ["10.0.0.0/8"]
+real_ip_fallback = "PeerAddress"
+"#;
+    let api: Api = toml::from_str(toml).unwrap();
+    // Act
+    let real_ip = api.real_ip_resolver();
+    // Assert
+    let mut expected_real_ip = RealIpResolver::new("X-Forwarded-For");
+    expected_real_ip.set_trusted_proxies(vec!["10.0.0.0/8".parse().unwrap()]);
+    expected_real_ip.set_fallback(RealIpFallback::PeerAddress);
+    assert_eq!(Some(expected_real_ip), real_ip);
+}
+
+#[test]
+pub fn real_ip_resolver_should_be_none_without_header() {
+    // Arrange
+    let api = Api {
+        base_url: Some(url("http://localhost:9100/")),
+        single_port: None,
+        real_ip_header: None,
+        trusted_proxies: Some(vec!["10.0.0.0/8".parse().unwrap()]),
+        real_ip_fallback: None,
+    };
+    // Act
+    let real_ip = api.real_ip_resolver();
+    // Assert
+    assert_eq!(None, real_ip);
+}
+
+#[test]
+pub fn forward_auth_ip_resolver_should_read_x_forwarded_for_without_real_ip_header() {
+    // Arrange
+    let toml = r#"
+base_url = "http://localhost:9100/"
+trusted_proxies = ["10.0.0.0/8"]
+"#;
+    let api: Api = toml::from_str(toml).unwrap();
+    // Act
+    let real_ip = api.forward_auth_ip_resolver();
+    // Assert
+    let mut expected_real_ip = RealIpResolver::new("X-Forwarded-For");
+    expected_real_ip.set_trusted_proxies(vec!["10.0.0.0/8".parse().unwrap()]);
+    assert_eq!(Some(expected_real_ip), real_ip);
+}
+
+#[test]
+pub fn forward_auth_ip_resolver_should_be_none_if_real_ip_header_resolves_client() {
+    // Arrange
+    let toml = r#"
+base_url = "http://localhost:9100/"
+real_ip_header = "X-Real-Ip"
+"#;
+    let api: Api = toml::from_str(toml).unwrap();
+    // Act
+    let real_ip = api.forward_auth_ip_resolver();
+    // Assert
+    assert_eq!(None, real_ip);
+}
+
+#[test_case(r#"base_url = "http://localhost:9100/""#, "/", "http://localhost:9100/" ; "api port")]
+#[test_case(r#"single_port = true"#, "/.tollkeeper/", "/.tollkeeper/" ; "single port")]
+#[test_case("base_url = \"http://localhost:9100/\"\nsingle_port = true", "/.tollkeeper/", "/.tollkeeper/" ; "single port ignores base url")]
+pub fn api_should_be_served_below_path(toml: &str, expected_path: &str, expected_public_url: &str) {
+    // Arrange
+    let api: Api = toml::from_str(toml).unwrap();
+    // Act
+    let path = api.path();
+    let public_url = api.public_url();
+    // Assert
+    assert_eq!(expected_path, path);
+    assert_eq!(expected_public_url, public_url);
+}
+
+#[test_case("single_port = false", false ; "api port without base url")]
+#[test_case("", false ; "nothing set")]
+#[test_case("base_url = \"http://localhost:9100/\"", true ; "api port")]
+#[test_case("single_port = true", true ; "single port")]
+pub fn from_toml_should_require_base_url_outside_single_port_mode(api: &str, is_ok: bool) {
+    // Arrange
+    let toml = format!(
+        r#"
+secret_key_provider = {{ InMemory = "verysecretkey" }}
+
+[api]
+{api}
+
+[gates.gate]
+destination = "http://example.com/"
+orders = []
+"#
+    );
+    // Act
+    let config = Config::from_toml(&toml);
+    // Assert
+    assert_eq!(is_ok, config.is_ok(), "{config:?}");
+}
+
+#[test]
+pub fn proxy_protocol_should_be_read_per_listener() {
+    // Arrange
+    let toml = r#"
+[proxy_protocol]
+proxy = ["10.0.0.0/8", "2001:db8::/32"]
+api = []
+"#;
+    let server: Server = toml::from_str(toml).unwrap();
+    // Act
+    let proxy_listener = server.proxy_listener_proxy_protocol();
+    let api_listener = server.api_listener_proxy_protocol();
+    // Assert
+    let expected_proxy_listener = ProxyProtocol::new(vec![
+        "10.0.0.0/8".parse().unwrap(),
+        "2001:db8::/32".parse().unwrap(),
+    ]);
+    assert_eq!(Some(expected_proxy_listener), proxy_listener);
+    assert_eq!(None, api_listener);
+}
+
+#[test]
+pub fn proxy_protocol_should_only_accept_cidrs() {
+    // Arrange
+    let toml = r#"
+[proxy_protocol]
+proxy = ["not-a-cidr"]
+"#;
+    // Act
+    let server = toml::from_str::<Server>(toml);
+    // Assert
+    assert!(server.is_err(), "Invalid CIDR was accepted");
+}
+
+#[test]
+pub fn connection_settings_should_be_read_from_keep_alive_config() {
+    // Arrange
+    let toml = r#"
+proxy_port = 9000
+keep_alive = { idle_timeout = "30s", max_requests = 10 }
+"#;
+    let server: Server = toml::from_str(toml).unwrap();
+    // Act
+    let settings = server.connection_settings();
+    // Assert
+    let expected_settings = ConnectionSettings {
+        idle_timeout: std::time::Duration::from_secs(30),
+        max_requests: 10,
+        ..Default::default()
+    };
+    assert_eq!(expected_settings, settings);
+}
+
+#[test]
+pub fn connection_settings_should_fall_back_to_defaults() {
+    // Arrange
+    let server = Server::default();
+    // Act
+    let settings = server.connection_settings();
+    // Assert
+    assert_eq!(ConnectionSettings::default(), settings);
+}
+
+#[test_case("0s" ; "zero timeout")]
+#[test_case("5x" ; "unknown unit")]
+#[test_case("s" ; "missing value")]
+#[should_panic]
+pub fn connection_settings_should_panic_on_invalid_idle_timeout(idle_timeout: &str) {
+    // Arrange
+    let server = Server {
+        proxy_port: None,
+        api_port: None,
+        keep_alive: Some(KeepAlive {
+            idle_timeout: Some(idle_timeout.into()),
+            max_requests: None,
+        }),
+        ..Default::default()
+    };
+    // Act
+    let _ = server.connection_settings();
+    // Assert
+}
+
+#[test_case(r#"backlog_policy = "Reject""#, limits::BacklogPolicy::Reject ; "reject")]
+#[test_case(r#"backlog_policy = { Queue = 16 }"#, limits::BacklogPolicy::Queue(16) ; "queue")]
+pub fn connection_limits_should_be_read_from_server_config(
+    backlog_policy: &str,
+    expected_backlog_policy: limits::BacklogPolicy,
+) {
+    // Arrange
+    let toml = format!(
+        r#"
+max_connections = 64
+max_connections_per_ip = 4
+{backlog_policy}
+"#
+    );
+    let server: Server = toml::from_str(&toml).unwrap();
+    // Act
+    let limits = server.connection_limits();
+    // Assert
+    let expected_limits = limits::ConnectionLimits {
+        max_connections: 64,
+        max_connections_per_ip: Some(4),
+        backlog_policy: expected_backlog_policy,
+    };
+    assert_eq!(expected_limits, limits);
+}
+
+#[test]
+pub fn connection_limits_should_fall_back_to_defaults() {
+    // Arrange
+    let server = Server::default();
+    // Act
+    let limits = server.connection_limits();
+    // Assert
+    assert_eq!(limits::ConnectionLimits::default(), limits);
+}
+
+#[test]
+#[should_panic]
+pub fn connection_limits_should_panic_without_connections() {
+    // Arrange
+    let server = Server {
+        max_connections: Some(0),
+        ..Default::default()
+    };
+    // Act
+    let _ = server.connection_limits();
+    // Assert
+}
+
+#[test]
+pub fn timeouts_should_be_read_from_server_config() {
+    // Arrange
+    let toml = r#"
+[timeouts]
+header_read = "500ms"
+body_read = "20s"
+upstream_connect = "2s"
+upstream_read = "1m"
+"#;
+    let server: Server = toml::from_str(toml).unwrap();
+    // Act
+    let settings = server.connection_settings();
+    let upstream_timeouts = server.upstream_timeouts();
+    // Assert
+    let expected_settings = ConnectionSettings {
+        header_read_timeout: std::time::Duration::from_millis(500),
+        body_read_timeout: std::time::Duration::from_secs(20),
+        ..Default::default()
+    };
+    assert_eq!(expected_settings, settings);
+    let expected_upstream_timeouts = UpstreamTimeouts {
+        connect: std::time::Duration::from_secs(2),
+        read: std::time::Duration::from_secs(60),
+    };
+    assert_eq!(expected_upstream_timeouts, upstream_timeouts);
+}
+
+#[test]
+pub fn upstream_timeouts_should_fall_back_to_defaults() {
+    // Arrange
+    let server = Server::default();
+    // Act
+    let upstream_timeouts = server.upstream_timeouts();
+    // Assert
+    assert_eq!(UpstreamTimeouts::default(), upstream_timeouts);
+}
+
+#[test_case(Some("5s"), std::time::Duration::from_secs(5) ; "configured")]
+#[test_case(None, http::server::Server::SHUTDOWN_TIMEOUT_DEFAULT ; "default")]
+pub fn shutdown_timeout_should_be_read_from_server_config(
+    shutdown: Option<&str>,
+    expected_shutdown_timeout: std::time::Duration,
+) {
+    // Arrange
+    let server = Server {
+        timeouts: Some(Timeouts {
+            shutdown: shutdown.map(String::from),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    // Act
+    let shutdown_timeout = server.shutdown_timeout();
+    // Assert
+    assert_eq!(expected_shutdown_timeout, shutdown_timeout);
+}
+
+#[test]
+#[should_panic]
+pub fn upstream_timeouts_should_panic_on_invalid_timeout() {
+    // Arrange
+    let server = Server {
+        timeouts: Some(Timeouts {
+            upstream_read: Some("0ms".into()),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    // Act
+    let _ = server.upstream_timeouts();
     // Assert
-    assert_eq!(Some(expected_internal_url), url);
 }
@@ -1,5 +1,6 @@
 pub trait AsHalJson {
-    fn as_hal_json(&self, base_url: &url::Url) -> serde_json::Value;
+    /// Links are relative to `base_url`, which may omit the origin (e.g. `/.tollkeeper/`)
+    fn as_hal_json(&self, base_url: &str) -> serde_json::Value;
 }
 
 pub trait AsHttpHeader {
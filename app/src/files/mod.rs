@@ -9,6 +9,7 @@ use std::{
 
 use crate::http::{
     self,
+    parsing::ParseError,
     response::{self, StatusCode},
     server::{HttpServe, InternalServerError},
     Body, Chunk, Headers, Request, Response, StreamBody,
@@ -82,8 +83,9 @@ impl FileServe {
         }
     }
 }
+#[async_trait::async_trait]
 impl HttpServe for FileServe {
-    fn serve_http(
+    async fn serve_http(
         &self,
         _: &std::net::SocketAddr,
         request: Request,
@@ -119,39 +121,43 @@ enum Encoding {
 }
 
 pub trait FileReader {
-    fn read(&self, path: &Path) -> io::Result<Box<dyn Read>>;
+    fn read(&self, path: &Path) -> io::Result<Box<dyn Read + Send>>;
 }
 pub struct FileReaderImpl;
 impl FileReader for FileReaderImpl {
-    fn read(&self, path: &Path) -> io::Result<Box<dyn Read>> {
-        File::open(path).map(|f| Box::new(f) as Box<dyn Read>)
+    fn read(&self, path: &Path) -> io::Result<Box<dyn Read + Send>> {
+        File::open(path).map(|f| Box::new(f) as Box<dyn Read + Send>)
     }
 }
 
 struct ChunkedFileStream {
-    file: Box<dyn Read>,
+    file: Box<dyn Read + Send>,
     is_eof: bool,
 }
 impl ChunkedFileStream {
-    pub fn new(file: Box<dyn Read>) -> Self {
+    pub fn new(file: Box<dyn Read + Send>) -> Self {
         ChunkedFileStream {
             file,
             is_eof: false,
         }
     }
 }
+#[async_trait::async_trait]
 impl http::ChunkedStream for ChunkedFileStream {
-    fn next_chunk(&mut self) -> Option<Chunk> {
+    async fn next_chunk(&mut self) -> Result<Chunk, ParseError> {
         if self.is_eof {
-            return None;
+            return Ok(Chunk::eof());
         }
         let mut chunk_buf = Vec::with_capacity(Chunk::MAX_CHUNK_SIZE);
-        let size = self.file.read_to_end(chunk_buf.as_mut()).ok()?;
+        let size = self
+            .file
+            .read_to_end(chunk_buf.as_mut())
+            .or(Err(ParseError::Body))?;
         if size == 0 {
             self.is_eof = true;
-            return Some(Chunk::eof());
+            return Ok(Chunk::eof());
         }
         let chunk = Chunk::new(size, chunk_buf);
-        Some(chunk)
+        Ok(chunk)
     }
 }
@@ -27,7 +27,8 @@ use test_case::test_case;
 #[test_case("file.css", "text/css")]
 #[test_case("file.woff2", "font/woff2")]
 #[test_case("file.senta", "text/plain" ; "unknown file type")]
-pub fn file_serve_should_return_requested_file(file_name: &str, expected_content_type: &str) {
+#[tokio::test]
+pub async fn file_serve_should_return_requested_file(file_name: &str, expected_content_type: &str) {
     // Arrange
     let content: VecDeque<u8> = String::from("Hello, World!").into_bytes().into();
     let server_file = format!("/assets/{file_name}");
@@ -43,6 +44,7 @@ pub fn file_serve_should_return_requested_file(file_name: &str, expected_content
     let request = Request::new(Method::Get, server_file, headers, Body::None).unwrap();
     let mut response = sut
         .serve_http(&addr(), request)
+        .await
         .expect("valid request failed");
     // Assert
     assert_eq!(StatusCode::OK, response.status_code());
@@ -59,14 +61,15 @@ pub fn file_serve_should_return_requested_file(file_name: &str, expected_content
         Body::Stream(b) => b,
         Body::None => panic!("File was sent without body!"),
     };
-    let mut actual_body = String::new();
-    body.read_to_string(&mut actual_body).unwrap();
+    let mut actual_body = Vec::new();
+    body.copy_to(&mut actual_body).await.unwrap();
+    let actual_body = String::from_utf8(actual_body).unwrap();
     let expected_body = "d\r\nHello, World!\r\n0\r\n\r\n";
     assert_eq!(expected_body, actual_body);
 }
 
-#[test]
-pub fn file_serve_should_return_file_compressed_by_default() {
+#[tokio::test]
+pub async fn file_serve_should_return_file_compressed_by_default() {
     // Arrange
     let content: VecDeque<u8> = String::from("Hello, World!").into_bytes().into();
     let server_file = "/assets/file.txt".to_string();
@@ -81,6 +84,7 @@ pub fn file_serve_should_return_file_compressed_by_default() {
     let request = Request::new(Method::Get, server_file, headers, Body::None).unwrap();
     let mut response = sut
         .serve_http(&addr(), request)
+        .await
         .expect("valid request failed");
     // Assert
     assert_eq!(StatusCode::OK, response.status_code());
@@ -98,13 +102,13 @@ pub fn file_serve_should_return_file_compressed_by_default() {
         Body::Stream(b) => b,
         Body::None => panic!("File was sent without body!"),
     };
-    let actual_body = decompress(body);
+    let actual_body = decompress(body).await;
     let expected_body = "Hello, World!";
     assert_eq!(expected_body, actual_body);
 }
 
-#[test]
-pub fn file_serve_should_not_compress_file_if_disabled() {
+#[tokio::test]
+pub async fn file_serve_should_not_compress_file_if_disabled() {
     // Arrange
     let content: VecDeque<u8> = String::from("Hello, World!").into_bytes().into();
     let server_file = "/assets/file.txt".to_string();
@@ -120,6 +124,7 @@ pub fn file_serve_should_not_compress_file_if_disabled() {
     let request = Request::new(Method::Get, server_file, headers, Body::None).unwrap();
     let mut response = sut
         .serve_http(&addr(), request)
+        .await
         .expect("valid request failed");
     // Assert
     assert_eq!(StatusCode::OK, response.status_code());
@@ -136,15 +141,16 @@ pub fn file_serve_should_not_compress_file_if_disabled() {
         Body::Stream(b) => b,
         Body::None => panic!("File was sent without body!"),
     };
-    let mut actual_body = String::new();
-    body.read_to_string(&mut actual_body).unwrap();
+    let mut actual_body = Vec::new();
+    body.copy_to(&mut actual_body).await.unwrap();
+    let actual_body = String::from_utf8(actual_body).unwrap();
     let expected_body = "d\r\nHello, World!\r\n0\r\n\r\n";
     assert_eq!(expected_body, actual_body);
 }
 
-fn decompress(body: &mut http::StreamBody) -> String {
+async fn decompress(body: &mut http::StreamBody) -> String {
     let mut chunks = Vec::new();
-    body.read_to_end(&mut chunks).unwrap();
+    body.copy_to(&mut chunks).await.unwrap();
     let data = parse_chunks(chunks);
     let mut decompressed = flate2::read::GzDecoder::new(data);
     let mut text = String::new();
@@ -171,8 +177,8 @@ fn parse_chunks(chunks: Vec<u8>) -> VecDeque<u8> {
     VecDeque::from(data)
 }
 
-#[test]
-pub fn file_serve_should_return_404_if_file_does_not_exist() {
+#[tokio::test]
+pub async fn file_serve_should_return_404_if_file_does_not_exist() {
     // Arrange
     let content: VecDeque<u8> = String::from("Hello, World!").into_bytes().into();
     let file_reader = FakeFileReader::new(indexmap::indexmap![
@@ -185,6 +191,7 @@ pub fn file_serve_should_return_404_if_file_does_not_exist() {
     let request = Request::new(Method::Get, "/etc/passwd", headers, Body::None).unwrap();
     let mut response = sut
         .serve_http(&addr(), request)
+        .await
         .expect("valid request failed");
     // Assert
     assert_eq!(StatusCode::NotFound, response.status_code());
@@ -207,7 +214,7 @@ impl FakeFileReader {
     }
 }
 impl FileReader for FakeFileReader {
-    fn read(&self, path: &std::path::Path) -> std::io::Result<Box<dyn std::io::Read>> {
+    fn read(&self, path: &std::path::Path) -> std::io::Result<Box<dyn std::io::Read + Send>> {
         let no_file_found = io::Error::new(io::ErrorKind::NotFound, "file not found");
         let file = self
             .files
@@ -215,6 +222,6 @@ impl FileReader for FakeFileReader {
             .ok_or(no_file_found)?
             .clone();
         let reader = BufReader::new(file);
-        Ok(Box::new(reader) as Box<dyn std::io::Read>)
+        Ok(Box::new(reader) as Box<dyn std::io::Read + Send>)
     }
 }
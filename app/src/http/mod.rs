@@ -1,12 +1,12 @@
 use indexmap::IndexMap;
 use std::collections::VecDeque;
-use std::io::{BufRead, BufReader, Read};
-use std::{fmt::Display, io, str::FromStr};
+use std::{fmt::Display, future::Future, io, str::FromStr};
+use tokio::io::{AsyncBufRead, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 
 #[cfg(test)]
 mod tests;
 
-mod parsing;
+pub mod parsing;
 pub mod request;
 pub mod response;
 pub mod server;
@@ -22,6 +22,17 @@ pub struct Headers {
 impl Headers {
     pub const MAX_HEADER_SIZE: usize = 8192;
     pub const MAX_HEADER_NUMBER: usize = 128;
+    /// Headers never forwarded by a proxy
+    const HOP_BY_HOP: [&str; 6] = [
+        "connection",
+        "keep-alive",
+        "proxy-connection",
+        "te",
+        "trailer",
+        "upgrade",
+    ];
+    /// Headers which may not be dropped by listing them in `Connection`
+    const FRAMING: [&str; 3] = ["content-length", "transfer-encoding", "host"];
 
     pub fn new(headers: Vec<(String, String)>) -> Self {
         let headers = Self::map_headers_case_insensitive(headers);
@@ -62,6 +73,15 @@ impl Headers {
         }
     }
 
+    /// Gets the values of all headers with the same key
+    pub fn get_all(&self, key: &str) -> Vec<&str> {
+        let key = key.to_ascii_lowercase();
+        match self.headers.get(&key) {
+            Some(v) => v.iter().map(|h| h.value.as_str()).collect(),
+            None => vec![],
+        }
+    }
+
     pub fn insert(&mut self, key: impl Into<String>, value: impl Into<String>) {
         let original_key = key.into();
         let key = &original_key.to_ascii_lowercase();
@@ -76,6 +96,42 @@ impl Headers {
         let bucket = self.headers.get_mut(key).unwrap();
         bucket.push(header);
     }
+
+    /// Removes all headers with the same key
+    pub fn remove(&mut self, key: &str) {
+        let key = key.to_ascii_lowercase();
+        self.headers.shift_remove(&key);
+    }
+
+    /// Checks if the comma separated header value contains the token (case-insensitive)
+    pub fn contains_token(&self, key: &str, token: &str) -> bool {
+        self.get(key)
+            .map(|v| v.split(',').any(|t| t.trim().eq_ignore_ascii_case(token)))
+            .unwrap_or(false)
+    }
+
+    /// Removes the headers only meant for the current connection
+    /// ([RFC 9110](https://www.rfc-editor.org/rfc/rfc9110#section-7.6.1)), including the ones
+    /// listed in `Connection`. Headers framing the message are kept, as the body is forwarded in
+    /// its transfer encoding
+    pub fn remove_hop_by_hop(&mut self) {
+        let listed: Vec<String> = self
+            .get_all("connection")
+            .iter()
+            .flat_map(|v| v.split(','))
+            .map(|t| t.trim().to_ascii_lowercase())
+            .filter(|t| !t.is_empty())
+            .collect();
+        for key in Self::HOP_BY_HOP
+            .iter()
+            .copied()
+            .chain(listed.iter().map(|k| k.as_str()))
+        {
+            if !Self::FRAMING.contains(&key) {
+                self.remove(key);
+            }
+        }
+    }
 }
 impl Display for Headers {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -93,9 +149,35 @@ struct Header {
     value: String,
 }
 
+/// HTTP/1.x protocol versions understood by the server
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum HttpVersion {
+    Http10,
+    Http11,
+}
+impl Display for HttpVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HttpVersion::Http10 => write!(f, "HTTP/1.0"),
+            HttpVersion::Http11 => write!(f, "HTTP/1.1"),
+        }
+    }
+}
+impl FromStr for HttpVersion {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "HTTP/1.0" => Ok(HttpVersion::Http10),
+            "HTTP/1.1" => Ok(HttpVersion::Http11),
+            _ => Err(()),
+        }
+    }
+}
+
 pub trait Parse<T>: Sized {
     type Err;
-    fn parse(stream: T) -> Result<Self, Self::Err>;
+    fn parse(stream: T) -> impl Future<Output = Result<Self, Self::Err>> + Send;
 }
 
 pub enum Body {
@@ -104,20 +186,17 @@ pub enum Body {
     None,
 }
 impl Body {
-    pub fn from_stream(mut stream: Box<dyn io::Read>, content_length: Option<usize>) -> Self {
-        match content_length {
-            Some(len) => {
-                let mut buffer = vec![0; len];
-                stream.read_exact(&mut buffer).unwrap();
-                let body = BufferBody::new(buffer.into());
-                Body::Buffer(body)
-            }
-            None => {
-                let stream = ChunkedTcpStream::new(Box::new(BufReader::new(stream)));
-                let body = StreamBody::new(Box::new(stream));
-                Body::Stream(body)
-            }
-        }
+    /// Creates a body streamed from `stream`. Delimited by `content_length` if known,
+    /// chunked otherwise
+    pub fn from_stream(
+        stream: Box<dyn AsyncBufRead + Send + Unpin>,
+        content_length: Option<usize>,
+    ) -> Self {
+        let body = match content_length {
+            Some(len) => StreamBody::with_length(stream, len),
+            None => StreamBody::new(Box::new(ChunkedTcpStream::new(stream))),
+        };
+        Body::Stream(body)
     }
 
     pub fn from_string(data: String) -> Self {
@@ -147,57 +226,124 @@ impl io::Read for BufferBody {
 
 /// HTTP Body with data stream
 pub struct StreamBody {
-    stream: Box<dyn ChunkedStream>,
-    current_chunk: Option<BufReader<VecDeque<u8>>>,
-    bytes_read: usize,
-    next_chunk: usize,
+    stream: BodyStream,
     is_eof: bool,
 }
+enum BodyStream {
+    Chunked(Box<dyn ChunkedStream + Send>),
+    Sized {
+        stream: Box<dyn AsyncRead + Send + Unpin>,
+        remaining: usize,
+    },
+    CloseDelimited(Box<dyn AsyncRead + Send + Unpin>),
+}
 impl StreamBody {
-    pub fn new(stream: Box<dyn ChunkedStream>) -> Self {
+    /// Maximum number of bytes read at once from a length-delimited stream
+    pub const READ_BUFFER_SIZE: usize = 16 * 1024;
+
+    /// Creates a body sent with `Transfer-Encoding: chunked`
+    pub fn new(stream: Box<dyn ChunkedStream + Send>) -> Self {
         Self {
-            stream,
-            current_chunk: None,
-            bytes_read: 0,
-            next_chunk: 0,
+            stream: BodyStream::Chunked(stream),
             is_eof: false,
         }
     }
-}
-impl Read for StreamBody {
-    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+
+    /// Creates a body of exactly `content_length` bytes
+    pub fn with_length(stream: Box<dyn AsyncRead + Send + Unpin>, content_length: usize) -> Self {
+        Self {
+            stream: BodyStream::Sized {
+                stream,
+                remaining: content_length,
+            },
+            is_eof: content_length == 0,
+        }
+    }
+
+    /// Creates a body that ends once the sender closes the connection
+    pub fn until_close(stream: Box<dyn AsyncRead + Send + Unpin>) -> Self {
+        Self {
+            stream: BodyStream::CloseDelimited(stream),
+            is_eof: false,
+        }
+    }
+
+    /// Reads the next part of the body in the form it is sent over the wire, i.e. encoded chunks
+    /// for chunked bodies. Returns [None] once the body was read completely
+    pub async fn read_chunk(&mut self) -> io::Result<Option<Vec<u8>>> {
+        self.read_next(false).await
+    }
+
+    /// Reads the next part of the body with the chunk encoding removed. Returns [None] once the
+    /// body was read completely
+    pub async fn read_data(&mut self) -> io::Result<Option<Vec<u8>>> {
+        self.read_next(true).await
+    }
+
+    async fn read_next(&mut self, decode_chunks: bool) -> io::Result<Option<Vec<u8>>> {
         if self.is_eof {
-            return Ok(0);
+            return Ok(None);
         }
-        if self.current_chunk.is_none() {
-            let chunk = match self.stream.next_chunk() {
-                Some(c) => c,
-                None => {
-                    if self.is_eof {
-                        return Ok(0);
-                    } else {
-                        return Err(io::Error::new(
-                            io::ErrorKind::QuotaExceeded,
-                            "chunk too large",
-                        ));
-                    }
+        match &mut self.stream {
+            BodyStream::Chunked(stream) => {
+                let chunk = stream.next_chunk().await.map_err(chunk_error)?;
+                self.is_eof = chunk.is_eof();
+                if !decode_chunks {
+                    Ok(Some(chunk.into_bytes()))
+                } else if chunk.is_eof() {
+                    Ok(None)
+                } else {
+                    Ok(Some(chunk.into_content()))
                 }
-            };
-            self.is_eof = chunk.is_eof();
-            let chunk = chunk.into_bytes();
-            let chunk_len = chunk.len();
-            self.current_chunk = Some(BufReader::new(chunk.into()));
-            self.next_chunk += chunk_len;
+            }
+            BodyStream::Sized { stream, remaining } => {
+                let mut buffer = vec![0; (*remaining).min(Self::READ_BUFFER_SIZE)];
+                let read = stream.read(&mut buffer).await?;
+                if read == 0 {
+                    return Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "stream ended before Content-Length was reached",
+                    ));
+                }
+                buffer.truncate(read);
+                *remaining -= read;
+                self.is_eof = *remaining == 0;
+                Ok(Some(buffer))
+            }
+            BodyStream::CloseDelimited(stream) => {
+                let mut buffer = vec![0; Self::READ_BUFFER_SIZE];
+                let read = stream.read(&mut buffer).await?;
+                if read == 0 {
+                    self.is_eof = true;
+                    return Ok(None);
+                }
+                buffer.truncate(read);
+                Ok(Some(buffer))
+            }
         }
-        let reader = self.current_chunk.as_mut().unwrap();
-        let res = reader.read(buf)?;
-        self.bytes_read += res;
-        if self.bytes_read >= self.next_chunk {
-            self.current_chunk = None;
+    }
+
+    /// Writes the remaining body to `writer`. Returns the number of bytes written
+    pub async fn copy_to(&mut self, writer: &mut (impl AsyncWrite + Unpin)) -> io::Result<u64> {
+        let mut bytes_written = 0;
+        while let Some(chunk) = self.read_chunk().await? {
+            writer.write_all(&chunk).await?;
+            bytes_written += chunk.len() as u64;
         }
-        Ok(res)
+        Ok(bytes_written)
     }
 }
+/// Maps a failed chunk read to [io::ErrorKind::QuotaExceeded] for oversized bodies,
+/// [io::ErrorKind::TimedOut] for stalled senders and [io::ErrorKind::InvalidData] otherwise
+fn chunk_error(error: parsing::ParseError) -> io::Error {
+    let kind = match error {
+        parsing::ParseError::Body => io::ErrorKind::QuotaExceeded,
+        parsing::ParseError::Timeout => io::ErrorKind::TimedOut,
+        _ => io::ErrorKind::InvalidData,
+    };
+    io::Error::new(kind, error)
+}
+
 #[derive(Debug, PartialEq, Eq)]
 pub struct Chunk {
     size: usize,
@@ -221,6 +367,11 @@ impl Chunk {
         self.size == 0
     }
 
+    /// Returns the data of the chunk without the chunk encoding
+    pub fn into_content(self) -> Vec<u8> {
+        self.content
+    }
+
     pub fn into_bytes(mut self) -> Vec<u8> {
         let mut data = Vec::new();
         data.append(&mut format!("{:x}\r\n", self.size).into_bytes());
@@ -229,57 +380,145 @@ impl Chunk {
         data
     }
 }
+#[async_trait::async_trait]
 pub trait ChunkedStream {
-    fn next_chunk(&mut self) -> Option<Chunk>;
+    /// Reads the next chunk. Fails with [parsing::ParseError::Body] if the body is too large
+    async fn next_chunk(&mut self) -> Result<Chunk, parsing::ParseError>;
 }
 
-pub struct ChunkedTcpStream {
-    stream: Box<io::BufReader<dyn io::Read>>,
+/// Reads chunks of a `Transfer-Encoding: chunked` body from a stream
+pub struct ChunkedTcpStream<S = Box<dyn AsyncBufRead + Send + Unpin>> {
+    stream: S,
+    max_size: Option<usize>,
+    size: usize,
 }
-impl ChunkedTcpStream {
-    pub fn new(stream: Box<io::BufReader<dyn io::Read>>) -> Self {
-        Self { stream }
+impl<S: AsyncBufRead + Send + Unpin> ChunkedTcpStream<S> {
+    /// Maximum length of a chunk size line, including chunk extensions
+    pub const MAX_CHUNK_LINE_SIZE: usize = 1024;
+
+    pub fn new(stream: S) -> Self {
+        Self {
+            stream,
+            max_size: None,
+            size: 0,
+        }
+    }
+
+    /// Fails reading chunks once their content exceeds `max_size` bytes in total
+    pub fn with_max_size(stream: S, max_size: usize) -> Self {
+        Self {
+            stream,
+            max_size: Some(max_size),
+            size: 0,
+        }
     }
 
-    pub fn read_chunk(&mut self) -> Option<Chunk> {
-        let chunk_size = self.read_chunk_size()?;
+    /// Reads the next chunk. Fails with [parsing::ParseError::Body] if the chunk is too large
+    /// and with [parsing::ParseError::InvalidChunk] if it is not framed correctly
+    pub async fn read_chunk(&mut self) -> Result<Chunk, parsing::ParseError> {
+        let chunk_size = self.read_chunk_size().await?;
         if chunk_size > Chunk::MAX_CHUNK_SIZE {
-            return None;
+            return Err(parsing::ParseError::Body);
+        }
+        self.size += chunk_size;
+        if self.max_size.is_some_and(|max| self.size > max) {
+            tracing::warn!("Chunked body exceeds maximum size");
+            return Err(parsing::ParseError::Body);
         }
         if chunk_size == 0 {
-            self.stream.consume(2); //Empty Content
-            Some(Chunk::eof())
+            self.skip_trailers().await?;
+            Ok(Chunk::eof())
         } else {
-            let content = self.read_chunk_content(chunk_size)?;
+            let content = self.read_chunk_content(chunk_size).await?;
             let chunk = Chunk::new(chunk_size, content);
-            Some(chunk)
+            Ok(chunk)
         }
     }
 
-    fn read_chunk_size(&mut self) -> Option<usize> {
-        let mut chunk_size = String::new();
-        let _ = self.stream.by_ref().read_line(&mut chunk_size).ok()?;
-        if chunk_size.trim().is_empty() {
-            chunk_size.clear();
-            let _ = self.stream.by_ref().read_line(&mut chunk_size).ok()?;
+    async fn read_chunk_size(&mut self) -> Result<usize, parsing::ParseError> {
+        let line = parsing::read_line(
+            &mut self.stream,
+            Self::MAX_CHUNK_LINE_SIZE,
+            parsing::ParseError::InvalidChunk,
+        )
+        .await?;
+        let line = String::from_utf8(line).or(Err(parsing::ParseError::InvalidChunk))?;
+        // Chunk extensions are not used, so they are ignored
+        let chunk_size = line.split(';').next().unwrap_or("");
+        let chunk_size = chunk_size.trim_end_matches([' ', '\t']);
+        if chunk_size.is_empty() || !chunk_size.bytes().all(|b| b.is_ascii_hexdigit()) {
+            tracing::warn!("invalid chunk size: '{chunk_size}'");
+            return Err(parsing::ParseError::InvalidChunk);
         }
-        let chunk_size = chunk_size.trim();
-        if chunk_size.is_empty() {
-            return None;
+        usize::from_str_radix(chunk_size, 16).or(Err(parsing::ParseError::InvalidChunk))
+    }
+
+    async fn read_chunk_content(
+        &mut self,
+        chunk_size: usize,
+    ) -> Result<Vec<u8>, parsing::ParseError> {
+        let mut content = vec![0; chunk_size + 2];
+        self.stream
+            .read_exact(&mut content)
+            .await
+            .map_err(|e| parsing::read_error(e, parsing::ParseError::InvalidChunk))?;
+        if content.split_off(chunk_size) != b"\r\n" {
+            tracing::warn!("chunk data is not followed by CRLF");
+            return Err(parsing::ParseError::InvalidChunk);
         }
-        let chunk_size = usize::from_str_radix(chunk_size, 16).unwrap();
-        Some(chunk_size)
+        Ok(content)
     }
 
-    fn read_chunk_content(&mut self, chunk_size: usize) -> Option<Vec<u8>> {
-        let mut content = vec![0; chunk_size];
-        self.stream.read_exact(&mut content).ok()?;
-        self.stream.consume(2); // Remove CRLF from stream
-        Some(content)
+    /// Reads past the trailer section ending the body
+    async fn skip_trailers(&mut self) -> Result<(), parsing::ParseError> {
+        for _ in 0..=Headers::MAX_HEADER_NUMBER {
+            let line = parsing::read_line(
+                &mut self.stream,
+                Headers::MAX_HEADER_SIZE,
+                parsing::ParseError::InvalidChunk,
+            )
+            .await?;
+            if line.is_empty() {
+                return Ok(());
+            }
+        }
+        tracing::warn!("Trailer section exceeds maximum number of fields");
+        Err(parsing::ParseError::InvalidChunk)
+    }
+}
+#[async_trait::async_trait]
+impl<S: AsyncBufRead + Send + Unpin> ChunkedStream for ChunkedTcpStream<S> {
+    async fn next_chunk(&mut self) -> Result<Chunk, parsing::ParseError> {
+        self.read_chunk().await
     }
 }
-impl ChunkedStream for ChunkedTcpStream {
-    fn next_chunk(&mut self) -> Option<Chunk> {
-        self.read_chunk()
+
+/// Connection handed over to another protocol by a `101 Switching Protocols` response
+/// ([RFC 9110](https://www.rfc-editor.org/rfc/rfc9110#section-7.8))
+pub struct Upgrade {
+    reader: Box<dyn AsyncRead + Send + Unpin>,
+    writer: Box<dyn AsyncWrite + Send + Unpin>,
+}
+impl Upgrade {
+    pub fn new(
+        reader: Box<dyn AsyncRead + Send + Unpin>,
+        writer: Box<dyn AsyncWrite + Send + Unpin>,
+    ) -> Self {
+        Self { reader, writer }
+    }
+
+    /// Copies data between the upgraded connection and the client in both directions until
+    /// both sides closed their connection. A side closing its connection only closes the
+    /// writing half of the other, so data still sent the other way is not lost
+    pub async fn splice(
+        mut self,
+        client_reader: &mut (impl AsyncRead + Unpin + Send),
+        client_writer: &mut (impl AsyncWrite + Unpin + Send),
+    ) -> io::Result<()> {
+        let mut upgraded = tokio::io::join(&mut self.reader, &mut self.writer);
+        let mut client = tokio::io::join(client_reader, client_writer);
+        tokio::io::copy_bidirectional(&mut client, &mut upgraded)
+            .await
+            .map(|_| ())
     }
 }
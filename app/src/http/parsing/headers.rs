@@ -1,14 +1,16 @@
+use std::str::FromStr;
+
 use crate::http::{Headers, Parse};
-use std::io::{self, BufRead, Read};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt};
 
-use super::ParseError;
+use super::{read_error, read_line, ParseError};
 
-impl<T: io::Read> Parse<&mut io::BufReader<T>> for Headers {
+impl<T: AsyncBufRead + Unpin + Send> Parse<&mut T> for Headers {
     type Err = ParseError;
 
-    fn parse(reader: &mut io::BufReader<T>) -> Result<Self, Self::Err> {
+    async fn parse(reader: &mut T) -> Result<Self, Self::Err> {
         let mut headers = vec![];
-        while !is_end_of_headers(reader)? {
+        while !is_end_of_headers(reader).await? {
             if headers.len() >= Headers::MAX_HEADER_NUMBER {
                 tracing::warn!(
                     "Request header number exceeds limit of {}",
@@ -16,13 +18,21 @@ impl<T: io::Read> Parse<&mut io::BufReader<T>> for Headers {
                 );
                 return Err(ParseError::Header);
             }
-            let header = read_header(reader).ok_or(ParseError::Header)?;
+            let header = read_header(reader).await?;
+            if header.starts_with([' ', '\t']) {
+                tracing::warn!("obsolete line folding in header: '{header}'");
+                return Err(ParseError::ObsoleteLineFolding);
+            }
             let (key, value) = header.split_once(':').ok_or(ParseError::Header)?;
-            if contains_whitespace(key) {
-                tracing::warn!("unexpected whitespace in header name: '{key}'");
+            if !is_token(key) {
+                tracing::warn!("invalid header name: '{key}'");
+                return Err(ParseError::Header);
+            }
+            let value = value.trim_matches([' ', '\t']);
+            if value.chars().any(|c| c.is_ascii_control() && c != '\t') {
+                tracing::warn!("control character in value of header '{key}'");
                 return Err(ParseError::Header);
             }
-            let value = value.trim();
             headers.push((key.to_string(), value.to_string()));
         }
         let headers = Headers::new(headers);
@@ -30,27 +40,105 @@ impl<T: io::Read> Parse<&mut io::BufReader<T>> for Headers {
     }
 }
 
-fn is_end_of_headers<T: io::Read>(reader: &mut io::BufReader<T>) -> Result<bool, ParseError> {
-    let unread_bytes = reader.fill_buf().or(Err(ParseError::Header))?;
-    if unread_bytes.len() < 2 {
-        Err(ParseError::Header)
-    } else {
-        Ok(unread_bytes[..2] == *b"\r\n")
+/// Returns `true` and consumes the empty line if it ends the headers
+async fn is_end_of_headers(reader: &mut (impl AsyncBufRead + Unpin)) -> Result<bool, ParseError> {
+    let unread_bytes = reader
+        .fill_buf()
+        .await
+        .map_err(|e| read_error(e, ParseError::Header))?;
+    match unread_bytes.first() {
+        Some(b'\r') => {
+            let line = read_line(reader, 2, ParseError::Header).await?;
+            Ok(line.is_empty())
+        }
+        Some(b'\n') => {
+            tracing::warn!("headers terminated by bare LF");
+            Err(ParseError::BareLineFeed)
+        }
+        Some(_) => Ok(false),
+        None => Err(ParseError::Header),
     }
 }
 
-fn read_header<T: io::Read>(reader: &mut io::BufReader<T>) -> Option<String> {
-    let mut header = Vec::with_capacity(Headers::MAX_HEADER_SIZE);
-    reader
-        .take(Headers::MAX_HEADER_SIZE as u64)
-        .read_until(b'\r', &mut header)
-        .ok()?;
-    reader.consume(1);
-    header.pop(); //Remove semicolon
-    let header = String::from_utf8(header).ok()?;
-    Some(header)
+async fn read_header(reader: &mut (impl AsyncBufRead + Unpin)) -> Result<String, ParseError> {
+    let header = read_line(reader, Headers::MAX_HEADER_SIZE, ParseError::Header).await?;
+    let header = String::from_utf8(header).or(Err(ParseError::Header))?;
+    Ok(header)
 }
 
-fn contains_whitespace(value: &str) -> bool {
-    value.chars().any(|c| c.is_whitespace())
+/// Checks if `value` is a non-empty token as defined by RFC 9110
+pub fn is_token(value: &str) -> bool {
+    !value.is_empty()
+        && value
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+}
+
+/// Returns `true` if `chunked` is the final coding of `transfer_encoding`, which then frames
+/// the message
+pub fn is_chunked(transfer_encoding: &str) -> bool {
+    let last_coding = transfer_encoding.rsplit(',').next().unwrap_or("").trim();
+    last_coding.eq_ignore_ascii_case("chunked")
+}
+
+/// Joins all transfer codings of a response into a single list. Responses without final
+/// `chunked` are read until the connection closes (RFC 9112, Section 6.3)
+pub fn merge_response_transfer_codings(values: &[&str]) -> Result<String, ParseError> {
+    let codings = split_transfer_codings(values)?;
+    Ok(codings.join(", "))
+}
+
+/// Joins all transfer codings of a request into a single list, making sure `chunked` is only
+/// applied last. The length of other request bodies can not be determined
+pub fn merge_request_transfer_codings(values: &[&str]) -> Result<String, ParseError> {
+    let codings = split_transfer_codings(values)?;
+    let chunked_positions: Vec<usize> = codings
+        .iter()
+        .enumerate()
+        .filter(|(_, c)| c.eq_ignore_ascii_case("chunked"))
+        .map(|(i, _)| i)
+        .collect();
+    let chunked_is_valid = match chunked_positions.as_slice() {
+        [] => true,
+        [position] => *position == codings.len() - 1,
+        _ => false,
+    };
+    if !chunked_is_valid {
+        tracing::warn!("chunked is not the final Transfer-Encoding: {values:?}");
+        return Err(ParseError::InvalidTransferEncoding);
+    }
+    Ok(codings.join(", "))
+}
+
+fn split_transfer_codings<'a>(values: &[&'a str]) -> Result<Vec<&'a str>, ParseError> {
+    let codings: Vec<&str> = values
+        .iter()
+        .flat_map(|v| v.split(','))
+        .map(str::trim)
+        .filter(|c| !c.is_empty())
+        .collect();
+    if codings.is_empty() || !codings.iter().all(|c| is_token(c)) {
+        tracing::warn!("invalid Transfer-Encoding: {values:?}");
+        return Err(ParseError::InvalidTransferEncoding);
+    }
+    Ok(codings)
+}
+
+/// Returns the single length announced by all `Content-Length` values
+pub fn merge_content_lengths(values: &[&str]) -> Result<String, ParseError> {
+    let mut content_length: Option<&str> = None;
+    for value in values.iter().flat_map(|v| v.split(',')).map(str::trim) {
+        if value.is_empty() || !value.bytes().all(|b| b.is_ascii_digit()) {
+            tracing::warn!("invalid Content-Length: '{value}'");
+            return Err(ParseError::InvalidContentLength);
+        }
+        if content_length.is_some_and(|len| len != value) {
+            tracing::warn!("conflicting Content-Length values: {values:?}");
+            return Err(ParseError::InvalidContentLength);
+        }
+        content_length = Some(value);
+    }
+    let content_length = content_length.ok_or(ParseError::InvalidContentLength)?;
+    usize::from_str(content_length).or(Err(ParseError::InvalidContentLength))?;
+    Ok(content_length.to_string())
 }
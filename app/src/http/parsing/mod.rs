@@ -1,4 +1,6 @@
-use std::{error, fmt};
+use std::{error, fmt, io};
+
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt};
 
 pub mod headers;
 pub mod request;
@@ -9,18 +11,101 @@ mod tests;
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum ParseError {
     RequestLine,
+    /// Request line is longer than [crate::http::Request::MAX_REQUEST_LINE_SIZE]
+    RequestLineTooLong,
     StatusLine,
     Header,
     Body,
+    /// Peer stopped sending data before the message was complete
+    Timeout,
+    /// Line terminated by LF without a preceding CR
+    BareLineFeed,
+    /// Header value continued on the next line (obs-fold)
+    ObsoleteLineFolding,
+    /// Message has both `Content-Length` and `Transfer-Encoding`
+    ConflictingFraming,
+    /// Request has more than one `Host` header or an empty one
+    InvalidHost,
+    /// `Content-Length` is not a number or sent multiple times with different values
+    InvalidContentLength,
+    /// `chunked` is not the final transfer coding or applied more than once
+    InvalidTransferEncoding,
+    /// Chunk size line or chunk delimiter of a chunked body is malformed
+    InvalidChunk,
+    /// Request uses an HTTP version other than HTTP/1.0 or HTTP/1.1
+    UnsupportedVersion,
 }
 impl error::Error for ParseError {}
 impl fmt::Display for ParseError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             ParseError::RequestLine => write!(f, "Invalid request line"),
+            ParseError::RequestLineTooLong => write!(f, "Request line too long"),
             ParseError::StatusLine => write!(f, "Invalid status line"),
             ParseError::Header => write!(f, "Invalid header line"),
             ParseError::Body => write!(f, "Invalid body"),
+            ParseError::Timeout => write!(f, "Timed out waiting for data"),
+            ParseError::BareLineFeed => write!(f, "Line not terminated by CRLF"),
+            ParseError::ObsoleteLineFolding => write!(f, "Obsolete line folding in header"),
+            ParseError::ConflictingFraming => {
+                write!(f, "Both Content-Length and Transfer-Encoding are present")
+            }
+            ParseError::InvalidHost => write!(f, "Invalid Host"),
+            ParseError::InvalidContentLength => write!(f, "Invalid Content-Length"),
+            ParseError::InvalidTransferEncoding => write!(f, "Invalid Transfer-Encoding"),
+            ParseError::InvalidChunk => write!(f, "Invalid chunk"),
+            ParseError::UnsupportedVersion => write!(f, "Unsupported HTTP version"),
         }
     }
 }
+
+/// Maps a failed read to [ParseError::Timeout] if the stream stopped waiting for data, to
+/// `error` otherwise
+pub fn read_error(err: io::Error, error: ParseError) -> ParseError {
+    match err.kind() {
+        io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => ParseError::Timeout,
+        _ => error,
+    }
+}
+
+/// Reads a line terminated by CRLF and returns it without the line terminator. Fails with
+/// [ParseError::BareLineFeed] if the line ends with a bare LF and with `error` if it is longer
+/// than `max_size`, contains a lone CR or the stream ends early
+pub async fn read_line(
+    reader: &mut (impl AsyncBufRead + Unpin),
+    max_size: usize,
+    error: ParseError,
+) -> Result<Vec<u8>, ParseError> {
+    read_limited_line(reader, max_size, error.clone(), error).await
+}
+
+/// Reads a line like [read_line], but fails with `too_long` if it is longer than `max_size`
+pub async fn read_limited_line(
+    reader: &mut (impl AsyncBufRead + Unpin),
+    max_size: usize,
+    error: ParseError,
+    too_long: ParseError,
+) -> Result<Vec<u8>, ParseError> {
+    let mut line = Vec::new();
+    reader
+        .take(max_size as u64)
+        .read_until(b'\n', &mut line)
+        .await
+        .map_err(|e| read_error(e, error.clone()))?;
+    if line.last() != Some(&b'\n') && line.len() >= max_size {
+        tracing::warn!("line exceeds limit of {max_size} bytes");
+        return Err(too_long);
+    }
+    if line.pop() != Some(b'\n') {
+        return Err(error);
+    }
+    if line.pop() != Some(b'\r') {
+        tracing::warn!("line terminated by bare LF");
+        return Err(ParseError::BareLineFeed);
+    }
+    if line.contains(&b'\r') {
+        tracing::warn!("line contains a lone CR");
+        return Err(error);
+    }
+    Ok(line)
+}
@@ -1,23 +1,40 @@
-use std::{
-    io::{self, BufRead, Read},
-    str::FromStr,
-};
+use std::str::FromStr;
+
+use tokio::io::{AsyncBufRead, AsyncRead, AsyncReadExt};
 
-use super::ParseError;
+use super::headers::{is_chunked, merge_content_lengths, merge_request_transfer_codings};
+use super::{read_error, read_limited_line, ParseError};
 use crate::http::{
     self,
     request::{self, BadRequestError, *},
 };
-use crate::http::{Body, Parse};
+use crate::http::{Body, BufferBody, ChunkedTcpStream, HttpVersion, Parse, StreamBody};
 
-impl<T: Read + 'static> Parse<T> for Request {
+impl<T: AsyncBufRead + Unpin + Send> Parse<&mut T> for Request {
     type Err = ParseError;
-    fn parse(stream: T) -> Result<Request, ParseError> {
-        let mut stream = io::BufReader::new(stream);
-        let request_line = RequestLine::parse(&mut stream)?;
-        let headers = request::Headers::parse(&mut stream)?;
-        stream.consume(2); //Consume trailing CRLF
-        let request = if let Some(content_length) = headers.content_length() {
+    async fn parse(stream: &mut T) -> Result<Request, ParseError> {
+        RequestHead::parse(&mut *stream)
+            .await?
+            .read_body(stream)
+            .await
+    }
+}
+
+/// Request line and headers of a [Request] whose body has not been read yet
+pub struct RequestHead {
+    request_line: RequestLine,
+    headers: request::Headers,
+}
+impl RequestHead {
+    /// Reads the body announced by the headers and completes the [Request]. Chunked bodies are
+    /// buffered in their encoded form
+    pub async fn read_body(
+        self,
+        stream: &mut (impl AsyncBufRead + Unpin + Send),
+    ) -> Result<Request, ParseError> {
+        let body = if self.has_chunked_body()? {
+            read_chunked_body(stream).await?
+        } else if let Some(content_length) = self.headers.content_length() {
             if content_length > Request::MAX_BODY_SIZE {
                 tracing::warn!(
                     "Content-Length exceeds maximum: {}MB > MAX_BODY_SIZE!",
@@ -25,27 +42,111 @@ impl<T: Read + 'static> Parse<T> for Request {
                 );
                 return Err(ParseError::Body);
             }
-            Request::new(
-                request_line.method,
-                request_line.request_target,
-                headers,
-                Body::from_stream(Box::new(stream), Some(content_length)),
-            )
+            read_body(stream, content_length).await?
+        } else {
+            Body::None
+        };
+        self.into_request(body)
+    }
+
+    /// Completes the [Request] with a chunked body that is read from `stream` while the request
+    /// is being handled
+    pub fn stream_chunked_body(
+        self,
+        stream: impl AsyncBufRead + Unpin + Send + 'static,
+    ) -> Result<Request, ParseError> {
+        let stream = ChunkedTcpStream::with_max_size(stream, Request::MAX_BODY_SIZE);
+        let body = Body::Stream(StreamBody::new(Box::new(stream)));
+        self.into_request(body)
+    }
+
+    /// Returns `true` if the body is sent with `Transfer-Encoding: chunked`.
+    /// Fails for other transfer codings, as their length can not be determined
+    pub fn has_chunked_body(&self) -> Result<bool, ParseError> {
+        let transfer_encoding = match self.headers.transfer_encoding() {
+            Some(te) => te,
+            None => return Ok(false),
+        };
+        if is_chunked(transfer_encoding) {
+            Ok(true)
         } else {
-            Request::new(
-                request_line.method,
-                request_line.request_target,
-                headers,
-                Body::None,
-            )
-        }?;
+            tracing::warn!("Unsupported Transfer-Encoding: {transfer_encoding}");
+            Err(ParseError::Header)
+        }
+    }
+
+    /// Returns `true` if the client waits for an interim `100 Continue` response before sending
+    /// the body
+    pub fn expects_continue(&self) -> bool {
+        let has_body = self.headers.transfer_encoding().is_some()
+            || self.headers.content_length().is_some_and(|len| len > 0);
+        // HTTP/1.0 clients do not know interim responses (RFC 9110, Section 10.1.1)
+        let knows_continue = self.request_line.http_version != HttpVersion::Http10;
+        has_body && knows_continue && self.headers.expect_continue()
+    }
+
+    fn into_request(self, body: Body) -> Result<Request, ParseError> {
+        let mut request = Request::new(
+            self.request_line.method,
+            self.request_line.request_target,
+            self.headers,
+            body,
+        )?;
+        request.set_http_version(self.request_line.http_version);
         Ok(request)
     }
 }
+impl<T: AsyncBufRead + Unpin + Send> Parse<&mut T> for RequestHead {
+    type Err = ParseError;
+    async fn parse(stream: &mut T) -> Result<RequestHead, ParseError> {
+        let request_line = RequestLine::parse(&mut *stream).await?;
+        let headers = request::Headers::parse(&mut *stream).await?;
+        if request_line.http_version == HttpVersion::Http10 && headers.transfer_encoding().is_some()
+        {
+            // HTTP/1.0 has no transfer codings, so the framing is faulty (RFC 9112, Section 6.1)
+            tracing::warn!("HTTP/1.0 request with Transfer-Encoding");
+            return Err(ParseError::InvalidTransferEncoding);
+        }
+        Ok(Self {
+            request_line,
+            headers,
+        })
+    }
+}
+
+/// Reads exactly `content_length` bytes, leaving any pipelined requests in the stream
+async fn read_body(
+    stream: &mut (impl AsyncRead + Unpin),
+    content_length: usize,
+) -> Result<Body, ParseError> {
+    let mut buffer = vec![0; content_length];
+    stream
+        .read_exact(&mut buffer)
+        .await
+        .map_err(|e| read_error(e, ParseError::Body))?;
+    Ok(Body::Buffer(BufferBody::new(buffer.into())))
+}
+
+/// Reads a chunked body into a buffer, keeping the chunk encoding
+async fn read_chunked_body(
+    stream: &mut (impl AsyncBufRead + Unpin + Send),
+) -> Result<Body, ParseError> {
+    let mut chunks = ChunkedTcpStream::with_max_size(stream, Request::MAX_BODY_SIZE);
+    let mut buffer = Vec::new();
+    loop {
+        let chunk = chunks.read_chunk().await?;
+        let is_eof = chunk.is_eof();
+        buffer.append(&mut chunk.into_bytes());
+        if is_eof {
+            return Ok(Body::Buffer(BufferBody::new(buffer.into())));
+        }
+    }
+}
 
 struct RequestLine {
     method: Method,
     request_target: String,
+    http_version: HttpVersion,
 }
 impl RequestLine {
     fn new(
@@ -53,26 +154,42 @@ impl RequestLine {
         request_target: String,
         http_version: String,
     ) -> Result<Self, ParseError> {
-        if http_version != "HTTP/1.1" {
-            return Err(ParseError::RequestLine);
-        }
         let request_line = Self {
             method,
             request_target: Self::check_field_format(request_target)?,
+            http_version: Self::parse_http_version(&http_version)?,
         };
         Ok(request_line)
     }
 
-    fn read_raw_request_line<T: io::Read>(reader: &mut io::BufReader<T>) -> Option<String> {
-        let mut request_line = Vec::with_capacity(Request::MAX_REQUEST_LINE_SIZE);
-        reader
-            .take(Request::MAX_REQUEST_LINE_SIZE as u64)
-            .read_until(b'\r', &mut request_line)
-            .ok()?;
-        reader.consume(1); // Consume newline
-        request_line.pop(); //Remove trailing CR from output
-        let request_line = String::from_utf8(request_line).ok()?;
-        Some(request_line)
+    /// Fails with [ParseError::UnsupportedVersion] for well-formed versions other than HTTP/1.x
+    fn parse_http_version(http_version: &str) -> Result<HttpVersion, ParseError> {
+        if let Ok(http_version) = HttpVersion::from_str(http_version) {
+            return Ok(http_version);
+        }
+        let is_http_version = http_version
+            .strip_prefix("HTTP/")
+            .is_some_and(|v| matches!(v.as_bytes(), [major, b'.', minor] if major.is_ascii_digit() && minor.is_ascii_digit()));
+        if is_http_version {
+            tracing::warn!("unsupported HTTP version: {http_version}");
+            Err(ParseError::UnsupportedVersion)
+        } else {
+            Err(ParseError::RequestLine)
+        }
+    }
+
+    async fn read_raw_request_line(
+        reader: &mut (impl AsyncBufRead + Unpin),
+    ) -> Result<String, ParseError> {
+        let request_line = read_limited_line(
+            reader,
+            Request::MAX_REQUEST_LINE_SIZE,
+            ParseError::RequestLine,
+            ParseError::RequestLineTooLong,
+        )
+        .await?;
+        let request_line = String::from_utf8(request_line).or(Err(ParseError::RequestLine))?;
+        Ok(request_line)
     }
 
     fn read_request_line_part<'a>(
@@ -95,11 +212,11 @@ impl RequestLine {
         }
     }
 }
-impl<T: Read> Parse<&mut io::BufReader<T>> for RequestLine {
+impl<T: AsyncBufRead + Unpin + Send> Parse<&mut T> for RequestLine {
     type Err = ParseError;
 
-    fn parse(reader: &mut io::BufReader<T>) -> Result<Self, Self::Err> {
-        let request_line = Self::read_raw_request_line(reader).ok_or(ParseError::RequestLine)?;
+    async fn parse(reader: &mut T) -> Result<Self, Self::Err> {
+        let request_line = Self::read_raw_request_line(reader).await?;
         if has_trailing_whitespace(&request_line) {
             tracing::warn!("request line has trailing whitespace");
             return Err(ParseError::RequestLine);
@@ -121,13 +238,43 @@ fn has_trailing_whitespace(request_line: &str) -> bool {
     request_line.trim_end() != request_line
 }
 
-impl<T: Read> Parse<&mut io::BufReader<T>> for Headers {
+impl<T: AsyncBufRead + Unpin + Send> Parse<&mut T> for Headers {
     type Err = ParseError;
 
-    fn parse(reader: &mut io::BufReader<T>) -> Result<Self, Self::Err> {
-        let headers = http::Headers::parse(reader);
-        Ok(Headers::new(headers?)?)
+    async fn parse(reader: &mut T) -> Result<Self, Self::Err> {
+        let mut headers = http::Headers::parse(reader).await?;
+        normalize_framing_headers(&mut headers)?;
+        Ok(Headers::new(headers)?)
+    }
+}
+
+/// Rejects headers that allow the length of the body to be read differently by different
+/// parsers (RFC 9112, Section 6). Repeated `Content-Length` and `Transfer-Encoding` headers are
+/// merged, so the request is forwarded with a single unambiguous value. Repeated or empty `Host`
+/// headers are rejected as well, since the gate would be chosen by one of them and the upstream
+/// might pick another (RFC 9112, Section 3.2)
+fn normalize_framing_headers(headers: &mut http::Headers) -> Result<(), ParseError> {
+    let hosts = headers.get_all("host");
+    if hosts.len() > 1 || hosts.iter().any(|host| host.trim().is_empty()) {
+        tracing::warn!("request has repeated or empty Host headers: {hosts:?}");
+        return Err(ParseError::InvalidHost);
+    }
+    let content_lengths = headers.get_all("content-length");
+    let transfer_encodings = headers.get_all("transfer-encoding");
+    if !content_lengths.is_empty() && !transfer_encodings.is_empty() {
+        tracing::warn!("request has both Content-Length and Transfer-Encoding");
+        return Err(ParseError::ConflictingFraming);
+    }
+    if !content_lengths.is_empty() {
+        let content_length = merge_content_lengths(&content_lengths)?;
+        headers.remove("content-length");
+        headers.insert("Content-Length", content_length);
+    } else if !transfer_encodings.is_empty() {
+        let transfer_codings = merge_request_transfer_codings(&transfer_encodings)?;
+        headers.remove("transfer-encoding");
+        headers.insert("Transfer-Encoding", transfer_codings);
     }
+    Ok(())
 }
 
 impl From<BadRequestError> for ParseError {
@@ -1,84 +1,191 @@
+use crate::http::request::Method;
 use crate::http::response::{self, Response, StatusCode};
-use crate::http::{self, Body, Parse};
-use std::io::{self};
-use std::io::{BufRead, BufReader};
+use crate::http::{self, Body, HttpVersion, Parse, StreamBody, Upgrade};
+use std::str::FromStr;
+use tokio::io::{self, AsyncBufRead, BufReader};
 
-use super::ParseError;
+use super::headers::{is_chunked, merge_content_lengths, merge_response_transfer_codings};
+use super::{read_line, ParseError};
 
-impl<T: io::Read + 'static> Parse<T> for Response {
+impl<T: io::AsyncRead + Send + Unpin + 'static> Parse<T> for Response {
     type Err = ParseError;
 
-    fn parse(stream: T) -> Result<Self, Self::Err> {
+    async fn parse(stream: T) -> Result<Self, Self::Err> {
+        Response::parse_for(stream, &Method::Get).await
+    }
+}
+impl Response {
+    /// Parses the final response to a request sent with `request_method`, skipping interim
+    /// (1xx) responses. The request method decides if the response can have a body
+    pub async fn parse_for(
+        stream: impl io::AsyncRead + Send + Unpin + 'static,
+        request_method: &Method,
+    ) -> Result<Response, ParseError> {
         let mut stream = BufReader::new(stream);
-        let status_line = StatusLine::parse(&mut stream)?;
-        let headers = response::Headers::parse(&mut stream)?;
-        let response = if has_body(&headers) {
-            stream.consume(2); //Consume additional newline for body
-            let body = Body::from_stream(Box::new(stream), headers.content_length());
-            Response::new(
-                status_line.status_code,
-                status_line.reason_phrase,
-                headers,
-                body,
-            )
+        let (status_line, headers) = parse_final_head(&mut stream).await?;
+        let status_code = status_line.status_code;
+        let body = if has_body(status_code, request_method) {
+            read_body(stream, &headers)
         } else {
-            Response::new(
-                status_line.status_code,
-                status_line.reason_phrase,
-                headers,
-                Body::None,
-            )
+            Body::None
         };
+        let mut response = Response::new(status_code, status_line.reason_phrase, headers, body);
+        response.set_http_version(status_line.http_version);
+        Ok(response)
+    }
+
+    /// Parses the final response like [Response::parse_for]. A `101 Switching Protocols`
+    /// response keeps the connection as [Upgrade], for the new protocol to continue on it
+    pub async fn parse_upgradable_for(
+        stream: impl io::AsyncRead + io::AsyncWrite + Send + Unpin + 'static,
+        request_method: &Method,
+    ) -> Result<Response, ParseError> {
+        let (reader, writer) = io::split(stream);
+        let mut reader = BufReader::new(reader);
+        let (status_line, headers) = parse_final_head(&mut reader).await?;
+        let status_code = status_line.status_code;
+        if status_code == StatusCode::SwitchingProtocols {
+            let mut response =
+                Response::new(status_code, status_line.reason_phrase, headers, Body::None);
+            response.set_http_version(status_line.http_version);
+            response.set_upgrade(Upgrade::new(Box::new(reader), Box::new(writer)));
+            return Ok(response);
+        }
+        let body = if has_body(status_code, request_method) {
+            read_body(reader, &headers)
+        } else {
+            Body::None
+        };
+        let mut response = Response::new(status_code, status_line.reason_phrase, headers, body);
+        response.set_http_version(status_line.http_version);
         Ok(response)
     }
 }
 
-fn has_body(headers: &response::Headers) -> bool {
-    headers.content_length().is_some() || headers.transfer_encoding().unwrap_or("") == "chunked"
+/// Parses status line and headers of the final response, skipping interim (1xx) responses
+async fn parse_final_head(
+    stream: &mut (impl AsyncBufRead + Send + Unpin),
+) -> Result<(StatusLine, response::Headers), ParseError> {
+    loop {
+        let status_line = StatusLine::parse(&mut *stream).await?;
+        let headers = response::Headers::parse(&mut *stream).await?;
+        let status_code = status_line.status_code;
+        if is_interim(status_code) {
+            tracing::debug!("Skipping interim response '{status_code}'");
+            continue;
+        }
+        return Ok((status_line, headers));
+    }
+}
+
+/// 1xx responses are followed by the final response, except for a protocol switch
+fn is_interim(status_code: StatusCode) -> bool {
+    (100..200).contains(&(status_code as isize)) && status_code != StatusCode::SwitchingProtocols
+}
+
+/// Responses to HEAD, informational, 204 and 304 responses and tunnels never have a body
+/// (RFC 9112, Section 6.3)
+fn has_body(status_code: StatusCode, request_method: &Method) -> bool {
+    let status_code = status_code as isize;
+    let is_tunnel = *request_method == Method::Connect && (200..300).contains(&status_code);
+    *request_method != Method::Head
+        && !is_tunnel
+        && !(100..200).contains(&status_code)
+        && status_code != 204
+        && status_code != 304
+}
+
+/// Streams the body framed by `Transfer-Encoding` or `Content-Length`. Bodies without either are
+/// read until the connection is closed
+fn read_body(
+    stream: impl AsyncBufRead + Send + Unpin + 'static,
+    headers: &response::Headers,
+) -> Body {
+    if let Some(transfer_encoding) = headers.transfer_encoding() {
+        if is_chunked(transfer_encoding) {
+            return Body::from_stream(Box::new(stream), None);
+        }
+        return Body::Stream(StreamBody::until_close(Box::new(stream)));
+    }
+    match headers.content_length() {
+        Some(0) => Body::None,
+        Some(content_length) => Body::from_stream(Box::new(stream), Some(content_length)),
+        None => Body::Stream(StreamBody::until_close(Box::new(stream))),
+    }
 }
 
 struct StatusLine {
+    http_version: HttpVersion,
     status_code: StatusCode,
     reason_phrase: Option<String>,
 }
 impl StatusLine {
-    pub fn new(status_code: StatusCode, reason_phrase: Option<String>) -> Self {
+    pub fn new(
+        http_version: HttpVersion,
+        status_code: StatusCode,
+        reason_phrase: Option<String>,
+    ) -> Self {
         Self {
+            http_version,
             status_code,
             reason_phrase,
         }
     }
 }
-impl<T: io::Read> Parse<&mut io::BufReader<T>> for StatusLine {
+impl<T: AsyncBufRead + Unpin + Send> Parse<&mut T> for StatusLine {
     type Err = ParseError;
 
-    fn parse(stream: &mut BufReader<T>) -> Result<Self, Self::Err> {
-        let mut status_line = Vec::new();
-        stream
-            .read_until(b'\r', &mut status_line)
-            .or(Err(ParseError::StatusLine))?;
-        status_line.pop(); // Remove trailing CR
+    async fn parse(stream: &mut T) -> Result<Self, Self::Err> {
+        let status_line = read_line(
+            stream,
+            Response::MAX_STATUS_LINE_SIZE,
+            ParseError::StatusLine,
+        )
+        .await?;
         let status_line = String::from_utf8(status_line).or(Err(ParseError::StatusLine))?;
         let status_line: Vec<&str> = status_line.splitn(3, ' ').collect();
+        let http_version = HttpVersion::from_str(status_line[0]).or(Err(ParseError::StatusLine))?;
         let status_code = status_line.get(1).ok_or(ParseError::StatusLine)?;
         let status_code = StatusCode::from(status_code).ok_or(ParseError::StatusLine)?;
         let reason_phrase = status_line.get(2).unwrap_or(&"");
-        stream.consume(1); //Consume trailing LF
         let status_line = if reason_phrase.is_empty() || reason_phrase.contains(char::is_whitespace)
         {
-            StatusLine::new(status_code, Option::None)
+            StatusLine::new(http_version, status_code, Option::None)
         } else {
-            StatusLine::new(status_code, Option::Some(reason_phrase.trim().into()))
+            StatusLine::new(
+                http_version,
+                status_code,
+                Option::Some(reason_phrase.trim().into()),
+            )
         };
         Ok(status_line)
     }
 }
 
-impl<T: io::Read> Parse<&mut io::BufReader<T>> for response::Headers {
+impl<T: AsyncBufRead + Unpin + Send> Parse<&mut T> for response::Headers {
     type Err = ParseError;
 
-    fn parse(stream: &mut io::BufReader<T>) -> Result<Self, Self::Err> {
-        let headers = http::Headers::parse(stream)?;
+    async fn parse(stream: &mut T) -> Result<Self, Self::Err> {
+        let mut headers = http::Headers::parse(stream).await?;
+        normalize_framing_headers(&mut headers)?;
         Ok(response::Headers::new(headers))
     }
 }
+
+/// `Transfer-Encoding` overrides `Content-Length`, which must then not be forwarded
+/// (RFC 9112, Section 6.3). Repeated framing headers are merged into a single value
+fn normalize_framing_headers(headers: &mut http::Headers) -> Result<(), ParseError> {
+    let transfer_encodings = headers.get_all("transfer-encoding");
+    let content_lengths = headers.get_all("content-length");
+    if !transfer_encodings.is_empty() {
+        let transfer_codings = merge_response_transfer_codings(&transfer_encodings)?;
+        headers.remove("content-length");
+        headers.remove("transfer-encoding");
+        headers.insert("Transfer-Encoding", transfer_codings);
+    } else if !content_lengths.is_empty() {
+        let content_length = merge_content_lengths(&content_lengths)?;
+        headers.remove("content-length");
+        headers.insert("Content-Length", content_length);
+    }
+    Ok(())
+}
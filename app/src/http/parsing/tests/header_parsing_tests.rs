@@ -1,16 +1,17 @@
 use pretty_assertions::assert_eq;
-use std::io::BufReader;
+use tokio::io::{AsyncReadExt, BufReader};
 
 use crate::http::{parsing::ParseError, Headers, Parse};
 
-#[test]
-fn parse_should_return_headers_from_stream() {
+#[tokio::test]
+async fn parse_should_return_headers_from_stream() {
     // Arrange
     let raw_request = "Hello: World\r\nCookie: Foo\r\nCookie: Bar\r\n\r\n";
     let mut raw_headers = BufReader::new(raw_request.as_bytes());
     // Act
-    let headers =
-        Headers::parse(&mut raw_headers).expect("Failed to parse perfectly valid headers");
+    let headers = Headers::parse(&mut raw_headers)
+        .await
+        .expect("Failed to parse perfectly valid headers");
     // Assert
     let expected_headers = vec![
         ("Hello".into(), "World".into()),
@@ -21,8 +22,25 @@ fn parse_should_return_headers_from_stream() {
     assert_eq!(expected_headers, headers);
 }
 
-#[test]
-fn parse_should_return_error_if_header_value_exceed_limit() {
+#[tokio::test]
+async fn parse_should_read_headers_arriving_byte_by_byte() {
+    // Arrange
+    let raw_request = "Hello: World\r\n\r\nBody";
+    let mut raw_headers = BufReader::with_capacity(1, raw_request.as_bytes());
+    // Act
+    let headers = Headers::parse(&mut raw_headers)
+        .await
+        .expect("Failed to parse headers split across reads");
+    // Assert
+    let expected_headers = Headers::new(vec![("Hello".into(), "World".into())]);
+    assert_eq!(expected_headers, headers);
+    let mut rest = String::new();
+    raw_headers.read_to_string(&mut rest).await.unwrap();
+    assert_eq!("Body", rest);
+}
+
+#[tokio::test]
+async fn parse_should_return_error_if_header_value_exceed_limit() {
     // Arrange
     let expected_max_size = 8192;
     let raw_header = format!(
@@ -31,13 +49,13 @@ fn parse_should_return_error_if_header_value_exceed_limit() {
     );
     let mut raw_header = BufReader::new(raw_header.as_bytes());
     // Act
-    let res = Headers::parse(&mut raw_header);
+    let res = Headers::parse(&mut raw_header).await;
     // Assert
     assert_eq!(Err(ParseError::Header), res);
 }
 
-#[test]
-fn parse_should_return_error_if_header_key_exceed_limit() {
+#[tokio::test]
+async fn parse_should_return_error_if_header_key_exceed_limit() {
     // Arrange
     let expected_max_size = 8192;
     let raw_header = format!(
@@ -46,13 +64,13 @@ fn parse_should_return_error_if_header_key_exceed_limit() {
     );
     let mut raw_header = BufReader::new(raw_header.as_bytes());
     // Act
-    let res = Headers::parse(&mut raw_header);
+    let res = Headers::parse(&mut raw_header).await;
     // Assert
     assert_eq!(Err(ParseError::Header), res);
 }
 
-#[test]
-fn parse_should_return_error_if_too_many_headers_are_sent() {
+#[tokio::test]
+async fn parse_should_return_error_if_too_many_headers_are_sent() {
     // Arrange
     let expected_max_number = 128;
     let mut raw_headers = String::new();
@@ -63,7 +81,7 @@ fn parse_should_return_error_if_too_many_headers_are_sent() {
     raw_headers.push_str("\r\n");
     let mut raw_headers = BufReader::new(raw_headers.as_bytes());
     // Act
-    let res = Headers::parse(&mut raw_headers);
+    let res = Headers::parse(&mut raw_headers).await;
     // Assert
     assert_eq!(Err(ParseError::Header), res);
 }
@@ -1,3 +1,4 @@
 mod header_parsing_tests;
 mod request_parsing_tests;
 mod response_parsing_tests;
+mod smuggling_tests;
@@ -1,42 +1,48 @@
 use crate::http::parsing::ParseError;
 use crate::http::request::{Headers, Method, Request};
-use crate::http::{self, Parse};
+use crate::http::{self, Chunk, HttpVersion, Parse};
 use pretty_assertions::assert_eq;
-use std::collections::VecDeque;
 use std::io::Read;
+use std::pin::Pin;
+use std::task::{Context, Poll};
 use test_case::test_case;
+use tokio::io::{AsyncRead, ReadBuf};
 
-#[test]
-pub fn parse_should_read_minimal_http_request() {
+#[tokio::test]
+pub async fn parse_should_read_minimal_http_request() {
     // Arrange
     let raw_request = concat!("GET / HTTP/1.1\r\n", "Host:localhost\r\n\r\n");
-    let raw_request = raw_request.as_bytes();
+    let mut raw_request = raw_request.as_bytes();
     // Act
-    let request = Request::parse(raw_request).expect("Failed to parse perfectly valid request");
+    let request = Request::parse(&mut raw_request)
+        .await
+        .expect("Failed to parse perfectly valid request");
     // Assert
     assert_eq!(Method::Get, *request.method());
     assert_eq!("/", request.request_target());
-    assert_eq!("HTTP/1.1", request.http_version());
+    assert_eq!(HttpVersion::Http11, request.http_version());
     let expected_headers = vec![("Host".into(), "localhost".into())];
     let expected_headers = http::Headers::new(expected_headers);
     let expected_headers = Headers::new(expected_headers).unwrap();
     assert_eq!(expected_headers, *request.headers());
 }
 
-#[test]
-pub fn parse_should_read_request_with_query_params_correctly() {
+#[tokio::test]
+pub async fn parse_should_read_request_with_query_params_correctly() {
     // Arrange
     let raw_request = concat!(
         "GET /?hello=world&foo=bar HTTP/1.1\r\n",
         "Host:localhost\r\n\r\n"
     );
-    let raw_request = raw_request.as_bytes();
+    let mut raw_request = raw_request.as_bytes();
     // Act
-    let request = Request::parse(raw_request).expect("Failed to parse perfectly valid request");
+    let request = Request::parse(&mut raw_request)
+        .await
+        .expect("Failed to parse perfectly valid request");
     // Assert
     assert_eq!(Method::Get, *request.method());
     assert_eq!("/?hello=world&foo=bar", request.request_target());
-    assert_eq!("HTTP/1.1", request.http_version());
+    assert_eq!(HttpVersion::Http11, request.http_version());
     let expected_headers = vec![("Host".into(), "localhost".into())];
     let expected_headers = http::Headers::new(expected_headers);
     let expected_headers = Headers::new(expected_headers).unwrap();
@@ -48,20 +54,22 @@ pub fn parse_should_read_request_with_query_params_correctly() {
     );
 }
 
-#[test]
-pub fn parse_should_read_request_with_multiple_same_name_headers() {
+#[tokio::test]
+pub async fn parse_should_read_request_with_multiple_same_name_headers() {
     // Arrange
     let raw_request = concat!(
         "GET /?hello=world&foo=bar HTTP/1.1\r\n",
         "Host:localhost\r\n\r\n"
     );
-    let raw_request = raw_request.as_bytes();
+    let mut raw_request = raw_request.as_bytes();
     // Act
-    let request = Request::parse(raw_request).expect("Failed to parse perfectly valid request");
+    let request = Request::parse(&mut raw_request)
+        .await
+        .expect("Failed to parse perfectly valid request");
     // Assert
     assert_eq!(Method::Get, *request.method());
     assert_eq!("/?hello=world&foo=bar", request.request_target());
-    assert_eq!("HTTP/1.1", request.http_version());
+    assert_eq!(HttpVersion::Http11, request.http_version());
     let expected_headers = vec![("Host".into(), "localhost".into())];
     let expected_headers = http::Headers::new(expected_headers);
     let expected_headers = Headers::new(expected_headers).unwrap();
@@ -73,8 +81,8 @@ pub fn parse_should_read_request_with_multiple_same_name_headers() {
     );
 }
 
-#[test]
-pub fn parse_should_read_http_request_with_body() {
+#[tokio::test]
+pub async fn parse_should_read_http_request_with_body() {
     // Arrange
     let raw_request = concat!(
         "POST / HTTP/1.1\r\n",
@@ -84,13 +92,15 @@ pub fn parse_should_read_http_request_with_body() {
         "\r\n",
         "Hello, World!\r\n"
     );
-    let raw_request = raw_request.as_bytes();
+    let mut raw_request = raw_request.as_bytes();
     // Act
-    let mut request = Request::parse(raw_request).expect("Failed to parse perfectly valid request");
+    let mut request = Request::parse(&mut raw_request)
+        .await
+        .expect("Failed to parse perfectly valid request");
     // Assert
     assert_eq!(&Method::Post, request.method());
     assert_eq!("/", request.request_target());
-    assert_eq!("HTTP/1.1", request.http_version());
+    assert_eq!(HttpVersion::Http11, request.http_version());
     let expected_headers = vec![
         ("Host".into(), "localhost".into()),
         ("Content-Type".into(), "text/raw; charset=utf8".into()),
@@ -115,20 +125,19 @@ pub fn parse_should_read_http_request_with_body() {
 #[test_case(String::from("GET\t/\tHTTP/1.1\r\n") ; "tab instead of whitespace")]
 #[test_case(String::from("GET   /   HTTP/1.1\r\n") ; "too much whitespace")]
 #[test_case(String::from("GET   /   HTTP/1.1\r") ; "no line feed")]
-#[test_case(String::from("GET   /   HTTP/1.1\n") ; "no carriage return")]
 #[test_case(String::from("GET   /   HTTP/1.1") ; "no new line")]
 #[test_case(String::from("    GET / HTTP/1.1\r\n") ; "leading whitespace")]
 #[test_case(String::from("GET / HTTP/1.1     \r\n") ; "trailing whitespace")]
 #[test_case(String::from(" / HTTP/1.1\r\n") ; "Missing method")]
 #[test_case(String::from("GET HTTP/1.1\r\n") ; "Missing request target")]
 #[test_case(String::from("GET /\r\n") ; "Missing HTTP version")]
-#[test_case(String::from("GET / HTTP/3.1\r\n") ; "wrong HTTP version")]
-pub fn parse_should_reject_request_line_with_invalid_format(request_line: String) {
+#[tokio::test]
+pub async fn parse_should_reject_request_line_with_invalid_format(request_line: String) {
     // Arrange
     let raw_request = request_line + "Host:localhost\r\n\r\n";
-    let raw_request: VecDeque<u8> = raw_request.into_bytes().into();
+    let mut raw_request = raw_request.as_bytes();
     // Act
-    let result = Request::parse(raw_request);
+    let result = Request::parse(&mut raw_request).await;
     // Assert
     let error = match result {
         Ok(r) => panic!(
@@ -143,20 +152,75 @@ pub fn parse_should_reject_request_line_with_invalid_format(request_line: String
     assert_eq!(expected, error);
 }
 
+#[tokio::test]
+pub async fn parse_should_read_http_1_0_request() {
+    // Arrange
+    let raw_request = concat!("GET / HTTP/1.0\r\n", "Host:localhost\r\n\r\n");
+    let mut raw_request = raw_request.as_bytes();
+    // Act
+    let request = Request::parse(&mut raw_request)
+        .await
+        .expect("Failed to parse valid HTTP/1.0 request");
+    // Assert
+    assert_eq!(HttpVersion::Http10, request.http_version());
+    assert_eq!(
+        "GET / HTTP/1.0\r\nHost: localhost\r\n\r\n",
+        request.to_string()
+    );
+}
+
+#[test_case("HTTP/0.9" ; "HTTP/0.9")]
+#[test_case("HTTP/2.0" ; "HTTP/2.0")]
+#[test_case("HTTP/3.1" ; "HTTP/3.1")]
+#[tokio::test]
+pub async fn parse_should_reject_unsupported_http_version(http_version: &str) {
+    // Arrange
+    let raw_request = format!("GET / {http_version}\r\nHost:localhost\r\n\r\n");
+    let mut raw_request = raw_request.as_bytes();
+    // Act
+    let result = Request::parse(&mut raw_request).await;
+    // Assert
+    assert!(
+        matches!(result, Err(ParseError::UnsupportedVersion)),
+        "Unsupported HTTP version was accepted!"
+    );
+}
+
+#[tokio::test]
+pub async fn parse_should_reject_http_1_0_request_with_transfer_encoding() {
+    // Arrange
+    let raw_request = concat!(
+        "POST / HTTP/1.0\r\n",
+        "Host: localhost\r\n",
+        "Transfer-Encoding: chunked\r\n",
+        "\r\n",
+        "0\r\n\r\n",
+    );
+    let mut raw_request = raw_request.as_bytes();
+    // Act
+    let result = Request::parse(&mut raw_request).await;
+    // Assert
+    assert!(
+        matches!(result, Err(ParseError::InvalidTransferEncoding)),
+        "Transfer-Encoding of HTTP/1.0 request was accepted!"
+    );
+}
+
 #[test_case("{} / HTTP/1.1\r\n" ; "method too long")]
 #[test_case("GET {} HTTP/1.1\r\n" ; "url too long")]
 #[test_case("GET / {}\r\n" ; "version too long")]
-pub fn parse_should_reject_too_long_status_line(request_line: &str) {
+#[tokio::test]
+pub async fn parse_should_reject_too_long_status_line(request_line: &str) {
     // Arrange
     let max_size_data: String = vec!['a'; Request::MAX_REQUEST_LINE_SIZE].iter().collect();
     let request_line = request_line.replace("{}", &max_size_data);
     let raw_request = request_line + "Host:localhost\r\n\r\n";
-    let raw_request: VecDeque<u8> = raw_request.into_bytes().into();
+    let mut raw_request = raw_request.as_bytes();
     // Act
-    let result = Request::parse(raw_request);
+    let result = Request::parse(&mut raw_request).await;
     // Assert
     assert!(
-        matches!(result, Err(ParseError::RequestLine)),
+        matches!(result, Err(ParseError::RequestLineTooLong)),
         "too long request line got accepted!"
     );
 }
@@ -165,13 +229,13 @@ pub fn parse_should_reject_too_long_status_line(request_line: &str) {
 #[test_case(String::from("Host:localhost\r\nX-Whitespace :text\r\n") ; "forbidden whitespace (SPACE) between field name and colon")]
 #[test_case(String::from("Host:localhost\r\nX-Whitespace\t:text\r\n") ; "forbidden whitespace (TAB) between field name and colon")]
 #[test_case(String::from("Host:localhost\r") ; "no line feed")]
-#[test_case(String::from("Host:localhost\n") ; "no carriage return")]
-pub fn parse_should_reject_headers_with_invalid_format(headers: String) {
+#[tokio::test]
+pub async fn parse_should_reject_headers_with_invalid_format(headers: String) {
     // Arrange
     let raw_request = format!("GET / HTTP/1.1\r\n{headers}\r\n");
-    let raw_request: VecDeque<u8> = raw_request.into_bytes().into();
+    let mut raw_request = raw_request.as_bytes();
     // Act
-    let result = Request::parse(raw_request);
+    let result = Request::parse(&mut raw_request).await;
     // Assert
     let error = match result {
         Ok(r) => panic!("Invalid headers got accepted!: '{}'", r.headers()),
@@ -181,8 +245,8 @@ pub fn parse_should_reject_headers_with_invalid_format(headers: String) {
     assert_eq!(expected, error);
 }
 
-#[test]
-pub fn parse_should_treat_headers_case_insensitive() {
+#[tokio::test]
+pub async fn parse_should_treat_headers_case_insensitive() {
     // Arrange
     let raw_request = concat!(
         "GET / HTTP/1.1\r\n",
@@ -190,9 +254,11 @@ pub fn parse_should_treat_headers_case_insensitive() {
         "USER-AGENT:value\r\n",
         "\r\n",
     );
-    let raw_request = raw_request.as_bytes();
+    let mut raw_request = raw_request.as_bytes();
     // Act
-    let request = Request::parse(raw_request).expect("Could not parse valid request");
+    let request = Request::parse(&mut raw_request)
+        .await
+        .expect("Could not parse valid request");
     // Assert
     let ua_header = request
         .headers()
@@ -201,17 +267,17 @@ pub fn parse_should_treat_headers_case_insensitive() {
     assert_eq!("value", ua_header);
 }
 
-#[test]
-pub fn parse_should_reject_message_with_mismatched_host_and_request_target() {
+#[tokio::test]
+pub async fn parse_should_reject_message_with_mismatched_host_and_request_target() {
     // Arrange
     let raw_request = concat!(
         "GET www.google.com/ HTTP/1.1\r\n",
         "Host:localhost\r\n",
         "\r\n",
     );
-    let raw_request = raw_request.as_bytes();
+    let mut raw_request = raw_request.as_bytes();
     // Act
-    let err = match Request::parse(raw_request) {
+    let err = match Request::parse(&mut raw_request).await {
         Ok(_) => panic!("Mismatched request target and host were parsed without error!"),
         Err(e) => e,
     };
@@ -219,18 +285,126 @@ pub fn parse_should_reject_message_with_mismatched_host_and_request_target() {
     assert_eq!(ParseError::Header, err);
 }
 
-#[test]
-pub fn parse_should_reject_requests_with_oversized_content_length() {
+#[tokio::test]
+pub async fn parse_should_reject_requests_with_oversized_content_length() {
     // Arrange
     let oversized_content_length = Request::MAX_BODY_SIZE + 1;
     let body: String = vec!['a'; oversized_content_length].iter().collect();
     let raw_request = format!("GET / HTTP/1.1\r\nHost:localhost\r\nContent-Length:{oversized_content_length}\r\n\r\n{body}");
-    let raw_request: VecDeque<u8> = raw_request.into_bytes().into();
+    let mut raw_request = raw_request.as_bytes();
     // Act
-    let request = Request::parse(raw_request);
+    let request = Request::parse(&mut raw_request).await;
     // Assert
     assert!(
         matches!(request, Err(ParseError::Body)),
         "Oversized body was accepted!"
     );
 }
+
+#[tokio::test]
+pub async fn parse_should_read_http_request_with_chunked_body() {
+    // Arrange
+    let raw_request = concat!(
+        "POST / HTTP/1.1\r\n",
+        "Host: localhost\r\n",
+        "Transfer-Encoding: chunked\r\n",
+        "\r\n",
+        "5;name=value\r\nHello\r\n",
+        "8\r\n, World!\r\n",
+        "0\r\n",
+        "Trailer: ignored\r\n",
+        "\r\n",
+        "GET /next HTTP/1.1\r\n",
+    );
+    let mut raw_request = raw_request.as_bytes();
+    // Act
+    let mut request = Request::parse(&mut raw_request)
+        .await
+        .expect("Failed to parse perfectly valid request");
+    // Assert
+    if let http::Body::Buffer(b) = request.body_mut() {
+        let mut content = String::new();
+        b.read_to_string(&mut content).unwrap();
+        let expected_content = "5\r\nHello\r\n8\r\n, World!\r\n0\r\n\r\n";
+        assert_eq!(expected_content, content);
+    } else {
+        panic!("No body found");
+    }
+    assert_eq!(b"GET /next HTTP/1.1\r\n", raw_request);
+}
+
+#[tokio::test]
+pub async fn parse_should_reject_requests_with_oversized_chunked_body() {
+    // Arrange
+    let chunk: String = vec!['a'; Chunk::MAX_CHUNK_SIZE].iter().collect();
+    let chunk = format!("{:x}\r\n{chunk}\r\n", chunk.len());
+    let chunk_count = Request::MAX_BODY_SIZE / Chunk::MAX_CHUNK_SIZE + 1;
+    let raw_request = format!(
+        "POST / HTTP/1.1\r\nHost:localhost\r\nTransfer-Encoding: chunked\r\n\r\n{}0\r\n\r\n",
+        chunk.repeat(chunk_count)
+    );
+    let mut raw_request = raw_request.as_bytes();
+    // Act
+    let request = Request::parse(&mut raw_request).await;
+    // Assert
+    assert!(
+        matches!(request, Err(ParseError::Body)),
+        "Oversized chunked body was accepted!"
+    );
+}
+
+#[tokio::test]
+pub async fn parse_should_reject_requests_with_unsupported_transfer_encoding() {
+    // Arrange
+    let raw_request = concat!(
+        "POST / HTTP/1.1\r\n",
+        "Host: localhost\r\n",
+        "Transfer-Encoding: gzip\r\n",
+        "\r\n",
+    );
+    let mut raw_request = raw_request.as_bytes();
+    // Act
+    let request = Request::parse(&mut raw_request).await;
+    // Assert
+    assert!(
+        matches!(request, Err(ParseError::Header)),
+        "Body of unknown length was accepted!"
+    );
+}
+
+/// Stream that stops delivering data after `data` was read
+struct StalledStream {
+    data: &'static [u8],
+}
+impl AsyncRead for StalledStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        _: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        if self.data.is_empty() {
+            return Poll::Ready(Err(std::io::ErrorKind::TimedOut.into()));
+        }
+        let size = buf.remaining().min(self.data.len());
+        let (read, rest) = self.data.split_at(size);
+        buf.put_slice(read);
+        self.data = rest;
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[test_case(b"GET / HTTP/1.1\r\nHost: loc" ; "stalled headers")]
+#[test_case(b"POST / HTTP/1.1\r\nHost: localhost\r\nContent-Length: 5\r\n\r\nHe" ; "stalled body")]
+#[tokio::test]
+pub async fn parse_should_return_timeout_error_when_stream_stalls(raw_request: &'static [u8]) {
+    // Arrange
+    let stream = StalledStream { data: raw_request };
+    let mut stream = tokio::io::BufReader::new(stream);
+    // Act
+    let request = Request::parse(&mut stream).await;
+    // Assert
+    assert!(
+        matches!(request, Err(ParseError::Timeout)),
+        "Stalled stream was not reported as timeout!"
+    );
+}
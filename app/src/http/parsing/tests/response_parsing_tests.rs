@@ -1,18 +1,21 @@
 use pretty_assertions::assert_eq;
-use std::{collections::VecDeque, io::Read};
+use std::io::Cursor;
+use test_case::test_case;
 
 use crate::http::{
+    parsing::ParseError,
+    request::Method,
     response::{Response, StatusCode},
-    Body, Parse,
+    Body, HttpVersion, Parse,
 };
 
-#[test]
-pub fn parse_should_return_response_for_valid_message() {
+#[tokio::test]
+pub async fn parse_should_return_response_for_valid_message() {
     // Arrange
     let raw_response = String::from("HTTP/1.1 200 OK\r\n\r\n");
-    let raw_response: VecDeque<u8> = raw_response.into_bytes().into();
+    let raw_response = Cursor::new(raw_response.into_bytes());
     // Act
-    let response = Response::parse(raw_response);
+    let response = Response::parse(raw_response).await;
     // Assert
     match response {
         Err(e) => panic!("Expected Response, got error: {e}"),
@@ -23,13 +26,48 @@ pub fn parse_should_return_response_for_valid_message() {
     };
 }
 
-#[test]
-pub fn parse_should_include_headers() {
+#[test_case(format!("HTTP/1.1 200 {}\r\n\r\n", "a".repeat(Response::MAX_STATUS_LINE_SIZE)), ParseError::StatusLine ; "too long")]
+#[test_case("HTTP/1.1 200 OK\n\r\n".into(), ParseError::BareLineFeed ; "bare line feed")]
+#[test_case("HTTP/1.1 200 O\rK\r\n\r\n".into(), ParseError::StatusLine ; "lone carriage return")]
+#[test_case("HTTP/1.1 200 OK".into(), ParseError::StatusLine ; "no line end")]
+#[test_case("HTTP/2.0 200 OK\r\n\r\n".into(), ParseError::StatusLine ; "unsupported version")]
+#[tokio::test]
+pub async fn parse_should_reject_malformed_status_line(raw_response: String, expected: ParseError) {
+    // Arrange
+    let raw_response = Cursor::new(raw_response.into_bytes());
+    // Act
+    let response = Response::parse(raw_response).await;
+    // Assert
+    match response {
+        Err(e) => assert_eq!(expected, e),
+        Ok(r) => panic!("Expected error, got '{}'", r.status_code()),
+    }
+}
+
+#[test_case("HTTP/1.0", HttpVersion::Http10 ; "http 1.0")]
+#[test_case("HTTP/1.1", HttpVersion::Http11 ; "http 1.1")]
+#[tokio::test]
+pub async fn parse_should_keep_http_version_of_status_line(
+    http_version: &str,
+    expected: HttpVersion,
+) {
+    // Arrange
+    let raw_response = format!("{http_version} 204 No Content\r\n\r\n");
+    let raw_response = Cursor::new(raw_response.into_bytes());
+    // Act
+    let response = Response::parse(raw_response).await;
+    // Assert
+    let response = response.expect("Expected response, got error");
+    assert_eq!(expected, response.http_version());
+}
+
+#[tokio::test]
+pub async fn parse_should_include_headers() {
     // Arrange
     let raw_response = String::from("HTTP/1.1 200 OK\r\nServer: Hello\r\n\r\n");
-    let raw_response: VecDeque<u8> = raw_response.into_bytes().into();
+    let raw_response = Cursor::new(raw_response.into_bytes());
     // Act
-    let response = Response::parse(raw_response);
+    let response = Response::parse(raw_response).await;
     // Assert
     match response {
         Err(e) => panic!("Expected Response, got error: {e}"),
@@ -37,27 +75,29 @@ pub fn parse_should_include_headers() {
     }
 }
 
-#[test]
-pub fn parse_should_include_body() {
+#[tokio::test]
+pub async fn parse_should_include_body() {
     // Arrange
     let raw_response = String::from("HTTP/1.1 200 OK\r\nContent-Length: 7\r\n\r\nHello\r\n");
-    let raw_response: VecDeque<u8> = raw_response.into_bytes().into();
+    let raw_response = Cursor::new(raw_response.into_bytes());
     // Act
-    let mut response = Response::parse(raw_response).expect("expected response, got error");
+    let mut response = Response::parse(raw_response)
+        .await
+        .expect("expected response, got error");
     // Assert
     match response.body() {
-        Body::Buffer(buffer_body) => {
-            let mut body = String::new();
-            buffer_body.read_to_string(&mut body).unwrap();
-            assert_eq!("Hello\r\n", body);
+        Body::Buffer(_) => panic!("Got fixed content buffered instead of streamed"),
+        Body::Stream(body) => {
+            let mut buf = vec![];
+            body.copy_to(&mut buf).await.unwrap();
+            assert_eq!(b"Hello\r\n", buf.as_slice());
         }
-        Body::Stream(_) => panic!("Got fixed content as stream"),
         Body::None => panic!("Expected body but none was sent"),
     }
 }
 
-#[test]
-pub fn parse_should_include_chunked_body() {
+#[tokio::test]
+pub async fn parse_should_include_chunked_body() {
     // Arrange
     let expected_body = [
         "3\r\nHel\r\n",
@@ -67,9 +107,11 @@ pub fn parse_should_include_chunked_body() {
     ];
     let body = expected_body.concat().to_string();
     let raw_response = format!("HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n{body}");
-    let raw_response: VecDeque<u8> = raw_response.into_bytes().into();
+    let raw_response = Cursor::new(raw_response.into_bytes());
     // Act
-    let mut response = Response::parse(raw_response).expect("Expected Response, got error");
+    let mut response = Response::parse(raw_response)
+        .await
+        .expect("Expected Response, got error");
     // Assert
     match response.body() {
         Body::Buffer(_) => {
@@ -77,10 +119,194 @@ pub fn parse_should_include_chunked_body() {
         }
         Body::Stream(body) => {
             let mut buf = vec![];
-            body.read_to_end(&mut buf).unwrap();
+            body.copy_to(&mut buf).await.unwrap();
             let actual_body = String::from_utf8_lossy(&buf).to_string();
             assert_eq!(expected_body.join(""), actual_body);
         }
         Body::None => panic!("Expected body but none was sent"),
     }
 }
+
+#[tokio::test]
+pub async fn parse_should_stream_body_only_up_to_content_length() {
+    // Arrange
+    let raw_response = String::from("HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nHello, World!");
+    let raw_response = Cursor::new(raw_response.into_bytes());
+    // Act
+    let mut response = Response::parse(raw_response)
+        .await
+        .expect("expected response, got error");
+    // Assert
+    let Body::Stream(body) = response.body() else {
+        panic!("Expected streamed body");
+    };
+    let mut buf = vec![];
+    let bytes_written = body.copy_to(&mut buf).await.unwrap();
+    assert_eq!(5, bytes_written);
+    assert_eq!(b"Hello", buf.as_slice());
+}
+
+#[tokio::test]
+pub async fn parse_should_return_error_reading_body_shorter_than_content_length() {
+    // Arrange
+    let raw_response = String::from("HTTP/1.1 200 OK\r\nContent-Length: 1073741824\r\n\r\nHello");
+    let raw_response = Cursor::new(raw_response.into_bytes());
+    // Act
+    let mut response = Response::parse(raw_response)
+        .await
+        .expect("expected response, got error");
+    // Assert
+    let Body::Stream(body) = response.body() else {
+        panic!("Expected streamed body");
+    };
+    let mut buf = vec![];
+    let err = body
+        .copy_to(&mut buf)
+        .await
+        .expect_err("Body ended early without error");
+    assert_eq!(std::io::ErrorKind::UnexpectedEof, err.kind());
+    assert_eq!(b"Hello", buf.as_slice());
+}
+
+#[test_case("HTTP/1.1 200 OK\r\n\r\nHello, World!" ; "no framing headers")]
+#[test_case("HTTP/1.1 200 OK\r\nTransfer-Encoding: gzip\r\n\r\nHello, World!" ; "non-chunked transfer coding")]
+#[test_case("HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked, gzip\r\n\r\nHello, World!" ; "chunked not last")]
+#[test_case("HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\nTransfer-Encoding: gzip\r\n\r\nHello, World!" ; "chunked not last in repeated TE")]
+#[tokio::test]
+pub async fn parse_should_read_body_until_connection_closes(raw_response: &str) {
+    // Arrange
+    let raw_response = Cursor::new(raw_response.as_bytes().to_vec());
+    // Act
+    let mut response = Response::parse(raw_response)
+        .await
+        .expect("expected response, got error");
+    // Assert
+    let Body::Stream(body) = response.body() else {
+        panic!("Expected streamed body");
+    };
+    let mut buf = vec![];
+    body.copy_to(&mut buf).await.unwrap();
+    assert_eq!(b"Hello, World!", buf.as_slice());
+}
+
+#[test_case(Method::Head, "200 OK" ; "response to HEAD")]
+#[test_case(Method::Get, "204 No Content" ; "204 No Content")]
+#[test_case(Method::Get, "304 Not Modified" ; "304 Not Modified")]
+#[test_case(Method::Connect, "200 OK" ; "established tunnel")]
+#[tokio::test]
+pub async fn parse_for_should_not_read_body_of_responses_without_content(
+    request_method: Method,
+    status: &str,
+) {
+    // Arrange
+    let raw_response = format!("HTTP/1.1 {status}\r\nContent-Length: 5\r\n\r\nHello");
+    let raw_response = Cursor::new(raw_response.into_bytes());
+    // Act
+    let mut response = Response::parse_for(raw_response, &request_method)
+        .await
+        .expect("expected response, got error");
+    // Assert
+    assert!(
+        matches!(response.body(), Body::None),
+        "Read body of response that can not have one"
+    );
+    assert_eq!(Some(5), response.headers().content_length());
+}
+
+#[tokio::test]
+pub async fn parse_should_skip_interim_responses() {
+    // Arrange
+    let raw_response = concat!(
+        "HTTP/1.1 100 Continue\r\n\r\n",
+        "HTTP/1.1 103 Early Hints\r\nLink: </style.css>; rel=preload\r\n\r\n",
+        "HTTP/1.1 201 Created\r\nContent-Length: 5\r\n\r\nHello",
+    );
+    let raw_response = Cursor::new(raw_response.as_bytes().to_vec());
+    // Act
+    let mut response = Response::parse(raw_response)
+        .await
+        .expect("expected response, got error");
+    // Assert
+    assert_eq!(StatusCode::Created, response.status_code());
+    assert_eq!(None, response.headers().extension("Link"));
+    let Body::Stream(body) = response.body() else {
+        panic!("Expected streamed body");
+    };
+    let mut buf = vec![];
+    body.copy_to(&mut buf).await.unwrap();
+    assert_eq!(b"Hello", buf.as_slice());
+}
+
+#[tokio::test]
+pub async fn parse_should_return_protocol_switch_as_final_response() {
+    // Arrange
+    let raw_response = "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\n\r\n";
+    let raw_response = Cursor::new(raw_response.as_bytes().to_vec());
+    // Act
+    let mut response = Response::parse(raw_response)
+        .await
+        .expect("expected response, got error");
+    // Assert
+    assert_eq!(StatusCode::SwitchingProtocols, response.status_code());
+    assert!(matches!(response.body(), Body::None));
+}
+
+#[tokio::test]
+pub async fn parse_should_let_transfer_encoding_override_content_length() {
+    // Arrange
+    let raw_response = concat!(
+        "HTTP/1.1 200 OK\r\n",
+        "Content-Length: 3\r\n",
+        "Transfer-Encoding: chunked\r\n",
+        "\r\n",
+        "5\r\nHello\r\n0\r\n\r\n",
+    );
+    let raw_response = Cursor::new(raw_response.as_bytes().to_vec());
+    // Act
+    let mut response = Response::parse(raw_response)
+        .await
+        .expect("expected response, got error");
+    // Assert
+    assert_eq!(None, response.headers().content_length());
+    let Body::Stream(body) = response.body() else {
+        panic!("Expected streamed body");
+    };
+    let mut buf = vec![];
+    body.copy_to(&mut buf).await.unwrap();
+    assert_eq!(b"5\r\nHello\r\n0\r\n\r\n", buf.as_slice());
+}
+
+#[tokio::test]
+pub async fn parse_should_return_error_for_conflicting_content_lengths() {
+    // Arrange
+    let raw_response = "HTTP/1.1 200 OK\r\nContent-Length: 5\r\nContent-Length: 6\r\n\r\nHello!";
+    let raw_response = Cursor::new(raw_response.as_bytes().to_vec());
+    // Act
+    let response = Response::parse(raw_response).await;
+    // Assert
+    assert!(
+        matches!(response, Err(ParseError::InvalidContentLength)),
+        "Response with ambiguous length was accepted!"
+    );
+}
+
+#[test_case("HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\n\r\n", true ; "switching protocols")]
+#[test_case("HTTP/1.1 426 Upgrade Required\r\nContent-Length: 0\r\n\r\n", false ; "refused upgrade")]
+#[tokio::test]
+pub async fn parse_upgradable_for_should_keep_connection_of_switched_protocol(
+    raw_response: &str,
+    expected_upgrade: bool,
+) {
+    // Arrange
+    let raw_response = Cursor::new(raw_response.as_bytes().to_vec());
+    // Act
+    let mut response = Response::parse_upgradable_for(raw_response, &Method::Get)
+        .await
+        .expect("expected response, got error");
+    // Assert
+    assert_eq!(expected_upgrade, response.is_upgrade());
+    assert!(
+        matches!(response.body(), Body::None),
+        "Read body of response without content"
+    );
+}
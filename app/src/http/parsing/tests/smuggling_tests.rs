@@ -0,0 +1,161 @@
+use pretty_assertions::assert_eq;
+use std::io::Read;
+use test_case::test_case;
+
+use crate::http::parsing::ParseError;
+use crate::http::{self, Parse, Request};
+
+#[test_case("Content-Length: 5\r\nTransfer-Encoding: chunked\r\n", ParseError::ConflictingFraming ; "CL.TE")]
+#[test_case("Transfer-Encoding: chunked\r\nContent-Length: 5\r\n", ParseError::ConflictingFraming ; "TE.CL")]
+#[test_case("Content-Length: 5\r\nTransfer-Encoding: identity\r\n", ParseError::ConflictingFraming ; "CL with non-chunked TE")]
+#[test_case("Content-Length: 5\r\nContent-Length: 6\r\n", ParseError::InvalidContentLength ; "duplicate conflicting CL")]
+#[test_case("Content-Length: 5, 6\r\n", ParseError::InvalidContentLength ; "conflicting CL list")]
+#[test_case("Content-Length: +5\r\n", ParseError::InvalidContentLength ; "CL with sign")]
+#[test_case("Content-Length: -1\r\n", ParseError::InvalidContentLength ; "negative CL")]
+#[test_case("Content-Length: 0x5\r\n", ParseError::InvalidContentLength ; "hex CL")]
+#[test_case("Content-Length: 5 5\r\n", ParseError::InvalidContentLength ; "CL with inner whitespace")]
+#[test_case("Content-Length:\r\n", ParseError::InvalidContentLength ; "empty CL")]
+#[test_case("Content-Length: 99999999999999999999999\r\n", ParseError::InvalidContentLength ; "overflowing CL")]
+#[test_case("Transfer-Encoding: chunked, identity\r\n", ParseError::InvalidTransferEncoding ; "chunked not last")]
+#[test_case("Transfer-Encoding: chunked\r\nTransfer-Encoding: identity\r\n", ParseError::InvalidTransferEncoding ; "chunked not last in repeated TE")]
+#[test_case("Transfer-Encoding: chunked, chunked\r\n", ParseError::InvalidTransferEncoding ; "chunked applied twice")]
+#[test_case("Transfer-Encoding: \"chunked\"\r\n", ParseError::InvalidTransferEncoding ; "quoted chunked")]
+#[test_case("Transfer-Encoding: chun ked\r\n", ParseError::InvalidTransferEncoding ; "TE with inner whitespace")]
+#[test_case("Transfer-Encoding:\r\n", ParseError::InvalidTransferEncoding ; "empty TE")]
+#[test_case("Transfer-Encoding: xchunked\r\n", ParseError::Header ; "unknown TE")]
+#[test_case("Transfer-Encoding : chunked\r\n", ParseError::Header ; "whitespace before colon")]
+#[test_case("Transfer-Encoding\x0b: chunked\r\n", ParseError::Header ; "vertical tab in name")]
+#[test_case("X-Foo: bar\r\n Transfer-Encoding: chunked\r\n", ParseError::ObsoleteLineFolding ; "obs-fold with space")]
+#[test_case("X-Foo: bar\r\n\tTransfer-Encoding: chunked\r\n", ParseError::ObsoleteLineFolding ; "obs-fold with tab")]
+#[test_case("Transfer-Encoding: chunked\nX-Foo: bar\r\n", ParseError::BareLineFeed ; "bare LF in headers")]
+#[test_case("X-Foo: bar\rTransfer-Encoding: chunked\r\n", ParseError::Header ; "lone CR in headers")]
+#[test_case("X-Foo: b\0ar\r\n", ParseError::Header ; "NUL in header value")]
+#[test_case(":chunked\r\n", ParseError::Header ; "empty header name")]
+#[tokio::test]
+pub async fn parse_should_reject_ambiguous_headers(headers: &str, expected_error: ParseError) {
+    // Arrange
+    let raw_request = format!("POST / HTTP/1.1\r\nHost: localhost\r\n{headers}\r\nHello");
+    let mut raw_request = raw_request.as_bytes();
+    // Act
+    let result = Request::parse(&mut raw_request).await;
+    // Assert
+    match result {
+        Ok(r) => panic!("Ambiguous request got accepted!: '{}'", r.headers()),
+        Err(e) => assert_eq!(expected_error, e),
+    }
+}
+
+#[test_case("Host: localhost\r\nHost: gated.example\r\n" ; "duplicate Host")]
+#[test_case("Host: localhost\r\nHost: localhost\r\n" ; "duplicate identical Host")]
+#[test_case("Host:\r\n" ; "empty Host")]
+#[test_case("Host:  \r\n" ; "whitespace only Host")]
+#[tokio::test]
+pub async fn parse_should_reject_invalid_host(headers: &str) {
+    // Arrange
+    let raw_request = format!("GET / HTTP/1.1\r\n{headers}\r\n");
+    let mut raw_request = raw_request.as_bytes();
+    // Act
+    let result = Request::parse(&mut raw_request).await;
+    // Assert
+    match result {
+        Ok(r) => panic!("Invalid Host got accepted!: '{}'", r.headers()),
+        Err(e) => assert_eq!(ParseError::InvalidHost, e),
+    }
+}
+
+#[test_case("GET / HTTP/1.1\nHost: localhost\r\n\r\n" ; "bare LF after request line")]
+#[test_case("GET / HTTP/1.1\r\nHost: localhost\n\r\n" ; "bare LF after header")]
+#[test_case("GET / HTTP/1.1\r\nHost: localhost\r\n\n" ; "bare LF ending headers")]
+#[tokio::test]
+pub async fn parse_should_reject_bare_line_feeds(raw_request: &str) {
+    // Arrange
+    let mut raw_request = raw_request.as_bytes();
+    // Act
+    let result = Request::parse(&mut raw_request).await;
+    // Assert
+    assert!(
+        matches!(result, Err(ParseError::BareLineFeed)),
+        "Bare LF was accepted!"
+    );
+}
+
+#[test_case("5\r\nHello\r\n0x0\r\n\r\n" ; "hex prefix in chunk size")]
+#[test_case("+5\r\nHello\r\n0\r\n\r\n" ; "sign in chunk size")]
+#[test_case(" 5\r\nHello\r\n0\r\n\r\n" ; "leading whitespace in chunk size")]
+#[test_case("\r\n5\r\nHello\r\n0\r\n\r\n" ; "empty chunk size")]
+#[test_case("g\r\nHello\r\n0\r\n\r\n" ; "invalid hex digit")]
+#[test_case("ffffffffffffffffff\r\n" ; "overflowing chunk size")]
+#[test_case("5\nHello\r\n0\r\n\r\n" ; "bare LF after chunk size")]
+#[test_case("5\r\nHelloXX0\r\n\r\n" ; "missing CRLF after chunk data")]
+#[test_case("3\r\nHello\r\n0\r\n\r\n" ; "chunk data longer than chunk size")]
+#[test_case("5\r\nHello\n0\r\n\r\n" ; "bare LF after chunk data")]
+#[test_case("5\r\nHello\r\n0\r\n\n" ; "bare LF ending trailers")]
+#[test_case("5\r\nHello\r\n0\r\n" ; "missing last chunk terminator")]
+#[tokio::test]
+pub async fn parse_should_reject_malformed_chunks(body: &str) {
+    // Arrange
+    let raw_request =
+        format!("POST / HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\n\r\n{body}");
+    let mut raw_request = raw_request.as_bytes();
+    // Act
+    let result = Request::parse(&mut raw_request).await;
+    // Assert
+    assert!(
+        matches!(
+            result,
+            Err(ParseError::InvalidChunk | ParseError::BareLineFeed)
+        ),
+        "Malformed chunked body was accepted!"
+    );
+}
+
+#[tokio::test]
+pub async fn parse_should_merge_repeated_identical_content_lengths() {
+    // Arrange
+    let raw_request = concat!(
+        "POST / HTTP/1.1\r\n",
+        "Host: localhost\r\n",
+        "Content-Length: 5\r\n",
+        "Content-Length: 5, 5\r\n",
+        "\r\n",
+        "HelloGET /next HTTP/1.1\r\n",
+    );
+    let mut raw_request = raw_request.as_bytes();
+    // Act
+    let mut request = Request::parse(&mut raw_request)
+        .await
+        .expect("Failed to parse request with repeated Content-Length");
+    // Assert
+    assert_eq!(Some(5), request.headers().content_length());
+    assert_eq!(
+        "Host: localhost\r\nContent-Length: 5\r\n",
+        request.headers().to_string()
+    );
+    let http::Body::Buffer(body) = request.body_mut() else {
+        panic!("No body found");
+    };
+    let mut content = String::new();
+    body.read_to_string(&mut content).unwrap();
+    assert_eq!("Hello", content);
+    assert_eq!(b"GET /next HTTP/1.1\r\n", raw_request);
+}
+
+#[tokio::test]
+pub async fn parse_should_merge_repeated_transfer_encodings() {
+    // Arrange
+    let raw_request = concat!(
+        "POST / HTTP/1.1\r\n",
+        "Host: localhost\r\n",
+        "Transfer-Encoding: gzip\r\n",
+        "Transfer-Encoding: chunked\r\n",
+        "\r\n",
+        "0\r\n\r\n",
+    );
+    let mut raw_request = raw_request.as_bytes();
+    // Act
+    let request = Request::parse(&mut raw_request)
+        .await
+        .expect("Failed to parse request with repeated Transfer-Encoding");
+    // Assert
+    assert_eq!(Some("gzip, chunked"), request.headers().transfer_encoding());
+}
@@ -41,6 +41,58 @@ impl ReadJson for http::Request {
     }
 }
 
+pub trait ReadForm {
+    /// Returns `true` if the body is sent as `application/x-www-form-urlencoded`
+    fn is_form(&self) -> bool;
+    /// Reads the decoded fields of an `application/x-www-form-urlencoded` body
+    fn read_form(&mut self) -> Result<Vec<(String, String)>, ReadFormError>;
+}
+
+impl ReadForm for http::Request {
+    fn is_form(&self) -> bool {
+        self.headers.content_type().is_some_and(|c| {
+            let mime_type = c.split(';').next().unwrap_or("").trim();
+            mime_type.eq_ignore_ascii_case("application/x-www-form-urlencoded")
+        })
+    }
+
+    fn read_form(&mut self) -> Result<Vec<(String, String)>, ReadFormError> {
+        if !self.is_form() {
+            let content_type = self.headers.content_type().unwrap_or("");
+            return Err(ReadFormError::MismatchedContentType(content_type.into()));
+        }
+        let content_length = self.headers().content_length().unwrap_or(0);
+        let mut form = vec![0; content_length];
+        if let http::Body::Buffer(buffer) = self.body_mut() {
+            buffer
+                .read_exact(&mut form)
+                .or(Err(ReadFormError::IoError))?;
+        } else if content_length > 0 {
+            return Err(ReadFormError::IoError);
+        }
+        let fields = url::form_urlencoded::parse(&form).into_owned().collect();
+        Ok(fields)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ReadFormError {
+    MismatchedContentType(String),
+    IoError,
+}
+impl Error for ReadFormError {}
+impl Display for ReadFormError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReadFormError::MismatchedContentType(content_type) => write!(
+                f,
+                "Expected 'application/x-www-form-urlencoded' content-type but got '{content_type}'"
+            ),
+            ReadFormError::IoError => write!(f, "Failure reading request stream!"),
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Eq)]
 pub enum ReadJsonError {
     MismatchedContentType(String),
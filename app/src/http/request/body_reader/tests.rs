@@ -1,6 +1,8 @@
 use pretty_assertions::assert_eq;
 
-use crate::http::request::body_reader::{ReadJson, ReadJsonError};
+use test_case::test_case;
+
+use crate::http::request::body_reader::{ReadForm, ReadFormError, ReadJson, ReadJsonError};
 use crate::http::request::{Headers, Method};
 use crate::http::{self};
 
@@ -166,3 +168,53 @@ pub fn read_json_from_body_should_return_io_error_when_missing_data() {
     // Assert
     assert_eq!(result, Err(ReadJsonError::IoError));
 }
+
+fn setup_form(form: &str, content_type: &str) -> http::Request {
+    let mut headers = http::Headers::empty();
+    headers.insert("Content-Type", content_type);
+    headers.insert("Content-Length", form.len().to_string());
+    setup_with_headers(form.into(), headers)
+}
+
+#[test_case("application/x-www-form-urlencoded" ; "plain")]
+#[test_case("application/x-www-form-urlencoded; charset=UTF-8" ; "with charset")]
+pub fn read_form_from_body_should_return_decoded_fields(content_type: &str) {
+    // Arrange
+    let mut request = setup_form("key=some+value&json=%7B%22a%22%3A1%7D", content_type);
+    // Act
+    let result = request.read_form();
+    // Assert
+    let expected_fields = vec![
+        ("key".into(), "some value".into()),
+        ("json".into(), r#"{"a":1}"#.into()),
+    ];
+    assert_eq!(result, Ok(expected_fields));
+}
+
+#[test]
+pub fn read_form_from_body_should_return_error_if_mismatched_content_type_header() {
+    // Arrange
+    let mut request = setup_form("key=value", "application/json");
+    // Act
+    let result = request.read_form();
+    // Assert
+    assert_eq!(
+        result,
+        Err(ReadFormError::MismatchedContentType(
+            "application/json".into()
+        ))
+    );
+}
+
+#[test]
+pub fn read_form_from_body_should_return_io_error_when_missing_data() {
+    // Arrange
+    let mut headers = http::Headers::empty();
+    headers.insert("Content-Type", "application/x-www-form-urlencoded");
+    headers.insert("Content-Length", "100");
+    let mut request = setup_with_headers("key=value".into(), headers);
+    // Act
+    let result = request.read_form();
+    // Assert
+    assert_eq!(result, Err(ReadFormError::IoError));
+}
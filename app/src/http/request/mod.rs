@@ -1,5 +1,3 @@
-use std::net;
-
 use super::*;
 use crate::http;
 pub mod body_reader;
@@ -11,8 +9,10 @@ pub struct Request {
     method: Method,
     request_target: String,
     absolute_target: url::Url,
+    http_version: HttpVersion,
     headers: Headers,
     body: Body,
+    is_secure: bool,
 }
 impl Request {
     pub const MAX_REQUEST_LINE_SIZE: usize = 4096;
@@ -38,8 +38,10 @@ impl Request {
             method,
             request_target,
             absolute_target,
+            http_version: HttpVersion::Http11,
             headers,
             body,
+            is_secure: false,
         };
         Ok(request)
     }
@@ -83,8 +85,34 @@ impl Request {
     }
 
     /// HTTP Protocol version
-    pub fn http_version(&self) -> &str {
-        "HTTP/1.1"
+    pub fn http_version(&self) -> HttpVersion {
+        self.http_version
+    }
+
+    pub fn set_http_version(&mut self, http_version: HttpVersion) {
+        self.http_version = http_version;
+    }
+
+    /// Returns `true` if the request was received over TLS
+    pub fn is_secure(&self) -> bool {
+        self.is_secure
+    }
+
+    /// Marks the request as received over TLS, which makes its
+    /// [absolute target](Self::absolute_target) an `https` url
+    pub fn set_secure(&mut self, is_secure: bool) {
+        self.is_secure = is_secure;
+        let scheme = if is_secure { "https" } else { "http" };
+        _ = self.absolute_target.set_scheme(scheme);
+    }
+
+    /// Returns `true` if the client wants to reuse the connection after this request.
+    /// HTTP/1.0 connections are only kept open if the client explicitly asks for it
+    pub fn keep_alive(&self) -> bool {
+        match self.http_version {
+            HttpVersion::Http10 => self.headers.contains_connection_token("keep-alive"),
+            HttpVersion::Http11 => !self.headers.connection_close(),
+        }
     }
 
     /// Location of the resource. Can be relative or absolute
@@ -105,6 +133,10 @@ impl Request {
         &self.headers
     }
 
+    pub fn headers_mut(&mut self) -> &mut Headers {
+        &mut self.headers
+    }
+
     pub fn body_mut(&mut self) -> &mut Body {
         &mut self.body
     }
@@ -151,7 +183,7 @@ impl Display for Request {
                 let data = body.data().clone().into();
                 String::from_utf8(data).unwrap_or("(Non UTF8 Body)".into())
             }
-            Body::Stream(_) => "(Chunked Body)".into(),
+            Body::Stream(_) => "(Streamed Body)".into(),
             Body::None => "".into(),
         };
         write!(f, "{request_line}\r\n{headers}\r\n{body}")
@@ -254,6 +286,11 @@ impl Headers {
         self.headers.get("host").unwrap()
     }
 
+    pub fn set_host(&mut self, host: impl Into<String>) {
+        self.headers.remove("host");
+        self.headers.insert("Host", host);
+    }
+
     pub fn user_agent(&self) -> Option<&str> {
         self.headers.get("user-agent")
     }
@@ -267,10 +304,33 @@ impl Headers {
         self.headers.get("content-type")
     }
 
+    pub fn transfer_encoding(&self) -> Option<&str> {
+        self.headers.get("transfer-encoding")
+    }
+
+    /// Returns `true` if the client waits for `100 Continue` before sending the body
+    pub fn expect_continue(&self) -> bool {
+        self.headers
+            .get("expect")
+            .is_some_and(|v| v.eq_ignore_ascii_case("100-continue"))
+    }
+
+    /// Returns `true` if the client asks to close the connection after this request
+    pub fn connection_close(&self) -> bool {
+        self.contains_connection_token("close")
+    }
+
+    /// Checks if the `Connection` header contains `token`
+    pub fn contains_connection_token(&self, token: &str) -> bool {
+        self.headers.contains_token("connection", token)
+    }
+
     pub fn cookie(&self, key: &str) -> Option<&str> {
-        let cookies = self.headers.get("Cookie")?;
-        let cookies = cookies
-            .split(";")
+        let cookies = self
+            .headers
+            .get_all("Cookie")
+            .into_iter()
+            .flat_map(|c| c.split(";"))
             .map(|s| s.trim().split_once("=").unwrap_or(("", "")))
             .collect::<Vec<(&str, &str)>>();
         for (cookie_key, cookie_value) in cookies {
@@ -281,15 +341,48 @@ impl Headers {
         None
     }
 
-    pub fn read_real_ip(&self, header_name: &str) -> Option<net::SocketAddr> {
-        let real_ip_header = self.extension(header_name)?;
-        let ip_with_stub_port = [real_ip_header, ":0"].join("");
-        net::SocketAddr::from_str(&ip_with_stub_port).ok()
+    /// Removes the cookie named `key`, keeping all other cookies
+    pub fn remove_cookie(&mut self, key: &str) {
+        let cookies: Vec<String> = self
+            .headers
+            .get_all("Cookie")
+            .iter()
+            .flat_map(|c| c.split(';'))
+            .map(|c| c.trim())
+            .filter(|c| !c.is_empty())
+            .filter(|c| c.split_once('=').map(|(k, _)| k).unwrap_or(c) != key)
+            .map(|c| c.to_string())
+            .collect();
+        self.headers.remove("Cookie");
+        if !cookies.is_empty() {
+            self.headers.insert("Cookie", cookies.join("; "));
+        }
     }
 
     pub fn extension(&self, name: &str) -> Option<&str> {
         self.headers.get(name)
     }
+
+    /// Gets the values of all headers named `name`
+    pub fn extensions(&self, name: &str) -> Vec<&str> {
+        self.headers.get_all(name)
+    }
+
+    pub fn insert(&mut self, key: impl Into<String>, value: impl Into<String>) {
+        self.headers.insert(key, value);
+    }
+
+    /// Removes all headers with the same key. The `Host` header is always kept
+    pub fn remove(&mut self, key: &str) {
+        if !key.eq_ignore_ascii_case("host") {
+            self.headers.remove(key);
+        }
+    }
+
+    /// See [http::Headers::remove_hop_by_hop]
+    pub fn remove_hop_by_hop(&mut self) {
+        self.headers.remove_hop_by_hop();
+    }
 }
 impl Display for Headers {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
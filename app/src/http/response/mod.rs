@@ -5,15 +5,19 @@ use std::fmt::Display;
 
 use crate::http::{self};
 
-use super::Body;
+use super::{Body, HttpVersion, Upgrade};
 
 pub struct Response {
+    http_version: HttpVersion,
     status_code: StatusCode,
     reason_phrase: Option<String>,
     headers: Headers,
     body: Body,
+    upgrade: Option<Upgrade>,
 }
 impl Response {
+    pub const MAX_STATUS_LINE_SIZE: usize = 4096;
+
     pub fn new(
         status_code: StatusCode,
         reason_phrase: Option<String>,
@@ -21,18 +25,23 @@ impl Response {
         body: Body,
     ) -> Self {
         Self {
+            http_version: HttpVersion::Http11,
             status_code,
             reason_phrase,
             headers,
             body,
+            upgrade: None,
         }
     }
 
-    pub fn http_version(&self) -> &str {
-        "HTTP/1.1"
+    pub fn http_version(&self) -> HttpVersion {
+        self.http_version
+    }
+
+    pub fn set_http_version(&mut self, http_version: HttpVersion) {
+        self.http_version = http_version;
     }
 
-    #[allow(dead_code)]
     pub fn status_code(&self) -> StatusCode {
         self.status_code
     }
@@ -46,10 +55,27 @@ impl Response {
         &self.headers
     }
 
+    pub fn headers_mut(&mut self) -> &mut Headers {
+        &mut self.headers
+    }
+
     pub fn body(&mut self) -> &mut Body {
         &mut self.body
     }
 
+    /// Returns `true` if the connection switches to another protocol after this response
+    pub fn is_upgrade(&self) -> bool {
+        self.upgrade.is_some()
+    }
+
+    pub fn set_upgrade(&mut self, upgrade: Upgrade) {
+        self.upgrade = Some(upgrade);
+    }
+
+    pub fn take_upgrade(&mut self) -> Option<Upgrade> {
+        self.upgrade.take()
+    }
+
     /// Turns [Response] into an HTTP representation
     pub fn as_bytes(&mut self) -> Vec<u8> {
         let http_message = self.to_string();
@@ -96,6 +122,26 @@ impl Response {
         )
     }
 
+    pub fn unauthorized() -> Self {
+        Self::new(
+            StatusCode::Unauthorized,
+            Some("Unauthorized".into()),
+            Headers::empty(),
+            Body::None,
+        )
+    }
+
+    /// Redirects to `location`, switching to `GET`
+    pub fn see_other(location: impl Into<String>, mut headers: Headers) -> Self {
+        headers.insert("Location", location);
+        Self::new(
+            StatusCode::SeeOther,
+            Some("See Other".into()),
+            headers,
+            Body::None,
+        )
+    }
+
     pub fn payment_required(headers: Headers, body: Body) -> Self {
         Self::new(
             StatusCode::PaymentRequired,
@@ -105,6 +151,15 @@ impl Response {
         )
     }
 
+    pub fn request_timeout() -> Response {
+        Self::new(
+            StatusCode::RequestTimeout,
+            Some("Request Timeout".into()),
+            Headers::empty(),
+            Body::None,
+        )
+    }
+
     pub fn uri_too_long() -> Response {
         Self::new(
             StatusCode::URITooLong,
@@ -122,6 +177,24 @@ impl Response {
             Body::None,
         )
     }
+
+    pub fn http_version_not_supported() -> Response {
+        Self::new(
+            StatusCode::HttpVersionNotSupported,
+            Some("HTTP Version Not Supported".into()),
+            Headers::empty(),
+            Body::None,
+        )
+    }
+
+    pub fn service_unavailable() -> Response {
+        Self::new(
+            StatusCode::ServiceUnavailable,
+            Some("Service Unavailable".into()),
+            Headers::empty(),
+            Body::None,
+        )
+    }
 }
 impl Display for Response {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -144,6 +217,7 @@ impl Display for Response {
 pub enum StatusCode {
     Continue = 100,
     SwitchingProtocols = 101,
+    EarlyHints = 103,
     OK = 200,
     Created = 201,
     Accepted = 202,
@@ -192,6 +266,7 @@ impl StatusCode {
         let status_code = match value {
             "100" => StatusCode::Continue,
             "101" => StatusCode::SwitchingProtocols,
+            "103" => StatusCode::EarlyHints,
             "200" => StatusCode::OK,
             "201" => StatusCode::Created,
             "202" => StatusCode::Accepted,
@@ -242,6 +317,7 @@ impl StatusCode {
         match self {
             StatusCode::Continue => "Continue",
             StatusCode::SwitchingProtocols => "Switching Protocols",
+            StatusCode::EarlyHints => "Early Hints",
             StatusCode::OK => "OK",
             StatusCode::Created => "Created",
             StatusCode::Accepted => "Accepted",
@@ -282,7 +358,7 @@ impl StatusCode {
             StatusCode::NotImplemented => "Not Implemented",
             StatusCode::BadGateway => "Bad Gateway",
             StatusCode::ServiceUnavailable => "Service Unavailable",
-            StatusCode::GatewayTimeout => "GatewayTimeout",
+            StatusCode::GatewayTimeout => "Gateway Timeout",
             StatusCode::HttpVersionNotSupported => "HTTP Version Not Supported",
         }
     }
@@ -342,10 +418,32 @@ impl Headers {
         self.0.get("Transfer-Encoding")
     }
 
-    #[allow(dead_code)]
+    /// Returns `true` if the connection gets closed after this response
+    pub fn connection_close(&self) -> bool {
+        self.0.contains_token("Connection", "close")
+    }
+
+    pub fn insert(&mut self, key: impl Into<String>, value: impl Into<String>) {
+        self.0.insert(key, value);
+    }
+
+    pub fn remove(&mut self, key: &str) {
+        self.0.remove(key);
+    }
+
+    /// See [http::Headers::remove_hop_by_hop]
+    pub fn remove_hop_by_hop(&mut self) {
+        self.0.remove_hop_by_hop();
+    }
+
     pub fn extension(&self, key: &str) -> Option<&str> {
         self.0.get(key)
     }
+
+    /// Gets the values of all headers named `key`
+    pub fn extensions(&self, key: &str) -> Vec<&str> {
+        self.0.get_all(key)
+    }
 }
 impl Display for Headers {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
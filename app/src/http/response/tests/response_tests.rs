@@ -1,4 +1,4 @@
-use std::collections::VecDeque;
+use std::io::Cursor;
 
 use crate::http::response::{self, Response, StatusCode};
 use crate::http::{self};
@@ -30,7 +30,7 @@ pub fn into_bytes_should_skip_parsing_body_when_is_chunked() {
     headers.insert("Transfer-Encoding", "chunked");
     let headers = response::Headers::new(headers);
     let chunked_body = String::from("5\r\nHello\r\n4\r\n, Wo\r\n3\r\nrld\r\n0\r\n\r\n");
-    let chunked_body: VecDeque<u8> = chunked_body.into_bytes().into();
+    let chunked_body = Cursor::new(chunked_body.into_bytes());
     let body = http::Body::from_stream(Box::new(chunked_body), None);
     let mut sut = Response::new(StatusCode::OK, Some("No-Error".into()), headers, body);
     // Act
@@ -0,0 +1,77 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use tokio::{
+    io::{self, AsyncBufRead, AsyncRead, BufReader, ReadBuf},
+    sync::{Mutex, OwnedMutexGuard},
+    time::{self, Sleep},
+};
+
+/// Read half of a client connection, shared with the body of the request being streamed from it
+pub type ConnectionReader<R> = std::sync::Arc<Mutex<BufReader<R>>>;
+
+/// Holds the [ConnectionReader] while a request body is read from it. Reads fail with
+/// [io::ErrorKind::TimedOut] once the client sent no body data for longer than the timeout
+pub struct BodyReader<R> {
+    reader: OwnedMutexGuard<BufReader<R>>,
+    timeout: Duration,
+    deadline: Pin<Box<Sleep>>,
+}
+impl<R> BodyReader<R> {
+    pub fn new(reader: OwnedMutexGuard<BufReader<R>>, timeout: Duration) -> Self {
+        Self {
+            reader,
+            timeout,
+            deadline: Box::pin(time::sleep(timeout)),
+        }
+    }
+
+    /// Restarts the timeout, as the client is still sending the body
+    fn extend_deadline(&mut self) {
+        let deadline = time::Instant::now() + self.timeout;
+        self.deadline.as_mut().reset(deadline);
+    }
+
+    fn check_deadline(&mut self, cx: &mut Context<'_>) -> io::Result<()> {
+        match self.deadline.as_mut().poll(cx) {
+            Poll::Ready(_) => Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "request body was not sent in time",
+            )),
+            Poll::Pending => Ok(()),
+        }
+    }
+}
+impl<R: AsyncRead + Unpin> AsyncRead for BodyReader<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        self.check_deadline(cx)?;
+        let filled = buf.filled().len();
+        let poll = Pin::new(&mut *self.reader).poll_read(cx, buf);
+        if buf.filled().len() > filled {
+            self.extend_deadline();
+        }
+        poll
+    }
+}
+impl<R: AsyncRead + Unpin> AsyncBufRead for BodyReader<R> {
+    fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+        let this = self.get_mut();
+        this.check_deadline(cx)?;
+        Pin::new(&mut *this.reader).poll_fill_buf(cx)
+    }
+
+    fn consume(mut self: Pin<&mut Self>, amt: usize) {
+        if amt > 0 {
+            self.extend_deadline();
+        }
+        Pin::new(&mut *self.reader).consume(amt)
+    }
+}
@@ -1,24 +1,31 @@
-use std::sync::mpsc::{self, channel};
+use tokio::sync::watch;
 
 pub fn create_cancellation_token() -> (CancelSender, CancelReceiver) {
-    let (sender, receiver) = channel();
+    let (sender, receiver) = watch::channel(false);
     (CancelSender(sender), CancelReceiver(receiver))
 }
 
-#[allow(dead_code)]
-/// Signals to the thread that it needs to shut down
-pub struct CancelSender(mpsc::Sender<bool>);
+/// Signals to the server that it needs to shut down
+pub struct CancelSender(watch::Sender<bool>);
 impl CancelSender {
-    #[allow(dead_code)]
-    pub fn send_shutdown(&self) -> Result<(), mpsc::SendError<bool>> {
+    pub fn send_shutdown(&self) -> Result<(), watch::error::SendError<bool>> {
         self.0.send(true)
     }
 }
 
-/// Receives signals to shut down the thread
-pub struct CancelReceiver(mpsc::Receiver<bool>);
+/// Receives signals to shut down the server. Can be cloned to shut down multiple servers at once
+#[derive(Clone)]
+pub struct CancelReceiver(watch::Receiver<bool>);
 impl CancelReceiver {
     pub fn is_shutting_down(&self) -> bool {
-        self.0.try_recv().is_ok()
+        *self.0.borrow()
+    }
+
+    /// Waits until shutdown is requested. Never returns if the [CancelSender] was dropped without
+    /// sending a shutdown
+    pub async fn cancelled(&mut self) {
+        if self.0.wait_for(|shutdown| *shutdown).await.is_err() {
+            std::future::pending::<()>().await;
+        }
     }
 }
@@ -0,0 +1,139 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt::Display,
+    net,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+/// Limits how many connections a [Server](super::Server) handles at once
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct ConnectionLimits {
+    /// Number of connections served concurrently
+    pub max_connections: usize,
+    /// Number of connections a single client IP may hold (including queued ones).
+    /// Unlimited if [None]
+    pub max_connections_per_ip: Option<usize>,
+    /// What happens to new connections while all workers are busy
+    pub backlog_policy: BacklogPolicy,
+}
+impl ConnectionLimits {
+    pub const MAX_CONNECTIONS_DEFAULT: usize = 128;
+
+    /// Number of connections either being served or waiting for a worker
+    pub fn capacity(&self) -> usize {
+        self.max_connections + self.backlog_policy.queue_size()
+    }
+}
+impl Default for ConnectionLimits {
+    fn default() -> Self {
+        Self {
+            max_connections: Self::MAX_CONNECTIONS_DEFAULT,
+            max_connections_per_ip: None,
+            backlog_policy: BacklogPolicy::Queue(Self::MAX_CONNECTIONS_DEFAULT),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum BacklogPolicy {
+    /// Answer with `503 Service Unavailable` right away
+    Reject,
+    /// Keep up to n connections waiting for a free worker, reject the rest
+    Queue(usize),
+}
+impl BacklogPolicy {
+    pub fn queue_size(&self) -> usize {
+        match self {
+            BacklogPolicy::Reject => 0,
+            BacklogPolicy::Queue(size) => *size,
+        }
+    }
+}
+
+/// Reason a connection was not admitted by the [ConnectionCounter]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Rejection {
+    /// All workers are busy and the backlog is full
+    ServerBusy,
+    /// Client already holds the maximum number of connections
+    ClientLimitReached,
+}
+impl Error for Rejection {}
+impl Display for Rejection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Rejection::ServerBusy => write!(f, "all workers are busy"),
+            Rejection::ClientLimitReached => write!(f, "too many connections from client"),
+        }
+    }
+}
+
+/// Counts open connections in total and per client IP
+pub struct ConnectionCounter {
+    limits: ConnectionLimits,
+    connections: Mutex<OpenConnections>,
+}
+#[derive(Default)]
+struct OpenConnections {
+    total: usize,
+    per_ip: HashMap<net::IpAddr, usize>,
+}
+impl ConnectionCounter {
+    pub fn new(limits: ConnectionLimits) -> Self {
+        Self {
+            limits,
+            connections: Mutex::new(OpenConnections::default()),
+        }
+    }
+
+    /// Counts a new connection from `ip` if it stays within the [ConnectionLimits]
+    pub fn acquire(self: &Arc<Self>, ip: net::IpAddr) -> Result<ConnectionPermit, Rejection> {
+        let mut connections = self.lock();
+        if connections.total >= self.limits.capacity() {
+            return Err(Rejection::ServerBusy);
+        }
+        let count = connections.per_ip.entry(ip).or_default();
+        if self
+            .limits
+            .max_connections_per_ip
+            .is_some_and(|l| *count >= l)
+        {
+            return Err(Rejection::ClientLimitReached);
+        }
+        *count += 1;
+        connections.total += 1;
+        Ok(ConnectionPermit {
+            ip,
+            counter: self.clone(),
+        })
+    }
+
+    fn release(&self, ip: net::IpAddr) {
+        let mut connections = self.lock();
+        connections.total -= 1;
+        if let Some(count) = connections.per_ip.get_mut(&ip) {
+            *count -= 1;
+            if *count == 0 {
+                connections.per_ip.remove(&ip);
+            }
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, OpenConnections> {
+        self.connections
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Connection counted by [ConnectionCounter]. Released when dropped
+pub struct ConnectionPermit {
+    ip: net::IpAddr,
+    counter: Arc<ConnectionCounter>,
+}
+impl Drop for ConnectionPermit {
+    fn drop(&mut self) {
+        self.counter.release(self.ip);
+    }
+}
@@ -1,98 +1,374 @@
+mod body_reader;
 pub mod cancellation_token;
+pub mod limits;
+pub mod proxy_protocol;
+pub mod real_ip;
 #[cfg(test)]
 mod tests;
+pub mod tls;
 
+use body_reader::{BodyReader, ConnectionReader};
 use cancellation_token::CancelReceiver;
+use limits::{ConnectionCounter, ConnectionLimits, ConnectionPermit};
+use proxy_protocol::{ProxiedStream, ProxyProtocol};
+use real_ip::RealIpResolver;
+use tls::TlsAcceptor;
 
-use crate::http::Body;
+use crate::http::{Body, HttpVersion, Upgrade};
 
 use super::{
-    parsing,
+    parsing::{self, request::RequestHead},
     request::{Method, Request},
     response::Response,
     Parse,
 };
 use std::net;
-use std::{
-    error::Error,
-    fmt::Display,
-    io::{self, Write},
-    panic, thread,
+use std::time::Duration;
+use std::{error::Error, fmt::Display, sync::Arc};
+use tokio::{
+    io::{self, AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, ReadHalf},
+    net::TcpStream,
+    sync::{Mutex, OwnedMutexGuard, Semaphore},
+    task::JoinSet,
+    time,
 };
 
 pub struct Server {
     listener: net::TcpListener,
-    handler: Box<dyn TcpServe + Send + Sync>,
+    handler: Arc<dyn TcpServe + Send + Sync>,
+    settings: ConnectionSettings,
+    limits: ConnectionLimits,
+    shutdown_timeout: Duration,
+    tls: Option<TlsAcceptor>,
+    proxy_protocol: Option<ProxyProtocol>,
 }
 impl Server {
+    /// Time open connections get to finish after shutdown was requested
+    pub const SHUTDOWN_TIMEOUT_DEFAULT: Duration = Duration::from_secs(30);
+
     /// Create low level TCP [Server]
+    #[allow(dead_code)]
     pub fn new(listener: net::TcpListener, handler: Box<dyn TcpServe + Send + Sync>) -> Self {
-        Self { listener, handler }
+        Self::with_settings(listener, handler, ConnectionSettings::default())
     }
 
-    /// Blocks execution and starts listening for connections.
-    /// Connections get handled in independent threads
+    /// Create low level TCP [Server] applying the [ConnectionSettings] to every connection
+    pub fn with_settings(
+        listener: net::TcpListener,
+        handler: Box<dyn TcpServe + Send + Sync>,
+        settings: ConnectionSettings,
+    ) -> Self {
+        Self {
+            listener,
+            handler: handler.into(),
+            settings,
+            limits: ConnectionLimits::default(),
+            shutdown_timeout: Self::SHUTDOWN_TIMEOUT_DEFAULT,
+            tls: None,
+            proxy_protocol: None,
+        }
+    }
+
+    pub fn set_limits(&mut self, limits: ConnectionLimits) {
+        self.limits = limits;
+    }
+
+    /// Sets how long open connections may take to finish once shutdown was requested.
+    /// Connections still open afterwards are aborted
+    pub fn set_shutdown_timeout(&mut self, shutdown_timeout: Duration) {
+        self.shutdown_timeout = shutdown_timeout;
+    }
+
+    /// Serves connections over TLS. The handshake has to finish within
+    /// [ConnectionSettings::header_read_timeout]
+    pub fn set_tls(&mut self, tls: TlsAcceptor) {
+        self.tls = Some(tls);
+    }
+
+    /// Reads the client address from the PROXY protocol header of connections from trusted
+    /// peers. The header has to arrive within [ConnectionSettings::header_read_timeout] and the
+    /// [ConnectionLimits] count the client instead of the peer
+    pub fn set_proxy_protocol(&mut self, proxy_protocol: ProxyProtocol) {
+        self.proxy_protocol = Some(proxy_protocol);
+    }
+
+    /// Blocks execution and [listens](Server::listen) for connections on a new async runtime
+    #[allow(dead_code)]
     pub fn start_listening(&mut self, cancel_receiver: CancelReceiver) {
-        thread::scope(|s| {
-            while !cancel_receiver.is_shutting_down() {
-                let stream = match self.listener.accept() {
-                    Ok(result) => result.0,
-                    Err(_) => continue,
-                };
-                let handler = &self.handler;
-                s.spawn(move || {
-                    let res = panic::catch_unwind(panic::AssertUnwindSafe(|| {
-                        handler.serve_tcp(stream);
-                    })); // Keep server alive when a request crashes handler
-                    match res {
-                        Ok(_) => tracing::debug!("Request handled exceptionless!"),
-                        Err(e) => tracing::error!(panic = ?e, "Request failed"),
-                    }
-                });
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .expect("Failed to start async runtime");
+        runtime.block_on(self.listen(cancel_receiver));
+    }
+
+    /// Listens for connections until cancelled. Every connection is served by its own task, with
+    /// at most [ConnectionLimits::max_connections] tasks serving at once. Connections exceeding
+    /// the [ConnectionLimits] get rejected with `503 Service Unavailable`.
+    /// On cancellation, open connections are drained for up to the shutdown timeout
+    pub async fn listen(&self, mut cancel_receiver: CancelReceiver) {
+        let listener = match self.create_async_listener() {
+            Ok(l) => l,
+            Err(e) => {
+                tracing::error!("Failed to listen for connections: {e}");
+                return;
             }
+        };
+        let certificate_reload = self.tls.clone().map(|tls| {
+            let cancel_receiver = cancel_receiver.clone();
+            tokio::spawn(async move {
+                tls.reload_on_change(tls::RELOAD_INTERVAL, cancel_receiver)
+                    .await
+            })
         });
+        let admission = Admission {
+            handler: self.handler.clone(),
+            settings: self.settings,
+            tls: self.tls.clone(),
+            counter: Arc::new(ConnectionCounter::new(self.limits)),
+            workers: Arc::new(Semaphore::new(self.limits.max_connections)),
+            cancel_receiver: cancel_receiver.clone(),
+        };
+        let mut connections = JoinSet::new();
+        loop {
+            while connections.try_join_next().is_some() {} // Forget finished connections
+            let accepted = tokio::select! {
+                accepted = listener.accept() => accepted,
+                _ = cancel_receiver.cancelled() => break,
+            };
+            let (stream, peer_addr) = match accepted {
+                Ok(result) => result,
+                Err(e) => {
+                    tracing::debug!("Failed to accept connection: {e}");
+                    continue;
+                }
+            };
+            let is_proxied = self
+                .proxy_protocol
+                .as_ref()
+                .is_some_and(|p| p.is_trusted(peer_addr.ip()));
+            if is_proxied {
+                connections.spawn(admission.clone().admit_proxied(stream));
+            } else {
+                connections.spawn(admission.admit(stream, peer_addr));
+            }
+        }
+        drop(listener); // Stop accepting while draining
+        self.drain_connections(&mut connections).await;
+        if let Some(certificate_reload) = certificate_reload {
+            certificate_reload.abort();
+        }
+    }
+
+    /// Waits for open connections to finish, aborting the ones exceeding the shutdown timeout
+    async fn drain_connections(&self, connections: &mut JoinSet<()>) {
+        if connections.is_empty() {
+            return;
+        }
+        tracing::info!("Waiting for {} open connections", connections.len());
+        let drain = async { while connections.join_next().await.is_some() {} };
+        if time::timeout(self.shutdown_timeout, drain).await.is_err() {
+            tracing::warn!(
+                "Aborting {} connections still open after shutdown timeout",
+                connections.len()
+            );
+            connections.shutdown().await;
+        }
+    }
+
+    fn create_async_listener(&self) -> io::Result<tokio::net::TcpListener> {
+        let listener = self.listener.try_clone()?;
+        listener.set_nonblocking(true)?;
+        tokio::net::TcpListener::from_std(listener)
+    }
+}
+
+/// Admits accepted connections within the [ConnectionLimits] and serves them
+#[derive(Clone)]
+struct Admission {
+    handler: Arc<dyn TcpServe + Send + Sync>,
+    settings: ConnectionSettings,
+    tls: Option<TlsAcceptor>,
+    counter: Arc<ConnectionCounter>,
+    workers: Arc<Semaphore>,
+    cancel_receiver: CancelReceiver,
+}
+impl Admission {
+    /// Counts the connection right away and serves it once a worker is free. Connections exceeding
+    /// the [ConnectionLimits] get rejected
+    fn admit(
+        &self,
+        stream: impl Connection + 'static,
+        client_addr: net::SocketAddr,
+    ) -> impl std::future::Future<Output = ()> + Send + 'static {
+        let admission = self.clone();
+        let permit = self.counter.acquire(client_addr.ip());
+        async move {
+            match permit {
+                Ok(permit) => admission.serve(stream, permit).await,
+                Err(rejection) => {
+                    tracing::warn!("Rejecting {client_addr}: {rejection}");
+                    if let Some(stream) = admission.handshake(stream).await {
+                        reject_connection(stream).await;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Reads the PROXY protocol header before admitting the connection of the client
+    async fn admit_proxied(self, stream: TcpStream) {
+        let timeout = self.settings.header_read_timeout;
+        match time::timeout(timeout, ProxiedStream::accept(stream)).await {
+            Ok(Ok(stream)) => {
+                let client_addr = stream.source();
+                self.admit(stream, client_addr).await;
+            }
+            Ok(Err(e)) => tracing::debug!("Closing connection: {e}"),
+            Err(_) => tracing::debug!("PROXY protocol header timed out"),
+        }
+    }
+
+    /// Waits for a free worker and serves the connection
+    async fn serve(self, stream: impl Connection + 'static, _permit: ConnectionPermit) {
+        let Ok(_worker) = self.workers.clone().acquire_owned().await else {
+            return;
+        };
+        let Some(stream) = self.handshake(stream).await else {
+            return;
+        };
+        // Separate task keeps the server alive when a request crashes the handler. Kept in a
+        // JoinSet, so it gets aborted together with the connection
+        let Self {
+            handler,
+            settings,
+            cancel_receiver,
+            ..
+        } = self;
+        let mut handler_task = JoinSet::new();
+        handler_task
+            .spawn(async move { handler.serve_tcp(stream, &settings, cancel_receiver).await });
+        match handler_task.join_next().await {
+            Some(Err(e)) if e.is_panic() => tracing::error!(panic = ?e, "Request failed"),
+            _ => tracing::debug!("Request handled exceptionless!"),
+        }
+    }
+
+    /// Performs the TLS handshake if the server uses TLS. Returns [None] if the handshake failed
+    async fn handshake(&self, stream: impl Connection + 'static) -> Option<Box<dyn Connection>> {
+        let Some(tls) = &self.tls else {
+            return Some(Box::new(stream));
+        };
+        let timeout = self.settings.header_read_timeout;
+        match time::timeout(timeout, tls.accept(stream)).await {
+            Ok(Ok(stream)) => Some(Box::new(stream)),
+            Ok(Err(e)) => {
+                tracing::debug!("TLS handshake failed: {e}");
+                None
+            }
+            Err(_) => {
+                tracing::debug!("TLS handshake timed out");
+                None
+            }
+        }
+    }
+}
+
+async fn reject_connection(mut stream: Box<dyn Connection>) {
+    send_response(&mut stream, Response::service_unavailable()).await;
+    if let Err(e) = stream.shutdown().await {
+        tracing::debug!("Failed to gracefully close connection: {e}");
+    }
+}
+
+/// Limits for persistent connections
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct ConnectionSettings {
+    /// Time to wait for the next request before closing the connection
+    pub idle_timeout: Duration,
+    /// Number of requests served over a single connection before it gets closed
+    pub max_requests: usize,
+    /// Time the client has to send request line and headers once a request started
+    pub header_read_timeout: Duration,
+    /// Time the client may stay silent while sending the request body
+    pub body_read_timeout: Duration,
+}
+impl ConnectionSettings {
+    pub const IDLE_TIMEOUT_DEFAULT: Duration = Duration::from_secs(5);
+    pub const MAX_REQUESTS_DEFAULT: usize = 100;
+    pub const HEADER_READ_TIMEOUT_DEFAULT: Duration = Duration::from_secs(10);
+    pub const BODY_READ_TIMEOUT_DEFAULT: Duration = Duration::from_secs(30);
+}
+impl Default for ConnectionSettings {
+    fn default() -> Self {
+        Self {
+            idle_timeout: Self::IDLE_TIMEOUT_DEFAULT,
+            max_requests: Self::MAX_REQUESTS_DEFAULT,
+            header_read_timeout: Self::HEADER_READ_TIMEOUT_DEFAULT,
+            body_read_timeout: Self::BODY_READ_TIMEOUT_DEFAULT,
+        }
     }
 }
 
 /// Serve implementation that handles HTTP [requests](Request) and returns HTTP
 /// [responses](Response)
 pub struct HttpEndpointsServe {
-    real_ip_header: Option<String>,
+    real_ip: Option<RealIpResolver>,
+    trusted_proxies: Vec<ipnet::IpNet>,
     endpoints: Vec<Endpoint>,
 }
 impl HttpEndpointsServe {
-    pub fn new(endpoints: Vec<Endpoint>, real_ip_header: Option<String>) -> Self {
+    pub fn new(endpoints: Vec<Endpoint>, real_ip: Option<RealIpResolver>) -> Self {
         Self {
             endpoints,
-            real_ip_header,
+            real_ip,
+            trusted_proxies: vec![],
         }
     }
+
+    /// Only peers within `trusted_proxies` may tell the scheme the client used with
+    /// `X-Forwarded-Proto`, it is removed from requests of other peers
+    pub fn set_trusted_proxies(&mut self, trusted_proxies: Vec<ipnet::IpNet>) {
+        self.trusted_proxies = trusted_proxies;
+    }
 }
+#[async_trait::async_trait]
 impl HttpServe for HttpEndpointsServe {
-    fn serve_http(
+    async fn serve_http(
         &self,
         client_addr: &net::SocketAddr,
-        request: Request,
+        mut request: Request,
     ) -> Result<Response, InternalServerError> {
-        let mut endpoints = self
+        let peer_ip = client_addr.ip().to_canonical();
+        let is_proxied = self.trusted_proxies.iter().any(|n| n.contains(&peer_ip));
+        if !is_proxied {
+            // Any client can claim https, only trusted proxies know the scheme of the client
+            request.headers_mut().remove("X-Forwarded-Proto");
+        }
+        let endpoints: Vec<&Endpoint> = self
             .endpoints
             .iter()
             .filter(|e| request.matches_path(&e.path))
-            .peekable();
-        let client_addr = match &self.real_ip_header {
-            Some(h) => &request
-                .headers()
-                .read_real_ip(h)
-                .expect("No IP Header found"),
-            None => client_addr,
+            .collect();
+        let client_addr = match &self.real_ip {
+            Some(real_ip) => match real_ip.resolve(client_addr, request.headers()) {
+                Some(addr) => addr,
+                None => {
+                    tracing::debug!("Rejecting request of {client_addr}: No trustworthy client IP");
+                    return Ok(Response::bad_request());
+                }
+            },
+            None => *client_addr,
         };
-        if endpoints.peek().is_some() {
-            match endpoints.find(|e| request.matches_method(&e.method)) {
-                Some(e) => e.serve(client_addr, request),
-                None => Ok(Response::method_not_allowed()),
-            }
-        } else {
-            Ok(Response::not_found())
+        if endpoints.is_empty() {
+            return Ok(Response::not_found());
+        }
+        let endpoint = endpoints
+            .into_iter()
+            .find(|e| request.matches_method(&e.method));
+        match endpoint {
+            Some(e) => e.serve(&client_addr, request).await,
+            None => Ok(Response::method_not_allowed()),
         }
     }
 }
@@ -115,67 +391,322 @@ impl Endpoint {
         }
     }
 
-    pub fn serve(
+    pub async fn serve(
         &self,
         client_addr: &net::SocketAddr,
         request: Request,
     ) -> Result<Response, InternalServerError> {
-        self.handler.serve_http(client_addr, request)
+        self.handler.serve_http(client_addr, request).await
+    }
+}
+
+/// Client connection requests are read from, e.g. a plain [TcpStream] or one secured with TLS
+pub trait Connection: AsyncRead + AsyncWrite + Unpin + Send {
+    fn peer_addr(&self) -> io::Result<net::SocketAddr>;
+    /// Whether the connection is encrypted
+    fn is_secure(&self) -> bool;
+}
+impl Connection for TcpStream {
+    fn peer_addr(&self) -> io::Result<net::SocketAddr> {
+        TcpStream::peer_addr(self)
+    }
+
+    fn is_secure(&self) -> bool {
+        false
+    }
+}
+impl<S: Connection> Connection for tokio_rustls::server::TlsStream<S> {
+    fn peer_addr(&self) -> io::Result<net::SocketAddr> {
+        self.get_ref().0.peer_addr()
+    }
+
+    fn is_secure(&self) -> bool {
+        true
+    }
+}
+impl<C: Connection + ?Sized> Connection for Box<C> {
+    fn peer_addr(&self) -> io::Result<net::SocketAddr> {
+        (**self).peer_addr()
+    }
+
+    fn is_secure(&self) -> bool {
+        (**self).is_secure()
     }
 }
 
-pub trait TcpServe {
-    fn serve_tcp(&self, stream: net::TcpStream);
+#[async_trait::async_trait]
+pub trait TcpServe<S = Box<dyn Connection>> {
+    /// Serves the connection. Should stop serving new requests once `cancel_receiver` signals
+    /// shutdown
+    async fn serve_tcp(
+        &self,
+        stream: S,
+        settings: &ConnectionSettings,
+        cancel_receiver: CancelReceiver,
+    );
 }
+#[async_trait::async_trait]
 pub trait HttpServe {
-    fn serve_http(
+    async fn serve_http(
         &self,
         client_addr: &net::SocketAddr,
         request: Request,
     ) -> Result<Response, InternalServerError>;
 }
-impl<T: HttpServe> TcpServe for T {
-    fn serve_tcp(&self, stream: net::TcpStream) {
-        match handle_incoming_request(self, stream.try_clone().unwrap()) {
-            Ok(_) => (),
-            Err(e) => match e {
-                parsing::ParseError::RequestLine => {
-                    send_response(&stream, Response::uri_too_long())
-                }
-                parsing::ParseError::Body => send_response(&stream, Response::content_too_large()),
-                _ => send_response(&stream, Response::bad_request()),
-            },
+#[async_trait::async_trait]
+impl<T: HttpServe + Sync, S: Connection + 'static> TcpServe<S> for T {
+    async fn serve_tcp(
+        &self,
+        stream: S,
+        settings: &ConnectionSettings,
+        mut cancel_receiver: CancelReceiver,
+    ) {
+        let addr = match stream.peer_addr() {
+            Ok(addr) => addr,
+            Err(e) => {
+                // The client may already be gone when the connection is served
+                tracing::debug!("Dropping connection without peer address: {e}");
+                return;
+            }
+        };
+        let client = Client {
+            addr,
+            is_secure: stream.is_secure(),
         };
-        stream
-            .shutdown(net::Shutdown::Both)
-            .expect("Failed to gracefully close connection");
-    }
-}
-fn handle_incoming_request(
-    http_serve: &impl HttpServe,
-    stream: net::TcpStream,
-) -> Result<(), parsing::ParseError> {
-    let mut write_stream = stream.try_clone().unwrap();
-    let reader = io::BufReader::new(stream);
-    let request = Request::parse(reader)?;
+        let (read_stream, mut write_stream) = io::split(stream);
+        let reader: ConnectionReader<ReadHalf<S>> =
+            Arc::new(Mutex::new(io::BufReader::new(read_stream)));
+        let mut requests_served = 0;
+        loop {
+            let mut connection = reader.clone().lock_owned().await;
+            if !wait_for_request(&mut connection, settings.idle_timeout, &mut cancel_receiver).await
+            {
+                break;
+            }
+            requests_served += 1;
+            let is_last_request = requests_served >= settings.max_requests;
+            let continuation = match handle_incoming_request(
+                self,
+                &client,
+                connection,
+                &mut write_stream,
+                settings,
+                is_last_request,
+                &cancel_receiver,
+            )
+            .await
+            {
+                Ok(continuation) => continuation,
+                Err(e) => {
+                    let response = match e {
+                        parsing::ParseError::RequestLineTooLong => Response::uri_too_long(),
+                        parsing::ParseError::Body => Response::content_too_large(),
+                        parsing::ParseError::Timeout => Response::request_timeout(),
+                        parsing::ParseError::UnsupportedVersion => {
+                            Response::http_version_not_supported()
+                        }
+                        _ => Response::bad_request(),
+                    };
+                    send_response(&mut write_stream, response).await;
+                    Continuation::Close
+                }
+            };
+            match continuation {
+                Continuation::KeepAlive => {}
+                Continuation::Close => break,
+                Continuation::Upgrade(upgrade) => {
+                    // Waits for a streamed request body to be dropped
+                    let mut connection = reader.lock_owned().await;
+                    if let Err(e) = upgrade.splice(&mut *connection, &mut write_stream).await {
+                        tracing::debug!("Upgraded connection failed: {e}");
+                    }
+                    break;
+                }
+            }
+        }
+        if let Err(e) = write_stream.shutdown().await {
+            tracing::debug!("Failed to gracefully close connection: {e}");
+        }
+    }
+}
+
+/// Waits until the next request arrives. Returns `false` if the client closed the connection,
+/// stayed idle for longer than `idle_timeout` or the server is shutting down
+async fn wait_for_request(
+    reader: &mut io::BufReader<impl AsyncRead + Unpin>,
+    idle_timeout: Duration,
+    cancel_receiver: &mut CancelReceiver,
+) -> bool {
+    if !reader.buffer().is_empty() {
+        return true; // Pipelined request is already waiting
+    }
+    let next_request = tokio::select! {
+        next_request = time::timeout(idle_timeout, reader.fill_buf()) => next_request,
+        _ = cancel_receiver.cancelled() => {
+            tracing::debug!("Closing idle connection due to shutdown");
+            return false;
+        }
+    };
+    match next_request {
+        Ok(Ok(buf)) => !buf.is_empty(),
+        Ok(Err(e)) => {
+            tracing::debug!("Closing connection: {e}");
+            false
+        }
+        Err(_) => {
+            tracing::debug!("Closing idle connection");
+            false
+        }
+    }
+}
+
+/// Peer a connection was accepted from
+struct Client {
+    addr: net::SocketAddr,
+    is_secure: bool,
+}
+
+/// Interim response asking the client to send the body of a request with `Expect: 100-continue`
+const CONTINUE_RESPONSE: &[u8] = b"HTTP/1.1 100 Continue\r\n\r\n";
+
+/// What happens with the connection after serving a request
+enum Continuation {
+    /// Connection is reused for the next request
+    KeepAlive,
+    Close,
+    /// Connection switched protocols and gets spliced with the [Upgrade]
+    Upgrade(Upgrade),
+}
+
+/// Serves a single request
+async fn handle_incoming_request(
+    http_serve: &(impl HttpServe + Sync),
+    client: &Client,
+    mut reader: OwnedMutexGuard<io::BufReader<impl AsyncRead + Unpin + Send + 'static>>,
+    write_stream: &mut (impl AsyncWrite + Unpin + Send),
+    settings: &ConnectionSettings,
+    is_last_request: bool,
+    cancel_receiver: &CancelReceiver,
+) -> Result<Continuation, parsing::ParseError> {
+    let request_head = time::timeout(
+        settings.header_read_timeout,
+        RequestHead::parse(&mut *reader),
+    )
+    .await
+    .unwrap_or(Err(parsing::ParseError::Timeout))?;
+    if request_head.expects_continue() {
+        if let Err(e) = write_stream.write_all(CONTINUE_RESPONSE).await {
+            tracing::debug!("Failed to send interim response: {e}");
+            return Ok(Continuation::Close);
+        }
+    }
+    let mut body_reader = BodyReader::new(reader, settings.body_read_timeout);
+    let mut request = if request_head.has_chunked_body()? {
+        // Streamed while the request is handled, holding on to the connection until dropped
+        request_head.stream_chunked_body(body_reader)?
+    } else {
+        request_head.read_body(&mut body_reader).await?
+    };
+    request.set_secure(client.is_secure);
     tracing::debug!("Incoming Request:\r\n{request}");
-    let mut response = match http_serve.serve_http(&write_stream.peer_addr().unwrap(), request) {
+    let http_version = request.http_version();
+    // Bodies without Content-Length can not be skipped reliably, so the connection is not reused
+    let keep_alive =
+        !is_last_request && request.keep_alive() && request.headers().transfer_encoding().is_none();
+    let mut response = match http_serve.serve_http(&client.addr, request).await {
         Ok(res) => res,
         Err(_) => Response::internal_server_error(),
     };
+    if let Some(upgrade) = response.take_upgrade() {
+        tracing::debug!("Switching Protocols:\r\n{response}");
+        if let Err(e) = write_stream.write_all(&response.as_bytes()).await {
+            tracing::debug!("Failed to send response: {e}");
+            return Ok(Continuation::Close);
+        }
+        return Ok(Continuation::Upgrade(upgrade));
+    }
+    let keep_alive =
+        keep_alive && !response.headers().connection_close() && !cancel_receiver.is_shutting_down();
+    let keep_alive = frame_response(&mut response, keep_alive, http_version);
     tracing::debug!("Outgoing Response:\r\n{response}",);
+    if let Err(e) = write_response(write_stream, &mut response, http_version).await {
+        tracing::debug!("Failed to send response: {e}");
+        return Ok(Continuation::Close);
+    }
+    if keep_alive {
+        Ok(Continuation::KeepAlive)
+    } else {
+        Ok(Continuation::Close)
+    }
+}
+
+/// Makes sure the client can tell where the response ends and announces whether the connection
+/// stays open. Returns `false` if the connection has to be closed to end the response
+fn frame_response(response: &mut Response, keep_alive: bool, http_version: HttpVersion) -> bool {
+    if http_version == HttpVersion::Http10 && response.headers().transfer_encoding().is_some() {
+        // HTTP/1.0 clients do not know chunked bodies, so they are sent until the connection closes
+        response.headers_mut().remove("Transfer-Encoding");
+    }
+    let status_code = response.status_code() as isize;
+    let forbids_body =
+        (100..200).contains(&status_code) || status_code == 204 || status_code == 304;
+    let transfer_encoding = response.headers().transfer_encoding();
+    let is_framed = response.headers().content_length().is_some() || transfer_encoding.is_some();
+    // Transfer codings without final chunked are delimited by closing the connection
+    let is_close_delimited = transfer_encoding.is_some_and(|te| !parsing::headers::is_chunked(te));
+    let mut keep_alive = keep_alive && !is_close_delimited;
+    if !is_framed && !forbids_body {
+        match response.body() {
+            Body::Buffer(body) => {
+                let content_length = body.data().len().to_string();
+                response
+                    .headers_mut()
+                    .insert("Content-Length", content_length);
+            }
+            Body::None => response.headers_mut().insert("Content-Length", "0"),
+            // Body is delimited by closing the connection
+            Body::Stream(_) => keep_alive = false,
+        }
+    }
+    let headers = response.headers_mut();
+    if !keep_alive {
+        headers.remove("Connection");
+        headers.insert("Connection", "close");
+    } else if http_version == HttpVersion::Http10 {
+        headers.remove("Connection");
+        headers.insert("Connection", "keep-alive");
+    }
+    keep_alive
+}
+
+async fn write_response(
+    write_stream: &mut (impl AsyncWrite + Unpin + Send),
+    response: &mut Response,
+    http_version: HttpVersion,
+) -> io::Result<()> {
     let response_raw = response.as_bytes();
-    write_stream.write_all(&response_raw).unwrap();
+    write_stream.write_all(&response_raw).await?;
     if let Body::Stream(body) = response.body() {
-        let mut body = body;
-        io::copy(&mut body, &mut write_stream).unwrap();
+        match http_version {
+            HttpVersion::Http10 => {
+                while let Some(data) = body.read_data().await? {
+                    write_stream.write_all(&data).await?;
+                }
+            }
+            HttpVersion::Http11 => {
+                body.copy_to(write_stream).await?;
+            }
+        }
     }
     Ok(())
 }
 
-fn send_response(mut stream: &net::TcpStream, mut response: Response) {
+async fn send_response(stream: &mut (impl AsyncWrite + Unpin + Send), mut response: Response) {
+    frame_response(&mut response, false, HttpVersion::Http11);
     let response = &response.as_bytes();
-    stream.write_all(response).unwrap();
+    if let Err(e) = stream.write_all(response).await {
+        tracing::debug!("Failed to send response: {e}");
+    }
 }
 
 /// Return this error in case an unrecoverable error happened
@@ -0,0 +1,216 @@
+use std::{
+    error::Error,
+    fmt::{self, Display},
+    net::{self, IpAddr, Ipv4Addr, Ipv6Addr},
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use ipnet::IpNet;
+use tokio::{
+    io::{self, AsyncRead, AsyncReadExt, AsyncWrite, ReadBuf},
+    net::TcpStream,
+};
+
+use super::Connection;
+
+/// Longest v1 header including the terminating CRLF
+const V1_MAX_LENGTH: usize = 107;
+const V1_SIGNATURE: &[u8] = b"PROXY ";
+const V2_SIGNATURE: &[u8] = b"\r\n\r\n\0\r\nQUIT\n";
+
+/// Accepts the [PROXY protocol](https://www.haproxy.org/download/2.9/doc/proxy-protocol.txt)
+/// (v1 and v2) from load balancers in front of the [Server](super::Server), telling it the
+/// address of the client
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ProxyProtocol {
+    trusted_sources: Vec<IpNet>,
+}
+impl ProxyProtocol {
+    /// Only peers within `trusted_sources` may send a header. Other peers are served as is
+    pub fn new(trusted_sources: Vec<IpNet>) -> Self {
+        Self { trusted_sources }
+    }
+
+    pub fn is_trusted(&self, ip: IpAddr) -> bool {
+        let ip = ip.to_canonical();
+        self.trusted_sources.iter().any(|n| n.contains(&ip))
+    }
+}
+
+/// Connection from a load balancer, reporting the client address of the PROXY protocol header as
+/// its peer
+pub struct ProxiedStream {
+    stream: TcpStream,
+    source: net::SocketAddr,
+}
+impl ProxiedStream {
+    /// Reads the header the peer sends before any other data. Connections without client address
+    /// (e.g. health checks) keep the address of the peer
+    pub async fn accept(mut stream: TcpStream) -> Result<Self, ProxyProtocolError> {
+        let source = match read_header(&mut stream).await? {
+            Some(source) => source,
+            None => stream.peer_addr()?,
+        };
+        Ok(Self { stream, source })
+    }
+
+    /// Address of the client
+    pub fn source(&self) -> net::SocketAddr {
+        self.source
+    }
+}
+impl Connection for ProxiedStream {
+    fn peer_addr(&self) -> io::Result<net::SocketAddr> {
+        Ok(self.source)
+    }
+
+    fn is_secure(&self) -> bool {
+        false
+    }
+}
+impl AsyncRead for ProxiedStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_read(cx, buf)
+    }
+}
+impl AsyncWrite for ProxiedStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.stream).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_shutdown(cx)
+    }
+}
+
+/// Reads a v1 or v2 header without consuming anything past it. Returns the source address of the
+/// client or [None] if the header does not carry one
+pub async fn read_header(
+    stream: &mut (impl AsyncRead + Unpin),
+) -> Result<Option<net::SocketAddr>, ProxyProtocolError> {
+    let mut signature = [0; 6];
+    stream.read_exact(&mut signature).await?;
+    if signature == V1_SIGNATURE {
+        read_v1_header(stream).await
+    } else if signature == V2_SIGNATURE[..6] {
+        read_v2_header(stream).await
+    } else {
+        Err(ProxyProtocolError::MissingHeader)
+    }
+}
+
+/// Reads the rest of `PROXY TCP4 <src> <dst> <src port> <dst port>\r\n`
+async fn read_v1_header(
+    stream: &mut (impl AsyncRead + Unpin),
+) -> Result<Option<net::SocketAddr>, ProxyProtocolError> {
+    let mut line = Vec::new();
+    while !line.ends_with(b"\r\n") {
+        if V1_SIGNATURE.len() + line.len() >= V1_MAX_LENGTH {
+            return Err(ProxyProtocolError::InvalidHeader("v1 header too long"));
+        }
+        // Read byte by byte, so no data after the header gets consumed
+        line.push(stream.read_u8().await?);
+    }
+    let line = str::from_utf8(&line[..line.len() - 2])
+        .map_err(|_| ProxyProtocolError::InvalidHeader("v1 header is not ASCII"))?;
+    let mut fields = line.split(' ');
+    let protocol = fields.next();
+    if protocol == Some("UNKNOWN") {
+        return Ok(None);
+    }
+    let (Some(source), Some(_), Some(source_port), Some(_), None) = (
+        fields.next(),
+        fields.next(),
+        fields.next(),
+        fields.next(),
+        fields.next(),
+    ) else {
+        return Err(ProxyProtocolError::InvalidHeader("invalid v1 header"));
+    };
+    let invalid_address = || ProxyProtocolError::InvalidHeader("invalid v1 address");
+    let source: IpAddr = source.parse().map_err(|_| invalid_address())?;
+    let source_port: u16 = source_port.parse().map_err(|_| invalid_address())?;
+    match (protocol, source) {
+        (Some("TCP4"), IpAddr::V4(_)) | (Some("TCP6"), IpAddr::V6(_)) => {}
+        _ => return Err(invalid_address()),
+    }
+    Ok(Some(net::SocketAddr::new(source, source_port)))
+}
+
+/// Reads the rest of the binary v2 header
+async fn read_v2_header(
+    stream: &mut (impl AsyncRead + Unpin),
+) -> Result<Option<net::SocketAddr>, ProxyProtocolError> {
+    let mut head = [0; 10];
+    stream.read_exact(&mut head).await?;
+    if head[..6] != V2_SIGNATURE[6..] {
+        return Err(ProxyProtocolError::MissingHeader);
+    }
+    let (version_command, family) = (head[6], head[7]);
+    let length = u16::from_be_bytes([head[8], head[9]]) as usize;
+    let mut addresses = vec![0; length];
+    stream.read_exact(&mut addresses).await?;
+    if version_command >> 4 != 2 {
+        return Err(ProxyProtocolError::InvalidHeader("unsupported version"));
+    }
+    match version_command & 0x0F {
+        0x0 => return Ok(None), // LOCAL: sent by the load balancer itself
+        0x1 => {}
+        _ => return Err(ProxyProtocolError::InvalidHeader("unknown v2 command")),
+    }
+    // Addresses are followed by the ports, possibly by TLVs too
+    let source = match family >> 4 {
+        0x1 if addresses.len() >= 12 => {
+            let ip: [u8; 4] = addresses[..4].try_into().unwrap();
+            let port = u16::from_be_bytes([addresses[8], addresses[9]]);
+            net::SocketAddr::new(Ipv4Addr::from(ip).into(), port)
+        }
+        0x2 if addresses.len() >= 36 => {
+            let ip: [u8; 16] = addresses[..16].try_into().unwrap();
+            let port = u16::from_be_bytes([addresses[32], addresses[33]]);
+            net::SocketAddr::new(Ipv6Addr::from(ip).into(), port)
+        }
+        0x1 | 0x2 => return Err(ProxyProtocolError::InvalidHeader("v2 addresses too short")),
+        // UNSPEC or unix sockets do not have an IP address
+        _ => return Ok(None),
+    };
+    Ok(Some(source))
+}
+
+#[derive(Debug)]
+pub enum ProxyProtocolError {
+    /// Connection did not start with a PROXY protocol signature
+    MissingHeader,
+    InvalidHeader(&'static str),
+    Io(io::Error),
+}
+impl From<io::Error> for ProxyProtocolError {
+    fn from(e: io::Error) -> Self {
+        ProxyProtocolError::Io(e)
+    }
+}
+impl Error for ProxyProtocolError {}
+impl Display for ProxyProtocolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProxyProtocolError::MissingHeader => write!(f, "Missing PROXY protocol header"),
+            ProxyProtocolError::InvalidHeader(e) => {
+                write!(f, "Invalid PROXY protocol header: {e}")
+            }
+            ProxyProtocolError::Io(e) => write!(f, "Failed to read PROXY protocol header: {e}"),
+        }
+    }
+}
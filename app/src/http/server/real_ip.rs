@@ -0,0 +1,127 @@
+use std::net::{self, IpAddr};
+
+use ipnet::IpNet;
+
+use crate::http::request;
+
+/// What happens if no trustworthy client IP is found, e.g. because the header is missing
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub enum RealIpFallback {
+    /// Treat the peer as the client
+    PeerAddress,
+    /// Answer with `400 Bad Request`
+    #[default]
+    Reject,
+}
+
+/// Reads the client IP from a header set by reverse proxies, e.g. `X-Real-Ip`, `X-Forwarded-For`
+/// or `Forwarded` (RFC 7239)
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct RealIpResolver {
+    header: String,
+    trusted_proxies: Vec<IpNet>,
+    fallback: RealIpFallback,
+}
+impl RealIpResolver {
+    /// Loopback and private networks
+    pub const TRUSTED_PROXIES_DEFAULT: [&str; 6] = [
+        "127.0.0.0/8",
+        "10.0.0.0/8",
+        "172.16.0.0/12",
+        "192.168.0.0/16",
+        "::1/128",
+        "fc00::/7",
+    ];
+
+    pub fn new(header: impl Into<String>) -> Self {
+        let trusted_proxies = Self::TRUSTED_PROXIES_DEFAULT
+            .iter()
+            .map(|n| n.parse().unwrap())
+            .collect();
+        Self {
+            header: header.into(),
+            trusted_proxies,
+            fallback: RealIpFallback::default(),
+        }
+    }
+
+    /// Only addresses within `trusted_proxies` may add to the header
+    pub fn set_trusted_proxies(&mut self, trusted_proxies: Vec<IpNet>) {
+        self.trusted_proxies = trusted_proxies;
+    }
+
+    pub fn set_fallback(&mut self, fallback: RealIpFallback) {
+        self.fallback = fallback;
+    }
+
+    /// Walks the addresses of the header from right to left, starting at the peer. The first
+    /// address not belonging to a trusted proxy is the client. Returns [None] if no trustworthy
+    /// address is found and the [RealIpFallback] rejects the request
+    pub fn resolve(
+        &self,
+        peer_addr: &net::SocketAddr,
+        headers: &request::Headers,
+    ) -> Option<net::SocketAddr> {
+        if !self.is_trusted(peer_addr.ip()) {
+            return Some(*peer_addr);
+        }
+        let mut client = None;
+        for addr in self.read_addresses(headers).into_iter().rev() {
+            match addr {
+                Some(addr) if self.is_trusted(addr.ip()) => client = Some(addr),
+                Some(addr) => return Some(addr),
+                // Obfuscated or unknown address, the chain can not be followed any further
+                None => return self.fall_back(peer_addr),
+            }
+        }
+        // Every address belongs to a trusted network, so the first one is the client
+        client.or_else(|| self.fall_back(peer_addr))
+    }
+
+    fn is_trusted(&self, ip: IpAddr) -> bool {
+        let ip = ip.to_canonical();
+        self.trusted_proxies.iter().any(|n| n.contains(&ip))
+    }
+
+    fn fall_back(&self, peer_addr: &net::SocketAddr) -> Option<net::SocketAddr> {
+        match self.fallback {
+            RealIpFallback::PeerAddress => Some(*peer_addr),
+            RealIpFallback::Reject => None,
+        }
+    }
+
+    /// Addresses of all header lines in order. [None] for addresses that can not be parsed
+    fn read_addresses(&self, headers: &request::Headers) -> Vec<Option<net::SocketAddr>> {
+        let is_forwarded = self.header.eq_ignore_ascii_case("Forwarded");
+        headers
+            .extensions(&self.header)
+            .into_iter()
+            .flat_map(|v| v.split(','))
+            .map(|node| match is_forwarded {
+                true => parse_forwarded_element(node),
+                false => parse_node(node),
+            })
+            .collect()
+    }
+}
+
+/// Reads the `for` parameter of a `Forwarded` element, e.g. `for="[2001:db8::1]:4711";proto=https`
+fn parse_forwarded_element(element: &str) -> Option<net::SocketAddr> {
+    let node = element
+        .split(';')
+        .filter_map(|p| p.split_once('='))
+        .find(|(k, _)| k.trim().eq_ignore_ascii_case("for"))
+        .map(|(_, v)| v)?;
+    parse_node(node)
+}
+
+/// Parses an IP with optional port. Port `0` is used if there is none
+fn parse_node(node: &str) -> Option<net::SocketAddr> {
+    let node = node.trim().trim_matches('"');
+    if let Ok(addr) = node.parse::<net::SocketAddr>() {
+        return Some(addr);
+    }
+    let ip = node.strip_prefix('[').and_then(|n| n.strip_suffix(']'));
+    let ip: IpAddr = ip.unwrap_or(node).parse().ok()?;
+    Some(net::SocketAddr::new(ip, 0))
+}
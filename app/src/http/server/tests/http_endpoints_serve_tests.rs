@@ -4,7 +4,8 @@ use crate::http::{
     self, request,
     response::StatusCode,
     server::{
-        tests::{HelloHandler, IpSpyHandler},
+        real_ip::RealIpResolver,
+        tests::{EchoForwardedProtoHandler, HelloHandler, IpSpyHandler},
         *,
     },
 };
@@ -33,8 +34,8 @@ pub fn assert_body_contains(expected_content: &str, body: &mut http::Body) {
     }
 }
 
-#[test]
-pub fn serve_should_handle_request_through_defined_endpoint() {
+#[tokio::test]
+pub async fn serve_should_handle_request_through_defined_endpoint() {
     // Arrange
     let handler = Box::new(HelloHandler {
         body: "Hello!\r\n".into(),
@@ -46,7 +47,7 @@ pub fn serve_should_handle_request_through_defined_endpoint() {
     headers.insert("Host", "localhost");
     let headers = request::Headers::new(headers).unwrap();
     let request = Request::new(Method::Get, "/hello", headers, http::Body::None).unwrap();
-    let response = sut.serve_http(&client_addr(), request);
+    let response = sut.serve_http(&client_addr(), request).await;
     // Assert
     assert!(response.is_ok());
     let mut response = response.unwrap();
@@ -55,8 +56,8 @@ pub fn serve_should_handle_request_through_defined_endpoint() {
     assert_body_contains("Hello!\r\n", response.body());
 }
 
-#[test]
-pub fn serve_should_handle_request_on_specific_path() {
+#[tokio::test]
+pub async fn serve_should_handle_request_on_specific_path() {
     // Arrange
     let hello_handler = Box::new(HelloHandler {
         body: "Hello!\r\n".into(),
@@ -74,7 +75,7 @@ pub fn serve_should_handle_request_on_specific_path() {
     headers.insert("Host", "localhost");
     let headers = request::Headers::new(headers).unwrap();
     let request = Request::new(Method::Get, "/hello", headers, http::Body::None).unwrap();
-    let mut response = sut.serve_http(&client_addr(), request).unwrap();
+    let mut response = sut.serve_http(&client_addr(), request).await.unwrap();
     // Assert
     assert_body_contains("Hello!\r\n", response.body());
 }
@@ -82,7 +83,8 @@ pub fn serve_should_handle_request_on_specific_path() {
 #[test_case("/hello/", "/hello" ; "missing trailing slash")]
 #[test_case("/hello", "/hello/" ; "additional trailing slash")]
 #[test_case("/hello", "/hello/////" ; "more trailing slashes")]
-pub fn serve_should_handle_missing_or_added_trailing_slashes(
+#[tokio::test]
+pub async fn serve_should_handle_missing_or_added_trailing_slashes(
     endpoint_path: &str,
     access_path: &str,
 ) {
@@ -97,13 +99,13 @@ pub fn serve_should_handle_missing_or_added_trailing_slashes(
     headers.insert("Host", "localhost");
     let headers = request::Headers::new(headers).unwrap();
     let request = Request::new(Method::Get, access_path, headers, http::Body::None).unwrap();
-    let response = sut.serve_http(&client_addr(), request).unwrap();
+    let response = sut.serve_http(&client_addr(), request).await.unwrap();
     // Assert
     assert_eq!(StatusCode::OK, response.status_code(), "did not find path");
 }
 
-#[test]
-pub fn serve_should_return_not_found_when_no_matching_endpoint_path_is_found() {
+#[tokio::test]
+pub async fn serve_should_return_not_found_when_no_matching_endpoint_path_is_found() {
     // Arrange
     let hello_handler = Box::new(HelloHandler {
         body: "Hello!\r\n".into(),
@@ -121,13 +123,13 @@ pub fn serve_should_return_not_found_when_no_matching_endpoint_path_is_found() {
         http::Body::None,
     )
     .unwrap();
-    let response = sut.serve_http(&client_addr(), request).unwrap();
+    let response = sut.serve_http(&client_addr(), request).await.unwrap();
     // Assert
     assert_eq!(StatusCode::NotFound, response.status_code());
 }
 
-#[test]
-pub fn serve_should_return_method_not_allowed_when_matching_endpoint_is_wrong_path() {
+#[tokio::test]
+pub async fn serve_should_return_method_not_allowed_when_matching_endpoint_is_wrong_path() {
     // Arrange
     let hello_handler = Box::new(HelloHandler {
         body: "Hello!\r\n".into(),
@@ -139,13 +141,13 @@ pub fn serve_should_return_method_not_allowed_when_matching_endpoint_is_wrong_pa
     headers.insert("Host", "localhost");
     let headers = request::Headers::new(headers).unwrap();
     let request = Request::new(Method::Head, "/hello", headers, http::Body::None).unwrap();
-    let response = sut.serve_http(&client_addr(), request).unwrap();
+    let response = sut.serve_http(&client_addr(), request).await.unwrap();
     // Assert
     assert_eq!(StatusCode::MethodNotAllowed, response.status_code());
 }
 
-#[test]
-pub fn serve_should_pass_client_address_to_inner_serve_if_no_header_configured() {
+#[tokio::test]
+pub async fn serve_should_pass_client_address_to_inner_serve_if_no_header_configured() {
     // Arrange
     let ip_spy_handler = IpSpyHandler::default();
     let endpoints = vec![Endpoint::new(
@@ -160,7 +162,7 @@ pub fn serve_should_pass_client_address_to_inner_serve_if_no_header_configured()
     let headers = request::Headers::new(headers).unwrap();
     let request = Request::new(Method::Get, "/", headers, http::Body::None).unwrap();
     let client_addr = client_addr();
-    let _ = sut.serve_http(&client_addr, request).unwrap();
+    let _ = sut.serve_http(&client_addr, request).await.unwrap();
     // Assert
     let expected_ip = &[client_addr];
     ip_spy_handler.assert_ips_equal(expected_ip);
@@ -168,7 +170,8 @@ pub fn serve_should_pass_client_address_to_inner_serve_if_no_header_configured()
 
 #[test_case("X-Real-Ip", "12.34.56.78")]
 #[test_case("My-Ip", "87.65.43.21")]
-pub fn serve_should_pass_peer_address_from_header_to_inner_serve_if_configured(
+#[tokio::test]
+pub async fn serve_should_pass_peer_address_from_header_to_inner_serve_if_configured(
     header_name: &str,
     expected_ip: &str,
 ) {
@@ -179,7 +182,7 @@ pub fn serve_should_pass_peer_address_from_header_to_inner_serve_if_configured(
         "/",
         Box::new(ip_spy_handler.clone()),
     )];
-    let sut = HttpEndpointsServe::new(endpoints, Some(header_name.to_string()));
+    let sut = HttpEndpointsServe::new(endpoints, Some(RealIpResolver::new(header_name)));
     // Act
     let mut headers = http::Headers::empty();
     headers.insert("Host", "localhost");
@@ -187,9 +190,38 @@ pub fn serve_should_pass_peer_address_from_header_to_inner_serve_if_configured(
     let headers = request::Headers::new(headers).unwrap();
     let request = Request::new(Method::Get, "/", headers, http::Body::None).unwrap();
     let client_addr = client_addr();
-    let _ = sut.serve_http(&client_addr, request).unwrap();
+    let _ = sut.serve_http(&client_addr, request).await.unwrap();
     // Assert
     let expected_ip = net::SocketAddr::from_str(&format!("{expected_ip}:0")).unwrap();
     let expected_ip = &[expected_ip];
     ip_spy_handler.assert_ips_equal(expected_ip);
 }
+
+#[test_case(&[], "127.0.0.1", "" ; "no trusted proxies")]
+#[test_case(&["10.0.0.0/8"], "203.0.113.7", "" ; "untrusted peer")]
+#[test_case(&["10.0.0.0/8"], "10.0.0.2", "https" ; "trusted proxy")]
+#[tokio::test]
+pub async fn serve_should_only_keep_forwarded_proto_of_trusted_proxies(
+    trusted_proxies: &[&str],
+    peer_ip: &str,
+    expected_proto: &str,
+) {
+    // Arrange
+    let endpoints = vec![Endpoint::new(
+        Method::Get,
+        "/",
+        Box::new(EchoForwardedProtoHandler),
+    )];
+    let mut sut = setup(endpoints);
+    sut.set_trusted_proxies(trusted_proxies.iter().map(|n| n.parse().unwrap()).collect());
+    // Act
+    let mut headers = http::Headers::empty();
+    headers.insert("Host", "localhost");
+    headers.insert("X-Forwarded-Proto", "https");
+    let headers = request::Headers::new(headers).unwrap();
+    let request = Request::new(Method::Get, "/", headers, http::Body::None).unwrap();
+    let peer_addr = net::SocketAddr::from_str(&format!("{peer_ip}:5501")).unwrap();
+    let mut response = sut.serve_http(&peer_addr, request).await.unwrap();
+    // Assert
+    assert_body_contains(expected_proto, response.body());
+}
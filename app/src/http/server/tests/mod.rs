@@ -2,25 +2,34 @@ use std::{
     collections::VecDeque,
     io, net,
     sync::{Arc, Mutex},
+    time::Duration,
 };
 
 use crate::http::{
     self,
     response::{self, StatusCode},
     server::{HttpServe, InternalServerError},
-    BufferBody, ChunkedTcpStream, Request, Response, StreamBody,
+    BufferBody, ChunkedTcpStream, Request, Response, StreamBody, Upgrade,
 };
 
 use pretty_assertions::assert_eq;
 
 mod http_endpoints_serve_tests;
+mod proxy_protocol_tests;
+mod real_ip_tests;
 mod server_tests;
+mod tls_tests;
 
 struct HelloHandler {
     body: Vec<u8>,
 }
+#[async_trait::async_trait]
 impl HttpServe for HelloHandler {
-    fn serve_http(&self, _: &net::SocketAddr, _: Request) -> Result<Response, InternalServerError> {
+    async fn serve_http(
+        &self,
+        _: &net::SocketAddr,
+        _: Request,
+    ) -> Result<Response, InternalServerError> {
         let mut headers = http::Headers::empty();
         headers.insert("Content-Length", self.body.len().to_string());
         let headers = response::Headers::new(headers);
@@ -35,13 +44,18 @@ impl HttpServe for HelloHandler {
 struct ChunkedHandler {
     chunked_body: Vec<u8>,
 }
+#[async_trait::async_trait]
 impl HttpServe for ChunkedHandler {
-    fn serve_http(&self, _: &net::SocketAddr, _: Request) -> Result<Response, InternalServerError> {
+    async fn serve_http(
+        &self,
+        _: &net::SocketAddr,
+        _: Request,
+    ) -> Result<Response, InternalServerError> {
         let mut headers = http::Headers::empty();
         headers.insert("Transfer-Encoding", "chunked");
         let headers = response::Headers::new(headers);
-        let data: VecDeque<u8> = self.chunked_body.clone().into();
-        let stream = ChunkedTcpStream::new(Box::new(io::BufReader::new(data)));
+        let data = io::Cursor::new(self.chunked_body.clone());
+        let stream = ChunkedTcpStream::new(Box::new(data));
         let body = StreamBody::new(Box::new(stream));
         let body = http::Body::Stream(body);
         let response = Response::new(StatusCode::OK, Some("OK".into()), headers, body);
@@ -49,13 +63,177 @@ impl HttpServe for ChunkedHandler {
     }
 }
 
+/// Streams all of `data` as body, delimited by closing the connection. `transfer_encoding`
+/// must not end with `chunked`
+struct UnframedStreamHandler {
+    data: Vec<u8>,
+    transfer_encoding: Option<&'static str>,
+}
+#[async_trait::async_trait]
+impl HttpServe for UnframedStreamHandler {
+    async fn serve_http(
+        &self,
+        _: &net::SocketAddr,
+        _: Request,
+    ) -> Result<Response, InternalServerError> {
+        let mut headers = http::Headers::empty();
+        if let Some(transfer_encoding) = self.transfer_encoding {
+            headers.insert("Transfer-Encoding", transfer_encoding);
+        }
+        let headers = response::Headers::new(headers);
+        let data = io::Cursor::new(self.data.clone());
+        let body = http::Body::Stream(StreamBody::until_close(Box::new(data)));
+        let response = Response::new(StatusCode::OK, Some("OK".into()), headers, body);
+        Ok(response)
+    }
+}
+
+/// Streams the first `content_length` bytes of `data` as body
+struct SizedStreamHandler {
+    data: Vec<u8>,
+    content_length: usize,
+}
+#[async_trait::async_trait]
+impl HttpServe for SizedStreamHandler {
+    async fn serve_http(
+        &self,
+        _: &net::SocketAddr,
+        _: Request,
+    ) -> Result<Response, InternalServerError> {
+        let mut headers = http::Headers::empty();
+        headers.insert("Content-Length", self.content_length.to_string());
+        let headers = response::Headers::new(headers);
+        let data = io::Cursor::new(self.data.clone());
+        let body = StreamBody::with_length(Box::new(data), self.content_length);
+        let body = http::Body::Stream(body);
+        let response = Response::new(StatusCode::OK, Some("OK".into()), headers, body);
+        Ok(response)
+    }
+}
+
+/// Responds with the request target as body
+struct EchoPathHandler;
+#[async_trait::async_trait]
+impl HttpServe for EchoPathHandler {
+    async fn serve_http(
+        &self,
+        _: &net::SocketAddr,
+        request: Request,
+    ) -> Result<Response, InternalServerError> {
+        let body = request.request_target().to_string();
+        let mut headers = http::Headers::empty();
+        headers.insert("Content-Length", body.len().to_string());
+        let headers = response::Headers::new(headers);
+        let body = http::Body::from_string(body);
+        let response = Response::new(StatusCode::OK, Some("OK".into()), headers, body);
+        Ok(response)
+    }
+}
+
+/// Responds with the `X-Forwarded-Proto` header of the request as body
+struct EchoForwardedProtoHandler;
+#[async_trait::async_trait]
+impl HttpServe for EchoForwardedProtoHandler {
+    async fn serve_http(
+        &self,
+        _: &net::SocketAddr,
+        request: Request,
+    ) -> Result<Response, InternalServerError> {
+        let body = request
+            .headers()
+            .extension("X-Forwarded-Proto")
+            .unwrap_or("")
+            .to_string();
+        let mut headers = http::Headers::empty();
+        headers.insert("Content-Length", body.len().to_string());
+        let headers = response::Headers::new(headers);
+        let body = http::Body::from_string(body);
+        let response = Response::new(StatusCode::OK, Some("OK".into()), headers, body);
+        Ok(response)
+    }
+}
+
+/// Responds with the request body in its transfer encoding
+struct EchoBodyHandler;
+#[async_trait::async_trait]
+impl HttpServe for EchoBodyHandler {
+    async fn serve_http(
+        &self,
+        _: &net::SocketAddr,
+        mut request: Request,
+    ) -> Result<Response, InternalServerError> {
+        let mut body = Vec::new();
+        match request.body_mut() {
+            http::Body::Buffer(b) => body.extend(b.data()),
+            http::Body::Stream(s) => {
+                s.copy_to(&mut body).await.or(Err(InternalServerError))?;
+            }
+            http::Body::None => {}
+        }
+        let mut headers = http::Headers::empty();
+        headers.insert("Content-Length", body.len().to_string());
+        let headers = response::Headers::new(headers);
+        let body = http::Body::Buffer(BufferBody::new(body.into()));
+        let response = Response::new(StatusCode::OK, Some("OK".into()), headers, body);
+        Ok(response)
+    }
+}
+
+/// Takes `delay` to respond with the request target as body
+struct SlowHandler {
+    delay: Duration,
+}
+#[async_trait::async_trait]
+impl HttpServe for SlowHandler {
+    async fn serve_http(
+        &self,
+        client_addr: &net::SocketAddr,
+        request: Request,
+    ) -> Result<Response, InternalServerError> {
+        tokio::time::sleep(self.delay).await;
+        EchoPathHandler.serve_http(client_addr, request).await
+    }
+}
+
 struct PanicHandler;
+#[async_trait::async_trait]
 impl HttpServe for PanicHandler {
-    fn serve_http(&self, _: &net::SocketAddr, _: Request) -> Result<Response, InternalServerError> {
+    async fn serve_http(
+        &self,
+        _: &net::SocketAddr,
+        _: Request,
+    ) -> Result<Response, InternalServerError> {
         panic!("I am trying to kill the server")
     }
 }
 
+/// Switches to a protocol echoing everything the client sends
+struct EchoUpgradeHandler;
+#[async_trait::async_trait]
+impl HttpServe for EchoUpgradeHandler {
+    async fn serve_http(
+        &self,
+        _: &net::SocketAddr,
+        _: Request,
+    ) -> Result<Response, InternalServerError> {
+        let (upgraded, echo) = tokio::io::duplex(64);
+        tokio::spawn(async move {
+            let (mut reader, mut writer) = tokio::io::split(echo);
+            _ = tokio::io::copy(&mut reader, &mut writer).await;
+        });
+        let mut headers = http::Headers::empty();
+        headers.insert("Connection", "upgrade");
+        headers.insert("Upgrade", "echo");
+        let headers = response::Headers::new(headers);
+        let reason_phrase = Some("Switching Protocols".into());
+        let status_code = StatusCode::SwitchingProtocols;
+        let mut response = Response::new(status_code, reason_phrase, headers, http::Body::None);
+        let (reader, writer) = tokio::io::split(upgraded);
+        response.set_upgrade(Upgrade::new(Box::new(reader), Box::new(writer)));
+        Ok(response)
+    }
+}
+
 #[derive(Default, Clone)]
 struct IpSpyHandler {
     ips: Arc<Mutex<Vec<net::SocketAddr>>>,
@@ -67,8 +245,9 @@ impl IpSpyHandler {
         assert_eq!(expected_ips, ips.as_slice());
     }
 }
+#[async_trait::async_trait]
 impl HttpServe for IpSpyHandler {
-    fn serve_http(
+    async fn serve_http(
         &self,
         client_addr: &net::SocketAddr,
         _: Request,
@@ -0,0 +1,183 @@
+use std::{
+    io::{Read, Write},
+    net::{self, IpAddr, SocketAddr},
+    thread,
+};
+
+use pretty_assertions::assert_eq;
+use test_case::test_case;
+
+use crate::http::server::{
+    cancellation_token,
+    proxy_protocol::{read_header, ProxyProtocol, ProxyProtocolError},
+    tests::IpSpyHandler,
+    Server,
+};
+
+const V2_SIGNATURE: &[u8] = b"\r\n\r\n\0\r\nQUIT\n";
+
+fn v2_header(command: u8, family: u8, addresses: &[u8]) -> Vec<u8> {
+    let mut header = V2_SIGNATURE.to_vec();
+    header.push(0x20 | command);
+    header.push(family);
+    header.extend((addresses.len() as u16).to_be_bytes());
+    header.extend(addresses);
+    header
+}
+
+fn v2_tcp4_header() -> Vec<u8> {
+    let addresses = [203, 0, 113, 7, 10, 0, 0, 1, 0xD4, 0x31, 0x1F, 0x40];
+    v2_header(0x1, 0x11, &addresses)
+}
+
+fn v2_tcp6_header() -> Vec<u8> {
+    let mut addresses = Vec::new();
+    addresses.extend("2001:db8::7".parse::<net::Ipv6Addr>().unwrap().octets());
+    addresses.extend("2001:db8::1".parse::<net::Ipv6Addr>().unwrap().octets());
+    addresses.extend([0xD4, 0x31, 0x1F, 0x40]);
+    v2_header(0x1, 0x21, &addresses)
+}
+
+fn v2_local_header() -> Vec<u8> {
+    v2_header(0x0, 0x00, &[])
+}
+
+fn v2_tcp4_header_with_tlv() -> Vec<u8> {
+    let mut addresses = vec![203, 0, 113, 7, 10, 0, 0, 1, 0xD4, 0x31, 0x1F, 0x40];
+    addresses.extend([0x04, 0x00, 0x02, 0xAB, 0xCD]); // NOOP TLV
+    v2_header(0x1, 0x11, &addresses)
+}
+
+#[test_case(b"PROXY TCP4 203.0.113.7 10.0.0.1 54321 8000\r\n".to_vec(), Some("203.0.113.7:54321") ; "v1 tcp4")]
+#[test_case(b"PROXY TCP6 2001:db8::7 2001:db8::1 54321 8000\r\n".to_vec(), Some("[2001:db8::7]:54321") ; "v1 tcp6")]
+#[test_case(b"PROXY UNKNOWN\r\n".to_vec(), None ; "v1 unknown")]
+#[test_case(v2_tcp4_header(), Some("203.0.113.7:54321") ; "v2 tcp4")]
+#[test_case(v2_tcp6_header(), Some("[2001:db8::7]:54321") ; "v2 tcp6")]
+#[test_case(v2_tcp4_header_with_tlv(), Some("203.0.113.7:54321") ; "v2 with tlv")]
+#[test_case(v2_local_header(), None ; "v2 local")]
+#[tokio::test]
+pub async fn read_header_should_return_source_address(
+    header: Vec<u8>,
+    expected_source: Option<&str>,
+) {
+    // Arrange
+    let mut data = header;
+    data.extend(b"GET / HTTP/1.1\r\n");
+    let mut stream = data.as_slice();
+    // Act
+    let source = read_header(&mut stream).await;
+    // Assert
+    let expected_source = expected_source.map(|s| s.parse::<SocketAddr>().unwrap());
+    assert_eq!(expected_source, source.unwrap());
+    assert_eq!(b"GET / HTTP/1.1\r\n", stream, "Consumed data after header");
+}
+
+#[test_case(b"GET / HTTP/1.1\r\n\r\n".to_vec() ; "no header")]
+#[test_case(b"PROXY TCP4 not-an-ip 10.0.0.1 54321 8000\r\n".to_vec() ; "v1 invalid address")]
+#[test_case(b"PROXY TCP4 2001:db8::7 2001:db8::1 54321 8000\r\n".to_vec() ; "v1 address of wrong family")]
+#[test_case(b"PROXY TCP4 203.0.113.7 10.0.0.1 54321\r\n".to_vec() ; "v1 missing port")]
+#[test_case(format!("PROXY TCP4 {}\r\n", "1".repeat(120)).into_bytes() ; "v1 too long")]
+#[test_case(v2_header(0x1, 0x11, &[203, 0, 113, 7]) ; "v2 addresses too short")]
+#[test_case([&V2_SIGNATURE[..11], b"X\x21\x11\0\0"].concat() ; "v2 invalid signature")]
+#[tokio::test]
+pub async fn read_header_should_fail_for_invalid_header(data: Vec<u8>) {
+    // Arrange
+    let mut stream = data.as_slice();
+    // Act
+    let source = read_header(&mut stream).await;
+    // Assert
+    assert!(
+        matches!(
+            source,
+            Err(ProxyProtocolError::MissingHeader | ProxyProtocolError::InvalidHeader(_))
+        ),
+        "Invalid header was accepted: {source:?}"
+    );
+}
+
+#[test_case("10.0.0.1", true ; "ip within trusted cidr")]
+#[test_case("::ffff:10.0.0.1", true ; "ipv4 mapped ipv6 within trusted cidr")]
+#[test_case("2001:db8::1", true ; "ipv6 within trusted cidr")]
+#[test_case("192.168.0.1", false ; "ip outside trusted cidrs")]
+pub fn proxy_protocol_should_only_trust_configured_sources(ip: &str, expected: bool) {
+    // Arrange
+    let trusted_sources = vec![
+        "10.0.0.0/8".parse().unwrap(),
+        "2001:db8::/32".parse().unwrap(),
+    ];
+    let sut = ProxyProtocol::new(trusted_sources);
+    // Act
+    let is_trusted = sut.is_trusted(ip.parse::<IpAddr>().unwrap());
+    // Assert
+    assert_eq!(expected, is_trusted);
+}
+
+fn setup_proxy_protocol_server(trusted_source: &str) -> (Server, SocketAddr, IpSpyHandler) {
+    let handler = IpSpyHandler::default();
+    let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let mut server = Server::new(listener, Box::new(handler.clone()));
+    server.set_proxy_protocol(ProxyProtocol::new(vec![trusted_source.parse().unwrap()]));
+    (server, addr, handler)
+}
+
+fn send_request(addr: SocketAddr, header: &[u8]) -> (String, SocketAddr) {
+    let mut connection = net::TcpStream::connect(addr).unwrap();
+    let request = b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n";
+    connection.write_all(&[header, request].concat()).unwrap();
+    let mut response = String::new();
+    _ = connection.read_to_string(&mut response);
+    (response, connection.local_addr().unwrap())
+}
+
+#[test]
+pub fn server_should_serve_source_address_of_trusted_peer() {
+    // Arrange
+    let (mut sut, addr, handler) = setup_proxy_protocol_server("127.0.0.0/8");
+    let (sender, receiver) = cancellation_token::create_cancellation_token();
+    // Act
+    let server_thread = thread::spawn(move || sut.start_listening(receiver));
+    let header = b"PROXY TCP4 203.0.113.7 127.0.0.1 54321 8000\r\n";
+    let (response, _) = send_request(addr, header);
+    // Assert
+    sender.send_shutdown().unwrap();
+    server_thread.join().unwrap();
+    assert!(response.starts_with("HTTP/1.1 204"), "{response}");
+    handler.assert_ips_equal(&["203.0.113.7:54321".parse().unwrap()]);
+}
+
+#[test]
+pub fn server_should_close_connection_of_trusted_peer_without_header() {
+    // Arrange
+    let (mut sut, addr, handler) = setup_proxy_protocol_server("127.0.0.0/8");
+    let (sender, receiver) = cancellation_token::create_cancellation_token();
+    // Act
+    let server_thread = thread::spawn(move || sut.start_listening(receiver));
+    let (response, _) = send_request(addr, b"");
+    // Assert
+    sender.send_shutdown().unwrap();
+    server_thread.join().unwrap();
+    assert_eq!("", response);
+    handler.assert_ips_equal(&[]);
+}
+
+#[test]
+pub fn server_should_ignore_proxy_protocol_of_untrusted_peer() {
+    // Arrange
+    let (mut sut, addr, handler) = setup_proxy_protocol_server("10.0.0.0/8");
+    let (sender, receiver) = cancellation_token::create_cancellation_token();
+    // Act
+    let server_thread = thread::spawn(move || sut.start_listening(receiver));
+    let (response, client_addr) = send_request(addr, b"");
+    let (spoofed_response, _) =
+        send_request(addr, b"PROXY TCP4 203.0.113.7 127.0.0.1 54321 8000\r\n");
+    // Assert
+    sender.send_shutdown().unwrap();
+    server_thread.join().unwrap();
+    assert!(response.starts_with("HTTP/1.1 204"), "{response}");
+    assert!(
+        !spoofed_response.starts_with("HTTP/1.1 204"),
+        "Header of untrusted peer was accepted: {spoofed_response}"
+    );
+    handler.assert_ips_equal(&[client_addr]);
+}
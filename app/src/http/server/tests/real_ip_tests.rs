@@ -0,0 +1,98 @@
+use std::net;
+
+use pretty_assertions::assert_eq;
+use test_case::test_case;
+
+use crate::http::{
+    self, request,
+    server::real_ip::{RealIpFallback, RealIpResolver},
+};
+
+fn headers(name: &str, values: &[&str]) -> request::Headers {
+    let mut headers = http::Headers::empty();
+    headers.insert("Host", "localhost");
+    for value in values {
+        headers.insert(name, *value);
+    }
+    request::Headers::new(headers).unwrap()
+}
+
+fn addr(addr: &str) -> net::SocketAddr {
+    addr.parse().unwrap()
+}
+
+const PROXY: &str = "10.0.0.1:4000";
+
+#[test_case("X-Real-Ip", &["1.2.3.4"], "1.2.3.4:0" ; "single ip")]
+#[test_case("X-Real-Ip", &["2001:db8::7"], "[2001:db8::7]:0" ; "ipv6")]
+#[test_case("X-Forwarded-For", &["1.2.3.4, 10.0.0.2"], "1.2.3.4:0" ; "skips trusted proxies")]
+#[test_case("X-Forwarded-For", &["6.6.6.6, 1.2.3.4, 10.0.0.2"], "1.2.3.4:0" ; "ignores ips added before untrusted client")]
+#[test_case("X-Forwarded-For", &["6.6.6.6", "1.2.3.4"], "1.2.3.4:0" ; "multiple header lines")]
+#[test_case("X-Forwarded-For", &["[2001:db8::7]:4711"], "[2001:db8::7]:4711" ; "ip with port")]
+#[test_case("X-Forwarded-For", &["192.168.1.5, 10.0.0.2"], "192.168.1.5:0" ; "first ip if all are trusted")]
+#[test_case("Forwarded", &[r#"for=1.2.3.4;proto=https, for=10.0.0.2"#], "1.2.3.4:0" ; "forwarded")]
+#[test_case("Forwarded", &[r#"For="[2001:db8::7]:4711""#], "[2001:db8::7]:4711" ; "forwarded quoted ipv6")]
+pub fn resolve_should_return_first_untrusted_address_from_the_right(
+    header: &str,
+    values: &[&str],
+    expected_addr: &str,
+) {
+    // Arrange
+    let sut = RealIpResolver::new(header);
+    let headers = headers(header, values);
+    // Act
+    let client_addr = sut.resolve(&addr(PROXY), &headers);
+    // Assert
+    assert_eq!(Some(addr(expected_addr)), client_addr);
+}
+
+#[test]
+pub fn resolve_should_ignore_header_of_untrusted_peer() {
+    // Arrange
+    let sut = RealIpResolver::new("X-Forwarded-For");
+    let headers = headers("X-Forwarded-For", &["1.2.3.4"]);
+    // Act
+    let client_addr = sut.resolve(&addr("6.6.6.6:4000"), &headers);
+    // Assert
+    assert_eq!(Some(addr("6.6.6.6:4000")), client_addr);
+}
+
+#[test]
+pub fn resolve_should_only_trust_configured_proxies() {
+    // Arrange
+    let mut sut = RealIpResolver::new("X-Forwarded-For");
+    sut.set_trusted_proxies(vec!["10.0.0.1/32".parse().unwrap()]);
+    let headers = headers("X-Forwarded-For", &["1.2.3.4, 10.0.0.2"]);
+    // Act
+    let client_addr = sut.resolve(&addr(PROXY), &headers);
+    // Assert
+    assert_eq!(Some(addr("10.0.0.2:0")), client_addr);
+}
+
+#[test_case(&[] ; "missing header")]
+#[test_case(&["unknown, 10.0.0.2"] ; "unknown address")]
+#[test_case(&["not-an-ip"] ; "invalid address")]
+pub fn resolve_should_apply_fallback_without_trustworthy_address(values: &[&str]) {
+    // Arrange
+    let reject = RealIpResolver::new("X-Forwarded-For");
+    let mut peer_address = RealIpResolver::new("X-Forwarded-For");
+    peer_address.set_fallback(RealIpFallback::PeerAddress);
+    let headers = headers("X-Forwarded-For", values);
+    // Act
+    let rejected = reject.resolve(&addr(PROXY), &headers);
+    let fallen_back = peer_address.resolve(&addr(PROXY), &headers);
+    // Assert
+    assert_eq!(None, rejected);
+    assert_eq!(Some(addr(PROXY)), fallen_back);
+}
+
+#[test]
+pub fn resolve_should_apply_fallback_if_forwarded_element_has_no_for_parameter() {
+    // Arrange
+    let sut = RealIpResolver::new("Forwarded");
+    let headers = headers("Forwarded", &["proto=https;by=10.0.0.2"]);
+    // Act
+    let client_addr = sut.resolve(&addr(PROXY), &headers);
+    // Assert
+    assert_eq!(None, client_addr);
+}
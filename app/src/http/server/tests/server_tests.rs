@@ -1,23 +1,48 @@
 use crate::http::server::{
-    tests::{ChunkedHandler, HelloHandler, PanicHandler},
+    limits::{BacklogPolicy, ConnectionLimits},
+    tests::{
+        ChunkedHandler, EchoBodyHandler, EchoPathHandler, EchoUpgradeHandler, HelloHandler,
+        PanicHandler, SizedStreamHandler, SlowHandler, UnframedStreamHandler,
+    },
     *,
 };
 use pretty_assertions::assert_eq;
 use std::{
-    io::{Read, Write},
+    io::{self, BufRead, Read, Write},
     net::{self},
+    pin::Pin,
+    task::{Context, Poll},
     thread,
+    time::{Duration, Instant},
 };
+use test_case::test_case;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, DuplexStream, ReadBuf};
 
 fn setup(handler: Box<dyn TcpServe + Send + Sync + 'static>) -> (Server, net::SocketAddr) {
+    setup_with_settings(handler, ConnectionSettings::default())
+}
+
+fn setup_with_settings(
+    handler: Box<dyn TcpServe + Send + Sync + 'static>,
+    settings: ConnectionSettings,
+) -> (Server, net::SocketAddr) {
     let listener = net::TcpListener::bind("127.0.0.1:0").expect("Failed to open test socket");
-    listener.set_nonblocking(true).unwrap(); //Allows shutting down server as it changes polling
-                                             //from blocking (never getting shutdown signal) to
-                                             //busy loop
     let local_addr = listener
         .local_addr()
         .expect("Failed to retrieve address of test socket");
-    (Server::new(listener, handler), local_addr)
+    (
+        Server::with_settings(listener, handler, settings),
+        local_addr,
+    )
+}
+
+fn setup_with_limits(
+    handler: Box<dyn TcpServe + Send + Sync + 'static>,
+    limits: ConnectionLimits,
+) -> (Server, net::SocketAddr) {
+    let (mut server, local_addr) = setup_with_settings(handler, ConnectionSettings::default());
+    server.set_limits(limits);
+    (server, local_addr)
 }
 
 fn send_request(addr: net::SocketAddr, request: &[u8]) -> (String, net::SocketAddr) {
@@ -45,6 +70,7 @@ pub fn server_should_handle_request() {
     let request = concat!(
         "POST /hello HTTP/1.1\r\n",
         "Host: localhost\r\n",
+        "Connection: close\r\n",
         "Content-Length: 13\r\n",
         "\r\n",
         "Hey Server!\r\n"
@@ -52,7 +78,8 @@ pub fn server_should_handle_request() {
     .as_bytes();
     let (response, _) = send_request(addr, request);
     // Assert
-    let expected_response = "HTTP/1.1 200 OK\r\nContent-Length: 8\r\n\r\nHello!\r\n";
+    let expected_response =
+        "HTTP/1.1 200 OK\r\nContent-Length: 8\r\nConnection: close\r\n\r\nHello!\r\n";
     assert_eq!(expected_response, response);
     sender.send_shutdown().unwrap();
     server_thread.join().unwrap();
@@ -76,7 +103,29 @@ pub fn server_should_return_414_for_too_long_uri() {
     );
     let (response, _) = send_request(addr, request.as_bytes());
     // Assert
-    let expected_response = "HTTP/1.1 414 URI Too Long\r\n\r\n";
+    let expected_response =
+        "HTTP/1.1 414 URI Too Long\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+    assert_eq!(expected_response, response);
+    sender.send_shutdown().unwrap();
+    server_thread.join().unwrap();
+}
+
+#[test_case("GET /hello\r\nHost: localhost\r\n\r\n" ; "missing version")]
+#[test_case("GET  /hello HTTP/1.1\r\nHost: localhost\r\n\r\n" ; "double space")]
+#[test_case("GET /hello HTTP/1.1 \r\nHost: localhost\r\n\r\n" ; "trailing whitespace")]
+pub fn server_should_return_400_for_malformed_request_line(request: &str) {
+    // Arrange
+    let handler = Box::new(HelloHandler {
+        body: b"Hello!\r\n".into(),
+    });
+    let (mut sut, addr) = setup(handler);
+    let (sender, receiver) = cancellation_token::create_cancellation_token();
+    // Act
+    let server_thread = thread::spawn(move || sut.start_listening(receiver));
+    let (response, _) = send_request(addr, request.as_bytes());
+    // Assert
+    let expected_response =
+        "HTTP/1.1 400 Bad Request\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
     assert_eq!(expected_response, response);
     sender.send_shutdown().unwrap();
     server_thread.join().unwrap();
@@ -100,7 +149,31 @@ pub fn server_should_return_413_for_too_big_of_a_body() {
     );
     let (response, _) = send_request(addr, request.as_bytes());
     // Assert
-    let expected_response = "HTTP/1.1 413 Content Too Large\r\n\r\n";
+    let expected_response =
+        "HTTP/1.1 413 Content Too Large\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+    assert_eq!(expected_response, response);
+    sender.send_shutdown().unwrap();
+    server_thread.join().unwrap();
+}
+
+#[test]
+pub fn server_should_return_400_and_close_connection_for_ambiguous_body_length() {
+    // Arrange
+    let handler = Box::new(EchoPathHandler);
+    let (mut sut, addr) = setup(handler);
+    let (sender, receiver) = cancellation_token::create_cancellation_token();
+    // Act
+    let server_thread = thread::spawn(move || sut.start_listening(receiver));
+    let request = concat!(
+        "POST /first HTTP/1.1\r\nHost: localhost\r\n",
+        "Content-Length: 24\r\nTransfer-Encoding: chunked\r\n\r\n",
+        "0\r\n\r\n",
+        "GET /smuggled HTTP/1.1\r\nHost: localhost\r\n\r\n",
+    );
+    let (response, _) = send_request(addr, request.as_bytes());
+    // Assert
+    let expected_response =
+        "HTTP/1.1 400 Bad Request\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
     assert_eq!(expected_response, response);
     sender.send_shutdown().unwrap();
     server_thread.join().unwrap();
@@ -120,6 +193,7 @@ pub fn server_should_return_chunked_response() {
     let request = concat!(
         "POST /hello HTTP/1.1\r\n",
         "Host: localhost\r\n",
+        "Connection: close\r\n",
         "Content-Length: 13\r\n",
         "\r\n",
         "Hey Server!\r\n"
@@ -127,8 +201,175 @@ pub fn server_should_return_chunked_response() {
     .as_bytes();
     let (response, _) = send_request(addr, request);
     // Assert
+    let expected_response = format!(
+        "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\nConnection: close\r\n\r\n{chunked_body}"
+    );
+    assert_eq!(expected_response, response);
+    sender.send_shutdown().unwrap();
+    server_thread.join().unwrap();
+}
+
+#[test]
+pub fn server_should_stream_length_delimited_response_and_keep_connection() {
+    // Arrange
+    let handler = Box::new(SizedStreamHandler {
+        data: b"Hello, World!".into(),
+        content_length: 5,
+    });
+    let (mut sut, addr) = setup(handler);
+    let (sender, receiver) = cancellation_token::create_cancellation_token();
+    // Act
+    let server_thread = thread::spawn(move || sut.start_listening(receiver));
+    let requests = concat!(
+        "GET /first HTTP/1.1\r\nHost: localhost\r\n\r\n",
+        "GET /second HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+    );
+    let (response, _) = send_request(addr, requests.as_bytes());
+    // Assert
+    let expected_response = concat!(
+        "HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nHello",
+        "HTTP/1.1 200 OK\r\nContent-Length: 5\r\nConnection: close\r\n\r\nHello",
+    );
+    assert_eq!(expected_response, response);
+    sender.send_shutdown().unwrap();
+    server_thread.join().unwrap();
+}
+
+#[test_case(None, "" ; "no framing headers")]
+#[test_case(Some("gzip"), "Transfer-Encoding: gzip\r\n" ; "non-chunked transfer coding")]
+pub fn server_should_close_connection_after_response_without_length(
+    transfer_encoding: Option<&'static str>,
+    expected_framing: &str,
+) {
+    // Arrange
+    let handler = Box::new(UnframedStreamHandler {
+        data: b"Hello, World!".into(),
+        transfer_encoding,
+    });
+    let (mut sut, addr) = setup(handler);
+    let (sender, receiver) = cancellation_token::create_cancellation_token();
+    // Act
+    let server_thread = thread::spawn(move || sut.start_listening(receiver));
+    let requests = concat!(
+        "GET /first HTTP/1.1\r\nHost: localhost\r\n\r\n",
+        "GET /second HTTP/1.1\r\nHost: localhost\r\n\r\n",
+    );
+    let (response, _) = send_request(addr, requests.as_bytes());
+    // Assert
+    let expected_response =
+        format!("HTTP/1.1 200 OK\r\n{expected_framing}Connection: close\r\n\r\nHello, World!");
+    assert_eq!(expected_response, response);
+    sender.send_shutdown().unwrap();
+    server_thread.join().unwrap();
+}
+
+#[test]
+pub fn server_should_send_100_continue_before_reading_expected_body() {
+    // Arrange
+    let handler = Box::new(HelloHandler {
+        body: b"Hello!\r\n".into(),
+    });
+    let (mut sut, addr) = setup(handler);
+    let (sender, receiver) = cancellation_token::create_cancellation_token();
+    // Act
+    let server_thread = thread::spawn(move || sut.start_listening(receiver));
+    let request = concat!(
+        "POST /hello HTTP/1.1\r\n",
+        "Host: localhost\r\n",
+        "Connection: close\r\n",
+        "Expect: 100-continue\r\n",
+        "Content-Length: 13\r\n",
+        "\r\n",
+        "Hey Server!\r\n"
+    );
+    let (response, _) = send_request(addr, request.as_bytes());
+    // Assert
+    let expected_response = concat!(
+        "HTTP/1.1 100 Continue\r\n\r\n",
+        "HTTP/1.1 200 OK\r\nContent-Length: 8\r\nConnection: close\r\n\r\nHello!\r\n"
+    );
+    assert_eq!(expected_response, response);
+    sender.send_shutdown().unwrap();
+    server_thread.join().unwrap();
+}
+
+#[test]
+pub fn server_should_send_chunked_response_until_close_to_http_1_0_client() {
+    // Arrange
+    let chunked_body = "6\r\nHello!\r\n5\r\nChunk\r\n0\r\n\r\n";
+    let handler = Box::new(ChunkedHandler {
+        chunked_body: chunked_body.into(),
+    });
+    let (mut sut, addr) = setup(handler);
+    let (sender, receiver) = cancellation_token::create_cancellation_token();
+    // Act
+    let server_thread = thread::spawn(move || sut.start_listening(receiver));
+    let request = "GET /hello HTTP/1.0\r\nHost: localhost\r\nConnection: keep-alive\r\n\r\n";
+    let (response, _) = send_request(addr, request.as_bytes());
+    // Assert
+    let expected_response = "HTTP/1.1 200 OK\r\nConnection: close\r\n\r\nHello!Chunk";
+    assert_eq!(expected_response, response);
+    sender.send_shutdown().unwrap();
+    server_thread.join().unwrap();
+}
+
+#[test]
+pub fn server_should_close_http_1_0_connection_by_default() {
+    // Arrange
+    let handler = Box::new(EchoPathHandler);
+    let (mut sut, addr) = setup(handler);
+    let (sender, receiver) = cancellation_token::create_cancellation_token();
+    // Act
+    let server_thread = thread::spawn(move || sut.start_listening(receiver));
+    let requests = concat!(
+        "GET /first HTTP/1.0\r\nHost: localhost\r\n\r\n",
+        "GET /second HTTP/1.0\r\nHost: localhost\r\n\r\n",
+    );
+    let (response, _) = send_request(addr, requests.as_bytes());
+    // Assert
     let expected_response =
-        format!("HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n{chunked_body}");
+        "HTTP/1.1 200 OK\r\nContent-Length: 6\r\nConnection: close\r\n\r\n/first";
+    assert_eq!(expected_response, response);
+    sender.send_shutdown().unwrap();
+    server_thread.join().unwrap();
+}
+
+#[test]
+pub fn server_should_keep_http_1_0_connection_alive_if_requested() {
+    // Arrange
+    let handler = Box::new(EchoPathHandler);
+    let (mut sut, addr) = setup(handler);
+    let (sender, receiver) = cancellation_token::create_cancellation_token();
+    // Act
+    let server_thread = thread::spawn(move || sut.start_listening(receiver));
+    let requests = concat!(
+        "GET /first HTTP/1.0\r\nHost: localhost\r\nConnection: keep-alive\r\n\r\n",
+        "GET /second HTTP/1.0\r\nHost: localhost\r\n\r\n",
+    );
+    let (response, _) = send_request(addr, requests.as_bytes());
+    // Assert
+    let expected_response = concat!(
+        "HTTP/1.1 200 OK\r\nContent-Length: 6\r\nConnection: keep-alive\r\n\r\n/first",
+        "HTTP/1.1 200 OK\r\nContent-Length: 7\r\nConnection: close\r\n\r\n/second",
+    );
+    assert_eq!(expected_response, response);
+    sender.send_shutdown().unwrap();
+    server_thread.join().unwrap();
+}
+
+#[test]
+pub fn server_should_return_505_for_unsupported_http_version() {
+    // Arrange
+    let handler = Box::new(EchoPathHandler);
+    let (mut sut, addr) = setup(handler);
+    let (sender, receiver) = cancellation_token::create_cancellation_token();
+    // Act
+    let server_thread = thread::spawn(move || sut.start_listening(receiver));
+    let request = "GET / HTTP/2.0\r\nHost: localhost\r\n\r\n";
+    let (response, _) = send_request(addr, request.as_bytes());
+    // Assert
+    let expected_response =
+        "HTTP/1.1 505 HTTP Version Not Supported\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
     assert_eq!(expected_response, response);
     sender.send_shutdown().unwrap();
     server_thread.join().unwrap();
@@ -142,7 +383,7 @@ pub fn server_should_continue_running_when_serve_panics() {
     let (sender, receiver) = cancellation_token::create_cancellation_token();
     // Act
     let server_thread = thread::spawn(move || sut.start_listening(receiver));
-    let request = String::from("GET / HTTP/1.1\r\nHost: localhost\r\n\r\n");
+    let request = String::from("GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n");
     let request = request.as_bytes();
     let _ = send_request(addr, request);
     // Assert
@@ -152,3 +393,533 @@ pub fn server_should_continue_running_when_serve_panics() {
         Err(_) => panic!("Server died after encountering panic in handler!"),
     }
 }
+
+fn read_response(reader: &mut io::BufReader<net::TcpStream>) -> String {
+    let mut response = String::new();
+    let mut content_length = 0;
+    loop {
+        let mut line = String::new();
+        reader
+            .read_line(&mut line)
+            .expect("Failed to read response");
+        if let Some(value) = line.strip_prefix("Content-Length: ") {
+            content_length = value.trim().parse().unwrap();
+        }
+        response.push_str(&line);
+        if line == "\r\n" || line.is_empty() {
+            break;
+        }
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body).expect("Failed to read body");
+    response.push_str(&String::from_utf8(body).unwrap());
+    response
+}
+
+#[test]
+pub fn server_should_keep_connection_alive_for_multiple_requests() {
+    // Arrange
+    let handler = Box::new(HelloHandler {
+        body: b"Hello!\r\n".into(),
+    });
+    let (mut sut, addr) = setup(handler);
+    let (sender, receiver) = cancellation_token::create_cancellation_token();
+    // Act
+    let server_thread = thread::spawn(move || sut.start_listening(receiver));
+    let mut connection = net::TcpStream::connect(addr).expect("Failed to connect to test socket");
+    let mut reader = io::BufReader::new(connection.try_clone().unwrap());
+    let request = "GET / HTTP/1.1\r\nHost: localhost\r\n\r\n";
+    connection.write_all(request.as_bytes()).unwrap();
+    let first_response = read_response(&mut reader);
+    connection.write_all(request.as_bytes()).unwrap();
+    let second_response = read_response(&mut reader);
+    let closing_request = "GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n";
+    connection.write_all(closing_request.as_bytes()).unwrap();
+    let mut last_response = String::new();
+    reader.read_to_string(&mut last_response).unwrap();
+    // Assert
+    let expected_response = "HTTP/1.1 200 OK\r\nContent-Length: 8\r\n\r\nHello!\r\n";
+    assert_eq!(expected_response, first_response);
+    assert_eq!(expected_response, second_response);
+    let expected_last_response =
+        "HTTP/1.1 200 OK\r\nContent-Length: 8\r\nConnection: close\r\n\r\nHello!\r\n";
+    assert_eq!(expected_last_response, last_response);
+    sender.send_shutdown().unwrap();
+    server_thread.join().unwrap();
+}
+
+#[test]
+pub fn server_should_splice_connection_after_switching_protocols() {
+    // Arrange
+    let (mut sut, addr) = setup(Box::new(EchoUpgradeHandler));
+    let (sender, receiver) = cancellation_token::create_cancellation_token();
+    // Act
+    let server_thread = thread::spawn(move || sut.start_listening(receiver));
+    let mut connection = net::TcpStream::connect(addr).expect("Failed to connect to test socket");
+    let mut reader = io::BufReader::new(connection.try_clone().unwrap());
+    let request = concat!(
+        "GET /chat HTTP/1.1\r\n",
+        "Host: localhost\r\n",
+        "Connection: upgrade\r\n",
+        "Upgrade: echo\r\n",
+        "\r\n",
+        "Hello",
+    );
+    connection.write_all(request.as_bytes()).unwrap();
+    let response = read_response(&mut reader);
+    let mut first_echo = [0; 5];
+    reader.read_exact(&mut first_echo).unwrap();
+    connection.write_all(b"World").unwrap();
+    let mut second_echo = [0; 5];
+    reader.read_exact(&mut second_echo).unwrap();
+    drop(reader);
+    drop(connection);
+    // Assert
+    let expected_response =
+        "HTTP/1.1 101 Switching Protocols\r\nConnection: upgrade\r\nUpgrade: echo\r\n\r\n";
+    assert_eq!(expected_response, response);
+    assert_eq!(b"Hello", &first_echo);
+    assert_eq!(b"World", &second_echo);
+    sender.send_shutdown().unwrap();
+    server_thread.join().unwrap();
+}
+
+#[test]
+pub fn server_should_answer_pipelined_requests_in_order() {
+    // Arrange
+    let handler = Box::new(EchoPathHandler);
+    let (mut sut, addr) = setup(handler);
+    let (sender, receiver) = cancellation_token::create_cancellation_token();
+    // Act
+    let server_thread = thread::spawn(move || sut.start_listening(receiver));
+    let requests = concat!(
+        "POST /first HTTP/1.1\r\nHost: localhost\r\nContent-Length: 5\r\n\r\nHello",
+        "GET /second HTTP/1.1\r\nHost: localhost\r\n\r\n",
+        "GET /third HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+    );
+    let (response, _) = send_request(addr, requests.as_bytes());
+    // Assert
+    let expected_response = concat!(
+        "HTTP/1.1 200 OK\r\nContent-Length: 6\r\n\r\n/first",
+        "HTTP/1.1 200 OK\r\nContent-Length: 7\r\n\r\n/second",
+        "HTTP/1.1 200 OK\r\nContent-Length: 6\r\nConnection: close\r\n\r\n/third",
+    );
+    assert_eq!(expected_response, response);
+    sender.send_shutdown().unwrap();
+    server_thread.join().unwrap();
+}
+
+#[test]
+pub fn server_should_close_connection_after_max_requests() {
+    // Arrange
+    let handler = Box::new(EchoPathHandler);
+    let settings = ConnectionSettings {
+        max_requests: 2,
+        ..Default::default()
+    };
+    let (mut sut, addr) = setup_with_settings(handler, settings);
+    let (sender, receiver) = cancellation_token::create_cancellation_token();
+    // Act
+    let server_thread = thread::spawn(move || sut.start_listening(receiver));
+    let requests = concat!(
+        "GET /first HTTP/1.1\r\nHost: localhost\r\n\r\n",
+        "GET /second HTTP/1.1\r\nHost: localhost\r\n\r\n",
+        "GET /third HTTP/1.1\r\nHost: localhost\r\n\r\n",
+    );
+    let (response, _) = send_request(addr, requests.as_bytes());
+    // Assert
+    let expected_response = concat!(
+        "HTTP/1.1 200 OK\r\nContent-Length: 6\r\n\r\n/first",
+        "HTTP/1.1 200 OK\r\nContent-Length: 7\r\nConnection: close\r\n\r\n/second",
+    );
+    assert_eq!(expected_response, response);
+    sender.send_shutdown().unwrap();
+    server_thread.join().unwrap();
+}
+
+#[test]
+pub fn server_should_close_idle_connection_after_timeout() {
+    // Arrange
+    let handler = Box::new(EchoPathHandler);
+    let settings = ConnectionSettings {
+        idle_timeout: Duration::from_millis(100),
+        ..Default::default()
+    };
+    let (mut sut, addr) = setup_with_settings(handler, settings);
+    let (sender, receiver) = cancellation_token::create_cancellation_token();
+    // Act
+    let server_thread = thread::spawn(move || sut.start_listening(receiver));
+    let request = "GET /idle HTTP/1.1\r\nHost: localhost\r\n\r\n";
+    let started = Instant::now();
+    let (response, _) = send_request(addr, request.as_bytes());
+    // Assert
+    let expected_response = "HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\n/idle";
+    assert_eq!(expected_response, response);
+    assert!(
+        started.elapsed() < ConnectionSettings::IDLE_TIMEOUT_DEFAULT,
+        "Idle connection was not closed after idle timeout"
+    );
+    sender.send_shutdown().unwrap();
+    server_thread.join().unwrap();
+}
+
+/// Opens a connection and waits until the server had time to hand it to a worker
+fn occupy_connection(addr: net::SocketAddr) -> net::TcpStream {
+    let connection = net::TcpStream::connect(addr).expect("Failed to connect to test socket");
+    thread::sleep(Duration::from_millis(100));
+    connection
+}
+
+#[test]
+pub fn server_should_reject_connections_when_all_workers_are_busy() {
+    // Arrange
+    let handler = Box::new(EchoPathHandler);
+    let limits = ConnectionLimits {
+        max_connections: 1,
+        max_connections_per_ip: None,
+        backlog_policy: BacklogPolicy::Reject,
+    };
+    let (mut sut, addr) = setup_with_limits(handler, limits);
+    let (sender, receiver) = cancellation_token::create_cancellation_token();
+    // Act
+    let server_thread = thread::spawn(move || sut.start_listening(receiver));
+    let busy_connection = occupy_connection(addr);
+    let (response, _) = send_request(addr, b"");
+    // Assert
+    let expected_response =
+        "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+    assert_eq!(expected_response, response);
+    drop(busy_connection);
+    sender.send_shutdown().unwrap();
+    server_thread.join().unwrap();
+}
+
+#[test]
+pub fn server_should_reject_connections_exceeding_limit_per_ip() {
+    // Arrange
+    let handler = Box::new(EchoPathHandler);
+    let limits = ConnectionLimits {
+        max_connections: 4,
+        max_connections_per_ip: Some(1),
+        backlog_policy: BacklogPolicy::Reject,
+    };
+    let (mut sut, addr) = setup_with_limits(handler, limits);
+    let (sender, receiver) = cancellation_token::create_cancellation_token();
+    // Act
+    let server_thread = thread::spawn(move || sut.start_listening(receiver));
+    let busy_connection = occupy_connection(addr);
+    let (response, _) = send_request(addr, b"");
+    // Assert
+    let expected_response =
+        "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+    assert_eq!(expected_response, response);
+    drop(busy_connection);
+    sender.send_shutdown().unwrap();
+    server_thread.join().unwrap();
+}
+
+#[test]
+pub fn server_should_serve_queued_connection_once_worker_is_free() {
+    // Arrange
+    let handler = Box::new(EchoPathHandler);
+    let limits = ConnectionLimits {
+        max_connections: 1,
+        max_connections_per_ip: None,
+        backlog_policy: BacklogPolicy::Queue(1),
+    };
+    let (mut sut, addr) = setup_with_limits(handler, limits);
+    let (sender, receiver) = cancellation_token::create_cancellation_token();
+    // Act
+    let server_thread = thread::spawn(move || sut.start_listening(receiver));
+    let mut busy_connection = occupy_connection(addr);
+    let queued_request = "GET /queued HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n";
+    let queued_thread = thread::spawn(move || send_request(addr, queued_request.as_bytes()).0);
+    thread::sleep(Duration::from_millis(100));
+    let busy_request = "GET /busy HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n";
+    busy_connection.write_all(busy_request.as_bytes()).unwrap();
+    let mut busy_response = String::new();
+    busy_connection.read_to_string(&mut busy_response).unwrap();
+    let queued_response = queued_thread.join().unwrap();
+    // Assert
+    let expected_busy_response =
+        "HTTP/1.1 200 OK\r\nContent-Length: 5\r\nConnection: close\r\n\r\n/busy";
+    assert_eq!(expected_busy_response, busy_response);
+    let expected_queued_response =
+        "HTTP/1.1 200 OK\r\nContent-Length: 7\r\nConnection: close\r\n\r\n/queued";
+    assert_eq!(expected_queued_response, queued_response);
+    sender.send_shutdown().unwrap();
+    server_thread.join().unwrap();
+}
+
+#[test]
+pub fn server_should_return_408_when_client_trickles_headers() {
+    // Arrange
+    let handler = Box::new(EchoPathHandler);
+    let settings = ConnectionSettings {
+        header_read_timeout: Duration::from_millis(300),
+        ..Default::default()
+    };
+    let (mut sut, addr) = setup_with_settings(handler, settings);
+    let (sender, receiver) = cancellation_token::create_cancellation_token();
+    // Act
+    let server_thread = thread::spawn(move || sut.start_listening(receiver));
+    let mut connection = net::TcpStream::connect(addr).expect("Failed to connect to test socket");
+    let started = Instant::now();
+    for byte in b"GET /slow HTTP/1.1\r\nHost: localhost\r\n" {
+        // Every byte arrives well within a plain read timeout
+        if connection.write_all(&[*byte]).is_err() {
+            break;
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+    let mut response = String::new();
+    connection.read_to_string(&mut response).unwrap();
+    // Assert
+    let expected_response =
+        "HTTP/1.1 408 Request Timeout\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+    assert_eq!(expected_response, response);
+    assert!(started.elapsed() < Duration::from_secs(1));
+    sender.send_shutdown().unwrap();
+    server_thread.join().unwrap();
+}
+
+#[test]
+pub fn server_should_return_408_when_body_is_not_sent_in_time() {
+    // Arrange
+    let handler = Box::new(EchoPathHandler);
+    let settings = ConnectionSettings {
+        body_read_timeout: Duration::from_millis(100),
+        ..Default::default()
+    };
+    let (mut sut, addr) = setup_with_settings(handler, settings);
+    let (sender, receiver) = cancellation_token::create_cancellation_token();
+    // Act
+    let server_thread = thread::spawn(move || sut.start_listening(receiver));
+    let mut connection = net::TcpStream::connect(addr).expect("Failed to connect to test socket");
+    let request = "POST /slow HTTP/1.1\r\nHost: localhost\r\nContent-Length: 10\r\n\r\nHey";
+    connection.write_all(request.as_bytes()).unwrap();
+    let mut response = String::new();
+    connection.read_to_string(&mut response).unwrap();
+    // Assert
+    let expected_response =
+        "HTTP/1.1 408 Request Timeout\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+    assert_eq!(expected_response, response);
+    sender.send_shutdown().unwrap();
+    server_thread.join().unwrap();
+}
+
+#[test_case("Content-Length: 10\r\n\r\n", "HelloWorld", "HelloWorld" ; "length delimited")]
+#[test_case("Transfer-Encoding: chunked\r\n\r\n", "5\r\nHello\r\n0\r\n\r\n", "5\r\nHello\r\n0\r\n\r\n" ; "chunked")]
+pub fn server_should_keep_reading_slow_body_while_client_sends_data(
+    framing: &str,
+    body: &str,
+    expected_body: &str,
+) {
+    // Arrange
+    let handler = Box::new(EchoBodyHandler);
+    let settings = ConnectionSettings {
+        body_read_timeout: Duration::from_millis(200),
+        ..Default::default()
+    };
+    let (mut sut, addr) = setup_with_settings(handler, settings);
+    let (sender, receiver) = cancellation_token::create_cancellation_token();
+    // Act
+    let server_thread = thread::spawn(move || sut.start_listening(receiver));
+    let mut connection = net::TcpStream::connect(addr).expect("Failed to connect to test socket");
+    let head = format!("POST /slow HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n{framing}");
+    connection.write_all(head.as_bytes()).unwrap();
+    for byte in body.as_bytes() {
+        // Sending the whole body takes longer than the timeout, every byte arrives within it
+        thread::sleep(Duration::from_millis(50));
+        connection.write_all(&[*byte]).unwrap();
+    }
+    let mut response = String::new();
+    connection.read_to_string(&mut response).unwrap();
+    // Assert
+    let expected_response = format!(
+        "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{expected_body}",
+        expected_body.len()
+    );
+    assert_eq!(expected_response, response);
+    sender.send_shutdown().unwrap();
+    server_thread.join().unwrap();
+}
+
+#[test]
+pub fn server_should_stop_listening_right_after_cancellation() {
+    // Arrange
+    let handler = Box::new(EchoPathHandler);
+    let (mut sut, _) = setup(handler);
+    let (sender, receiver) = cancellation_token::create_cancellation_token();
+    let server_thread = thread::spawn(move || sut.start_listening(receiver));
+    thread::sleep(Duration::from_millis(100));
+    // Act
+    let started = Instant::now();
+    sender.send_shutdown().unwrap();
+    server_thread.join().unwrap();
+    // Assert
+    assert!(
+        started.elapsed() < Duration::from_millis(100),
+        "Server did not wake up on cancellation"
+    );
+}
+
+#[test]
+pub fn server_should_finish_in_flight_request_on_shutdown() {
+    // Arrange
+    let handler = Box::new(SlowHandler {
+        delay: Duration::from_millis(300),
+    });
+    let (mut sut, addr) = setup(handler);
+    let (sender, receiver) = cancellation_token::create_cancellation_token();
+    // Act
+    let server_thread = thread::spawn(move || sut.start_listening(receiver));
+    let request = "GET /in-flight HTTP/1.1\r\nHost: localhost\r\n\r\n";
+    let client_thread = thread::spawn(move || send_request(addr, request.as_bytes()).0);
+    thread::sleep(Duration::from_millis(100));
+    sender.send_shutdown().unwrap();
+    let response = client_thread.join().unwrap();
+    server_thread.join().unwrap();
+    // Assert
+    let expected_response =
+        "HTTP/1.1 200 OK\r\nContent-Length: 10\r\nConnection: close\r\n\r\n/in-flight";
+    assert_eq!(expected_response, response);
+}
+
+#[test]
+pub fn server_should_close_idle_connections_on_shutdown() {
+    // Arrange
+    let handler = Box::new(EchoPathHandler);
+    let (mut sut, addr) = setup(handler);
+    let (sender, receiver) = cancellation_token::create_cancellation_token();
+    // Act
+    let server_thread = thread::spawn(move || sut.start_listening(receiver));
+    let mut connection = net::TcpStream::connect(addr).expect("Failed to connect to test socket");
+    let request = "GET /idle HTTP/1.1\r\nHost: localhost\r\n\r\n";
+    connection.write_all(request.as_bytes()).unwrap();
+    thread::sleep(Duration::from_millis(100));
+    let started = Instant::now();
+    sender.send_shutdown().unwrap();
+    let mut response = String::new();
+    connection.read_to_string(&mut response).unwrap();
+    server_thread.join().unwrap();
+    // Assert
+    let expected_response = "HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\n/idle";
+    assert_eq!(expected_response, response);
+    assert!(
+        started.elapsed() < ConnectionSettings::IDLE_TIMEOUT_DEFAULT,
+        "Idle connection kept the server from shutting down"
+    );
+}
+
+#[test]
+pub fn server_should_abort_connections_exceeding_shutdown_timeout() {
+    // Arrange
+    let handler = Box::new(SlowHandler {
+        delay: Duration::from_secs(10),
+    });
+    let (mut sut, addr) = setup(handler);
+    sut.set_shutdown_timeout(Duration::from_millis(100));
+    let (sender, receiver) = cancellation_token::create_cancellation_token();
+    // Act
+    let server_thread = thread::spawn(move || sut.start_listening(receiver));
+    let mut connection = net::TcpStream::connect(addr).expect("Failed to connect to test socket");
+    let request = "GET /stuck HTTP/1.1\r\nHost: localhost\r\n\r\n";
+    connection.write_all(request.as_bytes()).unwrap();
+    thread::sleep(Duration::from_millis(100));
+    let started = Instant::now();
+    sender.send_shutdown().unwrap();
+    server_thread.join().unwrap();
+    let mut response = String::new();
+    let _ = connection.read_to_string(&mut response);
+    // Assert
+    assert!(
+        started.elapsed() < Duration::from_secs(1),
+        "Server waited for connection longer than the shutdown timeout"
+    );
+    assert_eq!("", response);
+}
+
+#[test]
+pub fn server_should_stream_chunked_request_body_to_handler() {
+    // Arrange
+    let handler = Box::new(EchoBodyHandler);
+    let (mut sut, addr) = setup(handler);
+    let (sender, receiver) = cancellation_token::create_cancellation_token();
+    // Act
+    let server_thread = thread::spawn(move || sut.start_listening(receiver));
+    let request = concat!(
+        "POST /upload HTTP/1.1\r\n",
+        "Host: localhost\r\n",
+        "Transfer-Encoding: chunked\r\n",
+        "\r\n",
+        "5\r\nHello\r\n",
+        "0\r\n\r\n",
+    );
+    let (response, _) = send_request(addr, request.as_bytes());
+    // Assert
+    let expected_response =
+        "HTTP/1.1 200 OK\r\nContent-Length: 15\r\nConnection: close\r\n\r\n5\r\nHello\r\n0\r\n\r\n";
+    assert_eq!(expected_response, response);
+    sender.send_shutdown().unwrap();
+    server_thread.join().unwrap();
+}
+
+/// Connection of a client that disconnected before it was served
+struct DisconnectedConnection(DuplexStream);
+impl Connection for DisconnectedConnection {
+    fn peer_addr(&self) -> io::Result<net::SocketAddr> {
+        Err(io::ErrorKind::NotConnected.into())
+    }
+
+    fn is_secure(&self) -> bool {
+        false
+    }
+}
+impl AsyncRead for DisconnectedConnection {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_read(cx, buf)
+    }
+}
+impl AsyncWrite for DisconnectedConnection {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.0).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_shutdown(cx)
+    }
+}
+
+#[tokio::test]
+pub async fn serve_tcp_should_close_connection_of_disconnected_client() {
+    // Arrange
+    let sut = HelloHandler {
+        body: b"Hello!\r\n".into(),
+    };
+    let (mut client, connection) = tokio::io::duplex(64);
+    let (_sender, receiver) = cancellation_token::create_cancellation_token();
+    // Act
+    sut.serve_tcp(
+        DisconnectedConnection(connection),
+        &ConnectionSettings::default(),
+        receiver,
+    )
+    .await;
+    // Assert
+    let mut response = Vec::new();
+    client.read_to_end(&mut response).await.unwrap();
+    assert!(response.is_empty(), "Unexpected response: {response:?}");
+}
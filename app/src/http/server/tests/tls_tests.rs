@@ -0,0 +1,215 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use pretty_assertions::assert_eq;
+use test_case::test_case;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+use tokio_rustls::{
+    client::TlsStream,
+    rustls::{
+        self,
+        pki_types::{pem::PemObject, CertificateDer, ServerName},
+    },
+    TlsConnector,
+};
+
+use crate::http::server::{
+    cancellation_token,
+    tests::HelloHandler,
+    tls::{CertificateFiles, CertificateStore, TlsAcceptor},
+    Server,
+};
+
+fn cert_path(file: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("src/http/server/tests/certs")
+        .join(file)
+}
+
+fn certificate_files(name: &str) -> CertificateFiles {
+    CertificateFiles::new(
+        cert_path(&format!("{name}.pem")),
+        cert_path(&format!("{name}.key")),
+    )
+}
+
+fn read_certificate(path: &Path) -> CertificateDer<'static> {
+    CertificateDer::from_pem_file(path).expect("Failed to read test certificate")
+}
+
+/// Copies the certificate files of `name` into a new directory, so they can be changed
+fn copy_certificate_files(name: &str, dir: &str) -> CertificateFiles {
+    let dir = std::env::temp_dir().join(format!("tollkeeper-{dir}-{}", std::process::id()));
+    fs::create_dir_all(&dir).expect("Failed to create test directory");
+    let files = CertificateFiles::new(dir.join("cert.pem"), dir.join("cert.key"));
+    overwrite_certificate_files(name, &files);
+    files
+}
+
+fn overwrite_certificate_files(name: &str, files: &CertificateFiles) {
+    let source = certificate_files(name);
+    fs::copy(source.certificate, &files.certificate).expect("Failed to copy test certificate");
+    fs::copy(source.key, &files.key).expect("Failed to copy test key");
+}
+
+async fn connect(addr: std::net::SocketAddr, server_name: &str) -> TlsStream<TcpStream> {
+    let mut roots = rustls::RootCertStore::empty();
+    roots
+        .add(read_certificate(&cert_path("ca.pem")))
+        .expect("Failed to trust test CA");
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let config = rustls::ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    let connector = TlsConnector::from(Arc::new(config));
+    let stream = TcpStream::connect(addr)
+        .await
+        .expect("Failed to connect to test socket");
+    let server_name = ServerName::try_from(server_name.to_string()).unwrap();
+    connector
+        .connect(server_name, stream)
+        .await
+        .expect("TLS handshake failed")
+}
+
+/// Performs a handshake asking for `server_name` and returns the certificate presented
+async fn handshake(acceptor: &TlsAcceptor, server_name: &str) -> CertificateDer<'static> {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let acceptor = acceptor.clone();
+    let server = tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        acceptor.accept(stream).await
+    });
+    let client = connect(addr, server_name).await;
+    server.await.unwrap().expect("Server failed TLS handshake");
+    let (_, connection) = client.get_ref();
+    connection.peer_certificates().unwrap()[0]
+        .clone()
+        .into_owned()
+}
+
+#[test_case("localhost", "localhost.pem" ; "default certificate")]
+#[test_case("example.com", "example.com.pem" ; "certificate of server name")]
+#[test_case("EXAMPLE.com", "example.com.pem" ; "case insensitive server name")]
+#[tokio::test]
+pub async fn tls_acceptor_should_select_certificate_by_server_name(
+    server_name: &str,
+    expected_certificate: &str,
+) {
+    // Arrange
+    let mut certificates = CertificateStore::new(Some(certificate_files("localhost"))).unwrap();
+    certificates
+        .add_server_name("example.com", certificate_files("example.com"))
+        .unwrap();
+    let sut = TlsAcceptor::new(certificates).unwrap();
+    // Act
+    let certificate = handshake(&sut, server_name).await;
+    // Assert
+    let expected_certificate = read_certificate(&cert_path(expected_certificate));
+    assert_eq!(expected_certificate, certificate);
+}
+
+#[tokio::test]
+pub async fn tls_acceptor_should_reload_changed_certificate() {
+    // Arrange
+    let files = copy_certificate_files("localhost", "reload");
+    let certificates = CertificateStore::new(Some(files.clone())).unwrap();
+    let sut = TlsAcceptor::new(certificates).unwrap();
+    let (sender, receiver) = cancellation_token::create_cancellation_token();
+    let reload = sut.clone();
+    let reload = tokio::spawn(async move {
+        reload
+            .reload_on_change(Duration::from_millis(10), receiver)
+            .await
+    });
+    // Act
+    overwrite_certificate_files("example.com", &files);
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    let certificate = handshake(&sut, "example.com").await;
+    // Assert
+    sender.send_shutdown().unwrap();
+    reload.await.unwrap();
+    let expected_certificate = read_certificate(&cert_path("example.com.pem"));
+    assert_eq!(expected_certificate, certificate);
+}
+
+#[tokio::test]
+pub async fn tls_acceptor_should_keep_certificate_if_changed_files_are_invalid() {
+    // Arrange
+    let files = copy_certificate_files("localhost", "invalid-reload");
+    let certificates = CertificateStore::new(Some(files.clone())).unwrap();
+    let sut = TlsAcceptor::new(certificates).unwrap();
+    let (sender, receiver) = cancellation_token::create_cancellation_token();
+    let reload = sut.clone();
+    let reload = tokio::spawn(async move {
+        reload
+            .reload_on_change(Duration::from_millis(10), receiver)
+            .await
+    });
+    // Act
+    fs::write(&files.certificate, "not a certificate").unwrap();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    let certificate = handshake(&sut, "localhost").await;
+    // Assert
+    sender.send_shutdown().unwrap();
+    reload.await.unwrap();
+    let expected_certificate = read_certificate(&cert_path("localhost.pem"));
+    assert_eq!(expected_certificate, certificate);
+}
+
+#[test]
+pub fn certificate_store_should_fail_for_missing_files() {
+    // Arrange
+    let files = certificate_files("missing");
+    // Act
+    let certificates = CertificateStore::new(Some(files));
+    // Assert
+    assert!(certificates.is_err(), "Missing certificate was loaded");
+}
+
+#[tokio::test]
+pub async fn server_should_handle_request_over_tls() {
+    // Arrange
+    let handler = Box::new(HelloHandler {
+        body: b"Hello!\r\n".into(),
+    });
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let mut sut = Server::new(listener, handler);
+    let certificates = CertificateStore::new(Some(certificate_files("localhost"))).unwrap();
+    sut.set_tls(TlsAcceptor::new(certificates).unwrap());
+    let (sender, receiver) = cancellation_token::create_cancellation_token();
+    let server = tokio::spawn(async move { sut.listen(receiver).await });
+    // Act
+    let mut client = connect(addr, "localhost").await;
+    let request = concat!(
+        "GET /hello HTTP/1.1\r\n",
+        "Host: localhost\r\n",
+        "Connection: close\r\n",
+        "\r\n",
+    );
+    client.write_all(request.as_bytes()).await.unwrap();
+    let mut response = String::new();
+    _ = client.read_to_string(&mut response).await;
+    // Assert
+    sender.send_shutdown().unwrap();
+    server.await.unwrap();
+    let expected_response = concat!(
+        "HTTP/1.1 200 OK\r\n",
+        "Content-Length: 8\r\n",
+        "Connection: close\r\n",
+        "\r\n",
+        "Hello!\r\n",
+    );
+    assert_eq!(expected_response, response);
+}
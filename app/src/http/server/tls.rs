@@ -0,0 +1,216 @@
+use std::{
+    error::Error,
+    fmt::{self, Display},
+    io,
+    path::PathBuf,
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime},
+};
+
+use indexmap::IndexMap;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_rustls::rustls::{
+    self,
+    crypto::CryptoProvider,
+    pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer},
+    server::{ClientHello, ResolvesServerCert},
+    sign::CertifiedKey,
+};
+
+use super::cancellation_token::CancelReceiver;
+
+/// Interval in which certificate files are checked for changes
+pub const RELOAD_INTERVAL: Duration = Duration::from_secs(10);
+
+/// PEM encoded certificate chain and private key
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct CertificateFiles {
+    pub certificate: PathBuf,
+    pub key: PathBuf,
+}
+impl CertificateFiles {
+    pub fn new(certificate: impl Into<PathBuf>, key: impl Into<PathBuf>) -> Self {
+        Self {
+            certificate: certificate.into(),
+            key: key.into(),
+        }
+    }
+
+    fn load(&self, provider: &CryptoProvider) -> Result<CertifiedKey, TlsError> {
+        let certificates = CertificateDer::pem_file_iter(&self.certificate)
+            .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+            .map_err(|e| TlsError::InvalidCertificate(self.certificate.clone(), e.to_string()))?;
+        if certificates.is_empty() {
+            let error = "no certificate found".to_string();
+            return Err(TlsError::InvalidCertificate(
+                self.certificate.clone(),
+                error,
+            ));
+        }
+        let key = PrivateKeyDer::from_pem_file(&self.key)
+            .map_err(|e| TlsError::InvalidKey(self.key.clone(), e.to_string()))?;
+        CertifiedKey::from_der(certificates, key, provider)
+            .map_err(|e| TlsError::InvalidKey(self.key.clone(), e.to_string()))
+    }
+
+    fn modified(&self) -> Option<SystemTime> {
+        let certificate = std::fs::metadata(&self.certificate).ok()?.modified().ok()?;
+        let key = std::fs::metadata(&self.key).ok()?.modified().ok()?;
+        Some(certificate.max(key))
+    }
+}
+
+/// Certificate loaded from [CertificateFiles], replaced once the files change
+#[derive(Debug)]
+struct LoadedCertificate {
+    files: CertificateFiles,
+    key: RwLock<(Arc<CertifiedKey>, Option<SystemTime>)>,
+}
+impl LoadedCertificate {
+    fn load(files: CertificateFiles, provider: &CryptoProvider) -> Result<Self, TlsError> {
+        let modified = files.modified();
+        let key = files.load(provider)?;
+        Ok(Self {
+            files,
+            key: RwLock::new((Arc::new(key), modified)),
+        })
+    }
+
+    fn key(&self) -> Arc<CertifiedKey> {
+        self.key.read().unwrap().0.clone()
+    }
+
+    /// Loads the files again if they changed. Keeps the current certificate if they are invalid
+    fn reload_if_changed(&self, provider: &CryptoProvider) {
+        let modified = self.files.modified();
+        if modified == self.key.read().unwrap().1 {
+            return;
+        }
+        match self.files.load(provider) {
+            Ok(key) => {
+                tracing::info!("Reloaded certificate {}", self.files.certificate.display());
+                *self.key.write().unwrap() = (Arc::new(key), modified);
+            }
+            Err(e) => tracing::error!("Keeping current certificate: {e}"),
+        }
+    }
+}
+
+/// Selects the certificate by the server name the client asks for (SNI), falling back to the
+/// default certificate
+#[derive(Debug)]
+pub struct CertificateStore {
+    provider: Arc<CryptoProvider>,
+    default: Option<LoadedCertificate>,
+    server_names: IndexMap<String, LoadedCertificate>,
+}
+impl CertificateStore {
+    pub fn new(default: Option<CertificateFiles>) -> Result<Self, TlsError> {
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let default = match default {
+            Some(files) => Some(LoadedCertificate::load(files, &provider)?),
+            None => None,
+        };
+        Ok(Self {
+            provider,
+            default,
+            server_names: IndexMap::new(),
+        })
+    }
+
+    /// Uses the certificate for connections to `server_name`
+    pub fn add_server_name(
+        &mut self,
+        server_name: impl Into<String>,
+        files: CertificateFiles,
+    ) -> Result<(), TlsError> {
+        let certificate = LoadedCertificate::load(files, &self.provider)?;
+        let server_name = server_name.into().to_ascii_lowercase();
+        self.server_names.insert(server_name, certificate);
+        Ok(())
+    }
+
+    /// Reloads the certificates whose files changed since they were loaded
+    pub fn reload_changed(&self) {
+        let certificates = self.default.iter().chain(self.server_names.values());
+        for certificate in certificates {
+            certificate.reload_if_changed(&self.provider);
+        }
+    }
+}
+impl ResolvesServerCert for CertificateStore {
+    fn resolve(&self, client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        let certificate = client_hello
+            .server_name()
+            .and_then(|name| self.server_names.get(&name.to_ascii_lowercase()))
+            .or(self.default.as_ref());
+        if certificate.is_none() {
+            tracing::debug!(
+                "No certificate for server name {:?}",
+                client_hello.server_name()
+            );
+        }
+        certificate.map(|c| c.key())
+    }
+}
+
+/// Performs the server side of TLS handshakes
+#[derive(Clone)]
+pub struct TlsAcceptor {
+    acceptor: tokio_rustls::TlsAcceptor,
+    certificates: Arc<CertificateStore>,
+}
+impl TlsAcceptor {
+    pub fn new(certificates: CertificateStore) -> Result<Self, TlsError> {
+        let certificates = Arc::new(certificates);
+        let mut config = rustls::ServerConfig::builder_with_provider(certificates.provider.clone())
+            .with_safe_default_protocol_versions()
+            .map_err(|e| TlsError::Config(e.to_string()))?
+            .with_no_client_auth()
+            .with_cert_resolver(certificates.clone());
+        config.alpn_protocols = vec![b"http/1.1".to_vec()];
+        let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(config));
+        Ok(Self {
+            acceptor,
+            certificates,
+        })
+    }
+
+    pub async fn accept<S: AsyncRead + AsyncWrite + Unpin>(
+        &self,
+        stream: S,
+    ) -> io::Result<tokio_rustls::server::TlsStream<S>> {
+        self.acceptor.accept(stream).await
+    }
+
+    /// Reloads changed certificates every `interval` until cancelled
+    pub async fn reload_on_change(&self, interval: Duration, mut cancel_receiver: CancelReceiver) {
+        loop {
+            tokio::select! {
+                _ = tokio::time::sleep(interval) => self.certificates.reload_changed(),
+                _ = cancel_receiver.cancelled() => return,
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum TlsError {
+    InvalidCertificate(PathBuf, String),
+    InvalidKey(PathBuf, String),
+    Config(String),
+}
+impl Error for TlsError {}
+impl Display for TlsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TlsError::InvalidCertificate(path, e) => {
+                write!(f, "Invalid certificate {}: {e}", path.display())
+            }
+            TlsError::InvalidKey(path, e) => {
+                write!(f, "Invalid private key {}: {e}", path.display())
+            }
+            TlsError::Config(e) => write!(f, "Invalid TLS configuration: {e}"),
+        }
+    }
+}
@@ -31,3 +31,42 @@ pub fn get_by_key_should_be_case_insensitive(key: &str) {
     assert!(result.is_some(), "Header not found!");
     assert_eq!("bob", result.unwrap());
 }
+
+#[test]
+pub fn remove_hop_by_hop_should_remove_connection_headers() {
+    // Arrange
+    let mut sut = Headers::new(vec![
+        ("Host".into(), "localhost".into()),
+        ("Connection".into(), "keep-alive, X-Secret".into()),
+        ("Keep-Alive".into(), "timeout=5".into()),
+        ("TE".into(), "trailers".into()),
+        ("Upgrade".into(), "websocket".into()),
+        ("Proxy-Connection".into(), "keep-alive".into()),
+        ("x-secret".into(), "hunter2".into()),
+        ("Accept".into(), "*/*".into()),
+    ]);
+    // Act
+    sut.remove_hop_by_hop();
+    // Assert
+    assert_eq!("Host: localhost\r\nAccept: */*\r\n", sut.to_string());
+}
+
+#[test]
+pub fn remove_hop_by_hop_should_keep_framing_headers_listed_in_connection() {
+    // Arrange
+    let mut sut = Headers::new(vec![
+        ("Host".into(), "localhost".into()),
+        (
+            "Connection".into(),
+            "Content-Length, Transfer-Encoding, Host".into(),
+        ),
+        ("Transfer-Encoding".into(), "chunked".into()),
+    ]);
+    // Act
+    sut.remove_hop_by_hop();
+    // Assert
+    assert_eq!(
+        "Host: localhost\r\nTransfer-Encoding: chunked\r\n",
+        sut.to_string()
+    );
+}
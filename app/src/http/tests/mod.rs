@@ -1,2 +1,3 @@
 mod headers_tests;
 mod stream_body_tests;
+mod upgrade_tests;
@@ -1,13 +1,10 @@
 use pretty_assertions::assert_eq;
-use std::{
-    collections::VecDeque,
-    io::{self, BufReader},
-};
+use std::io::{self, Cursor};
 
 use crate::http::{Chunk, ChunkedTcpStream, StreamBody};
 
-#[test]
-pub fn read_should_return_unexpected_eof_if_chunk_exceeds_size_limit() {
+#[tokio::test]
+pub async fn read_should_return_unexpected_eof_if_chunk_exceeds_size_limit() {
     // Arrange
     let oversized_chunk_data: String = vec!['a'; Chunk::MAX_CHUNK_SIZE + 1].iter().collect();
     let stream = format!(
@@ -15,13 +12,33 @@ pub fn read_should_return_unexpected_eof_if_chunk_exceeds_size_limit() {
         oversized_chunk_data.len(),
         oversized_chunk_data
     );
-    let stream: VecDeque<u8> = stream.into_bytes().into();
-    let stream = BufReader::new(stream);
+    let stream = Cursor::new(stream.into_bytes());
     let stream = ChunkedTcpStream::new(Box::new(stream));
     let mut sut = StreamBody::new(Box::new(stream));
     // Act
     let mut buf = Vec::new();
-    let res = io::copy(&mut sut, &mut buf).expect_err("wrote too big chunk to target");
+    let res = sut
+        .copy_to(&mut buf)
+        .await
+        .expect_err("wrote too big chunk to target");
     // Assert
     assert_eq!(io::ErrorKind::QuotaExceeded, res.kind())
 }
+
+#[tokio::test]
+pub async fn read_should_return_error_if_chunks_exceed_max_size() {
+    // Arrange
+    let stream = "5\r\nHello\r\n5\r\nWorld\r\n0\r\n\r\n";
+    let stream = Cursor::new(stream.as_bytes().to_vec());
+    let stream = ChunkedTcpStream::with_max_size(stream, 8);
+    let mut sut = StreamBody::new(Box::new(stream));
+    // Act
+    let mut buf = Vec::new();
+    let res = sut
+        .copy_to(&mut buf)
+        .await
+        .expect_err("wrote body exceeding max size to target");
+    // Assert
+    assert_eq!(io::ErrorKind::QuotaExceeded, res.kind());
+    assert_eq!(b"5\r\nHello\r\n", buf.as_slice());
+}
@@ -0,0 +1,35 @@
+use pretty_assertions::assert_eq;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use crate::http::Upgrade;
+
+#[tokio::test]
+pub async fn splice_should_keep_forwarding_after_client_closed_writing() {
+    // Arrange
+    let (upstream, upgraded) = tokio::io::duplex(64);
+    let (upgraded_reader, upgraded_writer) = tokio::io::split(upgraded);
+    let sut = Upgrade::new(Box::new(upgraded_reader), Box::new(upgraded_writer));
+    let upstream = tokio::spawn(async move {
+        let (mut reader, mut writer) = tokio::io::split(upstream);
+        let mut request = Vec::new();
+        reader.read_to_end(&mut request).await.unwrap();
+        writer.write_all(b"World").await.unwrap();
+        writer.shutdown().await.unwrap();
+        request
+    });
+    let (client, connection) = tokio::io::duplex(64);
+    // Act
+    let splice = tokio::spawn(async move {
+        let (mut reader, mut writer) = tokio::io::split(connection);
+        sut.splice(&mut reader, &mut writer).await
+    });
+    let (mut client_reader, mut client_writer) = tokio::io::split(client);
+    client_writer.write_all(b"Hello").await.unwrap();
+    client_writer.shutdown().await.unwrap();
+    let mut response = Vec::new();
+    client_reader.read_to_end(&mut response).await.unwrap();
+    // Assert
+    splice.await.unwrap().expect("Splicing failed");
+    assert_eq!(b"Hello".to_vec(), upstream.await.unwrap());
+    assert_eq!(b"World".to_vec(), response);
+}
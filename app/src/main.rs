@@ -5,9 +5,9 @@ use std::{
     path::{Path, PathBuf},
     str::FromStr,
     sync::Arc,
-    thread,
 };
 use tollkeeper::Tollkeeper;
+use tracing::Instrument;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 use crate::{
@@ -23,43 +23,137 @@ mod payment;
 mod proxy;
 mod templates;
 
-fn main() -> Result<(), io::Error> {
+#[tokio::main]
+async fn main() -> Result<(), io::Error> {
     setup_logging();
-    thread::scope(|s| {
-        let config = read_config();
-        let tollkeeper = Arc::new(
-            config
-                .create_tollkeeper()
-                .expect("Failed to create tollkeeper"),
-        );
-        let url_resolver = Box::new(config.create_url_resolver());
-        let proxy_tollkeeper = tollkeeper.clone();
-        let proxy_config = config.api.clone();
-        let server_config = config.server();
-        let proxy_port = server_config.proxy_port();
-        s.spawn(move || {
-            let _span = tracing::debug_span!("[Proxy]").entered();
-            tracing::info!("Startup on Port {proxy_port}");
-            let (mut proxy_server, proxy_server_cancellation) =
-                create_proxy_server(proxy_port, proxy_config, proxy_tollkeeper, url_resolver)
-                    .expect("Error during startup (proxy)");
-            proxy_server.start_listening(proxy_server_cancellation);
-        });
-        let api_tollkeeper = tollkeeper.clone();
-        let api_config = config.api.clone();
-        let api_port = server_config.api_port();
-        s.spawn(move || {
-            let _span = tracing::debug_span!("[API]").entered();
-            tracing::info!("Startup on Port {api_port}");
-            let (mut api_server, api_server_cancellation) =
-                create_api_server(api_port, api_config, api_tollkeeper)
-                    .expect("Error during startup (api)");
-            api_server.start_listening(api_server_cancellation);
-        });
+    let config = read_config();
+    let tollkeeper = Arc::new(
+        config
+            .create_tollkeeper()
+            .expect("Failed to create tollkeeper"),
+    );
+    let url_resolver = Box::new(config.create_url_resolver());
+    let server_config = config.server();
+    let connection_settings = server_config.connection_settings();
+    let connection_limits = server_config.connection_limits();
+    let upstream_timeouts = server_config.upstream_timeouts();
+    let shutdown_timeout = server_config.shutdown_timeout();
+    let (cancel_sender, cancel_receiver) = cancellation_token::create_cancellation_token();
+
+    let visa_cookies = Arc::new(config.create_visa_cookies());
+    // Behind forward auth, the reverse proxy has to route the hand-off to the proxy socket
+    let handoff_service = payment::visa_cookie::HandoffServiceImpl::new(tollkeeper.clone());
+    let visa_handoff = Endpoint::new(
+        http::Method::Post,
+        payment::visa_cookie::handoff_path(),
+        Box::new(payment::visa_cookie::VisaHandoffServe::new(
+            visa_cookies.clone(),
+            Box::new(handoff_service),
+        )),
+    );
+    let mut api_endpoints =
+        create_api_endpoints(config.api.clone(), tollkeeper.clone(), visa_cookies);
+    let (proxy_api, api_endpoints) = match config.api.single_port() {
+        true => {
+            api_endpoints.push(visa_handoff);
+            (api_endpoints, None)
+        }
+        false => (vec![visa_handoff], Some(api_endpoints)),
+    };
+    let proxy_api = create_endpoints_serve(proxy_api, &config.api);
+    let api_endpoints = api_endpoints.map(|e| create_endpoints_serve(e, &config.api));
+
+    let proxy_port = server_config.proxy_port();
+    let mut proxy_handler = create_proxy_handler(
+        upstream_timeouts,
+        config.api.clone(),
+        tollkeeper,
+        url_resolver,
+    );
+    proxy_handler.set_api(Box::new(proxy_api));
+    let mut proxy_server = create_server(
+        proxy_port,
+        connection_settings,
+        connection_limits,
+        Box::new(proxy_handler),
+    )
+    .expect("Error during startup (proxy)");
+    proxy_server.set_shutdown_timeout(shutdown_timeout);
+    if let Some(tls) = config
+        .create_proxy_tls()
+        .expect("Invalid TLS config (proxy)")
+    {
+        proxy_server.set_tls(tls);
+    }
+    if let Some(proxy_protocol) = server_config.proxy_listener_proxy_protocol() {
+        proxy_server.set_proxy_protocol(proxy_protocol);
+    }
+    let proxy_cancellation = cancel_receiver.clone();
+    let proxy = async {
+        tracing::info!("Startup on Port {proxy_port}");
+        proxy_server.listen(proxy_cancellation).await;
+        tracing::info!("Shut down");
+    }
+    .instrument(tracing::debug_span!("[Proxy]"));
+
+    let api_port = server_config.api_port();
+    let api_server = api_endpoints.map(|api_endpoints| {
+        let mut api_server = create_server(
+            api_port,
+            connection_settings,
+            connection_limits,
+            Box::new(api_endpoints),
+        )
+        .expect("Error during startup (api)");
+        api_server.set_shutdown_timeout(shutdown_timeout);
+        if let Some(tls) = server_config
+            .create_api_tls()
+            .expect("Invalid TLS config (api)")
+        {
+            api_server.set_tls(tls);
+        }
+        if let Some(proxy_protocol) = server_config.api_listener_proxy_protocol() {
+            api_server.set_proxy_protocol(proxy_protocol);
+        }
+        api_server
     });
+    let api = async {
+        let Some(api_server) = api_server else {
+            tracing::info!("Single-port mode, serving on proxy port");
+            return;
+        };
+        tracing::info!("Startup on Port {api_port}");
+        api_server.listen(cancel_receiver).await;
+        tracing::info!("Shut down");
+    }
+    .instrument(tracing::debug_span!("[API]"));
+
+    let shutdown = async {
+        wait_for_shutdown_signal().await;
+        tracing::info!("Shutting down, draining connections for up to {shutdown_timeout:?}");
+        _ = cancel_sender.send_shutdown();
+    };
+    tokio::join!(proxy, api, shutdown);
     Ok(())
 }
 
+/// Waits for SIGTERM (e.g. `docker stop`) or SIGINT (Ctrl+C)
+async fn wait_for_shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut sigterm = signal(SignalKind::terminate()).expect("Failed to listen for SIGTERM");
+        tokio::select! {
+            _ = sigterm.recv() => {},
+            _ = tokio::signal::ctrl_c() => {},
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        _ = tokio::signal::ctrl_c().await;
+    }
+}
+
 fn setup_logging() {
     let format = tracing_subscriber::fmt::layer()
         .with_level(true)
@@ -86,57 +180,90 @@ fn read_config() -> config::Config {
     config::Config::from_toml(&config).unwrap()
 }
 
-fn create_proxy_server(
-    port: usize,
+fn create_proxy_handler(
+    upstream_timeouts: proxy::upstream::UpstreamTimeouts,
     server_config: config::Api,
     tollkeeper: Arc<Tollkeeper>,
     url_resolver: Box<dyn proxy::UrlResolver + Send + Sync>,
-) -> Result<(Server, cancellation_token::CancelReceiver), io::Error> {
-    let listener = net::TcpListener::bind(format!("0.0.0.0:{port}"))?;
-
-    let proxy_service = ProxyServiceImpl::new(tollkeeper, url_resolver);
-    let exe_root_dir = std::env::current_dir().unwrap().join("app/templates");
-    tracing::info!("Using templates located at: '{}'", exe_root_dir.display());
-    let template_store = FileTemplateStore::new(exe_root_dir);
-    let template_renderer =
-        HandlebarTemplateRenderer::new(Box::new(template_store), server_config.base_url.clone());
-    let proxy_handler = ProxyServe::new(
+) -> ProxyServe {
+    let mut proxy_service = ProxyServiceImpl::new(tollkeeper, url_resolver);
+    proxy_service.set_timeouts(upstream_timeouts);
+    let template_renderer = create_template_renderer(&server_config.public_url());
+    ProxyServe::new(
         server_config,
         Box::new(proxy_service),
         Box::new(template_renderer),
-    );
-    let server = Server::new(listener, Box::new(proxy_handler));
-    let (_, receiver) = cancellation_token::create_cancellation_token();
-    Ok((server, receiver))
+    )
 }
 
-fn create_api_server(
+fn create_server(
     port: usize,
+    connection_settings: ConnectionSettings,
+    connection_limits: limits::ConnectionLimits,
+    handler: Box<dyn TcpServe + Send + Sync>,
+) -> Result<Server, io::Error> {
+    let listener = net::TcpListener::bind(format!("0.0.0.0:{port}"))?;
+    let mut server = Server::with_settings(listener, handler, connection_settings);
+    server.set_limits(connection_limits);
+    Ok(server)
+}
+
+fn create_endpoints_serve(
+    endpoints: Vec<Endpoint>,
+    api_config: &config::Api,
+) -> HttpEndpointsServe {
+    let mut endpoints_serve = HttpEndpointsServe::new(endpoints, api_config.real_ip_resolver());
+    endpoints_serve.set_trusted_proxies(api_config.trusted_proxies());
+    endpoints_serve
+}
+
+/// Payment, forward auth and asset endpoints, served below [config::Api::path]
+fn create_api_endpoints(
     server_config: config::Api,
     tollkeeper: Arc<Tollkeeper>,
-) -> Result<(Server, cancellation_token::CancelReceiver), io::Error> {
-    let listener = net::TcpListener::bind(format!("0.0.0.0:{port}"))?;
-    let payment_service = payment::PaymentServiceImpl::new(tollkeeper);
+    visa_cookies: Arc<payment::visa_cookie::VisaCookies>,
+) -> Vec<Endpoint> {
+    let path = server_config.path().to_string();
+    let payment_service = payment::PaymentServiceImpl::new(tollkeeper.clone());
+    let template_renderer = Arc::new(create_template_renderer(&server_config.public_url()));
     let mut api_endpoints = vec![];
     let mut payment_endpoints = payment::create_pay_toll_endpoint(
-        "/api/pay/",
+        &format!("{path}api/pay/"),
         server_config.clone(),
         Box::new(payment_service),
+        visa_cookies,
+        template_renderer.clone(),
     );
     api_endpoints.append(&mut payment_endpoints);
-    api_endpoints.append(&mut create_file_endpoints("app/assets", "app/assets/"));
-    let http_endpoints = HttpEndpointsServe::new(api_endpoints, server_config.real_ip_header);
-    let server = Server::new(listener, Box::new(http_endpoints));
-    let (_, receiver) = cancellation_token::create_cancellation_token();
-    Ok((server, receiver))
+    let auth_service = proxy::forward_auth::ForwardAuthServiceImpl::new(tollkeeper);
+    let mut auth_endpoints = proxy::forward_auth::create_forward_auth_endpoints(
+        &format!("{path}api/auth/"),
+        server_config,
+        Arc::new(auth_service),
+        template_renderer,
+    );
+    api_endpoints.append(&mut auth_endpoints);
+    api_endpoints.append(&mut create_file_endpoints(
+        "app/assets",
+        "app/assets/",
+        &path,
+    ));
+    api_endpoints
+}
+
+fn create_template_renderer(base_url: &str) -> HandlebarTemplateRenderer {
+    let exe_root_dir = std::env::current_dir().unwrap().join("app/templates");
+    tracing::info!("Using templates located at: '{}'", exe_root_dir.display());
+    let template_store = FileTemplateStore::new(exe_root_dir);
+    HandlebarTemplateRenderer::new(Box::new(template_store), base_url)
 }
 
-fn create_file_endpoints(fs_dir: &str, path_prefix: &str) -> Vec<Endpoint> {
+fn create_file_endpoints(fs_dir: &str, path_prefix: &str, api_path: &str) -> Vec<Endpoint> {
     let mut endpoints = vec![];
     let path = PathBuf::from_str(fs_dir).unwrap();
     let files = find_files(&path);
     for file in files {
-        let endpoint = create_file_endpoint(file, path_prefix);
+        let endpoint = create_file_endpoint(file, path_prefix, api_path);
         endpoints.push(endpoint);
     }
     endpoints
@@ -156,8 +283,8 @@ fn find_files(path: &Path) -> Vec<PathBuf> {
     all_entries
 }
 
-fn create_file_endpoint(file: PathBuf, path_prefix: &str) -> Endpoint {
-    let mut api_path = PathBuf::from_str("/").unwrap();
+fn create_file_endpoint(file: PathBuf, path_prefix: &str, api_path: &str) -> Endpoint {
+    let mut api_path = PathBuf::from_str(api_path).unwrap();
     let api_file = file.strip_prefix(path_prefix).unwrap();
     api_path.push(api_file);
     let mut file_serve = FileServe::new(api_path.clone(), Box::new(FileReaderImpl));
@@ -1,5 +1,6 @@
 #[cfg(test)]
 mod tests;
+pub mod visa_cookie;
 
 use std::{error::Error, fmt::Display, str::FromStr, sync::Arc};
 
@@ -12,18 +13,22 @@ use crate::{
     data_formats::{self, AsHalJson, AsHttpHeader},
     http::{
         self,
-        request::body_reader::{ReadJson, ReadJsonError},
+        request::body_reader::{ReadForm, ReadFormError, ReadJson, ReadJsonError},
         server::HttpServe,
     },
     proxy::{self},
+    templates::{SerializedData, TemplateRenderer},
 };
 
 pub fn create_pay_toll_endpoint(
     path: &str,
     config: Api,
     payment_service: Box<dyn PaymentService + Send + Sync>,
+    visa_cookies: Arc<visa_cookie::VisaCookies>,
+    template_renderer: Arc<dyn TemplateRenderer + Send + Sync>,
 ) -> Vec<http::server::Endpoint> {
-    let pay_toll_handler = PayTollServe::new(config, payment_service);
+    let pay_toll_handler =
+        PayTollServe::new(config, payment_service, visa_cookies, template_renderer);
     let pay_post_endpoint =
         http::server::Endpoint::new(http::Method::Post, path, Box::new(pay_toll_handler));
     let pay_options_endpoint =
@@ -34,14 +39,18 @@ pub fn create_pay_toll_endpoint(
 pub struct PayTollServe {
     config: config::Api,
     payment_service: Box<dyn PaymentService + Send + Sync>,
+    visa_cookies: Arc<visa_cookie::VisaCookies>,
+    template_renderer: Arc<dyn TemplateRenderer + Send + Sync>,
 }
+#[async_trait::async_trait]
 impl HttpServe for PayTollServe {
-    fn serve_http(
+    async fn serve_http(
         &self,
         client_addr: &std::net::SocketAddr,
         mut request: http::Request,
     ) -> Result<http::Response, http::server::InternalServerError> {
-        let payment: Payment = match request.read_json() {
+        let is_form = request.is_form();
+        let payment = match Self::read_payment(&mut request) {
             Ok(v) => v,
             Err(e) => return Self::create_parsing_error_response(&e),
         };
@@ -52,29 +61,45 @@ impl HttpServe for PayTollServe {
             payment.toll.recipient().destination(),
         );
         match self.payment_service.pay_toll(recipient, payment) {
-            Ok(v) => self.create_visa_response(v),
+            Ok(v) if is_form => self.create_form_visa_response(&request, v),
+            Ok(v) => self.create_visa_response(&request, v),
+            Err(payment_error) if is_form => {
+                self.create_form_payment_error_response(&request, payment_error)
+            }
             Err(payment_error) => Self::create_payment_error_response(self, payment_error),
         }
     }
 }
 impl PayTollServe {
+    /// Stores the visa in a cookie if the payment was sent to the gate, else hands it over to
+    /// the gate. Form payments get the hand-off page rendered by `template_renderer`
     pub fn new(
         config: config::Api,
         payment_service: Box<dyn PaymentService + Send + Sync>,
+        visa_cookies: Arc<visa_cookie::VisaCookies>,
+        template_renderer: Arc<dyn TemplateRenderer + Send + Sync>,
     ) -> Self {
         Self {
             config,
             payment_service,
+            visa_cookies,
+            template_renderer,
         }
     }
 
     fn create_visa_response(
         &self,
+        request: &http::Request,
         visa: Visa,
     ) -> Result<http::Response, http::server::InternalServerError> {
-        let visa_json = visa.as_hal_json(&self.config.base_url);
-        let visa_json = visa_json.to_string();
+        let mut visa_json = visa.as_hal_json(&self.config.public_url());
         let mut headers = http::Headers::empty();
+        if visa_cookie::is_destination_host(request, &visa) {
+            headers.insert("Set-Cookie", self.visa_cookies.set_cookie(request, &visa));
+        } else if let Some(handoff_url) = visa_cookie::handoff_url(request, &visa) {
+            visa_json["_links"]["handoff"] = handoff_url.to_string().into();
+        }
+        let visa_json = visa_json.to_string();
         headers.insert("Content-Type", "application/hal+json");
         headers.insert("Content-Length", visa_json.len().to_string());
         let headers = http::response::Headers::with_cors(headers, Some(&[http::Method::Post]));
@@ -88,11 +113,97 @@ impl PayTollServe {
         Ok(response)
     }
 
+    /// Sends clients paying by form back to the page the visa was issued for, or to the
+    /// hand-off of its gate
+    fn create_form_visa_response(
+        &self,
+        request: &http::Request,
+        visa: Visa,
+    ) -> Result<http::Response, http::server::InternalServerError> {
+        if !visa_cookie::is_destination_host(request, &visa) {
+            return self.create_handoff_page_response(request, &visa);
+        }
+        let Some(destination) = visa.destination_url() else {
+            tracing::error!(
+                "Visa for invalid destination {}",
+                visa.recipient().destination()
+            );
+            return Err(http::server::InternalServerError::new());
+        };
+        let mut headers = http::response::Headers::empty();
+        headers.insert("Cache-Control", "no-store");
+        headers.insert("Set-Cookie", self.visa_cookies.set_cookie(request, &visa));
+        let location = &destination[url::Position::BeforePath..];
+        Ok(http::Response::see_other(location, headers))
+    }
+
+    /// Sends clients whose form payment failed back to the page, which declares a new toll
+    fn create_form_payment_error_response(
+        &self,
+        request: &http::Request,
+        payment_error: Box<PaymentError>,
+    ) -> Result<http::Response, http::server::InternalServerError> {
+        let new_toll = match payment_error.as_ref() {
+            PaymentError::ChallengeFailed(toll, _) => toll,
+            PaymentError::MismatchedRecipient(_, toll) => toll,
+            _ => return self.create_payment_error_response(payment_error),
+        };
+        let destination = new_toll.recipient().destination();
+        let Some(location) = visa_cookie::destination_url(request, destination) else {
+            tracing::error!("Toll for invalid destination {destination}");
+            return Err(http::server::InternalServerError::new());
+        };
+        let mut headers = http::response::Headers::empty();
+        headers.insert("Cache-Control", "no-store");
+        Ok(http::Response::see_other(location.to_string(), headers))
+    }
+
+    /// Page posting the visa to the hand-off of its gate, keeping it out of urls
+    fn create_handoff_page_response(
+        &self,
+        request: &http::Request,
+        visa: &Visa,
+    ) -> Result<http::Response, http::server::InternalServerError> {
+        let Some(handoff_url) = visa_cookie::handoff_url(request, visa) else {
+            return Err(http::server::InternalServerError::new());
+        };
+        let (_, token) = visa.as_http_header();
+        let data = serde_json::json!({
+            "destination": visa.recipient().destination(),
+            "handoff_url": handoff_url.to_string(),
+            "token": token,
+        });
+        let page_html = self
+            .template_renderer
+            .render("visa_handoff.html", &SerializedData::new(data))
+            .or(Err(http::server::InternalServerError::new()))?;
+        let mut headers = http::Headers::empty();
+        headers.insert("Content-Type", "text/html");
+        headers.insert("Content-Length", page_html.len().to_string());
+        headers.insert("Cache-Control", "no-store");
+        let headers = http::response::Headers::new(headers);
+        let body = http::Body::from_string(page_html);
+        Ok(http::Response::new(
+            http::response::StatusCode::OK,
+            Some("OK".into()),
+            headers,
+            body,
+        ))
+    }
+
+    fn read_payment(request: &mut http::Request) -> Result<Payment, ReadPaymentError> {
+        if !request.is_form() {
+            return request.read_json().map_err(ReadPaymentError::Json);
+        }
+        let fields = request.read_form().map_err(ReadPaymentError::Form)?;
+        Payment::from_form(&fields).map_err(ReadPaymentError::FormPayment)
+    }
+
     fn create_payment_error_response(
         &self,
         payment_error: Box<PaymentError>,
     ) -> Result<http::Response, http::server::InternalServerError> {
-        let error_json = payment_error.as_hal_json(&self.config.base_url);
+        let error_json = payment_error.as_hal_json(&self.config.public_url());
         let error_json = error_json.to_string();
         let mut headers = http::Headers::empty();
         headers.insert("Content-Type", "application/hal+json");
@@ -116,10 +227,10 @@ impl PayTollServe {
     }
 
     fn create_parsing_error_response(
-        json_error: &ReadJsonError,
+        read_error: &ReadPaymentError,
     ) -> Result<http::Response, http::server::InternalServerError> {
         let error_json = serde_json::json!({
-            "error": json_error.to_string()
+            "error": read_error.to_string()
         });
         let error_json = error_json.to_string();
         let mut headers = http::Headers::empty();
@@ -127,8 +238,12 @@ impl PayTollServe {
         headers.insert("Content-Length", error_json.len().to_string());
         let headers = http::response::Headers::with_cors(headers, Some(&[http::Method::Post]));
         let body = http::Body::from_string(error_json);
-        let status_code = match json_error {
-            ReadJsonError::InvalidJsonData(_) => http::response::StatusCode::UnprocessableContent,
+        let status_code = match read_error {
+            ReadPaymentError::Json(ReadJsonError::InvalidJsonData(_))
+            | ReadPaymentError::FormPayment(FormPaymentError::MissingField(_))
+            | ReadPaymentError::FormPayment(FormPaymentError::InvalidToll(_)) => {
+                http::response::StatusCode::UnprocessableContent
+            }
             _ => http::response::StatusCode::BadRequest,
         };
         let response = http::Response::new(
@@ -179,6 +294,23 @@ impl Payment {
     pub fn new(toll: proxy::Toll, value: String) -> Self {
         Self { toll, value }
     }
+
+    /// Reads payment from form fields, `toll` containing the toll as JSON and `value` the stamp
+    pub fn from_form(fields: &[(String, String)]) -> Result<Self, FormPaymentError> {
+        let field = |name: &str| {
+            fields
+                .iter()
+                .find(|(k, _)| k == name)
+                .map(|(_, v)| v.as_str())
+                .ok_or(FormPaymentError::MissingField(name.into()))
+        };
+        let toll: serde_json::Value =
+            serde_json::from_str(field("toll")?).or(Err(FormPaymentError::NonJsonToll))?;
+        let toll = serde_json::from_value(toll)
+            .map_err(|e| FormPaymentError::InvalidToll(e.to_string()))?;
+        let payment = Self::new(toll, field("value")?.into());
+        Ok(payment)
+    }
 }
 impl TryFrom<Payment> for tollkeeper::SignedPayment {
     type Error = base64::DecodeError;
@@ -190,6 +322,44 @@ impl TryFrom<Payment> for tollkeeper::SignedPayment {
     }
 }
 
+/// Payment request that could not be read
+#[derive(Debug, PartialEq, Eq)]
+pub enum ReadPaymentError {
+    Json(ReadJsonError),
+    Form(ReadFormError),
+    FormPayment(FormPaymentError),
+}
+impl Error for ReadPaymentError {}
+impl Display for ReadPaymentError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReadPaymentError::Json(e) => write!(f, "{e}"),
+            ReadPaymentError::Form(e) => write!(f, "{e}"),
+            ReadPaymentError::FormPayment(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+/// Form fields that do not make up a [Payment]
+#[derive(Debug, PartialEq, Eq)]
+pub enum FormPaymentError {
+    MissingField(String),
+    NonJsonToll,
+    InvalidToll(String),
+}
+impl Error for FormPaymentError {}
+impl Display for FormPaymentError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FormPaymentError::MissingField(name) => write!(f, "Missing form field '{name}'"),
+            FormPaymentError::NonJsonToll => write!(f, "Form field 'toll' is not JSON"),
+            FormPaymentError::InvalidToll(reason) => {
+                write!(f, "Form field 'toll' is not a valid toll: {reason}")
+            }
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Eq)]
 pub struct Visa {
     order_id: proxy::OrderId,
@@ -231,6 +401,11 @@ impl Visa {
     pub fn signature(&self) -> &Base64 {
         &self.signature
     }
+
+    /// Url of the page the visa was issued for
+    pub fn destination_url(&self) -> Option<url::Url> {
+        url::Url::parse(&format!("http://{}", self.recipient.destination())).ok()
+    }
 }
 impl data_formats::AsHttpHeader for Visa {
     fn as_http_header(&self) -> (String, String) {
@@ -267,7 +442,7 @@ impl data_formats::FromHttpHeader for Visa {
     }
 }
 impl data_formats::AsHalJson for Visa {
-    fn as_hal_json(&self, _: &url::Url) -> serde_json::Value {
+    fn as_hal_json(&self, _: &str) -> serde_json::Value {
         let origin_url = self.recipient.destination().to_string();
         let (header_name, token) = self.as_http_header();
         serde_json::json!({
@@ -329,7 +504,7 @@ impl PaymentError {
         message: &str,
         toll: &proxy::Toll,
         failed_payment: &str,
-        base_url: &url::Url,
+        base_url: &str,
     ) -> serde_json::Value {
         serde_json::json!({
             "error": "Challenge failed!",
@@ -343,7 +518,7 @@ impl PaymentError {
         message: &str,
         expected_recipient: &proxy::Recipient,
         toll: &proxy::Toll,
-        base_url: &url::Url,
+        base_url: &str,
     ) -> serde_json::Value {
         serde_json::json!({
             "error": "Mismatched Recipient!",
@@ -381,7 +556,7 @@ impl Display for PaymentError {
     }
 }
 impl data_formats::AsHalJson for PaymentError {
-    fn as_hal_json(&self, base_url: &url::Url) -> serde_json::Value {
+    fn as_hal_json(&self, base_url: &str) -> serde_json::Value {
         match self {
             PaymentError::ChallengeFailed(toll, failed_payment) => {
                 Self::challenge_failed_json(&self.to_string(), toll, failed_payment, base_url)
@@ -412,14 +587,18 @@ impl From<tollkeeper::err::PaymentDeniedError> for Box<PaymentError> {
 
 //TODO: Handle OPTIONS more elegantly
 struct PayTollOptionsServe;
+#[async_trait::async_trait]
 impl HttpServe for PayTollOptionsServe {
-    fn serve_http(
+    async fn serve_http(
         &self,
         _: &std::net::SocketAddr,
         _: http::Request,
     ) -> Result<http::Response, http::server::InternalServerError> {
         let mut headers = http::Headers::empty();
-        headers.insert("Accept", "application/json");
+        headers.insert(
+            "Accept",
+            "application/json, application/x-www-form-urlencoded",
+        );
         headers.insert("Allow", "POST");
         let headers = http::response::Headers::with_cors(headers, Some(&[http::Method::Post]));
         let response = http::Response::new(
@@ -1,2 +1,54 @@
 mod pay_toll_serve_tests;
 mod payment_service_tests;
+mod visa_cookie_tests;
+
+/// Declares tolls answered by `password`, issuing visas valid for a year
+#[derive(Clone)]
+struct FakeTollDeclaration {
+    password: String,
+}
+impl FakeTollDeclaration {
+    fn new(password: String) -> Self {
+        Self { password }
+    }
+}
+impl tollkeeper::Declaration for FakeTollDeclaration {
+    fn declare(
+        &self,
+        suspect: tollkeeper::descriptions::Suspect,
+        order_id: tollkeeper::declarations::OrderIdentifier,
+    ) -> tollkeeper::declarations::Toll {
+        let mut challenge = tollkeeper::declarations::Challenge::new();
+        challenge.insert("hello".into(), "world".into());
+        tollkeeper::declarations::Toll::new(suspect, order_id, challenge)
+    }
+
+    fn pay(
+        &self,
+        payment: tollkeeper::declarations::Payment,
+        suspect: &tollkeeper::descriptions::Suspect,
+    ) -> Result<tollkeeper::declarations::Visa, tollkeeper::declarations::PaymentError> {
+        let order_id = payment.toll().order_id().clone();
+        let expires = chrono::Utc::now()
+            .checked_add_months(chrono::Months::new(12))
+            .unwrap();
+        if payment.value() == self.password {
+            let visa =
+                tollkeeper::declarations::Visa::new(order_id.clone(), suspect.clone(), expires);
+            Ok(visa)
+        } else {
+            let error = tollkeeper::declarations::PaymentError::new(
+                Box::new(payment),
+                Box::new(self.declare(suspect.clone(), order_id)),
+            );
+            Err(error)
+        }
+    }
+}
+
+struct StubDescription;
+impl tollkeeper::Description for StubDescription {
+    fn matches(&self, _: &tollkeeper::descriptions::Suspect) -> bool {
+        true
+    }
+}
@@ -1,22 +1,24 @@
 use chrono::TimeZone;
 use pretty_assertions::assert_eq;
 use std::{
-    collections::VecDeque,
+    collections::{HashMap, VecDeque},
     io::Read,
     net::{SocketAddr, SocketAddrV4},
     str::FromStr,
+    sync::Arc,
 };
 
 use serde_json::json;
 use test_case::test_case;
-use tollkeeper::signatures::Base64;
+use tollkeeper::{signatures::Base64, util::FakeDateTimeProvider};
 
 use crate::{
     config,
-    data_formats::AsHalJson,
+    data_formats::{AsHalJson, AsHttpHeader},
     http::{self, server::HttpServe},
-    payment::{self, PayTollServe, PaymentError, PaymentService},
+    payment::{self, visa_cookie, PayTollServe, PaymentError, PaymentService},
     proxy::{self, Challenge},
+    templates::{handlebars::HandlebarTemplateRenderer, InMemoryTemplateStore, TemplateRenderer},
 };
 
 fn setup(
@@ -26,11 +28,40 @@ fn setup(
 ) -> PayTollServe {
     let base_api_url = setup_server_url();
     let config = config::Api {
-        base_url: base_api_url,
+        base_url: Some(base_api_url),
+        single_port: None,
         real_ip_header: None,
+        trusted_proxies: None,
+        real_ip_fallback: None,
     };
     let stub_payment_service = StubPaymentService::new(result);
-    PayTollServe::new(config, Box::new(stub_payment_service))
+    PayTollServe::new(
+        config,
+        Box::new(stub_payment_service),
+        setup_visa_cookies(),
+        setup_template_renderer(),
+    )
+}
+
+fn setup_template_renderer() -> Arc<dyn TemplateRenderer + Send + Sync> {
+    let mut templates = HashMap::new();
+    templates.insert(
+        "visa_handoff.html".to_string(),
+        "{{{handoff_url}}} {{{token}}}".to_string(),
+    );
+    let template_store = InMemoryTemplateStore::new(templates);
+    Arc::new(HandlebarTemplateRenderer::new(
+        Box::new(template_store),
+        setup_server_url(),
+    ))
+}
+
+fn setup_visa_cookies() -> Arc<visa_cookie::VisaCookies> {
+    let date_provider = FakeDateTimeProvider(chrono::Utc::now());
+    Arc::new(visa_cookie::VisaCookies::new(
+        HashMap::new(),
+        Box::new(date_provider),
+    ))
 }
 
 fn setup_server_url() -> url::Url {
@@ -60,6 +91,14 @@ fn assert_body_contains_json(expected_content: serde_json::Value, mut response:
 }
 
 fn setup_payment_request(recipient: proxy::Recipient, order_id: proxy::OrderId) -> http::Request {
+    setup_payment_request_to("localhost", recipient, order_id)
+}
+
+fn setup_payment_request_to(
+    host: &str,
+    recipient: proxy::Recipient,
+    order_id: proxy::OrderId,
+) -> http::Request {
     let payment = json!({
         "toll": {
             "recipient": recipient,
@@ -72,17 +111,17 @@ fn setup_payment_request(recipient: proxy::Recipient, order_id: proxy::OrderId)
     let json = payment.to_string();
     let data: VecDeque<u8> = json.into_bytes().into();
     let content_length = data.len();
-    let body = http::Body::from_stream(Box::new(data), Some(content_length));
+    let body = http::Body::Buffer(http::BufferBody::new(data));
     let mut headers = http::Headers::empty();
-    headers.insert("Host", "localhost");
+    headers.insert("Host", host);
     headers.insert("Content-Type", "application/json");
     headers.insert("Content-Length", content_length.to_string());
     let headers = http::request::Headers::new(headers).unwrap();
     http::Request::new(http::Method::Post, "/payment-endpoint", headers, body).unwrap()
 }
 
-#[test]
-pub fn pay_toll_serve_should_return_visa_as_json() {
+#[tokio::test]
+pub async fn pay_toll_serve_should_return_visa_as_json() {
     // Arrange
     let create_visa = move || {
         let recipient = proxy::Recipient::new("1.2.3.4", "Bob", "example.com:80/");
@@ -106,7 +145,7 @@ pub fn pay_toll_serve_should_return_visa_as_json() {
         expected_visa.order_id().clone(),
     );
     // Act
-    let response = sut.serve_http(&client_ip, request).unwrap();
+    let response = sut.serve_http(&client_ip, request).await.unwrap();
     // Assert
     assert_eq!(http::response::StatusCode::OK, response.status_code());
     assert_eq!(
@@ -117,7 +156,8 @@ pub fn pay_toll_serve_should_return_visa_as_json() {
         "token": "eyJpcCI6IjEuMi4zLjQiLCJ1YSI6IkJvYiIsImRlc3QiOiJleGFtcGxlLmNvbTo4MC8iLCJleHAiOjE5MjQzNDU4MTUsIm9yZGVyX2lkIjoiZ2F0ZSNvcmRlciJ9.cmVhbCBzaWduYXR1cmUgO0Q=",
         "header_name": "X-Keeper-Token",
         "_links": {
-            "origin_url": "example.com:80/"
+            "origin_url": "example.com:80/",
+            "handoff": "http://example.com/.tollkeeper/visa/"
         }
     });
     assert_body_contains_json(expected_body, response);
@@ -125,7 +165,8 @@ pub fn pay_toll_serve_should_return_visa_as_json() {
 
 #[test_case("<hello>World<hello>" ; "XML")]
 #[test_case(r#"{"hello" = "world"}"# ; "malformed json")]
-pub fn pay_toll_serve_should_return_400_for_non_json_data(non_json_data: &str) {
+#[tokio::test]
+pub async fn pay_toll_serve_should_return_400_for_non_json_data(non_json_data: &str) {
     // Arrange
     let payment_service_stub = || panic!("Malformed request got processed!");
     let sut = setup(Box::new(payment_service_stub));
@@ -138,7 +179,7 @@ pub fn pay_toll_serve_should_return_400_for_non_json_data(non_json_data: &str) {
     let headers = http::request::Headers::new(headers).unwrap();
     let request = http::Request::new(http::Method::Post, "/api/pay", headers, body).unwrap();
     // Act
-    let response = sut.serve_http(&client_ip, request).unwrap();
+    let response = sut.serve_http(&client_ip, request).await.unwrap();
     // Assert
     assert_eq!(
         http::response::StatusCode::BadRequest,
@@ -146,8 +187,8 @@ pub fn pay_toll_serve_should_return_400_for_non_json_data(non_json_data: &str) {
     );
 }
 
-#[test]
-pub fn pay_toll_serve_should_return_400_for_invalid_json_data() {
+#[tokio::test]
+pub async fn pay_toll_serve_should_return_400_for_invalid_json_data() {
     // Arrange
     let payment_service_stub = || panic!("Malformed request got processed!");
     let sut = setup(Box::new(payment_service_stub));
@@ -169,7 +210,7 @@ pub fn pay_toll_serve_should_return_400_for_invalid_json_data() {
     let headers = http::request::Headers::new(headers).unwrap();
     let request = http::Request::new(http::Method::Post, "/api/pay", headers, body).unwrap();
     // Act
-    let response = sut.serve_http(&client_ip, request).unwrap();
+    let response = sut.serve_http(&client_ip, request).await.unwrap();
     // Assert
     assert_eq!(
         http::response::StatusCode::UnprocessableContent,
@@ -177,8 +218,8 @@ pub fn pay_toll_serve_should_return_400_for_invalid_json_data() {
     );
 }
 
-#[test]
-pub fn pay_toll_serve_should_return_400_and_new_toll_for_failed_challenge() {
+#[tokio::test]
+pub async fn pay_toll_serve_should_return_400_and_new_toll_for_failed_challenge() {
     // Arrange
     let create_challenge_failed = move || {
         let recipient = proxy::Recipient::new("1.2.3.4", "Bob", "example.com:80/");
@@ -203,7 +244,7 @@ pub fn pay_toll_serve_should_return_400_and_new_toll_for_failed_challenge() {
         expected_err.0.order_id().clone(),
     );
     // Act
-    let response = sut.serve_http(&client_ip, request).unwrap();
+    let response = sut.serve_http(&client_ip, request).await.unwrap();
     // Assert
     assert_eq!(
         http::response::StatusCode::BadRequest,
@@ -217,13 +258,13 @@ pub fn pay_toll_serve_should_return_400_and_new_toll_for_failed_challenge() {
         "error": "Challenge failed!",
         "message": "'hello' was not the right answer! Try again with new toll",
         "failed_payment": expected_err.1,
-        "new_toll": expected_err.0.as_hal_json(&setup_server_url()), //Link for paying toll already included in toll json :D
+        "new_toll": expected_err.0.as_hal_json(setup_server_url().as_str()), //Link for paying toll already included in toll json :D
     });
     assert_body_contains_json(expected_body, response);
 }
 
-#[test]
-pub fn pay_toll_serve_should_return_400_with_error_information_for_mismatched_recipients() {
+#[tokio::test]
+pub async fn pay_toll_serve_should_return_400_with_error_information_for_mismatched_recipients() {
     // Arrange
     let create_mismatched_recipient = move || {
         let expected_recipient = proxy::Recipient::new("1.2.3.4", "Bob", "example.com:80/");
@@ -251,7 +292,7 @@ pub fn pay_toll_serve_should_return_400_with_error_information_for_mismatched_re
         expected_err.1.order_id().clone(),
     );
     // Act
-    let response = sut.serve_http(&client_ip, request).unwrap();
+    let response = sut.serve_http(&client_ip, request).await.unwrap();
     // Assert
     assert_eq!(
         http::response::StatusCode::BadRequest,
@@ -265,13 +306,13 @@ pub fn pay_toll_serve_should_return_400_with_error_information_for_mismatched_re
         "error": "Mismatched Recipient!",
         "message": "Toll was issued for a different recipient. New toll issued for current recipient",
         "expected_recipient": expected_err.0,
-        "new_toll": expected_err.1.as_hal_json(&setup_server_url())
+        "new_toll": expected_err.1.as_hal_json(setup_server_url().as_str())
     });
     assert_body_contains_json(expected_body, response);
 }
 
-#[test]
-pub fn pay_toll_serve_should_return_422_with_message_for_invalid_signature() {
+#[tokio::test]
+pub async fn pay_toll_serve_should_return_422_with_message_for_invalid_signature() {
     // Arrange
     let create_invalid_signature = move || Err(Box::new(PaymentError::InvalidSignature));
     let sut = setup(Box::new(create_invalid_signature));
@@ -281,7 +322,7 @@ pub fn pay_toll_serve_should_return_422_with_message_for_invalid_signature() {
         proxy::OrderId::new("gate", "order"),
     );
     // Act
-    let response = sut.serve_http(&client_ip, request).unwrap();
+    let response = sut.serve_http(&client_ip, request).await.unwrap();
     // Assert
     assert_eq!(
         http::response::StatusCode::UnprocessableContent,
@@ -298,8 +339,8 @@ pub fn pay_toll_serve_should_return_422_with_message_for_invalid_signature() {
     assert_body_contains_json(expected_body, response);
 }
 
-#[test]
-pub fn pay_toll_serve_should_return_409_with_message_for_no_longer_existing_order() {
+#[tokio::test]
+pub async fn pay_toll_serve_should_return_409_with_message_for_no_longer_existing_order() {
     // Arrange
     let create_gateway_error = move || Err(Box::new(PaymentError::GatewayError));
     let sut = setup(Box::new(create_gateway_error));
@@ -309,7 +350,7 @@ pub fn pay_toll_serve_should_return_409_with_message_for_no_longer_existing_orde
         proxy::OrderId::new("gate", "order"),
     );
     // Act
-    let response = sut.serve_http(&client_ip, request).unwrap();
+    let response = sut.serve_http(&client_ip, request).await.unwrap();
     // Assert
     assert_eq!(
         http::response::StatusCode::Conflict, //Most likely to occur because an order was removed while it still had pending tolls
@@ -349,3 +390,250 @@ impl PaymentService for StubPaymentService {
         (*self.pay_toll_result)()
     }
 }
+
+fn create_visa() -> Result<payment::Visa, Box<payment::PaymentError>> {
+    let recipient = proxy::Recipient::new("1.2.3.4", "Bob", "example.com:80/page?debug");
+    let order_id = proxy::OrderId::new("gate", "order");
+    let expires = chrono::Utc::now() + chrono::Duration::hours(1);
+    let visa = payment::Visa::new(
+        order_id,
+        recipient,
+        payment::UnixTimestamp(expires),
+        Base64::encode(b"real signature ;D"),
+    );
+    Ok(visa)
+}
+
+#[tokio::test]
+pub async fn pay_toll_serve_should_set_visa_cookie_if_paid_on_gate() {
+    // Arrange
+    let visa = create_visa().unwrap();
+    let sut = setup(Box::new(create_visa));
+    let client_ip = SocketAddr::V4(SocketAddrV4::from_str("1.2.3.4:42420").unwrap());
+    let request = setup_payment_request_to(
+        "example.com",
+        visa.recipient().clone(),
+        visa.order_id().clone(),
+    );
+    // Act
+    let mut response = sut.serve_http(&client_ip, request).await.unwrap();
+    // Assert
+    assert_eq!(http::response::StatusCode::OK, response.status_code());
+    let set_cookie = response.headers().extension("Set-Cookie").unwrap_or("");
+    assert!(set_cookie.starts_with("X-Keeper-Token="), "{set_cookie}");
+    assert!(set_cookie.contains("; HttpOnly"), "{set_cookie}");
+    let mut body = String::new();
+    if let http::Body::Buffer(buffer_body) = response.body() {
+        buffer_body.read_to_string(&mut body).unwrap();
+    }
+    let body: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(serde_json::Value::Null, body["_links"]["handoff"]);
+}
+
+#[tokio::test]
+pub async fn pay_toll_serve_should_link_handoff_if_paid_on_other_domain() {
+    // Arrange
+    let visa = create_visa().unwrap();
+    let sut = setup(Box::new(create_visa));
+    let client_ip = SocketAddr::V4(SocketAddrV4::from_str("1.2.3.4:42420").unwrap());
+    let request = setup_payment_request_to(
+        "tollkeeper.example.com",
+        visa.recipient().clone(),
+        visa.order_id().clone(),
+    );
+    // Act
+    let mut response = sut.serve_http(&client_ip, request).await.unwrap();
+    // Assert
+    assert_eq!(http::response::StatusCode::OK, response.status_code());
+    assert_eq!(None, response.headers().extension("Set-Cookie"));
+    let mut body = String::new();
+    if let http::Body::Buffer(buffer_body) = response.body() {
+        buffer_body.read_to_string(&mut body).unwrap();
+    }
+    let body: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(
+        json!("http://example.com/.tollkeeper/visa/"),
+        body["_links"]["handoff"]
+    );
+}
+
+fn setup_form_payment_request_to(host: &str, form: &str) -> http::Request {
+    let body = http::Body::from_string(form.into());
+    let mut headers = http::Headers::empty();
+    headers.insert("Host", host);
+    headers.insert("Content-Type", "application/x-www-form-urlencoded");
+    headers.insert("Content-Length", form.len().to_string());
+    let headers = http::request::Headers::new(headers).unwrap();
+    http::Request::new(http::Method::Post, "/payment-endpoint", headers, body).unwrap()
+}
+
+fn payment_form(visa: &payment::Visa) -> String {
+    let toll = json!({
+        "recipient": visa.recipient(),
+        "order_id": visa.order_id(),
+        "challenge": {},
+        "signature": Base64::encode(b"very real; much secure;")
+    });
+    url::form_urlencoded::Serializer::new(String::new())
+        .append_pair("toll", &toll.to_string())
+        .append_pair("value", "hello")
+        .finish()
+}
+
+#[tokio::test]
+pub async fn pay_toll_serve_should_redirect_form_payment_to_destination_with_cookie() {
+    // Arrange
+    let visa = create_visa().unwrap();
+    let sut = setup(Box::new(create_visa));
+    let client_ip = SocketAddr::V4(SocketAddrV4::from_str("1.2.3.4:42420").unwrap());
+    let request = setup_form_payment_request_to("example.com", &payment_form(&visa));
+    // Act
+    let response = sut.serve_http(&client_ip, request).await.unwrap();
+    // Assert
+    assert_eq!(http::response::StatusCode::SeeOther, response.status_code());
+    assert_eq!(
+        Some("/page?debug"),
+        response.headers().extension("Location")
+    );
+    let set_cookie = response.headers().extension("Set-Cookie").unwrap_or("");
+    assert!(set_cookie.starts_with("X-Keeper-Token="), "{set_cookie}");
+}
+
+#[test_case("tollkeeper.example.com", None, "http://example.com/.tollkeeper/visa/" ; "http")]
+#[test_case("tollkeeper.example.com", Some("https"), "https://example.com/.tollkeeper/visa/" ; "https")]
+#[tokio::test]
+pub async fn pay_toll_serve_should_post_form_payment_on_other_domain_to_handoff(
+    host: &str,
+    forwarded_proto: Option<&str>,
+    expected_handoff_url: &str,
+) {
+    // Arrange
+    let visa = create_visa().unwrap();
+    let sut = setup(Box::new(create_visa));
+    let client_ip = SocketAddr::V4(SocketAddrV4::from_str("1.2.3.4:42420").unwrap());
+    let mut request = setup_form_payment_request_to(host, &payment_form(&visa));
+    if let Some(forwarded_proto) = forwarded_proto {
+        request
+            .headers_mut()
+            .insert("X-Forwarded-Proto", forwarded_proto);
+    }
+    // Act
+    let mut response = sut.serve_http(&client_ip, request).await.unwrap();
+    // Assert
+    assert_eq!(http::response::StatusCode::OK, response.status_code());
+    assert_eq!(None, response.headers().extension("Location"));
+    assert_eq!(None, response.headers().extension("Set-Cookie"));
+    let mut body = String::new();
+    if let http::Body::Buffer(buffer_body) = response.body() {
+        buffer_body.read_to_string(&mut body).unwrap();
+    }
+    let (_, token) = visa.as_http_header();
+    assert_eq!(format!("{expected_handoff_url} {token}"), body);
+}
+
+#[test_case("example.com" ; "paid on gate")]
+#[test_case("localhost" ; "paid on other domain")]
+#[tokio::test]
+pub async fn pay_toll_serve_should_deliver_visa_of_form_payment_without_configured_visa_cookie(
+    host: &str,
+) {
+    // Arrange
+    let visa = create_visa().unwrap();
+    let sut = setup(Box::new(create_visa));
+    let client_ip = SocketAddr::V4(SocketAddrV4::from_str("1.2.3.4:42420").unwrap());
+    let request = setup_form_payment_request_to(host, &payment_form(&visa));
+    // Act
+    let mut response = sut.serve_http(&client_ip, request).await.unwrap();
+    // Assert
+    let (_, token) = visa.as_http_header();
+    let set_cookie = response
+        .headers()
+        .extension("Set-Cookie")
+        .unwrap_or("")
+        .to_string();
+    let mut body = String::new();
+    if let http::Body::Buffer(buffer_body) = response.body() {
+        buffer_body.read_to_string(&mut body).unwrap();
+    }
+    assert!(
+        set_cookie.contains(&token) || body.contains(&token),
+        "Visa was not delivered to the client"
+    );
+}
+
+fn create_new_toll() -> proxy::Toll {
+    let recipient = proxy::Recipient::new("1.2.3.4", "Bob", "example.com:8080/page?debug");
+    let order_id = proxy::OrderId::new("gate", "order");
+    proxy::Toll::new(
+        recipient,
+        order_id,
+        Challenge::empty(),
+        Base64::encode(b"signature"),
+    )
+}
+
+#[test_case(|| PaymentError::ChallengeFailed(create_new_toll(), "hello".into()), None, "http://example.com:8080/page?debug" ; "failed challenge")]
+#[test_case(|| PaymentError::MismatchedRecipient(create_new_toll().recipient().clone(), create_new_toll()), None, "http://example.com:8080/page?debug" ; "mismatched recipient")]
+#[test_case(|| PaymentError::ChallengeFailed(create_new_toll(), "hello".into()), Some("https"), "https://example.com:8080/page?debug" ; "https")]
+#[tokio::test]
+pub async fn pay_toll_serve_should_redirect_failed_form_payment_to_new_toll(
+    payment_error: fn() -> PaymentError,
+    forwarded_proto: Option<&str>,
+    expected_location: &str,
+) {
+    // Arrange
+    let visa = create_visa().unwrap();
+    let sut = setup(Box::new(move || Err(Box::new(payment_error()))));
+    let client_ip = SocketAddr::V4(SocketAddrV4::from_str("1.2.3.4:42420").unwrap());
+    let mut request = setup_form_payment_request_to("tollkeeper.example.com", &payment_form(&visa));
+    if let Some(forwarded_proto) = forwarded_proto {
+        request
+            .headers_mut()
+            .insert("X-Forwarded-Proto", forwarded_proto);
+    }
+    // Act
+    let response = sut.serve_http(&client_ip, request).await.unwrap();
+    // Assert
+    assert_eq!(http::response::StatusCode::SeeOther, response.status_code());
+    assert_eq!(
+        Some(expected_location),
+        response.headers().extension("Location")
+    );
+    assert_eq!(None, response.headers().extension("Set-Cookie"));
+}
+
+#[tokio::test]
+pub async fn pay_toll_serve_should_return_422_for_form_payment_with_invalid_signature() {
+    // Arrange
+    let visa = create_visa().unwrap();
+    let sut = setup(Box::new(|| Err(Box::new(PaymentError::InvalidSignature))));
+    let client_ip = SocketAddr::V4(SocketAddrV4::from_str("1.2.3.4:42420").unwrap());
+    let request = setup_form_payment_request_to("localhost", &payment_form(&visa));
+    // Act
+    let response = sut.serve_http(&client_ip, request).await.unwrap();
+    // Assert
+    assert_eq!(
+        http::response::StatusCode::UnprocessableContent,
+        response.status_code()
+    );
+    assert_eq!(None, response.headers().extension("Location"));
+}
+
+#[test_case("value=hello", http::response::StatusCode::UnprocessableContent ; "missing toll")]
+#[test_case("toll=%3Chello%3E&value=hello", http::response::StatusCode::BadRequest ; "non json toll")]
+#[test_case("toll=%7B%7D&value=hello", http::response::StatusCode::UnprocessableContent ; "invalid toll")]
+#[tokio::test]
+pub async fn pay_toll_serve_should_reject_malformed_form_payment(
+    form: &str,
+    expected_status_code: http::response::StatusCode,
+) {
+    // Arrange
+    let payment_service_stub = || panic!("Malformed request got processed!");
+    let sut = setup(Box::new(payment_service_stub));
+    let client_ip = SocketAddr::V4(SocketAddrV4::from_str("1.2.3.4:42420").unwrap());
+    let request = setup_form_payment_request_to("localhost", form);
+    // Act
+    let response = sut.serve_http(&client_ip, request).await.unwrap();
+    // Assert
+    assert_eq!(expected_status_code, response.status_code());
+}
@@ -8,7 +8,10 @@ use tollkeeper::{
 };
 
 use crate::{
-    payment::{Payment, PaymentError, PaymentService, PaymentServiceImpl},
+    payment::{
+        tests::{FakeTollDeclaration, StubDescription},
+        Payment, PaymentError, PaymentService, PaymentServiceImpl,
+    },
     proxy::{Recipient, Toll},
 };
 
@@ -165,53 +168,3 @@ pub fn pay_toll_should_return_error_for_unknown_order_id() {
     let expected_err = Box::new(expected_err);
     assert_eq!(Err(expected_err), payment_result);
 }
-
-#[derive(Clone)]
-struct FakeTollDeclaration {
-    password: String,
-}
-impl FakeTollDeclaration {
-    fn new(password: String) -> Self {
-        Self { password }
-    }
-}
-impl tollkeeper::Declaration for FakeTollDeclaration {
-    fn declare(
-        &self,
-        suspect: tollkeeper::descriptions::Suspect,
-        order_id: tollkeeper::declarations::OrderIdentifier,
-    ) -> tollkeeper::declarations::Toll {
-        let mut challenge = tollkeeper::declarations::Challenge::new();
-        challenge.insert("hello".into(), "world".into());
-        tollkeeper::declarations::Toll::new(suspect, order_id, challenge)
-    }
-
-    fn pay(
-        &self,
-        payment: tollkeeper::declarations::Payment,
-        suspect: &tollkeeper::descriptions::Suspect,
-    ) -> Result<tollkeeper::declarations::Visa, tollkeeper::declarations::PaymentError> {
-        let order_id = payment.toll().order_id().clone();
-        let expires = chrono::Utc::now()
-            .checked_add_months(chrono::Months::new(12))
-            .unwrap();
-        if payment.value() == self.password {
-            let visa =
-                tollkeeper::declarations::Visa::new(order_id.clone(), suspect.clone(), expires);
-            Ok(visa)
-        } else {
-            let error = tollkeeper::declarations::PaymentError::new(
-                Box::new(payment),
-                Box::new(self.declare(suspect.clone(), order_id)),
-            );
-            Err(error)
-        }
-    }
-}
-
-struct StubDescription;
-impl tollkeeper::Description for StubDescription {
-    fn matches(&self, _: &tollkeeper::descriptions::Suspect) -> bool {
-        true
-    }
-}
@@ -0,0 +1,295 @@
+use std::{
+    collections::HashMap,
+    net,
+    sync::{Arc, Mutex},
+};
+
+use chrono::TimeZone;
+use pretty_assertions::assert_eq;
+use test_case::test_case;
+use tollkeeper::{
+    signatures::{Base64, InMemorySecretKeyProvider, Signed},
+    util::FakeDateTimeProvider,
+};
+
+use crate::{
+    data_formats::AsHttpHeader,
+    http::{self, response::StatusCode, server::HttpServe},
+    payment::{
+        tests::{FakeTollDeclaration, StubDescription},
+        visa_cookie::{
+            HandoffService, HandoffServiceImpl, SameSite, VisaCookie, VisaCookies, VisaHandoffServe,
+        },
+        UnixTimestamp, Visa,
+    },
+    proxy,
+};
+
+fn now() -> chrono::DateTime<chrono::Utc> {
+    chrono::Utc
+        .with_ymd_and_hms(2030, 12, 24, 12, 30, 15)
+        .unwrap()
+}
+
+fn create_visa(expires: chrono::DateTime<chrono::Utc>) -> Visa {
+    Visa::new(
+        proxy::OrderId::new("gate", "order"),
+        proxy::Recipient::new("1.2.3.4", "Bob", "example.com:80/page?debug"),
+        UnixTimestamp(expires),
+        Base64::encode(b"real signature ;D"),
+    )
+}
+
+fn token(visa: &Visa) -> String {
+    visa.as_http_header().1
+}
+
+#[test_case(VisaCookie::default(), false, "; HttpOnly; SameSite=Lax" ; "defaults")]
+#[test_case(VisaCookie::default(), true, "; Secure; HttpOnly; SameSite=Lax" ; "secure request")]
+#[test_case({
+    let mut cookie = VisaCookie::default();
+    cookie.set_domain("example.com");
+    cookie.set_secure(true);
+    cookie.set_http_only(false);
+    cookie.set_same_site(SameSite::Strict);
+    cookie
+}, false, "; Domain=example.com; Secure; SameSite=Strict" ; "configured")]
+#[test_case({
+    let mut cookie = VisaCookie::default();
+    cookie.set_secure(false);
+    cookie.set_same_site(SameSite::None);
+    cookie
+}, true, "; HttpOnly; SameSite=None" ; "insecure on secure request")]
+pub fn set_cookie_should_apply_attributes(
+    cookie: VisaCookie,
+    is_secure: bool,
+    expected_attributes: &str,
+) {
+    // Arrange
+    let visa = create_visa(now() + chrono::Duration::minutes(30));
+    // Act
+    let set_cookie = cookie.set_cookie(&visa, is_secure, now());
+    // Assert
+    let expected_cookie = format!(
+        "X-Keeper-Token={}; Path=/; Max-Age=1800; Expires=Tue, 24 Dec 2030 13:00:15 GMT{expected_attributes}",
+        token(&visa)
+    );
+    assert_eq!(expected_cookie, set_cookie);
+}
+
+#[test]
+pub fn set_cookie_should_expire_right_away_for_expired_visa() {
+    // Arrange
+    let visa = create_visa(now() - chrono::Duration::minutes(30));
+    let sut = VisaCookie::default();
+    // Act
+    let set_cookie = sut.set_cookie(&visa, false, now());
+    // Assert
+    assert!(set_cookie.contains("; Max-Age=0;"), "{set_cookie}");
+}
+
+#[test]
+pub fn visa_cookies_should_return_cookie_of_issuing_gate() {
+    // Arrange
+    let mut gate_cookie = VisaCookie::default();
+    gate_cookie.set_domain("example.com");
+    let mut cookies = HashMap::new();
+    cookies.insert("gate".into(), gate_cookie.clone());
+    cookies.insert("other_gate".into(), VisaCookie::default());
+    let sut = VisaCookies::new(cookies, Box::new(FakeDateTimeProvider(now())));
+    // Act
+    let cookie = sut.get("gate");
+    // Assert
+    assert_eq!(gate_cookie, cookie);
+}
+
+fn handoff_request(host: &str, form: &str) -> http::Request {
+    let body = http::Body::from_string(form.into());
+    let mut headers = http::Headers::empty();
+    headers.insert("Host", host);
+    headers.insert("Content-Type", "application/x-www-form-urlencoded");
+    headers.insert("Content-Length", form.len().to_string());
+    let headers = http::request::Headers::new(headers).unwrap();
+    http::Request::new(http::Method::Post, "/.tollkeeper/visa/", headers, body).unwrap()
+}
+
+fn token_form(token: &str) -> String {
+    url::form_urlencoded::Serializer::new(String::new())
+        .append_pair("token", token)
+        .finish()
+}
+
+fn visa_cookies() -> Arc<VisaCookies> {
+    let date_provider = FakeDateTimeProvider(now());
+    Arc::new(VisaCookies::new(HashMap::new(), Box::new(date_provider)))
+}
+
+fn client_addr() -> net::SocketAddr {
+    "1.2.3.4:5501".parse().unwrap()
+}
+
+/// Accepts or rejects every visa, recording the recipients asked for
+#[derive(Clone)]
+struct StubHandoffService {
+    is_valid: bool,
+    recipients: Arc<Mutex<Vec<proxy::Recipient>>>,
+}
+impl StubHandoffService {
+    fn new(is_valid: bool) -> Self {
+        Self {
+            is_valid,
+            recipients: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+}
+impl HandoffService for StubHandoffService {
+    fn verify_visa(&self, recipient: proxy::Recipient, visa: Visa) -> Option<Visa> {
+        self.recipients.lock().unwrap().push(recipient);
+        self.is_valid.then_some(visa)
+    }
+}
+
+#[tokio::test]
+pub async fn handoff_should_set_cookie_and_redirect_to_visited_page() {
+    // Arrange
+    let visa = create_visa(now() + chrono::Duration::hours(1));
+    let handoff_service = StubHandoffService::new(true);
+    let sut = VisaHandoffServe::new(visa_cookies(), Box::new(handoff_service.clone()));
+    let request = handoff_request("example.com", &token_form(&token(&visa)));
+    // Act
+    let response = sut.serve_http(&client_addr(), request).await.unwrap();
+    // Assert
+    assert_eq!(StatusCode::SeeOther, response.status_code());
+    assert_eq!(
+        Some("/page?debug"),
+        response.headers().extension("Location")
+    );
+    let set_cookie = response.headers().extension("Set-Cookie").unwrap_or("");
+    let expected_prefix = format!("X-Keeper-Token={}; Path=/; Max-Age=3600;", token(&visa));
+    assert!(set_cookie.starts_with(&expected_prefix), "{set_cookie}");
+    let expected_recipient = proxy::Recipient::new("1.2.3.4", "", "example.com:80/page?debug");
+    assert_eq!(
+        vec![expected_recipient],
+        *handoff_service.recipients.lock().unwrap()
+    );
+}
+
+#[test_case("example.com", "" ; "missing token")]
+#[test_case("example.com", "token=not-a-visa" ; "invalid token")]
+#[test_case("evil.example.com", "visa" ; "other host")]
+#[test_case("example.com:8080", "visa" ; "other port")]
+#[tokio::test]
+pub async fn handoff_should_reject_visa_not_issued_for_host(host: &str, form: &str) {
+    // Arrange
+    let visa = create_visa(now() + chrono::Duration::hours(1));
+    let sut = VisaHandoffServe::new(visa_cookies(), Box::new(StubHandoffService::new(true)));
+    let form = match form {
+        "visa" => token_form(&token(&visa)),
+        form => form.into(),
+    };
+    let request = handoff_request(host, &form);
+    // Act
+    let response = sut.serve_http(&client_addr(), request).await.unwrap();
+    // Assert
+    assert_eq!(StatusCode::BadRequest, response.status_code());
+    assert_eq!(None, response.headers().extension("Set-Cookie"));
+}
+
+#[tokio::test]
+pub async fn handoff_should_ignore_token_in_query() {
+    // Arrange
+    let visa = create_visa(now() + chrono::Duration::hours(1));
+    let sut = VisaHandoffServe::new(visa_cookies(), Box::new(StubHandoffService::new(true)));
+    let mut headers = http::Headers::empty();
+    headers.insert("Host", "example.com");
+    let headers = http::request::Headers::new(headers).unwrap();
+    let target = format!("/.tollkeeper/visa/?{}", token_form(&token(&visa)));
+    let request = http::Request::new(http::Method::Post, target, headers, http::Body::None);
+    // Act
+    let response = sut
+        .serve_http(&client_addr(), request.unwrap())
+        .await
+        .unwrap();
+    // Assert
+    assert_eq!(StatusCode::BadRequest, response.status_code());
+    assert_eq!(None, response.headers().extension("Set-Cookie"));
+}
+
+#[tokio::test]
+pub async fn handoff_should_reject_unverified_visa() {
+    // Arrange
+    let visa = create_visa(now() + chrono::Duration::hours(1));
+    let sut = VisaHandoffServe::new(visa_cookies(), Box::new(StubHandoffService::new(false)));
+    let request = handoff_request("example.com", &token_form(&token(&visa)));
+    // Act
+    let response = sut.serve_http(&client_addr(), request).await.unwrap();
+    // Assert
+    assert_eq!(StatusCode::BadRequest, response.status_code());
+    assert_eq!(None, response.headers().extension("Set-Cookie"));
+}
+
+fn setup_handoff_service() -> HandoffServiceImpl {
+    let destination = tollkeeper::descriptions::Destination::new("example.com", 80, "/");
+    let orders = vec![tollkeeper::Order::with_id(
+        "order",
+        vec![Box::new(StubDescription)],
+        tollkeeper::AccessPolicy::Blacklist,
+        Box::new(FakeTollDeclaration::new("secret".into())),
+    )];
+    let gates = vec![tollkeeper::Gate::with_id("gate", destination, orders).unwrap()];
+    let secret_key_provider = Box::new(InMemorySecretKeyProvider::new(b"Secret key".into()));
+    let date_provider = Box::new(FakeDateTimeProvider(now()));
+    let tollkeeper =
+        tollkeeper::Tollkeeper::new(gates, secret_key_provider, date_provider).unwrap();
+    HandoffServiceImpl::new(Arc::new(tollkeeper))
+}
+
+fn sign_visa(
+    recipient: &proxy::Recipient,
+    expires: chrono::DateTime<chrono::Utc>,
+    secret_key: &[u8],
+) -> Visa {
+    let visa = tollkeeper::declarations::Visa::new(
+        tollkeeper::declarations::OrderIdentifier::new("gate", "order"),
+        recipient.clone().into(),
+        expires,
+    );
+    Signed::sign(visa, secret_key).into()
+}
+
+#[test]
+pub fn handoff_service_should_return_valid_visa() {
+    // Arrange
+    let recipient = proxy::Recipient::new("1.2.3.4", "Bob", "example.com:80/page?debug");
+    let visa = sign_visa(
+        &recipient,
+        now() + chrono::Duration::hours(1),
+        b"Secret key",
+    );
+    let expected_token = token(&visa);
+    let sut = setup_handoff_service();
+    // Act
+    let verified = sut.verify_visa(recipient, visa);
+    // Assert
+    assert_eq!(Some(expected_token), verified.map(|v| token(&v)));
+}
+
+#[test_case("9.9.9.9", 1, b"Secret key" ; "other client")]
+#[test_case("1.2.3.4", -1, b"Secret key" ; "expired")]
+#[test_case("1.2.3.4", 1, b"Forged key" ; "forged")]
+pub fn handoff_service_should_reject_invalid_visa(
+    client_ip: &str,
+    expires_in_hours: i64,
+    secret_key: &[u8],
+) {
+    // Arrange
+    let issued_for = proxy::Recipient::new("1.2.3.4", "Bob", "example.com:80/page?debug");
+    let expires = now() + chrono::Duration::hours(expires_in_hours);
+    let visa = sign_visa(&issued_for, expires, secret_key);
+    let recipient = proxy::Recipient::new(client_ip, "Bob", "example.com:80/page?debug");
+    let sut = setup_handoff_service();
+    // Act
+    let verified = sut.verify_visa(recipient, visa);
+    // Assert
+    assert_eq!(None, verified);
+}
@@ -0,0 +1,247 @@
+use std::{collections::HashMap, net, sync::Arc};
+
+use tollkeeper::{signatures::Signed, util::DateTimeProvider};
+
+use crate::{
+    config,
+    data_formats::{AsHttpHeader, FromHttpHeader},
+    http::{
+        self,
+        request::body_reader::ReadForm,
+        server::{HttpServe, InternalServerError},
+    },
+    proxy,
+};
+
+use super::Visa;
+
+/// `SameSite` attribute of the visa cookie
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum SameSite {
+    Strict,
+    Lax,
+    /// Also sent on cross-site requests. Browsers require `Secure` for this
+    None,
+}
+impl SameSite {
+    fn as_str(&self) -> &'static str {
+        match self {
+            SameSite::Strict => "Strict",
+            SameSite::Lax => "Lax",
+            SameSite::None => "None",
+        }
+    }
+}
+
+/// Attributes of the cookie the visa is stored in. Without domain, the cookie is only sent to
+/// the host that set it
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct VisaCookie {
+    domain: Option<String>,
+    secure: Option<bool>,
+    http_only: bool,
+    same_site: SameSite,
+}
+impl Default for VisaCookie {
+    fn default() -> Self {
+        Self {
+            domain: None,
+            secure: None,
+            http_only: true,
+            same_site: SameSite::Lax,
+        }
+    }
+}
+impl VisaCookie {
+    pub fn set_domain(&mut self, domain: impl Into<String>) {
+        self.domain = Some(domain.into());
+    }
+
+    /// Sets `Secure` regardless of the request being served over https
+    pub fn set_secure(&mut self, secure: bool) {
+        self.secure = Some(secure);
+    }
+
+    pub fn set_http_only(&mut self, http_only: bool) {
+        self.http_only = http_only;
+    }
+
+    pub fn set_same_site(&mut self, same_site: SameSite) {
+        self.same_site = same_site;
+    }
+
+    /// `Set-Cookie` header value storing `visa` until it expires
+    pub fn set_cookie(
+        &self,
+        visa: &Visa,
+        is_secure: bool,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> String {
+        let (name, token) = visa.as_http_header();
+        let expires = visa.expires().0;
+        let max_age = (expires - now).num_seconds().max(0);
+        let expires = expires.format("%a, %d %b %Y %H:%M:%S GMT");
+        let mut cookie = format!("{name}={token}; Path=/; Max-Age={max_age}; Expires={expires}");
+        if let Some(domain) = &self.domain {
+            cookie.push_str(&format!("; Domain={domain}"));
+        }
+        if self.secure.unwrap_or(is_secure) {
+            cookie.push_str("; Secure");
+        }
+        if self.http_only {
+            cookie.push_str("; HttpOnly");
+        }
+        cookie.push_str(&format!("; SameSite={}", self.same_site.as_str()));
+        cookie
+    }
+}
+
+/// Visa cookies of all gates, by gate id
+pub struct VisaCookies {
+    cookies: HashMap<String, VisaCookie>,
+    date_provider: Box<dyn DateTimeProvider + Send + Sync>,
+}
+impl VisaCookies {
+    pub fn new(
+        cookies: HashMap<String, VisaCookie>,
+        date_provider: Box<dyn DateTimeProvider + Send + Sync>,
+    ) -> Self {
+        Self {
+            cookies,
+            date_provider,
+        }
+    }
+
+    /// Cookie of the gate with `gate_id`
+    pub fn get(&self, gate_id: &str) -> VisaCookie {
+        self.cookies.get(gate_id).cloned().unwrap_or_default()
+    }
+
+    /// `Set-Cookie` header value storing `visa` on the client sending `request`
+    pub fn set_cookie(&self, request: &http::Request, visa: &Visa) -> String {
+        let cookie = self.get(visa.order_id().gate_id());
+        cookie.set_cookie(visa, is_secure(request), self.date_provider.now())
+    }
+}
+
+/// Path of the [VisaHandoffServe] on every gate
+pub fn handoff_path() -> String {
+    format!("{}visa/", config::Api::RESERVED_PATH)
+}
+
+/// Url of the [VisaHandoffServe] on the gate the visa was issued for, using the scheme the
+/// client sent `request` with
+pub fn handoff_url(request: &http::Request, visa: &Visa) -> Option<url::Url> {
+    let mut url = destination_url(request, visa.recipient().destination())?;
+    url.set_path(&handoff_path());
+    url.set_query(None);
+    Some(url)
+}
+
+/// Url of the `destination` of a toll or visa, using the scheme the client sent `request` with
+pub fn destination_url(request: &http::Request, destination: &str) -> Option<url::Url> {
+    let mut url = url::Url::parse(&format!("http://{destination}")).ok()?;
+    let scheme = if is_secure(request) { "https" } else { "http" };
+    url.set_scheme(scheme).ok()?;
+    Some(url)
+}
+
+/// Returns `true` if `request` was sent to the host the visa was issued for
+pub fn is_destination_host(request: &http::Request, visa: &Visa) -> bool {
+    let target = request.absolute_target();
+    let Some(destination) = visa.destination_url() else {
+        return false;
+    };
+    target.host_str() == destination.host_str()
+        && target.port_or_known_default() == destination.port_or_known_default()
+}
+
+/// Returns `true` if the client reached tollkeeper or the proxy in front of it over https.
+/// `X-Forwarded-Proto` only reaches endpoints if sent by a trusted proxy, see
+/// [HttpEndpointsServe](http::server::HttpEndpointsServe)
+pub fn is_secure(request: &http::Request) -> bool {
+    let forwarded_proto = request.headers().extension("X-Forwarded-Proto");
+    request.is_secure() || forwarded_proto.is_some_and(|p| p.eq_ignore_ascii_case("https"))
+}
+
+/// Stores the visa posted as `token` form field in a cookie and redirects to the page the visa
+/// was issued for. Hands the visa over from the API to gates on another domain
+pub struct VisaHandoffServe {
+    visa_cookies: Arc<VisaCookies>,
+    handoff_service: Box<dyn HandoffService + Send + Sync>,
+}
+impl VisaHandoffServe {
+    pub fn new(
+        visa_cookies: Arc<VisaCookies>,
+        handoff_service: Box<dyn HandoffService + Send + Sync>,
+    ) -> Self {
+        Self {
+            visa_cookies,
+            handoff_service,
+        }
+    }
+
+    fn read_token(request: &mut http::Request) -> Option<String> {
+        let fields = request.read_form().ok()?;
+        fields
+            .into_iter()
+            .find(|(k, _)| k == "token")
+            .map(|(_, v)| v)
+    }
+}
+#[async_trait::async_trait]
+impl HttpServe for VisaHandoffServe {
+    async fn serve_http(
+        &self,
+        client_addr: &net::SocketAddr,
+        mut request: http::Request,
+    ) -> Result<http::Response, InternalServerError> {
+        let token = Self::read_token(&mut request);
+        let visa = token.and_then(|t| Visa::from_http_header(&t).ok());
+        let Some(visa) = visa.filter(|v| is_destination_host(&request, v)) else {
+            tracing::debug!("Rejecting visa hand-off on {}", request.headers().host());
+            return Ok(http::Response::bad_request());
+        };
+        let recipient = proxy::Recipient::new(
+            client_addr.ip().to_string(),
+            request.headers().user_agent().unwrap_or(""),
+            visa.recipient().destination(),
+        );
+        let Some(visa) = self.handoff_service.verify_visa(recipient, visa) else {
+            tracing::debug!("Rejecting invalid visa handed off by {client_addr}");
+            return Ok(http::Response::bad_request());
+        };
+        let Some(destination) = visa.destination_url() else {
+            return Ok(http::Response::bad_request());
+        };
+        let set_cookie = self.visa_cookies.set_cookie(&request, &visa);
+        let mut headers = http::response::Headers::empty();
+        headers.insert("Set-Cookie", set_cookie);
+        headers.insert("Cache-Control", "no-store");
+        // Only redirect within the gate, the location is never taken from the request
+        let location = &destination[url::Position::BeforePath..];
+        Ok(http::Response::see_other(location, headers))
+    }
+}
+
+/// Verifies visas before they are handed over to a gate
+pub trait HandoffService {
+    /// Returns the `visa` if it was signed by tollkeeper and grants `recipient` access
+    fn verify_visa(&self, recipient: proxy::Recipient, visa: Visa) -> Option<Visa>;
+}
+pub struct HandoffServiceImpl {
+    tollkeeper: Arc<tollkeeper::Tollkeeper>,
+}
+impl HandoffServiceImpl {
+    pub fn new(tollkeeper: Arc<tollkeeper::Tollkeeper>) -> Self {
+        Self { tollkeeper }
+    }
+}
+impl HandoffService for HandoffServiceImpl {
+    fn verify_visa(&self, recipient: proxy::Recipient, visa: Visa) -> Option<Visa> {
+        let suspect = recipient.into();
+        let visa: Signed<tollkeeper::declarations::Visa> = visa.into();
+        self.tollkeeper.verify_visa(&suspect, &visa)?;
+        Some(visa.into())
+    }
+}
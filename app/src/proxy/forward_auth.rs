@@ -0,0 +1,238 @@
+use std::{
+    error::Error,
+    fmt::{self, Display},
+    net,
+    sync::Arc,
+};
+
+use tollkeeper::{signatures::Signed, Tollkeeper};
+
+use crate::{
+    config,
+    http::{
+        self,
+        request::Request,
+        response::{Response, StatusCode},
+        server::{real_ip::RealIpResolver, Endpoint, HttpServe, InternalServerError},
+    },
+    templates::TemplateRenderer,
+};
+
+use super::{
+    toll_to_html_response, toll_to_json_response, url_to_destination, ProxyServiceImpl, Toll,
+};
+
+/// Methods reverse proxies send their auth requests with
+const METHODS: [http::Method; 6] = [
+    http::Method::Get,
+    http::Method::Head,
+    http::Method::Post,
+    http::Method::Put,
+    http::Method::Delete,
+    http::Method::Options,
+];
+
+/// Creates the endpoints reverse proxies ask for access decisions. `path` answers denied
+/// requests with the challenge (Caddy `forward_auth`, Traefik `ForwardAuth`), `{path}nginx/`
+/// with `401 Unauthorized` (nginx `auth_request`)
+pub fn create_forward_auth_endpoints(
+    path: &str,
+    config: config::Api,
+    auth_service: Arc<dyn ForwardAuthService + Send + Sync>,
+    template_renderer: Arc<dyn TemplateRenderer + Send + Sync>,
+) -> Vec<Endpoint> {
+    let nginx_path = format!("{}/nginx/", path.trim_end_matches('/'));
+    let denials = [
+        (path, Denial::PaymentRequired),
+        (nginx_path.as_str(), Denial::Unauthorized),
+    ];
+    let mut endpoints = Vec::new();
+    for (path, denial) in denials {
+        for method in METHODS {
+            let handler = ForwardAuthServe::new(
+                config.clone(),
+                denial,
+                auth_service.clone(),
+                template_renderer.clone(),
+            );
+            endpoints.push(Endpoint::new(method, path, Box::new(handler)));
+        }
+    }
+    endpoints
+}
+
+/// Response to requests that were denied access
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Denial {
+    /// `402 Payment Required` with the challenge, passed on to the client
+    PaymentRequired,
+    /// `401 Unauthorized` without body, as only 401 and 403 are understood as denial
+    Unauthorized,
+}
+
+/// Decides whether the request a reverse proxy received may pass. The original request is
+/// described by `X-Forwarded-Host` (or `Host`) and `X-Forwarded-Uri` (or `X-Original-URI`),
+/// all other headers are the ones of the client
+pub struct ForwardAuthServe {
+    config: config::Api,
+    real_ip: Option<RealIpResolver>,
+    denial: Denial,
+    auth_service: Arc<dyn ForwardAuthService + Send + Sync>,
+    template_renderer: Arc<dyn TemplateRenderer + Send + Sync>,
+}
+impl ForwardAuthServe {
+    pub fn new(
+        config: config::Api,
+        denial: Denial,
+        auth_service: Arc<dyn ForwardAuthService + Send + Sync>,
+        template_renderer: Arc<dyn TemplateRenderer + Send + Sync>,
+    ) -> Self {
+        Self {
+            real_ip: config.forward_auth_ip_resolver(),
+            config,
+            denial,
+            auth_service,
+            template_renderer,
+        }
+    }
+
+    /// Url of the request the reverse proxy asks about
+    fn original_url(headers: &http::request::Headers) -> Option<url::Url> {
+        let host = headers
+            .extension("X-Forwarded-Host")
+            .and_then(|h| h.split(',').next())
+            .map(|h| h.trim())
+            .unwrap_or(headers.host());
+        let uri = headers
+            .extension("X-Forwarded-Uri")
+            .or_else(|| headers.extension("X-Original-URI"))
+            .unwrap_or("/");
+        if !uri.starts_with('/') {
+            return None;
+        }
+        // Only reaches the endpoint if sent by a trusted proxy
+        let scheme = match headers.extension("X-Forwarded-Proto") {
+            Some(proto) if proto.trim().eq_ignore_ascii_case("https") => "https",
+            _ => "http",
+        };
+        url::Url::parse(&format!("{scheme}://{host}{uri}")).ok()
+    }
+
+    fn create_denial_response(
+        &self,
+        toll: &Toll,
+        accepts_html: bool,
+    ) -> Result<Response, InternalServerError> {
+        match self.denial {
+            Denial::Unauthorized => Ok(Response::unauthorized()),
+            Denial::PaymentRequired if accepts_html => toll_to_html_response(
+                toll,
+                &self.config.public_url(),
+                self.template_renderer.as_ref(),
+            ),
+            Denial::PaymentRequired => Ok(toll_to_json_response(toll, &self.config.public_url())),
+        }
+    }
+}
+#[async_trait::async_trait]
+impl HttpServe for ForwardAuthServe {
+    async fn serve_http(
+        &self,
+        client_addr: &net::SocketAddr,
+        request: Request,
+    ) -> Result<Response, InternalServerError> {
+        let Some(original_url) = Self::original_url(request.headers()) else {
+            tracing::debug!("Auth request without valid original url");
+            return Ok(Response::bad_request());
+        };
+        let client_addr = match &self.real_ip {
+            Some(real_ip) => match real_ip.resolve(client_addr, request.headers()) {
+                Some(addr) => addr,
+                None => {
+                    tracing::debug!("Rejecting auth request of {client_addr}: No client IP");
+                    return Ok(Response::bad_request());
+                }
+            },
+            None => *client_addr,
+        };
+        let accepts_html = request.headers().accept().unwrap_or("").contains("html");
+        let access = self
+            .auth_service
+            .check_access(&client_addr, &original_url, request.headers());
+        match access {
+            Ok(()) => Ok(Response::new(
+                StatusCode::OK,
+                Some("OK".into()),
+                http::response::Headers::empty(),
+                http::Body::None,
+            )),
+            Err(AuthError::PaymentRequired(toll)) => {
+                self.create_denial_response(&toll, accepts_html)
+            }
+            Err(AuthError::DestinationNotFound) => {
+                tracing::warn!("No gate guards {original_url}");
+                Ok(Response::not_found())
+            }
+        }
+    }
+}
+
+pub trait ForwardAuthService {
+    /// Checks if the client may access `original_url`, using the visa of `headers`
+    fn check_access(
+        &self,
+        client_addr: &net::SocketAddr,
+        original_url: &url::Url,
+        headers: &http::request::Headers,
+    ) -> Result<(), AuthError>;
+}
+pub struct ForwardAuthServiceImpl {
+    tollkeeper: Arc<Tollkeeper>,
+}
+impl ForwardAuthServiceImpl {
+    pub fn new(tollkeeper: Arc<Tollkeeper>) -> Self {
+        Self { tollkeeper }
+    }
+}
+impl ForwardAuthService for ForwardAuthServiceImpl {
+    fn check_access(
+        &self,
+        client_addr: &net::SocketAddr,
+        original_url: &url::Url,
+        headers: &http::request::Headers,
+    ) -> Result<(), AuthError> {
+        let suspect = tollkeeper::descriptions::Suspect::new(
+            client_addr.ip().to_string(),
+            headers.user_agent().unwrap_or(""),
+            url_to_destination(original_url),
+        );
+        let visa = ProxyServiceImpl::extract_visa(headers);
+        let visa: Option<Signed<tollkeeper::declarations::Visa>> = visa.map(|v| v.into());
+        self.tollkeeper
+            .check_access(&suspect, visa)
+            .map_err(|e| match e {
+                tollkeeper::err::AccessError::AccessDeniedError(toll) => {
+                    AuthError::PaymentRequired(Box::new(toll.as_ref().into()))
+                }
+                tollkeeper::err::AccessError::DestinationNotFound(_) => {
+                    AuthError::DestinationNotFound
+                }
+            })
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum AuthError {
+    PaymentRequired(Box<Toll>),
+    /// No gate guards the original url
+    DestinationNotFound,
+}
+impl Error for AuthError {}
+impl Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthError::PaymentRequired(_) => write!(f, "No payment found for accessing url!"),
+            AuthError::DestinationNotFound => write!(f, "No gate guards the url"),
+        }
+    }
+}
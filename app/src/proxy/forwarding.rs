@@ -0,0 +1,78 @@
+use std::net;
+
+use crate::http::{self, parsing::headers::is_token};
+
+/// Name the proxy uses for itself in `Via` headers
+pub const PSEUDONYM: &str = "tollkeeper";
+
+/// Creates the `Via` header value for a message received with `http_version`
+/// ([RFC 9110](https://www.rfc-editor.org/rfc/rfc9110#section-7.6.3))
+pub fn via(http_version: &str) -> String {
+    let protocol_version = http_version.strip_prefix("HTTP/").unwrap_or(http_version);
+    format!("{protocol_version} {PSEUDONYM}")
+}
+
+/// Headers telling the upstream about the client a request was forwarded for
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub enum ForwardedHeaders {
+    /// Upstream does not learn about the client
+    None,
+    /// `X-Forwarded-For`, `X-Forwarded-Host` and `X-Forwarded-Proto`
+    #[default]
+    XForwarded,
+    /// `Forwarded` ([RFC 7239](https://www.rfc-editor.org/rfc/rfc7239))
+    Forwarded,
+}
+impl ForwardedHeaders {
+    /// Adds the headers for a request from `client_ip` received over `proto`. The client is
+    /// appended to the list of previous proxies, host and protocol are always the ones seen by
+    /// this proxy. Headers of the other family are removed, as the upstream could not tell them
+    /// apart from the ones set by this proxy
+    pub fn apply(&self, headers: &mut http::request::Headers, client_ip: net::IpAddr, proto: &str) {
+        let host = headers.host().to_string();
+        match self {
+            ForwardedHeaders::None => {}
+            ForwardedHeaders::XForwarded => {
+                headers.remove("Forwarded");
+                let forwarded_for = append_to_list(
+                    headers.extensions("X-Forwarded-For"),
+                    &client_ip.to_string(),
+                );
+                headers.remove("X-Forwarded-For");
+                headers.insert("X-Forwarded-For", forwarded_for);
+                headers.remove("X-Forwarded-Host");
+                headers.insert("X-Forwarded-Host", host);
+                headers.remove("X-Forwarded-Proto");
+                headers.insert("X-Forwarded-Proto", proto);
+            }
+            ForwardedHeaders::Forwarded => {
+                headers.remove("X-Forwarded-For");
+                headers.remove("X-Forwarded-Host");
+                headers.remove("X-Forwarded-Proto");
+                let node = match client_ip {
+                    net::IpAddr::V4(ip) => ip.to_string(),
+                    net::IpAddr::V6(ip) => format!("\"[{ip}]\""),
+                };
+                let element = format!("for={node};host={};proto={proto}", quote(&host));
+                let forwarded = append_to_list(headers.extensions("Forwarded"), &element);
+                headers.remove("Forwarded");
+                headers.insert("Forwarded", forwarded);
+            }
+        }
+    }
+}
+
+fn append_to_list(values: Vec<&str>, value: &str) -> String {
+    let mut values = values;
+    values.push(value);
+    values.join(", ")
+}
+
+/// Quotes `value` unless it is a token
+fn quote(value: &str) -> String {
+    if is_token(value) {
+        value.into()
+    } else {
+        format!("\"{value}\"")
+    }
+}
@@ -1,30 +1,37 @@
 use std::error::Error;
 use std::fmt::{self, Display};
-use std::io::Write;
+use std::io;
 use std::net;
 use std::str::FromStr;
 use std::sync::Arc;
 
 use serde::ser::SerializeMap;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
 use tollkeeper::signatures::{Base64, Signed};
 use tollkeeper::Tollkeeper;
 
 use crate::data_formats::{self, AsHalJson, FromHttpHeader};
+use crate::http;
 use crate::http::request::Request;
 use crate::http::response::Response;
-use crate::http::{self, Parse};
 use crate::templates::{SerializedData, TemplateRenderer};
 use crate::{config, payment};
 
-use super::http::server::*;
+use super::http::server::{real_ip::RealIpResolver, *};
 
+pub mod forward_auth;
+pub mod forwarding;
+pub mod rewriting;
 #[cfg(test)]
 mod tests;
+pub mod upstream;
 
 pub struct ProxyServe {
     config: config::Api,
+    real_ip: Option<RealIpResolver>,
     proxy_service: Box<dyn ProxyService + Send + Sync>,
     template_renderer: Box<dyn TemplateRenderer + Send + Sync>,
+    api: Option<Box<dyn HttpServe + Send + Sync>>,
 }
 
 impl ProxyServe {
@@ -34,79 +41,158 @@ impl ProxyServe {
         template_renderer: Box<dyn TemplateRenderer + Send + Sync>,
     ) -> Self {
         Self {
+            real_ip: config.real_ip_resolver(),
             config,
             proxy_service,
             template_renderer,
+            api: None,
         }
     }
 
-    fn toll_to_json_response(&self, toll: &Toll) -> Response {
-        let json = toll.as_hal_json(&self.config.base_url);
+    /// Serves requests below [config::Api::RESERVED_PATH] with `api` instead of proxying
+    /// them, regardless of the gate
+    pub fn set_api(&mut self, api: Box<dyn HttpServe + Send + Sync>) {
+        self.api = Some(api);
+    }
+
+    fn upstream_error_to_json_response(&self, error: &UpstreamError) -> Response {
+        let json = serde_json::json!({
+            "error": error.to_string()
+        });
         let data = json.to_string();
-        let content_length = data.len().to_string();
-        let body = http::Body::from_string(data);
         let mut headers = http::Headers::empty();
-        headers.insert("Content-Type", "application/hal+json");
-        headers.insert("Content-Length", content_length);
+        headers.insert("Content-Type", "application/json");
+        headers.insert("Content-Length", data.len().to_string());
         let headers = http::response::Headers::new(headers);
-        Response::payment_required(headers, body)
+        let body = http::Body::from_string(data);
+        let status_code = error.status_code();
+        Response::new(
+            status_code,
+            Some(status_code.reason_phrase().into()),
+            headers,
+            body,
+        )
     }
 
-    fn toll_to_html_response(&self, toll: &Toll) -> Result<Response, InternalServerError> {
-        let base_url = &self.config.base_url;
-        let toll = toll.as_hal_json(base_url);
+    fn upstream_error_to_html_response(
+        &self,
+        error: &UpstreamError,
+    ) -> Result<Response, InternalServerError> {
+        let status_code = error.status_code();
+        let data = serde_json::json!({
+            "status": status_code as isize,
+            "reason": status_code.reason_phrase(),
+            "error": error.to_string()
+        });
         let page_html = self
             .template_renderer
-            .render("challenge.html", &SerializedData::new(toll))
+            .render("upstream_error.html", &SerializedData::new(data))
             .or(Err(InternalServerError::new()))?;
         let mut headers = http::Headers::empty();
         headers.insert("Content-Type", "text/html");
         headers.insert("Content-Length", page_html.len().to_string());
         let headers = http::response::Headers::new(headers);
-        let page_html_stream = page_html;
-        let body = http::Body::from_string(page_html_stream);
-        Ok(Response::payment_required(headers, body))
+        let body = http::Body::from_string(page_html);
+        Ok(Response::new(
+            status_code,
+            Some(status_code.reason_phrase().into()),
+            headers,
+            body,
+        ))
     }
 }
+#[async_trait::async_trait]
 impl HttpServe for ProxyServe {
-    fn serve_http(
+    async fn serve_http(
         &self,
         client_addr: &net::SocketAddr,
         request: Request,
     ) -> Result<Response, InternalServerError> {
+        if let Some(api) = &self.api {
+            let path = request.absolute_target().path();
+            if path.starts_with(config::Api::RESERVED_PATH) {
+                return api.serve_http(client_addr, request).await;
+            }
+        }
         let accept_header: String = request.headers().accept().unwrap_or("").into();
-        let client_addr = match &self.config.real_ip_header {
-            Some(h) => &request
-                .headers()
-                .read_real_ip(h)
-                .expect("No IP Header found"),
-            None => client_addr,
+        let client_addr = match &self.real_ip {
+            Some(real_ip) => match real_ip.resolve(client_addr, request.headers()) {
+                Some(addr) => addr,
+                None => {
+                    tracing::debug!("Rejecting request of {client_addr}: No trustworthy client IP");
+                    return Ok(Response::bad_request());
+                }
+            },
+            None => *client_addr,
         };
-        let response = self.proxy_service.proxy_request(client_addr, request);
+        let response = self
+            .proxy_service
+            .proxy_request(&client_addr, request)
+            .await;
+        let accepts_html = accept_header.contains("html");
         let response = match response {
             Ok(res) => res,
-            Err(err) => {
-                if accept_header.contains("html") {
-                    self.toll_to_html_response(&err.0)?
-                } else {
-                    self.toll_to_json_response(&err.0)
-                }
+            Err(ProxyError::PaymentRequired(err)) if accepts_html => toll_to_html_response(
+                &err.0,
+                &self.config.public_url(),
+                self.template_renderer.as_ref(),
+            )?,
+            Err(ProxyError::PaymentRequired(err)) => {
+                toll_to_json_response(&err.0, &self.config.public_url())
+            }
+            Err(ProxyError::Upstream(err)) if accepts_html => {
+                self.upstream_error_to_html_response(&err)?
             }
+            Err(ProxyError::Upstream(err)) => self.upstream_error_to_json_response(&err),
+            Err(ProxyError::ClientBody(err)) => err.as_response(),
         };
         Ok(response)
     }
 }
 
+/// Answers with the [Toll] to pay as json
+fn toll_to_json_response(toll: &Toll, base_url: &str) -> Response {
+    let json = toll.as_hal_json(base_url);
+    let data = json.to_string();
+    let content_length = data.len().to_string();
+    let body = http::Body::from_string(data);
+    let mut headers = http::Headers::empty();
+    headers.insert("Content-Type", "application/hal+json");
+    headers.insert("Content-Length", content_length);
+    let headers = http::response::Headers::new(headers);
+    Response::payment_required(headers, body)
+}
+
+/// Answers with the challenge page solving the [Toll]
+fn toll_to_html_response(
+    toll: &Toll,
+    base_url: &str,
+    template_renderer: &(dyn TemplateRenderer + Send + Sync),
+) -> Result<Response, InternalServerError> {
+    let toll = toll.as_hal_json(base_url);
+    let page_html = template_renderer
+        .render("challenge.html", &SerializedData::new(toll))
+        .or(Err(InternalServerError::new()))?;
+    let mut headers = http::Headers::empty();
+    headers.insert("Content-Type", "text/html");
+    headers.insert("Content-Length", page_html.len().to_string());
+    let headers = http::response::Headers::new(headers);
+    let body = http::Body::from_string(page_html);
+    Ok(Response::payment_required(headers, body))
+}
+
+#[async_trait::async_trait]
 pub trait ProxyService {
-    fn proxy_request(
+    async fn proxy_request(
         &self,
         client_addr: &net::SocketAddr,
         req: http::Request,
-    ) -> Result<http::Response, PaymentRequiredError>;
+    ) -> Result<http::Response, ProxyError>;
 }
 pub struct ProxyServiceImpl {
     tollkeeper: Arc<Tollkeeper>,
     url_resolver: Box<dyn UrlResolver + Send + Sync>,
+    timeouts: upstream::UpstreamTimeouts,
 }
 impl ProxyServiceImpl {
     pub fn new(
@@ -116,8 +202,14 @@ impl ProxyServiceImpl {
         Self {
             tollkeeper,
             url_resolver,
+            timeouts: upstream::UpstreamTimeouts::default(),
         }
     }
+
+    pub fn set_timeouts(&mut self, timeouts: upstream::UpstreamTimeouts) {
+        self.timeouts = timeouts;
+    }
+
     fn create_suspect(
         client_addr: &net::SocketAddr,
         req: &Request,
@@ -141,35 +233,190 @@ impl ProxyServiceImpl {
         Some(visa)
     }
 
-    fn send_request_to_proxy(&self, mut req: Request) -> Response {
-        let addr = Self::get_host_addr(&req);
-        let url = Self::host_to_url(&addr);
-        let resolved_addr = &self
-            .url_resolver
-            .resolve(&url)
-            .expect("Could not resolve url!"); //TODO: Return Error
-        let resolved_addr = resolved_addr.socket_addrs(|| None).unwrap();
-        let resolved_addr = resolved_addr
-            .iter()
-            .find(|a| a.is_ipv4())
-            .expect("Could not resolve internal url to real target");
-        let mut target_conn = net::TcpStream::connect(resolved_addr).unwrap();
-        target_conn.write_all(&req.as_bytes()).unwrap();
+    /// Removes the visa, so it does not leak into upstream logs. Only the proxy may tell the
+    /// upstream about verified visas
+    fn strip_visa(headers: &mut http::request::Headers) {
+        headers.remove("X-Keeper-Token");
+        headers.remove_cookie("X-Keeper-Token");
+        headers.remove("X-Keeper-Verified");
+    }
 
-        Response::parse(target_conn.try_clone().unwrap()).unwrap()
+    async fn send_request_to_proxy(
+        &self,
+        client_addr: &net::SocketAddr,
+        mut req: Request,
+        verified_order: Option<OrderId>,
+    ) -> Result<Response, ProxyError> {
+        let public_url = Self::public_url(&req);
+        let target = self.resolve_target(&public_url)?;
+        let proto = if req.is_secure() { "https" } else { "http" };
+        let method = req.method().clone();
+        let via = forwarding::via(&req.http_version().to_string());
+        let upgrade = Self::requested_upgrade(&req);
+        let headers = req.headers_mut();
+        headers.remove_hop_by_hop();
+        if let Some(protocols) = &upgrade {
+            headers.insert("Connection", "upgrade");
+            headers.insert("Upgrade", protocols);
+        }
+        headers.insert("Via", via);
+        Self::strip_visa(headers);
+        if let Some(order_id) = verified_order.filter(|_| target.verified_header()) {
+            headers.insert("X-Keeper-Verified", order_id.to_string());
+        }
+        target
+            .forwarded_headers()
+            .apply(headers, client_addr.ip(), proto);
+        if target.host_header() == rewriting::HostHeader::Rewrite {
+            headers.set_host(rewriting::host_header(target.url()));
+        }
+        // Requests are forwarded with the version of the proxy, regardless of the client version
+        req.set_http_version(http::HttpVersion::Http11);
+        let target_conn = self.connect_to_target(&target).await?;
+        let mut target_conn = upstream::ReadTimeoutStream::new(target_conn, self.timeouts.read);
+        let read_timeout = target_conn.switch();
+        let request = req.as_bytes();
+        self.send_to_target(&mut target_conn, &request)
+            .await
+            .map_err(|e| {
+                tracing::error!("Could not send request to upstream: {e}");
+                UpstreamError::from_io_error(&e)
+            })?;
+        if let http::Body::Stream(body) = req.body_mut() {
+            self.stream_body_to_target(&mut target_conn, body).await?;
+        }
+        let response = match upgrade {
+            Some(_) => Response::parse_upgradable_for(target_conn, &method).await,
+            None => Response::parse_for(target_conn, &method).await,
+        };
+        let mut response = response.map_err(|e| {
+            tracing::error!("Could not parse upstream response: {e}");
+            match e {
+                http::parsing::ParseError::Timeout => UpstreamError::Timeout,
+                _ => UpstreamError::InvalidResponse,
+            }
+        })?;
+        let switches_protocols =
+            response.status_code() == http::response::StatusCode::SwitchingProtocols;
+        if switches_protocols && !response.is_upgrade() {
+            tracing::error!("Upstream switched protocols without being asked to");
+            return Err(UpstreamError::InvalidResponse.into());
+        }
+        let upgraded_protocols = response.headers().extensions("Upgrade").join(", ");
+        let via = forwarding::via(&response.http_version().to_string());
+        // Responses are passed on with the version of the proxy, regardless of the upstream version
+        response.set_http_version(http::HttpVersion::Http11);
+        let headers = response.headers_mut();
+        headers.remove_hop_by_hop();
+        if switches_protocols {
+            // The new protocol may stay silent for longer than a response
+            read_timeout.disable();
+            headers.insert("Connection", "upgrade");
+            headers.insert("Upgrade", upgraded_protocols);
+        }
+        headers.insert("Via", via);
+        rewriting::rewrite_response_headers(headers, target.url(), &public_url);
+        Ok(response)
+    }
+
+    async fn send_to_target(
+        &self,
+        target_conn: &mut (impl AsyncWrite + Unpin),
+        data: &[u8],
+    ) -> io::Result<()> {
+        let sent = tokio::time::timeout(self.timeouts.read, target_conn.write_all(data)).await;
+        sent.unwrap_or(Err(io::ErrorKind::TimedOut.into()))
+    }
+
+    /// Forwards the chunks of a streamed request body as they arrive from the client
+    async fn stream_body_to_target(
+        &self,
+        target_conn: &mut (impl AsyncWrite + Unpin),
+        body: &mut http::StreamBody,
+    ) -> Result<(), ProxyError> {
+        loop {
+            let chunk = body.read_chunk().await.map_err(|e| {
+                tracing::warn!("Could not read request body from client: {e}");
+                ClientBodyError::from_io_error(&e)
+            })?;
+            let Some(chunk) = chunk else {
+                return Ok(());
+            };
+            self.send_to_target(target_conn, &chunk)
+                .await
+                .map_err(|e| {
+                    tracing::error!("Could not send request body to upstream: {e}");
+                    UpstreamError::from_io_error(&e)
+                })?;
+        }
+    }
+
+    fn resolve_target(&self, public_url: &url::Url) -> Result<upstream::Target, UpstreamError> {
+        self.url_resolver.resolve(public_url).ok_or_else(|| {
+            tracing::error!("No internal url configured for {public_url}");
+            UpstreamError::Unresolvable
+        })
+    }
+
+    async fn connect_to_target(
+        &self,
+        target: &upstream::Target,
+    ) -> Result<Box<dyn upstream::UpstreamStream>, UpstreamError> {
+        let resolved_url = target.url();
+        let host = resolved_url.host_str().unwrap_or_default();
+        let port = resolved_url.port_or_known_default().unwrap_or(80);
+        let resolved_addrs: Vec<net::SocketAddr> =
+            tokio::net::lookup_host(format!("{host}:{port}"))
+                .await
+                .map_err(|e| {
+                    tracing::error!("Could not resolve internal url {resolved_url}: {e}");
+                    UpstreamError::Unresolvable
+                })?
+                .collect();
+        let stream = upstream::connect(
+            &resolved_addrs,
+            upstream::CONNECTION_ATTEMPT_DELAY,
+            self.timeouts.connect,
+        )
+        .await
+        .map_err(|e| {
+            tracing::error!("Could not connect to {resolved_url} ({resolved_addrs:?}): {e}");
+            UpstreamError::from_io_error(&e)
+        })?;
+        let Some(tls) = target.tls() else {
+            return Ok(Box::new(stream));
+        };
+        let tls_host = resolved_url.host().unwrap_or(url::Host::Domain(""));
+        let handshake = tokio::time::timeout(self.timeouts.connect, tls.connect(tls_host, stream))
+            .await
+            .unwrap_or(Err(io::ErrorKind::TimedOut.into()));
+        match handshake {
+            Ok(stream) => Ok(Box::new(stream)),
+            Err(e) => {
+                tracing::error!("TLS handshake with {resolved_url} failed: {e}");
+                Err(UpstreamError::from_io_error(&e))
+            }
+        }
     }
 
-    fn host_to_url(host: &str) -> url::Url {
-        let url = &format!("http://{}", &host);
-        url::Url::from_str(url).expect("Failed to convert host to url::Url")
+    /// Protocols the client asks to switch to. Only HTTP/1.1 supports upgrades
+    /// ([RFC 9110](https://www.rfc-editor.org/rfc/rfc9110#section-7.8))
+    fn requested_upgrade(request: &Request) -> Option<String> {
+        let headers = request.headers();
+        let protocols = headers.extensions("Upgrade").join(", ");
+        let is_upgrade = request.http_version() == http::HttpVersion::Http11
+            && headers.contains_connection_token("upgrade")
+            && !protocols.is_empty();
+        is_upgrade.then_some(protocols)
     }
 
-    fn get_host_addr(request: &Request) -> String {
-        let target = request.absolute_target();
-        let host = target.host_str().unwrap();
-        let port = target.port().unwrap_or(80);
-        let addr = format!("{host}:{port}");
-        addr
+    /// Url of the gate the request was sent to, using the scheme of the listener
+    fn public_url(request: &Request) -> url::Url {
+        let mut url = request.absolute_target().clone();
+        url.set_path("");
+        url.set_query(None);
+        url.set_fragment(None);
+        url
     }
 }
 
@@ -182,25 +429,32 @@ fn url_to_destination(url: &url::Url) -> tollkeeper::descriptions::Destination {
     let path = format!("{}{}", url.path(), query);
     tollkeeper::descriptions::Destination::new(
         url.host().unwrap().to_string(),
-        url.port().unwrap_or(80),
+        url.port_or_known_default().unwrap_or(80),
         path,
     )
 }
+#[async_trait::async_trait]
 impl ProxyService for ProxyServiceImpl {
-    fn proxy_request(
+    async fn proxy_request(
         &self,
         client_addr: &net::SocketAddr,
         req: http::Request,
-    ) -> Result<http::Response, PaymentRequiredError> {
+    ) -> Result<http::Response, ProxyError> {
         let suspect = Self::create_suspect(client_addr, &req);
         let visa = Self::extract_visa(req.headers());
-        let visa = visa.map(|v| v.into());
+        let visa: Option<Signed<tollkeeper::declarations::Visa>> = visa.map(|v| v.into());
+        let verified_order = visa
+            .as_ref()
+            .and_then(|v| self.tollkeeper.verify_visa(&suspect, v))
+            .map(OrderId::from);
         match self.tollkeeper.check_access(&suspect, visa) {
-            Ok(()) => Ok(self.send_request_to_proxy(req)),
+            Ok(()) => Ok(self
+                .send_request_to_proxy(client_addr, req, verified_order)
+                .await?),
             Err(access_err) => match access_err {
                 tollkeeper::err::AccessError::AccessDeniedError(toll) => {
                     let toll: Toll = toll.as_ref().into();
-                    Err(PaymentRequiredError(Box::new(toll)))
+                    Err(PaymentRequiredError(Box::new(toll)).into())
                 }
                 tollkeeper::err::AccessError::DestinationNotFound(_) => {
                     Ok(http::Response::not_found())
@@ -209,6 +463,38 @@ impl ProxyService for ProxyServiceImpl {
         }
     }
 }
+#[derive(Debug, PartialEq, Eq)]
+pub enum ProxyError {
+    PaymentRequired(PaymentRequiredError),
+    Upstream(UpstreamError),
+    ClientBody(ClientBodyError),
+}
+impl Error for ProxyError {}
+impl Display for ProxyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProxyError::PaymentRequired(e) => write!(f, "{e}"),
+            ProxyError::Upstream(e) => write!(f, "{e}"),
+            ProxyError::ClientBody(e) => write!(f, "{e}"),
+        }
+    }
+}
+impl From<PaymentRequiredError> for ProxyError {
+    fn from(value: PaymentRequiredError) -> Self {
+        Self::PaymentRequired(value)
+    }
+}
+impl From<UpstreamError> for ProxyError {
+    fn from(value: UpstreamError) -> Self {
+        Self::Upstream(value)
+    }
+}
+impl From<ClientBodyError> for ProxyError {
+    fn from(value: ClientBodyError) -> Self {
+        Self::ClientBody(value)
+    }
+}
+
 #[derive(Debug, PartialEq, Eq)]
 pub struct PaymentRequiredError(Box<Toll>);
 impl Error for PaymentRequiredError {}
@@ -218,6 +504,87 @@ impl Display for PaymentRequiredError {
     }
 }
 
+/// Failure while forwarding a request to the upstream target.
+///
+/// Details are only logged, as they may contain internal addresses
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum UpstreamError {
+    /// Internal url could not be resolved to an address
+    Unresolvable,
+    /// No connection could be established or it broke while sending the request
+    ConnectionFailed,
+    /// Upstream did not answer in time
+    Timeout,
+    /// Upstream did not answer with a valid HTTP response
+    InvalidResponse,
+}
+impl UpstreamError {
+    fn from_io_error(error: &io::Error) -> Self {
+        match error.kind() {
+            io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => Self::Timeout,
+            _ => Self::ConnectionFailed,
+        }
+    }
+
+    pub fn status_code(&self) -> http::response::StatusCode {
+        match self {
+            UpstreamError::Timeout => http::response::StatusCode::GatewayTimeout,
+            _ => http::response::StatusCode::BadGateway,
+        }
+    }
+}
+impl Error for UpstreamError {}
+impl Display for UpstreamError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UpstreamError::Unresolvable => write!(f, "Could not resolve upstream target"),
+            UpstreamError::ConnectionFailed => write!(f, "Could not connect to upstream target"),
+            UpstreamError::Timeout => write!(f, "Upstream target did not respond in time"),
+            UpstreamError::InvalidResponse => {
+                write!(f, "Upstream target responded with an invalid response")
+            }
+        }
+    }
+}
+
+/// Failure while reading the streamed request body from the client
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ClientBodyError {
+    /// Body is not framed correctly or the client disconnected
+    Invalid,
+    /// Body exceeds [Request::MAX_BODY_SIZE]
+    TooLarge,
+    /// Client did not send the body in time
+    Timeout,
+}
+impl ClientBodyError {
+    fn from_io_error(error: &io::Error) -> Self {
+        match error.kind() {
+            io::ErrorKind::QuotaExceeded => Self::TooLarge,
+            io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => Self::Timeout,
+            _ => Self::Invalid,
+        }
+    }
+
+    pub fn as_response(&self) -> Response {
+        match self {
+            ClientBodyError::Invalid => Response::bad_request(),
+            ClientBodyError::TooLarge => Response::content_too_large(),
+            ClientBodyError::Timeout => Response::request_timeout(),
+        }
+    }
+}
+impl Error for ClientBodyError {}
+impl Display for ClientBodyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ClientBodyError::Invalid => write!(f, "Request body is invalid"),
+            ClientBodyError::TooLarge => write!(f, "Request body is too large"),
+            ClientBodyError::Timeout => write!(f, "Request body was not sent in time"),
+        }
+    }
+}
+
 #[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq, Eq, Clone)]
 pub struct Toll {
     recipient: Recipient,
@@ -291,7 +658,7 @@ impl TryFrom<Toll> for Signed<tollkeeper::declarations::Toll> {
     }
 }
 impl data_formats::AsHalJson for Toll {
-    fn as_hal_json(&self, base_url: &url::Url) -> serde_json::Value {
+    fn as_hal_json(&self, base_url: &str) -> serde_json::Value {
         serde_json::json!({
             "toll": self,
             "_links": {
@@ -526,21 +893,21 @@ impl From<Recipient> for tollkeeper::descriptions::Suspect {
     }
 }
 
-/// Resolves public url to internal url
+/// Resolves public url to internal [upstream::Target]
 pub trait UrlResolver {
-    fn resolve(&self, public_url: &url::Url) -> Option<url::Url>;
+    fn resolve(&self, public_url: &url::Url) -> Option<upstream::Target>;
 }
 pub struct UrlResolverImpl {
-    urls: indexmap::IndexMap<url::Url, url::Url>,
+    urls: indexmap::IndexMap<url::Url, upstream::Target>,
 }
 
 impl UrlResolverImpl {
-    pub fn new(urls: indexmap::IndexMap<url::Url, url::Url>) -> Self {
+    pub fn new(urls: indexmap::IndexMap<url::Url, upstream::Target>) -> Self {
         Self { urls }
     }
 }
 impl UrlResolver for UrlResolverImpl {
-    fn resolve(&self, public_url: &url::Url) -> Option<url::Url> {
+    fn resolve(&self, public_url: &url::Url) -> Option<upstream::Target> {
         self.urls.get(public_url).cloned()
     }
 }
@@ -0,0 +1,87 @@
+use crate::http::response;
+
+/// `Host` header the upstream receives
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub enum HostHeader {
+    /// Forwards the public host sent by the client
+    #[default]
+    Preserve,
+    /// Replaces the host with the one of the internal url
+    Rewrite,
+}
+
+/// Host of `url` as sent in the `Host` header. Default ports are omitted
+pub fn host_header(url: &url::Url) -> String {
+    let host = url.host_str().unwrap_or_default();
+    match url.port() {
+        Some(port) => format!("{host}:{port}"),
+        None => host.into(),
+    }
+}
+
+/// Points headers referencing the `internal` origin back to the `public` one, so the internal
+/// url does not leak to clients
+pub fn rewrite_response_headers(
+    headers: &mut response::Headers,
+    internal: &url::Url,
+    public: &url::Url,
+) {
+    if internal.origin() == public.origin() {
+        return;
+    }
+    for key in ["Location", "Content-Location"] {
+        let rewritten = headers
+            .extension(key)
+            .and_then(|v| rewrite_location(v, internal, public));
+        if let Some(location) = rewritten {
+            headers.remove(key);
+            headers.insert(key, location);
+        }
+    }
+    let internal_host = internal.host_str().unwrap_or_default();
+    let public_host = public.host_str().unwrap_or_default();
+    let cookies: Vec<String> = headers
+        .extensions("Set-Cookie")
+        .iter()
+        .map(|c| rewrite_cookie_domain(c, internal_host, public_host))
+        .collect();
+    if !cookies.is_empty() {
+        headers.remove("Set-Cookie");
+        for cookie in cookies {
+            headers.insert("Set-Cookie", cookie);
+        }
+    }
+}
+
+/// Replaces the origin of an absolute `location` pointing at `internal`. Returns [None] for
+/// relative locations and other origins
+pub fn rewrite_location(location: &str, internal: &url::Url, public: &url::Url) -> Option<String> {
+    let location_url = url::Url::parse(location).ok()?;
+    if location_url.origin() != internal.origin() {
+        return None;
+    }
+    let public_origin = public.origin().ascii_serialization();
+    let path = &location_url[url::Position::BeforePath..];
+    Some(format!("{public_origin}{path}"))
+}
+
+/// Replaces the `Domain` attribute of a `Set-Cookie` value if it names the `internal_host`
+pub fn rewrite_cookie_domain(set_cookie: &str, internal_host: &str, public_host: &str) -> String {
+    set_cookie
+        .split(';')
+        .map(|attribute| {
+            let (key, value) = attribute.split_once('=').unwrap_or((attribute, ""));
+            let domain = value.trim();
+            let is_internal_domain = key.trim().eq_ignore_ascii_case("domain")
+                && domain
+                    .trim_start_matches('.')
+                    .eq_ignore_ascii_case(internal_host);
+            if is_internal_domain {
+                format!("{key}={public_host}")
+            } else {
+                attribute.into()
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(";")
+}
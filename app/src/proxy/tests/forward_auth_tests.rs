@@ -0,0 +1,356 @@
+use std::{
+    collections::HashMap,
+    io::Read,
+    net,
+    sync::{Arc, Mutex},
+};
+
+use pretty_assertions::assert_eq;
+use test_case::test_case;
+use tollkeeper::{
+    descriptions,
+    signatures::{Base64, InMemorySecretKeyProvider},
+    util::FakeDateTimeProvider,
+};
+
+use crate::{
+    config,
+    http::{
+        self, request, response::StatusCode, server::HttpServe, Body, Method, Request, Response,
+    },
+    proxy::{
+        forward_auth::{
+            create_forward_auth_endpoints, AuthError, Denial, ForwardAuthServe, ForwardAuthService,
+            ForwardAuthServiceImpl,
+        },
+        tests::{StubDescription, StubTollDeclaration},
+        Challenge, OrderId, Recipient, Toll,
+    },
+    templates::{handlebars::HandlebarTemplateRenderer, InMemoryTemplateStore},
+};
+
+/// Answers every check with `result`, remembering the urls it was asked about
+struct StubForwardAuthService {
+    result: Result<(), AuthError>,
+    checked_urls: Mutex<Vec<(net::SocketAddr, url::Url)>>,
+}
+impl StubForwardAuthService {
+    fn new(result: Result<(), AuthError>) -> Self {
+        Self {
+            result,
+            checked_urls: Mutex::new(Vec::new()),
+        }
+    }
+}
+impl ForwardAuthService for StubForwardAuthService {
+    fn check_access(
+        &self,
+        client_addr: &net::SocketAddr,
+        original_url: &url::Url,
+        _: &request::Headers,
+    ) -> Result<(), AuthError> {
+        self.checked_urls
+            .lock()
+            .unwrap()
+            .push((*client_addr, original_url.clone()));
+        match &self.result {
+            Ok(()) => Ok(()),
+            Err(AuthError::PaymentRequired(toll)) => Err(AuthError::PaymentRequired(toll.clone())),
+            Err(AuthError::DestinationNotFound) => Err(AuthError::DestinationNotFound),
+        }
+    }
+}
+
+fn config() -> config::Api {
+    config::Api {
+        base_url: Some(url::Url::parse("http://guard.tollkeeper.ch/").unwrap()),
+        single_port: None,
+        real_ip_header: None,
+        trusted_proxies: None,
+        real_ip_fallback: None,
+    }
+}
+
+fn toll() -> Toll {
+    Toll {
+        recipient: Recipient {
+            client_ip: "192.1.2.3".into(),
+            user_agent: "Bot".into(),
+            destination: "example.com".into(),
+        },
+        order_id: OrderId {
+            gate_id: "12".into(),
+            order_id: "13".into(),
+        },
+        challenge: Challenge::new(Vec::new()),
+        signature: Base64::encode(b"do-not-modify"),
+    }
+}
+
+fn setup(
+    result: Result<(), AuthError>,
+    denial: Denial,
+) -> (ForwardAuthServe, Arc<StubForwardAuthService>) {
+    let auth_service = Arc::new(StubForwardAuthService::new(result));
+    let mut templates = HashMap::new();
+    templates.insert("challenge.html".into(), "<div>Stub</div>".into());
+    let template_renderer = HandlebarTemplateRenderer::new(
+        Box::new(InMemoryTemplateStore::new(templates)),
+        url::Url::parse("http://localhost/").unwrap(),
+    );
+    let sut = ForwardAuthServe::new(
+        config(),
+        denial,
+        auth_service.clone(),
+        Arc::new(template_renderer),
+    );
+    (sut, auth_service)
+}
+
+fn auth_request(headers: &[(&str, &str)]) -> Request {
+    let mut request_headers = http::Headers::empty();
+    request_headers.insert("Host", "tollkeeper:8080");
+    for (name, value) in headers {
+        request_headers.insert(*name, *value);
+    }
+    let request_headers = request::Headers::new(request_headers).unwrap();
+    Request::new(Method::Get, "/api/auth/", request_headers, Body::None).unwrap()
+}
+
+fn client_addr() -> net::SocketAddr {
+    "1.2.3.4:5501".parse().unwrap()
+}
+
+fn read_body(response: &mut Response) -> String {
+    let mut body = String::new();
+    match response.body() {
+        Body::Buffer(buffer_body) => _ = buffer_body.read_to_string(&mut body).unwrap(),
+        Body::Stream(_) => panic!("unexpected stream body"),
+        Body::None => {}
+    }
+    body
+}
+
+#[test_case(&[("X-Forwarded-Host", "example.com"), ("X-Forwarded-Uri", "/page?debug")], "http://example.com/page?debug" ; "forward_auth")]
+#[test_case(&[("X-Forwarded-Host", "example.com:8000"), ("X-Forwarded-Proto", "https"), ("X-Forwarded-Uri", "/")], "https://example.com:8000/" ; "host with port")]
+#[test_case(&[("X-Forwarded-Host", "example.com"), ("X-Forwarded-Proto", "https"), ("X-Forwarded-Uri", "/")], "https://example.com/" ; "https")]
+#[test_case(&[("X-Original-URI", "/page")], "http://tollkeeper:8080/page" ; "auth_request with host")]
+#[test_case(&[("X-Forwarded-Host", "example.com")], "http://example.com/" ; "missing uri")]
+#[tokio::test]
+pub async fn serve_should_allow_access_to_original_url(
+    headers: &[(&str, &str)],
+    expected_url: &str,
+) {
+    // Arrange
+    let (sut, auth_service) = setup(Ok(()), Denial::PaymentRequired);
+    let request = auth_request(headers);
+    // Act
+    let response = sut.serve_http(&client_addr(), request).await.unwrap();
+    // Assert
+    assert_eq!(StatusCode::OK, response.status_code());
+    let expected_checks = vec![(client_addr(), url::Url::parse(expected_url).unwrap())];
+    assert_eq!(expected_checks, *auth_service.checked_urls.lock().unwrap());
+}
+
+#[tokio::test]
+pub async fn serve_should_check_access_for_client_forwarded_by_proxy() {
+    // Arrange
+    let (sut, auth_service) = setup(Ok(()), Denial::PaymentRequired);
+    let proxy_addr: net::SocketAddr = "10.0.0.2:5501".parse().unwrap();
+    // Act
+    for client in ["1.1.1.1", "2.2.2.2"] {
+        let request = auth_request(&[
+            ("X-Forwarded-Host", "example.com"),
+            ("X-Forwarded-For", client),
+        ]);
+        sut.serve_http(&proxy_addr, request).await.unwrap();
+    }
+    // Assert
+    let checked_clients: Vec<net::SocketAddr> = auth_service
+        .checked_urls
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(client_addr, _)| *client_addr)
+        .collect();
+    let expected_clients: Vec<net::SocketAddr> =
+        vec!["1.1.1.1:0".parse().unwrap(), "2.2.2.2:0".parse().unwrap()];
+    assert_eq!(expected_clients, checked_clients);
+}
+
+#[tokio::test]
+pub async fn serve_should_reject_proxy_without_forwarded_client() {
+    // Arrange
+    let (sut, auth_service) = setup(Ok(()), Denial::PaymentRequired);
+    let proxy_addr: net::SocketAddr = "10.0.0.2:5501".parse().unwrap();
+    let request = auth_request(&[("X-Forwarded-Host", "example.com")]);
+    // Act
+    let response = sut.serve_http(&proxy_addr, request).await.unwrap();
+    // Assert
+    assert_eq!(StatusCode::BadRequest, response.status_code());
+    assert!(auth_service.checked_urls.lock().unwrap().is_empty());
+}
+
+#[tokio::test]
+pub async fn serve_should_return_challenge_if_access_is_denied() {
+    // Arrange
+    let (sut, _) = setup(
+        Err(AuthError::PaymentRequired(Box::new(toll()))),
+        Denial::PaymentRequired,
+    );
+    let request = auth_request(&[("X-Forwarded-Host", "example.com")]);
+    // Act
+    let mut response = sut.serve_http(&client_addr(), request).await.unwrap();
+    // Assert
+    assert_eq!(StatusCode::PaymentRequired, response.status_code());
+    assert_eq!(
+        Some("application/hal+json"),
+        response.headers().content_type()
+    );
+    let body: serde_json::Value = serde_json::from_str(&read_body(&mut response)).unwrap();
+    assert_eq!(
+        serde_json::json!("http://guard.tollkeeper.ch/api/pay/"),
+        body["_links"]["pay"]
+    );
+}
+
+#[tokio::test]
+pub async fn serve_should_return_challenge_page_if_client_accepts_html() {
+    // Arrange
+    let (sut, _) = setup(
+        Err(AuthError::PaymentRequired(Box::new(toll()))),
+        Denial::PaymentRequired,
+    );
+    let request = auth_request(&[
+        ("X-Forwarded-Host", "example.com"),
+        ("Accept", "text/html,*/*;q=0.8"),
+    ]);
+    // Act
+    let mut response = sut.serve_http(&client_addr(), request).await.unwrap();
+    // Assert
+    assert_eq!(StatusCode::PaymentRequired, response.status_code());
+    assert_eq!(Some("text/html"), response.headers().content_type());
+    assert_eq!("<div>Stub</div>", read_body(&mut response));
+}
+
+#[tokio::test]
+pub async fn serve_should_return_unauthorized_for_auth_request() {
+    // Arrange
+    let (sut, _) = setup(
+        Err(AuthError::PaymentRequired(Box::new(toll()))),
+        Denial::Unauthorized,
+    );
+    let request = auth_request(&[("X-Original-URI", "/")]);
+    // Act
+    let response = sut.serve_http(&client_addr(), request).await.unwrap();
+    // Assert
+    assert_eq!(StatusCode::Unauthorized, response.status_code());
+}
+
+#[test_case(Err(AuthError::DestinationNotFound), &[("X-Forwarded-Uri", "/")], StatusCode::NotFound ; "unguarded destination")]
+#[test_case(Ok(()), &[("X-Forwarded-Uri", "http://example.com/")], StatusCode::BadRequest ; "absolute uri")]
+#[tokio::test]
+pub async fn serve_should_return_error_for_invalid_auth_request(
+    result: Result<(), AuthError>,
+    headers: &[(&str, &str)],
+    expected_status_code: StatusCode,
+) {
+    // Arrange
+    let (sut, _) = setup(result, Denial::PaymentRequired);
+    let request = auth_request(headers);
+    // Act
+    let response = sut.serve_http(&client_addr(), request).await.unwrap();
+    // Assert
+    assert_eq!(expected_status_code, response.status_code());
+}
+
+#[tokio::test]
+pub async fn create_forward_auth_endpoints_should_serve_nginx_under_own_path() {
+    // Arrange
+    let auth_service = Arc::new(StubForwardAuthService::new(Err(
+        AuthError::PaymentRequired(Box::new(toll())),
+    )));
+    let template_renderer = HandlebarTemplateRenderer::new(
+        Box::new(InMemoryTemplateStore::new(HashMap::new())),
+        url::Url::parse("http://localhost/").unwrap(),
+    );
+    let endpoints = create_forward_auth_endpoints(
+        "/api/auth/",
+        config(),
+        auth_service,
+        Arc::new(template_renderer),
+    );
+    let sut = http::server::HttpEndpointsServe::new(endpoints, None);
+    // Act
+    let mut status_codes = Vec::new();
+    for target in ["/api/auth/", "/api/auth/nginx/"] {
+        let mut request = auth_request(&[("X-Forwarded-Uri", "/")]);
+        request =
+            Request::new(Method::Head, target, request.headers().clone(), Body::None).unwrap();
+        let response = sut.serve_http(&client_addr(), request).await.unwrap();
+        status_codes.push(response.status_code());
+    }
+    // Assert
+    let expected_status_codes = vec![StatusCode::PaymentRequired, StatusCode::Unauthorized];
+    assert_eq!(expected_status_codes, status_codes);
+}
+
+fn setup_service(requires_challenge: bool) -> ForwardAuthServiceImpl {
+    let orders = vec![tollkeeper::Order::new(
+        vec![Box::new(StubDescription {
+            is_match: requires_challenge,
+        })],
+        tollkeeper::AccessPolicy::Blacklist,
+        Box::new(StubTollDeclaration),
+    )];
+    let destination = descriptions::Destination::new("example.com", 80, "/");
+    let gates = vec![tollkeeper::Gate::new(destination, orders).unwrap()];
+    let secret_key_provider = Box::new(InMemorySecretKeyProvider::new("Secret key".into()));
+    let date_provider = Box::new(FakeDateTimeProvider(chrono::Utc::now()));
+    let tollkeeper =
+        tollkeeper::Tollkeeper::new(gates, secret_key_provider, date_provider).unwrap();
+    ForwardAuthServiceImpl::new(Arc::new(tollkeeper))
+}
+
+#[test_case(false, "http://example.com/", true ; "allowed suspect")]
+#[test_case(true, "http://example.com/", false ; "challenged suspect")]
+pub fn check_access_should_ask_tollkeeper(
+    requires_challenge: bool,
+    original_url: &str,
+    expected_access: bool,
+) {
+    // Arrange
+    let sut = setup_service(requires_challenge);
+    let headers = auth_request(&[("User-Agent", "Bot")]).headers().clone();
+    // Act
+    let access = sut.check_access(
+        &client_addr(),
+        &url::Url::parse(original_url).unwrap(),
+        &headers,
+    );
+    // Assert
+    assert_eq!(expected_access, access.is_ok(), "{access:?}");
+    if let Err(AuthError::PaymentRequired(toll)) = access {
+        let expected_recipient = Recipient {
+            client_ip: "1.2.3.4".into(),
+            user_agent: "Bot".into(),
+            destination: "example.com:80/".into(),
+        };
+        assert_eq!(expected_recipient, toll.recipient);
+    }
+}
+
+#[test]
+pub fn check_access_should_fail_for_unguarded_destination() {
+    // Arrange
+    let sut = setup_service(false);
+    let headers = auth_request(&[]).headers().clone();
+    // Act
+    let access = sut.check_access(
+        &client_addr(),
+        &url::Url::parse("http://unknown.example.com/").unwrap(),
+        &headers,
+    );
+    // Assert
+    assert_eq!(Err(AuthError::DestinationNotFound), access);
+}
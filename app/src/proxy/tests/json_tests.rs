@@ -30,7 +30,7 @@ pub fn serializing_toll_should_return_expected_json() {
     };
     // Act
     let base_url = url::Url::parse("http://tollkeeper.com").unwrap();
-    let toll_json = toll.as_hal_json(&base_url);
+    let toll_json = toll.as_hal_json(base_url.as_str());
     // Assert
     let expected_json = json!({
         "toll": {
@@ -5,22 +5,26 @@ use std::{
 
 use crate::http;
 use pretty_assertions::assert_eq;
+use tollkeeper::{declarations, descriptions};
 
-use super::{PaymentRequiredError, ProxyService};
+use super::{ProxyError, ProxyService};
 
+mod forward_auth_tests;
 mod header_tests;
 mod json_tests;
 mod proxy_serve_tests;
 mod proxy_service_tests;
+mod rewriting_tests;
+mod upstream_tests;
 
 struct StubProxyService {
     proxy_request_result:
-        Box<dyn Fn() -> Result<http::Response, PaymentRequiredError> + Send + Sync + 'static>,
+        Box<dyn Fn() -> Result<http::Response, ProxyError> + Send + Sync + 'static>,
 }
 impl StubProxyService {
     pub fn new(
         proxy_request_result: Box<
-            dyn Fn() -> Result<http::Response, PaymentRequiredError> + Send + Sync + 'static,
+            dyn Fn() -> Result<http::Response, ProxyError> + Send + Sync + 'static,
         >,
     ) -> Self {
         Self {
@@ -28,12 +32,13 @@ impl StubProxyService {
         }
     }
 }
+#[async_trait::async_trait]
 impl ProxyService for StubProxyService {
-    fn proxy_request(
+    async fn proxy_request(
         &self,
         _: &net::SocketAddr,
         _: http::Request,
-    ) -> Result<http::Response, PaymentRequiredError> {
+    ) -> Result<http::Response, ProxyError> {
         (self.proxy_request_result)()
     }
 }
@@ -42,12 +47,13 @@ impl ProxyService for StubProxyService {
 struct SpyProxyService {
     proxy_request_calls: Arc<Mutex<Vec<ProxyRequestCall>>>,
 }
+#[async_trait::async_trait]
 impl ProxyService for SpyProxyService {
-    fn proxy_request(
+    async fn proxy_request(
         &self,
         client_addr: &net::SocketAddr,
         req: http::Request,
-    ) -> Result<http::Response, PaymentRequiredError> {
+    ) -> Result<http::Response, ProxyError> {
         let call = ProxyRequestCall {
             client_addr: *client_addr,
             request_headers: req.headers().clone(),
@@ -78,3 +84,37 @@ struct ProxyRequestCall {
     client_addr: net::SocketAddr,
     request_headers: http::request::Headers,
 }
+
+struct StubDescription {
+    is_match: bool,
+}
+impl tollkeeper::Description for StubDescription {
+    fn matches(&self, _: &descriptions::Suspect) -> bool {
+        self.is_match
+    }
+}
+
+/// Declares tolls without challenge and rejects every payment with a new one
+struct StubTollDeclaration;
+impl tollkeeper::Declaration for StubTollDeclaration {
+    fn declare(
+        &self,
+        suspect: descriptions::Suspect,
+        order_id: declarations::OrderIdentifier,
+    ) -> declarations::Toll {
+        declarations::Toll::new(suspect, order_id, declarations::Challenge::new())
+    }
+
+    fn pay(
+        &self,
+        payment: declarations::Payment,
+        suspect: &descriptions::Suspect,
+    ) -> Result<declarations::Visa, declarations::PaymentError> {
+        let order_id = payment.toll().order_id().clone();
+        let new_toll = self.declare(suspect.clone(), order_id);
+        Err(declarations::PaymentError::new(
+            Box::new(payment),
+            Box::new(new_toll),
+        ))
+    }
+}
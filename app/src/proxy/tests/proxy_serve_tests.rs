@@ -1,25 +1,50 @@
 use pretty_assertions::assert_eq;
 use std::collections::HashMap;
-use std::io::Read;
+use std::io::{Cursor, Read, Write};
 use std::net;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
 
-use tollkeeper::signatures::Base64;
+use tollkeeper::descriptions;
+use tollkeeper::signatures::{Base64, InMemorySecretKeyProvider};
+use tollkeeper::util::FakeDateTimeProvider;
 
 use crate::config;
 use crate::http::response::{self, StatusCode};
-use crate::http::server::HttpServe;
-use crate::http::{self, request, Body, Headers, Request};
-use crate::proxy::tests::{ProxyRequestCall, SpyProxyService};
-use crate::proxy::{Challenge, OrderId, ProxyServe};
-use crate::proxy::{PaymentRequiredError, Recipient, Toll};
+use crate::http::server::{cancellation_token, ConnectionSettings, HttpServe, Server};
+use crate::http::{self, request, Body, Chunk, ChunkedTcpStream, Headers, Request, StreamBody};
+use crate::proxy::tests::{
+    ProxyRequestCall, SpyProxyService, StubDescription, StubTollDeclaration,
+};
+use crate::proxy::upstream::Target;
+use crate::proxy::{Challenge, OrderId, ProxyServe, ProxyServiceImpl, UrlResolverImpl};
+use crate::proxy::{PaymentRequiredError, ProxyError, Recipient, Toll, UpstreamError};
 use crate::templates::handlebars::HandlebarTemplateRenderer;
 use crate::templates::InMemoryTemplateStore;
 use test_case::test_case;
 
 use super::StubProxyService;
 
+struct StubApi;
+#[async_trait::async_trait]
+impl HttpServe for StubApi {
+    async fn serve_http(
+        &self,
+        _: &net::SocketAddr,
+        _: Request,
+    ) -> Result<http::Response, http::server::InternalServerError> {
+        Ok(http::Response::new(
+            StatusCode::OK,
+            Some("Api".into()),
+            response::Headers::empty(),
+            Body::None,
+        ))
+    }
+}
+
 fn setup_with_ok_stub() -> ProxyServe {
-    fn create_response() -> Result<http::Response, PaymentRequiredError> {
+    fn create_response() -> Result<http::Response, ProxyError> {
         let response = http::Response::new(
             StatusCode::OK,
             Some("OK".into()),
@@ -31,8 +56,11 @@ fn setup_with_ok_stub() -> ProxyServe {
     let create_response = Box::new(create_response);
     let stub_proxy_service = StubProxyService::new(create_response);
     let server_config = config::Api {
-        base_url: url::Url::parse("http://guard.tollkeeper.ch/").unwrap(),
+        base_url: Some(url::Url::parse("http://guard.tollkeeper.ch/").unwrap()),
+        single_port: None,
         real_ip_header: None,
+        trusted_proxies: None,
+        real_ip_fallback: None,
     };
     let template_store = InMemoryTemplateStore::new(HashMap::new());
     let template_renderer = HandlebarTemplateRenderer::new(
@@ -47,7 +75,21 @@ fn setup_with_ok_stub() -> ProxyServe {
 }
 
 fn setup_with_failing_stub(templates: Option<HashMap<String, String>>) -> ProxyServe {
-    fn create_error() -> Result<http::Response, PaymentRequiredError> {
+    let server_config = config::Api {
+        base_url: Some(url::Url::parse("http://guard.tollkeeper.ch/").unwrap()),
+        single_port: None,
+        real_ip_header: None,
+        trusted_proxies: None,
+        real_ip_fallback: None,
+    };
+    setup_with_failing_stub_and_config(server_config, templates)
+}
+
+fn setup_with_failing_stub_and_config(
+    server_config: config::Api,
+    templates: Option<HashMap<String, String>>,
+) -> ProxyServe {
+    fn create_error() -> Result<http::Response, ProxyError> {
         let toll = Toll {
             recipient: Recipient {
                 client_ip: "192.1.2.3".into(),
@@ -61,13 +103,35 @@ fn setup_with_failing_stub(templates: Option<HashMap<String, String>>) -> ProxyS
             challenge: Challenge::new(Vec::new()),
             signature: Base64::encode(b"do-not-modify"),
         };
-        Err(PaymentRequiredError(Box::new(toll)))
+        Err(PaymentRequiredError(Box::new(toll)).into())
     }
     let create_error = Box::new(create_error);
     let stub_proxy_service = StubProxyService::new(create_error);
+    let templates = templates.unwrap_or_default();
+    let template_store = InMemoryTemplateStore::new(templates);
+    let template_renderer = HandlebarTemplateRenderer::new(
+        Box::new(template_store),
+        url::Url::parse("http://localhost/").unwrap(),
+    );
+    ProxyServe::new(
+        server_config,
+        Box::new(stub_proxy_service),
+        Box::new(template_renderer),
+    )
+}
+
+fn setup_with_upstream_error(
+    error: UpstreamError,
+    templates: Option<HashMap<String, String>>,
+) -> ProxyServe {
+    let create_error = Box::new(move || Err(error.into()));
+    let stub_proxy_service = StubProxyService::new(create_error);
     let server_config = config::Api {
-        base_url: url::Url::parse("http://guard.tollkeeper.ch/").unwrap(),
+        base_url: Some(url::Url::parse("http://guard.tollkeeper.ch/").unwrap()),
+        single_port: None,
         real_ip_header: None,
+        trusted_proxies: None,
+        real_ip_fallback: None,
     };
     let templates = templates.unwrap_or_default();
     let template_store = InMemoryTemplateStore::new(templates);
@@ -103,8 +167,8 @@ const fn client_addr() -> net::SocketAddr {
     net::SocketAddr::V4(v4_addr)
 }
 
-#[test]
-pub fn serve_should_return_response_of_target() {
+#[tokio::test]
+pub async fn serve_should_return_response_of_target() {
     // Arrange
     let sut = setup_with_ok_stub();
     // Act
@@ -112,7 +176,7 @@ pub fn serve_should_return_response_of_target() {
     headers.insert("Host", "127.0.0.1:65000");
     let headers = request::Headers::new(headers).unwrap();
     let request = Request::new(http::Method::Get, "/", headers, Body::None).unwrap();
-    let response = sut.serve_http(&client_addr(), request);
+    let response = sut.serve_http(&client_addr(), request).await;
     // Assert
     assert!(response.is_ok());
     let response = response.unwrap();
@@ -121,11 +185,15 @@ pub fn serve_should_return_response_of_target() {
 
 #[test_case("X-Real-Ip")]
 #[test_case("My-Ip")]
-pub fn serve_should_pass_real_ip_from_header_if_specified(real_ip_header_name: &str) {
+#[tokio::test]
+pub async fn serve_should_pass_real_ip_from_header_if_specified(real_ip_header_name: &str) {
     // Arrange
     let config = config::Api {
-        base_url: url::Url::parse("http://guard.tollkeeper.ch/").unwrap(),
+        base_url: Some(url::Url::parse("http://guard.tollkeeper.ch/").unwrap()),
+        single_port: None,
         real_ip_header: Some(real_ip_header_name.to_string()),
+        trusted_proxies: None,
+        real_ip_fallback: None,
     };
     let (sut, spy_proxy_service) = setup_with_spy(config);
     // Act
@@ -134,7 +202,7 @@ pub fn serve_should_pass_real_ip_from_header_if_specified(real_ip_header_name: &
     headers.insert(real_ip_header_name, "1.2.3.4");
     let headers = request::Headers::new(headers).unwrap();
     let request = Request::new(http::Method::Get, "/", headers.clone(), Body::None).unwrap();
-    let _ = sut.serve_http(&client_addr(), request);
+    let _ = sut.serve_http(&client_addr(), request).await;
     // Assert
     let expected_calls = vec![ProxyRequestCall {
         client_addr: net::SocketAddrV4::new(net::Ipv4Addr::new(1, 2, 3, 4), 0).into(),
@@ -143,8 +211,42 @@ pub fn serve_should_pass_real_ip_from_header_if_specified(real_ip_header_name: &
     spy_proxy_service.assert_all_calls(&expected_calls);
 }
 
-#[test]
-pub fn serve_should_return_payment_required_if_access_is_denied() {
+#[test_case(None, StatusCode::BadRequest ; "reject")]
+#[test_case(Some(config::RealIpFallback::PeerAddress), StatusCode::NoContent ; "peer address")]
+#[tokio::test]
+pub async fn serve_should_apply_fallback_if_real_ip_header_is_missing(
+    fallback: Option<config::RealIpFallback>,
+    expected_status_code: StatusCode,
+) {
+    // Arrange
+    let config = config::Api {
+        base_url: Some(url::Url::parse("http://guard.tollkeeper.ch/").unwrap()),
+        single_port: None,
+        real_ip_header: Some("X-Forwarded-For".into()),
+        trusted_proxies: None,
+        real_ip_fallback: fallback,
+    };
+    let (sut, spy_proxy_service) = setup_with_spy(config);
+    // Act
+    let mut headers = Headers::empty();
+    headers.insert("Host", "127.0.0.1:65000");
+    let headers = request::Headers::new(headers).unwrap();
+    let request = Request::new(http::Method::Get, "/", headers.clone(), Body::None).unwrap();
+    let response = sut.serve_http(&client_addr(), request).await.unwrap();
+    // Assert
+    assert_eq!(expected_status_code, response.status_code());
+    let expected_calls = match expected_status_code {
+        StatusCode::NoContent => vec![ProxyRequestCall {
+            client_addr: client_addr(),
+            request_headers: headers,
+        }],
+        _ => vec![],
+    };
+    spy_proxy_service.assert_all_calls(&expected_calls);
+}
+
+#[tokio::test]
+pub async fn serve_should_return_payment_required_if_access_is_denied() {
     // Arrange
     let sut = setup_with_failing_stub(None);
     // Act
@@ -154,7 +256,7 @@ pub fn serve_should_return_payment_required_if_access_is_denied() {
     let headers = request::Headers::new(headers).unwrap();
     let body = http::Body::from_string("Hello, Server!\r\n".into());
     let request = Request::new(http::Method::Get, "/", headers, body).unwrap();
-    let response = sut.serve_http(&client_addr(), request);
+    let response = sut.serve_http(&client_addr(), request).await;
     // Assert
     assert!(response.is_ok());
     let mut response = response.unwrap();
@@ -200,7 +302,8 @@ pub fn serve_should_return_payment_required_if_access_is_denied() {
 #[test_case("text/html,application/xhtml+xml,application/xml;q=0.9,image/avif,image/webp,image/apng,*/*;q=0.8,application/signed-exchange;v=b3;q=0.7" ; "Chrome 131+")]
 #[test_case("text/html, application/xhtml+xml, image/jxr, */*" ; "Edge")]
 #[test_case("text/html, application/xml;q=0.9, application/xhtml+xml, image/png, image/webp, image/jpeg, image/gif, image/x-xbitmap, */*;q=0.1" ; "Opera")]
-pub fn serve_should_return_challenge_html_page_if_request_accepts_html(accept_header: &str) {
+#[tokio::test]
+pub async fn serve_should_return_challenge_html_page_if_request_accepts_html(accept_header: &str) {
     // Arrange
     let mut stub_templates = HashMap::new();
     stub_templates.insert("challenge.html".into(), "<div>Stub</div>".into());
@@ -213,7 +316,7 @@ pub fn serve_should_return_challenge_html_page_if_request_accepts_html(accept_he
     let headers = request::Headers::new(headers).unwrap();
     let body = Body::from_string("Hello, Server!\r\n".into());
     let request = Request::new(http::Method::Get, "/", headers, body).unwrap();
-    let response = sut.serve_http(&client_addr(), request);
+    let response = sut.serve_http(&client_addr(), request).await;
     // Assert
     assert!(response.is_ok());
     let mut response = response.unwrap();
@@ -232,8 +335,8 @@ pub fn serve_should_return_challenge_html_page_if_request_accepts_html(accept_he
     }
 }
 
-#[test]
-pub fn serve_should_return_internal_server_error_on_render_failure() {
+#[tokio::test]
+pub async fn serve_should_return_internal_server_error_on_render_failure() {
     // Arrange
     let mut stub_templates = HashMap::new();
     stub_templates.insert(
@@ -249,10 +352,252 @@ pub fn serve_should_return_internal_server_error_on_render_failure() {
     let headers = request::Headers::new(headers).unwrap();
     let body = Body::from_string("Hello, Server!\r\n".into());
     let request = Request::new(http::Method::Get, "/", headers, body).unwrap();
-    let response = sut.serve_http(&client_addr(), request);
+    let response = sut.serve_http(&client_addr(), request).await;
     // Assert
     assert!(
         response.is_err(),
         "invalid template did not return Internal Server Error"
     );
 }
+
+#[test_case(UpstreamError::Unresolvable, StatusCode::BadGateway ; "unresolvable upstream")]
+#[test_case(UpstreamError::ConnectionFailed, StatusCode::BadGateway ; "unreachable upstream")]
+#[test_case(UpstreamError::InvalidResponse, StatusCode::BadGateway ; "invalid upstream response")]
+#[test_case(UpstreamError::Timeout, StatusCode::GatewayTimeout ; "upstream timeout")]
+#[tokio::test]
+pub async fn serve_should_return_json_error_on_upstream_failure(
+    error: UpstreamError,
+    expected_status_code: StatusCode,
+) {
+    // Arrange
+    let sut = setup_with_upstream_error(error, None);
+    // Act
+    let mut headers = Headers::empty();
+    headers.insert("Host", "127.0.0.1:65000");
+    headers.insert("Accept", "application/json");
+    let headers = request::Headers::new(headers).unwrap();
+    let request = Request::new(http::Method::Get, "/", headers, Body::None).unwrap();
+    let response = sut.serve_http(&client_addr(), request).await;
+    // Assert
+    let mut response = response.expect("Expected error response, got InternalServerError");
+    assert_eq!(expected_status_code, response.status_code());
+    assert_eq!(Some("application/json"), response.headers().content_type());
+    assert!(response.headers().content_length().is_some());
+    let expected_body = serde_json::json!({
+        "error": error.to_string()
+    });
+    match response.body() {
+        Body::Buffer(buffer_body) => {
+            let mut actual_body = String::new();
+            buffer_body.read_to_string(&mut actual_body).unwrap();
+            let actual_body: serde_json::Value = serde_json::from_str(&actual_body).unwrap();
+            assert_eq!(expected_body, actual_body);
+        }
+        Body::Stream(_) => panic!("unexpected stream body"),
+        Body::None => panic!("no body"),
+    }
+}
+
+#[test_case(UpstreamError::ConnectionFailed, "<p>502 Bad Gateway</p>" ; "bad gateway")]
+#[test_case(UpstreamError::Timeout, "<p>504 Gateway Timeout</p>" ; "gateway timeout")]
+#[tokio::test]
+pub async fn serve_should_return_html_error_page_on_upstream_failure_if_request_accepts_html(
+    error: UpstreamError,
+    expected_body: &str,
+) {
+    // Arrange
+    let mut stub_templates = HashMap::new();
+    stub_templates.insert(
+        "upstream_error.html".into(),
+        "<p>{{status}} {{reason}}</p>".into(),
+    );
+    let sut = setup_with_upstream_error(error, Some(stub_templates));
+    // Act
+    let mut headers = Headers::empty();
+    headers.insert("Host", "127.0.0.1:65000");
+    headers.insert("Accept", "text/html");
+    let headers = request::Headers::new(headers).unwrap();
+    let request = Request::new(http::Method::Get, "/", headers, Body::None).unwrap();
+    let response = sut.serve_http(&client_addr(), request).await;
+    // Assert
+    let mut response = response.expect("Expected error page, got InternalServerError");
+    assert_eq!(error.status_code(), response.status_code());
+    assert_eq!(Some("text/html"), response.headers().content_type());
+    match response.body() {
+        Body::Buffer(buffer_body) => {
+            let mut actual_body = String::new();
+            buffer_body.read_to_string(&mut actual_body).unwrap();
+            assert_eq!(expected_body, actual_body);
+        }
+        Body::Stream(_) => panic!("unexpected stream body"),
+        Body::None => panic!("no body"),
+    }
+}
+
+fn single_port_config() -> config::Api {
+    config::Api {
+        base_url: None,
+        single_port: Some(true),
+        real_ip_header: None,
+        trusted_proxies: None,
+        real_ip_fallback: None,
+    }
+}
+
+#[test_case("/.tollkeeper/api/pay/", StatusCode::OK ; "api")]
+#[test_case("/.tollkeeper/challenge.js?v=1", StatusCode::OK ; "asset")]
+#[test_case("/.tollkeeper", StatusCode::NoContent ; "outside reserved path")]
+#[test_case("/page/.tollkeeper/", StatusCode::NoContent ; "nested path")]
+#[tokio::test]
+pub async fn serve_should_pass_reserved_path_to_api(
+    target: &str,
+    expected_status_code: StatusCode,
+) {
+    // Arrange
+    let (mut sut, _) = setup_with_spy(single_port_config());
+    sut.set_api(Box::new(StubApi));
+    // Act
+    let mut headers = Headers::empty();
+    headers.insert("Host", "example.com");
+    let headers = request::Headers::new(headers).unwrap();
+    let request = Request::new(http::Method::Get, target, headers, Body::None).unwrap();
+    let response = sut.serve_http(&client_addr(), request).await.unwrap();
+    // Assert
+    assert_eq!(expected_status_code, response.status_code());
+}
+
+#[tokio::test]
+pub async fn serve_should_link_toll_to_same_origin_in_single_port_mode() {
+    // Arrange
+    let sut = setup_with_failing_stub_and_config(single_port_config(), None);
+    // Act
+    let mut headers = Headers::empty();
+    headers.insert("Host", "example.com");
+    let headers = request::Headers::new(headers).unwrap();
+    let request = Request::new(http::Method::Get, "/", headers, Body::None).unwrap();
+    let mut response = sut.serve_http(&client_addr(), request).await.unwrap();
+    // Assert
+    assert_eq!(StatusCode::PaymentRequired, response.status_code());
+    match response.body() {
+        Body::Buffer(buffer_body) => {
+            let mut actual_toll = String::new();
+            buffer_body.read_to_string(&mut actual_toll).unwrap();
+            let actual_toll: serde_json::Value = serde_json::from_str(&actual_toll).unwrap();
+            assert_eq!(
+                serde_json::json!("/.tollkeeper/api/pay/"),
+                actual_toll["_links"]["pay"]
+            );
+        }
+        Body::Stream(_) => panic!("unexpected stream body"),
+        Body::None => panic!("no body"),
+    }
+}
+
+/// Serves requests through a gate to a fake upstream, which reads whatever is forwarded to it
+fn setup_with_upstream() -> (ProxyServe, net::SocketAddr, thread::JoinHandle<()>) {
+    let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let upstream_addr = listener.local_addr().unwrap();
+    let upstream = thread::spawn(move || {
+        let (mut conn, _) = listener.accept().unwrap();
+        let _ = conn.read_to_end(&mut Vec::new());
+    });
+    let orders = vec![tollkeeper::Order::new(
+        vec![Box::new(StubDescription { is_match: false })],
+        tollkeeper::AccessPolicy::Blacklist,
+        Box::new(StubTollDeclaration),
+    )];
+    let destination = descriptions::Destination::new("127.0.0.1", upstream_addr.port(), "/");
+    let gates = vec![tollkeeper::Gate::new(destination, orders).unwrap()];
+    let secret_key_provider = Box::new(InMemorySecretKeyProvider::new("Secret key".into()));
+    let date_provider = Box::new(FakeDateTimeProvider(chrono::Utc::now()));
+    let tollkeeper =
+        tollkeeper::Tollkeeper::new(gates, secret_key_provider, date_provider).unwrap();
+    let upstream_url = url::Url::parse(&format!("http://{upstream_addr}")).unwrap();
+    let url_resolver = UrlResolverImpl::new(indexmap::indexmap![
+        upstream_url.clone() => Target::new(upstream_url)
+    ]);
+    let proxy_service = ProxyServiceImpl::new(Arc::new(tollkeeper), Box::new(url_resolver));
+    let server_config = config::Api {
+        base_url: Some(url::Url::parse("http://guard.tollkeeper.ch/").unwrap()),
+        single_port: None,
+        real_ip_header: None,
+        trusted_proxies: None,
+        real_ip_fallback: None,
+    };
+    let template_store = InMemoryTemplateStore::new(HashMap::new());
+    let template_renderer = HandlebarTemplateRenderer::new(
+        Box::new(template_store),
+        url::Url::parse("http://localhost/").unwrap(),
+    );
+    let sut = ProxyServe::new(
+        server_config,
+        Box::new(proxy_service),
+        Box::new(template_renderer),
+    );
+    (sut, upstream_addr, upstream)
+}
+
+#[test_case(
+    format!("{:x}\r\n", Chunk::MAX_CHUNK_SIZE + 1),
+    StatusCode::ContentTooLarge ; "oversized chunk"
+)]
+#[test_case(
+    format!("{:x}\r\n{}\r\n", Chunk::MAX_CHUNK_SIZE, "a".repeat(Chunk::MAX_CHUNK_SIZE)).repeat(2),
+    StatusCode::ContentTooLarge ; "body exceeding max size"
+)]
+#[test_case("zz\r\nHello\r\n0\r\n\r\n".into(), StatusCode::BadRequest ; "malformed chunk size")]
+#[test_case("5\r\nHello World\r\n0\r\n\r\n".into(), StatusCode::BadRequest ; "missing chunk delimiter")]
+#[test_case("5\r\nHel".into(), StatusCode::BadRequest ; "disconnected client")]
+#[tokio::test]
+pub async fn serve_should_reject_invalid_chunked_upload(
+    body: String,
+    expected_status_code: StatusCode,
+) {
+    // Arrange
+    let (sut, upstream_addr, upstream) = setup_with_upstream();
+    let mut headers = Headers::empty();
+    headers.insert("Host", format!("127.0.0.1:{}", upstream_addr.port()));
+    headers.insert("Transfer-Encoding", "chunked");
+    let headers = request::Headers::new(headers).unwrap();
+    let body = Cursor::new(body.into_bytes());
+    let body = ChunkedTcpStream::with_max_size(body, Request::MAX_BODY_SIZE);
+    let body = Body::Stream(StreamBody::new(Box::new(body)));
+    let request = Request::new(http::Method::Post, "/upload", headers, body).unwrap();
+    // Act
+    let response = sut.serve_http(&client_addr(), request).await.unwrap();
+    // Assert
+    assert_eq!(expected_status_code, response.status_code());
+    upstream.join().unwrap();
+}
+
+#[test]
+pub fn server_should_return_408_when_proxied_chunked_body_stalls() {
+    // Arrange
+    let (proxy_serve, upstream_addr, upstream) = setup_with_upstream();
+    let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let settings = ConnectionSettings {
+        body_read_timeout: Duration::from_millis(100),
+        ..Default::default()
+    };
+    let mut sut = Server::with_settings(listener, Box::new(proxy_serve), settings);
+    let (sender, receiver) = cancellation_token::create_cancellation_token();
+    // Act
+    let server_thread = thread::spawn(move || sut.start_listening(receiver));
+    let mut connection = net::TcpStream::connect(addr).unwrap();
+    let request = format!(
+        "POST /upload HTTP/1.1\r\nHost: 127.0.0.1:{}\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nHello\r\n",
+        upstream_addr.port()
+    );
+    connection.write_all(request.as_bytes()).unwrap();
+    let mut response = String::new();
+    connection.read_to_string(&mut response).unwrap();
+    // Assert
+    assert!(
+        response.starts_with("HTTP/1.1 408 Request Timeout\r\n"),
+        "Unexpected response: {response}"
+    );
+    upstream.join().unwrap();
+    sender.send_shutdown().unwrap();
+    server_thread.join().unwrap();
+}
@@ -1,15 +1,16 @@
 use pretty_assertions::assert_eq;
 use std::{
-    io::{Read, Write},
+    io::{self, BufRead, Read, Write},
     net,
     str::FromStr,
     sync::Arc,
     thread,
+    time::Duration,
 };
 use test_case::test_case;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt};
 
 use tollkeeper::{
-    declarations::{self},
     descriptions::{self},
     signatures::{AsBytes, Base64, InMemorySecretKeyProvider},
     util::FakeDateTimeProvider,
@@ -20,9 +21,17 @@ use crate::{
         self,
         request::{self, Method},
         response::StatusCode,
-        Parse, Request,
+        server::tls::{CertificateFiles, CertificateStore, TlsAcceptor},
+        Request,
+    },
+    proxy::{
+        forwarding::ForwardedHeaders,
+        rewriting::HostHeader,
+        tests::{StubDescription, StubTollDeclaration},
+        upstream::{Target, TlsConnector, TlsSettings, UpstreamTimeouts},
+        Challenge, OrderId, ProxyError, ProxyService, ProxyServiceImpl, Recipient, Toll,
+        UpstreamError, UrlResolverImpl,
     },
-    proxy::{Challenge, OrderId, ProxyService, ProxyServiceImpl, Recipient, Toll, UrlResolverImpl},
 };
 
 fn setup_and_get_id(
@@ -32,9 +41,11 @@ fn setup_and_get_id(
     current_time: Option<chrono::DateTime<chrono::Utc>>,
 ) -> (OrderId, ProxyServiceImpl) {
     let destination = match &public_url {
-        Some(u) => {
-            descriptions::Destination::new(u.host_str().unwrap(), u.port().unwrap_or(80), u.path())
-        }
+        Some(u) => descriptions::Destination::new(
+            u.host_str().unwrap(),
+            u.port_or_known_default().unwrap_or(80),
+            u.path(),
+        ),
         None => descriptions::Destination::new(
             internal_addr.ip().to_string(),
             internal_addr.port(),
@@ -62,7 +73,7 @@ fn setup_and_get_id(
     let internal_addr = to_url(&internal_addr);
     let public_url = public_url.unwrap_or(internal_addr.clone());
     let url_resolver = UrlResolverImpl::new(indexmap::indexmap![
-        public_url => internal_addr
+        public_url => Target::new(internal_addr)
     ]);
     (
         order_id,
@@ -104,7 +115,7 @@ fn setup_proxy(response: Vec<u8>) -> (thread::JoinHandle<()>, net::SocketAddr) {
     let local_addr = listener.local_addr().unwrap();
     let thread = thread::spawn(move || {
         let (mut conn, _) = listener.accept().unwrap();
-        _ = Request::parse(conn.try_clone().unwrap());
+        consume_request(&conn);
         conn.write_all(&response).unwrap();
     });
     (thread, local_addr)
@@ -117,7 +128,7 @@ fn setup_proxy_with_chunked_response(
     let local_addr = listener.local_addr().unwrap();
     let thread = thread::spawn(move || {
         let (mut conn, _) = listener.accept().unwrap();
-        _ = Request::parse(conn.try_clone().unwrap());
+        consume_request(&conn);
         for chunk in chunks {
             conn.write_all(chunk).unwrap();
         }
@@ -125,6 +136,87 @@ fn setup_proxy_with_chunked_response(
     (thread, local_addr)
 }
 
+/// Reads the request head sent to a fake target
+fn consume_request(conn: &net::TcpStream) {
+    let mut reader = io::BufReader::new(conn);
+    let mut line = String::new();
+    while reader.read_line(&mut line).is_ok_and(|n| n > 2) {
+        line.clear();
+    }
+}
+
+/// Answers with `response` and returns the head of the received request
+fn setup_recording_proxy(response: &'static [u8]) -> (thread::JoinHandle<String>, net::SocketAddr) {
+    let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let local_addr = listener.local_addr().unwrap();
+    let thread = thread::spawn(move || {
+        let (mut conn, _) = listener.accept().unwrap();
+        let mut reader = io::BufReader::new(&conn);
+        let mut head = String::new();
+        while reader.read_line(&mut head).is_ok_and(|n| n > 2) {}
+        conn.write_all(response).unwrap();
+        head
+    });
+    (thread, local_addr)
+}
+
+fn set_forwarded_headers(
+    sut: &mut ProxyServiceImpl,
+    proxy_addr: net::SocketAddr,
+    forwarded_headers: ForwardedHeaders,
+) {
+    let mut target = Target::new(to_url(&proxy_addr));
+    target.set_forwarded_headers(forwarded_headers);
+    sut.url_resolver = Box::new(UrlResolverImpl::new(indexmap::indexmap![
+        to_url(&proxy_addr) => target
+    ]));
+}
+
+fn cert_path(file: &str) -> std::path::PathBuf {
+    std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("src/http/server/tests/certs")
+        .join(file)
+}
+
+/// Fake target listening on `ip`, answering with `response` over TLS, using the test certificate
+/// of `localhost`. Returns the request head, or the error of a failed handshake
+fn setup_tls_proxy(
+    ip: &str,
+    response: &'static [u8],
+) -> (tokio::task::JoinHandle<io::Result<String>>, net::SocketAddr) {
+    let listener = net::TcpListener::bind(format!("{ip}:0")).unwrap();
+    let local_addr = listener.local_addr().unwrap();
+    listener.set_nonblocking(true).unwrap();
+    let listener = tokio::net::TcpListener::from_std(listener).unwrap();
+    let certificate = CertificateFiles::new(cert_path("localhost.pem"), cert_path("localhost.key"));
+    let certificates = CertificateStore::new(Some(certificate)).unwrap();
+    let acceptor = TlsAcceptor::new(certificates).unwrap();
+    let task = tokio::spawn(async move {
+        let (conn, _) = listener.accept().await?;
+        let conn = acceptor.accept(conn).await?;
+        let mut conn = tokio::io::BufReader::new(conn);
+        let mut head = String::new();
+        while conn.read_line(&mut head).await? > 2 {}
+        conn.write_all(response).await?;
+        conn.shutdown().await?;
+        Ok(head)
+    });
+    (task, local_addr)
+}
+
+fn set_upstream_tls(
+    sut: &mut ProxyServiceImpl,
+    proxy_addr: net::SocketAddr,
+    settings: TlsSettings,
+) {
+    let internal_url = url::Url::from_str(&format!("https://{proxy_addr}")).unwrap();
+    let mut target = Target::new(internal_url);
+    target.set_tls(TlsConnector::new(settings).unwrap());
+    sut.url_resolver = Box::new(UrlResolverImpl::new(indexmap::indexmap![
+        to_url(&proxy_addr) => target
+    ]));
+}
+
 const fn client_addr() -> net::SocketAddr {
     let v4_addr = net::Ipv4Addr::new(127, 0, 0, 1);
     let v4_addr = net::SocketAddrV4::new(v4_addr, 5501);
@@ -133,13 +225,14 @@ const fn client_addr() -> net::SocketAddr {
 
 #[test_case(None ; "response with no body")]
 #[test_case(Some(b"ABC\r\n") ; "response with fixed size body")]
-pub fn proxy_request_should_send_request_to_target_and_return_response(
+#[tokio::test]
+pub async fn proxy_request_should_send_request_to_target_and_return_response(
     expected_body: Option<&[u8]>,
 ) {
     // Arrange
     let mut proxy_response = b"HTTP/1.1 200 OK\r\n".as_bytes();
     let headers: Vec<u8> = match expected_body {
-        None => b"\r\n".into(),
+        None => b"Content-Length: 0\r\n\r\n".into(),
         Some(b) => format!("Content-Length: {}\r\n\r\n", b.len()).into(),
     };
     proxy_response.extend_from_slice(&headers);
@@ -155,16 +248,17 @@ pub fn proxy_request_should_send_request_to_target_and_return_response(
     let request = Request::new(Method::Get, "/", headers, http::Body::None).unwrap();
     let mut response = sut
         .proxy_request(&client_addr(), request)
+        .await
         .expect("Expected response, got denied");
     // Assert
     assert_eq!(StatusCode::OK, response.status_code());
     let body = match response.body() {
-        http::Body::Buffer(buffer_body) => {
-            let mut buff = String::new();
-            buffer_body.read_to_string(&mut buff).unwrap();
-            Some(buff)
+        http::Body::Buffer(_) => panic!("unexpected buffered body"),
+        http::Body::Stream(body) => {
+            let mut buff = Vec::new();
+            body.copy_to(&mut buff).await.unwrap();
+            Some(String::from_utf8(buff).unwrap())
         }
-        http::Body::Stream(_) => panic!("unexpected stream body"),
         http::Body::None => None,
     };
     proxy.join().unwrap();
@@ -172,8 +266,32 @@ pub fn proxy_request_should_send_request_to_target_and_return_response(
     assert_eq!(expected_body, body);
 }
 
-#[test]
-pub fn proxy_request_should_send_request_to_resolved_target() {
+#[tokio::test]
+pub async fn proxy_request_should_not_read_body_of_response_to_head_request() {
+    // Arrange
+    let proxy_response = b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\n".to_vec();
+    let (proxy, proxy_addr) = setup_proxy(proxy_response);
+    let sut = setup(false, proxy_addr, None);
+    // Act
+    let mut headers = http::Headers::empty();
+    headers.insert("Host", format!("127.0.0.1:{}", proxy_addr.port()));
+    let headers = request::Headers::new(headers).unwrap();
+    let request = Request::new(Method::Head, "/", headers, http::Body::None).unwrap();
+    let mut response = sut
+        .proxy_request(&client_addr(), request)
+        .await
+        .expect("Expected response, got denied");
+    // Assert
+    proxy.join().unwrap();
+    assert_eq!(Some(5), response.headers().content_length());
+    assert!(
+        matches!(response.body(), http::Body::None),
+        "Expected no body for response to HEAD request"
+    );
+}
+
+#[tokio::test]
+pub async fn proxy_request_should_send_request_to_resolved_target() {
     // Arrange
     let proxy_response = b"HTTP/1.1 200 OK\r\n\r\n".as_bytes();
     let (proxy, internal_addr) = setup_proxy(proxy_response);
@@ -186,14 +304,126 @@ pub fn proxy_request_should_send_request_to_resolved_target() {
     let request = Request::new(Method::Get, "/", headers, http::Body::None).unwrap();
     let response = sut
         .proxy_request(&client_addr(), request)
+        .await
+        .expect("Expected response, got denied");
+    // Assert
+    assert_eq!(StatusCode::OK, response.status_code());
+    proxy.join().unwrap();
+}
+
+#[tokio::test]
+pub async fn proxy_request_should_send_request_to_ipv6_target() {
+    // Arrange
+    let listener = net::TcpListener::bind("[::1]:0").unwrap();
+    let internal_addr = listener.local_addr().unwrap();
+    let proxy = thread::spawn(move || {
+        let (mut conn, _) = listener.accept().unwrap();
+        consume_request(&conn);
+        conn.write_all(b"HTTP/1.1 200 OK\r\n\r\n").unwrap();
+    });
+    let public_addr = url::Url::from_str("http://example.ascendise.ch:80").unwrap();
+    let sut = setup_with_redirect(false, internal_addr, public_addr, None);
+    // Act
+    let mut headers = http::Headers::empty();
+    headers.insert("Host", "example.ascendise.ch");
+    let headers = request::Headers::new(headers).unwrap();
+    let request = Request::new(Method::Get, "/", headers, http::Body::None).unwrap();
+    let response = sut
+        .proxy_request(&client_addr(), request)
+        .await
         .expect("Expected response, got denied");
     // Assert
     assert_eq!(StatusCode::OK, response.status_code());
     proxy.join().unwrap();
 }
 
-#[test]
-pub fn proxy_request_should_send_request_to_target_and_return_chunked_response() {
+#[tokio::test]
+pub async fn proxy_request_should_return_connection_error_when_target_is_unreachable() {
+    // Arrange
+    let internal_addr = net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap(); // Listener gets dropped immediately, so nothing listens on this port
+    let public_addr = url::Url::from_str("http://example.ascendise.ch:80").unwrap();
+    let sut = setup_with_redirect(false, internal_addr, public_addr, None);
+    // Act
+    let mut headers = http::Headers::empty();
+    headers.insert("Host", "example.ascendise.ch");
+    let headers = request::Headers::new(headers).unwrap();
+    let request = Request::new(Method::Get, "/", headers, http::Body::None).unwrap();
+    let result = sut.proxy_request(&client_addr(), request).await;
+    // Assert
+    let expected = Err(ProxyError::Upstream(UpstreamError::ConnectionFailed));
+    assert_eq!(expected, result.map(|r| r.status_code()));
+}
+
+#[tokio::test]
+pub async fn proxy_request_should_return_invalid_response_error_when_target_responds_with_garbage()
+{
+    // Arrange
+    let (proxy, internal_addr) = setup_proxy(b"SSH-2.0-OpenSSH_9.6\r\n".into());
+    let public_addr = url::Url::from_str("http://example.ascendise.ch:80").unwrap();
+    let sut = setup_with_redirect(false, internal_addr, public_addr, None);
+    // Act
+    let mut headers = http::Headers::empty();
+    headers.insert("Host", "example.ascendise.ch");
+    let headers = request::Headers::new(headers).unwrap();
+    let request = Request::new(Method::Get, "/", headers, http::Body::None).unwrap();
+    let result = sut.proxy_request(&client_addr(), request).await;
+    // Assert
+    proxy.join().unwrap();
+    let expected = Err(ProxyError::Upstream(UpstreamError::InvalidResponse));
+    assert_eq!(expected, result.map(|r| r.status_code()));
+}
+
+#[tokio::test]
+pub async fn proxy_request_should_return_timeout_error_when_target_stalls() {
+    // Arrange
+    let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let internal_addr = listener.local_addr().unwrap();
+    let proxy = thread::spawn(move || {
+        let (conn, _) = listener.accept().unwrap();
+        consume_request(&conn);
+        thread::sleep(Duration::from_millis(500)); // Never answer in time
+    });
+    let public_addr = url::Url::from_str("http://example.ascendise.ch:80").unwrap();
+    let mut sut = setup_with_redirect(false, internal_addr, public_addr, None);
+    sut.set_timeouts(UpstreamTimeouts {
+        read: Duration::from_millis(100),
+        ..Default::default()
+    });
+    // Act
+    let mut headers = http::Headers::empty();
+    headers.insert("Host", "example.ascendise.ch");
+    let headers = request::Headers::new(headers).unwrap();
+    let request = Request::new(Method::Get, "/", headers, http::Body::None).unwrap();
+    let result = sut.proxy_request(&client_addr(), request).await;
+    // Assert
+    proxy.join().unwrap();
+    let expected = Err(ProxyError::Upstream(UpstreamError::Timeout));
+    assert_eq!(expected, result.map(|r| r.status_code()));
+}
+
+#[tokio::test]
+pub async fn proxy_request_should_return_unresolvable_error_when_no_internal_url_is_configured() {
+    // Arrange
+    let internal_addr = "127.0.0.1:80".parse().unwrap();
+    let public_addr = url::Url::from_str("http://example.ascendise.ch:80").unwrap();
+    let mut sut = setup_with_redirect(false, internal_addr, public_addr, None);
+    sut.url_resolver = Box::new(UrlResolverImpl::new(indexmap::indexmap![]));
+    // Act
+    let mut headers = http::Headers::empty();
+    headers.insert("Host", "example.ascendise.ch");
+    let headers = request::Headers::new(headers).unwrap();
+    let request = Request::new(Method::Get, "/", headers, http::Body::None).unwrap();
+    let result = sut.proxy_request(&client_addr(), request).await;
+    // Assert
+    let expected = Err(ProxyError::Upstream(UpstreamError::Unresolvable));
+    assert_eq!(expected, result.map(|r| r.status_code()));
+}
+
+#[tokio::test]
+pub async fn proxy_request_should_send_request_to_target_and_return_chunked_response() {
     // Arrange
     let mut proxy_response: Vec<&[u8]> =
         vec![b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n"];
@@ -219,12 +449,14 @@ pub fn proxy_request_should_send_request_to_target_and_return_chunked_response()
     let request = Request::new(Method::Get, "/", headers, http::Body::None).unwrap();
     let mut response = sut
         .proxy_request(&client_addr(), request)
+        .await
         .expect("Expected response, got denied");
     // Assert
     assert_eq!(StatusCode::OK, response.status_code());
     if let http::Body::Stream(body) = response.body() {
-        let mut actual_body = String::new();
-        body.read_to_string(&mut actual_body).unwrap();
+        let mut actual_body = Vec::new();
+        body.copy_to(&mut actual_body).await.unwrap();
+        let actual_body = String::from_utf8(actual_body).unwrap();
         assert_eq!(expected_body.join(""), actual_body);
     } else {
         panic!("unexpected body");
@@ -232,8 +464,531 @@ pub fn proxy_request_should_send_request_to_target_and_return_chunked_response()
     proxy.join().unwrap();
 }
 
-#[test]
-pub fn proxy_request_should_return_error_when_payment_is_required() {
+#[tokio::test]
+pub async fn proxy_request_should_stream_chunked_request_body_to_target() {
+    // Arrange
+    let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let proxy_addr = listener.local_addr().unwrap();
+    let proxy = thread::spawn(move || {
+        let (mut conn, _) = listener.accept().unwrap();
+        let mut received = Vec::new();
+        let mut buf = [0; 1024];
+        while !received.ends_with(b"0\r\n\r\n") {
+            let read = conn.read(&mut buf).unwrap();
+            if read == 0 {
+                break;
+            }
+            received.extend_from_slice(&buf[..read]);
+        }
+        conn.write_all(b"HTTP/1.1 204 No Content\r\n\r\n").unwrap();
+        String::from_utf8(received).unwrap()
+    });
+    let sut = setup(false, proxy_addr, None);
+    // Act
+    let mut headers = http::Headers::empty();
+    headers.insert("Host", format!("127.0.0.1:{}", proxy_addr.port()));
+    headers.insert("Transfer-Encoding", "chunked");
+    let headers = request::Headers::new(headers).unwrap();
+    let body = io::Cursor::new(b"5\r\nHello\r\n8\r\n, World!\r\n0\r\n\r\n".to_vec());
+    let body = http::StreamBody::new(Box::new(http::ChunkedTcpStream::new(body)));
+    let request = Request::new(Method::Post, "/upload", headers, http::Body::Stream(body)).unwrap();
+    let response = sut
+        .proxy_request(&client_addr(), request)
+        .await
+        .expect("Expected response, got denied");
+    // Assert
+    assert_eq!(StatusCode::NoContent, response.status_code());
+    let received = proxy.join().unwrap();
+    assert!(
+        received.starts_with("POST /upload HTTP/1.1\r\n"),
+        "Unexpected request line: {received}"
+    );
+    assert!(
+        received.ends_with("\r\n\r\n5\r\nHello\r\n8\r\n, World!\r\n0\r\n\r\n"),
+        "Body was not forwarded: {received}"
+    );
+}
+
+#[tokio::test]
+pub async fn proxy_request_should_forward_http_1_0_request_as_http_1_1() {
+    // Arrange
+    let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let proxy_addr = listener.local_addr().unwrap();
+    let proxy = thread::spawn(move || {
+        let (mut conn, _) = listener.accept().unwrap();
+        let mut request_line = String::new();
+        io::BufReader::new(&conn)
+            .read_line(&mut request_line)
+            .unwrap();
+        conn.write_all(b"HTTP/1.1 204 No Content\r\n\r\n").unwrap();
+        request_line
+    });
+    let sut = setup(false, proxy_addr, None);
+    // Act
+    let mut headers = http::Headers::empty();
+    headers.insert("Host", format!("127.0.0.1:{}", proxy_addr.port()));
+    let headers = request::Headers::new(headers).unwrap();
+    let mut request = Request::new(Method::Get, "/", headers, http::Body::None).unwrap();
+    request.set_http_version(http::HttpVersion::Http10);
+    let response = sut
+        .proxy_request(&client_addr(), request)
+        .await
+        .expect("Expected response, got denied");
+    // Assert
+    assert_eq!(StatusCode::NoContent, response.status_code());
+    let request_line = proxy.join().unwrap();
+    assert_eq!("GET / HTTP/1.1\r\n", request_line);
+}
+
+#[tokio::test]
+pub async fn proxy_request_should_strip_hop_by_hop_headers_and_add_forwarding_headers() {
+    // Arrange
+    let (proxy, proxy_addr) = setup_recording_proxy(b"HTTP/1.1 204 No Content\r\n\r\n");
+    let sut = setup(false, proxy_addr, None);
+    // Act
+    let host = format!("127.0.0.1:{}", proxy_addr.port());
+    let mut headers = http::Headers::empty();
+    headers.insert("Host", &host);
+    headers.insert("Connection", "keep-alive, X-Hop");
+    headers.insert("Keep-Alive", "timeout=5");
+    headers.insert("TE", "trailers");
+    headers.insert("X-Hop", "secret");
+    headers.insert("X-Forwarded-For", "10.0.0.1");
+    headers.insert("X-Forwarded-Proto", "https");
+    headers.insert("Forwarded", "for=10.0.0.2");
+    let headers = request::Headers::new(headers).unwrap();
+    let request = Request::new(Method::Get, "/", headers, http::Body::None).unwrap();
+    sut.proxy_request(&client_addr(), request)
+        .await
+        .expect("Expected response, got denied");
+    // Assert
+    let received = proxy.join().unwrap();
+    let expected = format!(
+        "GET / HTTP/1.1\r\nHost: {host}\r\nVia: 1.1 tollkeeper\r\n\
+        X-Forwarded-For: 10.0.0.1, 127.0.0.1\r\nX-Forwarded-Host: {host}\r\n\
+        X-Forwarded-Proto: http\r\n\r\n"
+    );
+    assert_eq!(expected, received);
+}
+
+#[test_case("127.0.0.1:5501", "for=127.0.0.1" ; "ipv4 client")]
+#[test_case("[::1]:5501", "for=\"[::1]\"" ; "ipv6 client")]
+#[tokio::test]
+pub async fn proxy_request_should_add_forwarded_header_if_configured(
+    client_addr: &str,
+    expected_for: &str,
+) {
+    // Arrange
+    let (proxy, proxy_addr) = setup_recording_proxy(b"HTTP/1.1 204 No Content\r\n\r\n");
+    let mut sut = setup(false, proxy_addr, None);
+    set_forwarded_headers(&mut sut, proxy_addr, ForwardedHeaders::Forwarded);
+    // Act
+    let host = format!("127.0.0.1:{}", proxy_addr.port());
+    let mut headers = http::Headers::empty();
+    headers.insert("Host", &host);
+    headers.insert("Forwarded", "for=10.0.0.1");
+    headers.insert("X-Forwarded-For", "10.0.0.2");
+    headers.insert("X-Forwarded-Host", "evil.example");
+    headers.insert("X-Forwarded-Proto", "https");
+    let headers = request::Headers::new(headers).unwrap();
+    let request = Request::new(Method::Get, "/", headers, http::Body::None).unwrap();
+    let client_addr = net::SocketAddr::from_str(client_addr).unwrap();
+    sut.proxy_request(&client_addr, request)
+        .await
+        .expect("Expected response, got denied");
+    // Assert
+    let received = proxy.join().unwrap();
+    let expected_header =
+        format!("Forwarded: for=10.0.0.1, {expected_for};host=\"{host}\";proto=http\r\n");
+    assert!(
+        received.contains(&expected_header),
+        "Missing {expected_header} in {received}"
+    );
+    assert!(
+        !received.contains("X-Forwarded"),
+        "Unexpected X-Forwarded headers: {received}"
+    );
+}
+
+#[tokio::test]
+pub async fn proxy_request_should_not_add_forwarding_headers_if_disabled() {
+    // Arrange
+    let (proxy, proxy_addr) = setup_recording_proxy(b"HTTP/1.1 204 No Content\r\n\r\n");
+    let mut sut = setup(false, proxy_addr, None);
+    set_forwarded_headers(&mut sut, proxy_addr, ForwardedHeaders::None);
+    // Act
+    let host = format!("127.0.0.1:{}", proxy_addr.port());
+    let mut headers = http::Headers::empty();
+    headers.insert("Host", &host);
+    let headers = request::Headers::new(headers).unwrap();
+    let request = Request::new(Method::Get, "/", headers, http::Body::None).unwrap();
+    sut.proxy_request(&client_addr(), request)
+        .await
+        .expect("Expected response, got denied");
+    // Assert
+    let received = proxy.join().unwrap();
+    let expected = format!("GET / HTTP/1.1\r\nHost: {host}\r\nVia: 1.1 tollkeeper\r\n\r\n");
+    assert_eq!(expected, received);
+}
+
+#[tokio::test]
+pub async fn proxy_request_should_strip_hop_by_hop_headers_from_response() {
+    // Arrange
+    let proxy_response = concat!(
+        "HTTP/1.1 200 OK\r\n",
+        "Connection: close, X-Hop\r\n",
+        "Keep-Alive: timeout=5\r\n",
+        "X-Hop: secret\r\n",
+        "Via: 1.0 upstream\r\n",
+        "Content-Length: 0\r\n",
+        "\r\n"
+    );
+    let (proxy, proxy_addr) = setup_proxy(proxy_response.as_bytes().to_vec());
+    let sut = setup(false, proxy_addr, None);
+    // Act
+    let mut headers = http::Headers::empty();
+    headers.insert("Host", format!("127.0.0.1:{}", proxy_addr.port()));
+    let headers = request::Headers::new(headers).unwrap();
+    let request = Request::new(Method::Get, "/", headers, http::Body::None).unwrap();
+    let response = sut
+        .proxy_request(&client_addr(), request)
+        .await
+        .expect("Expected response, got denied");
+    // Assert
+    proxy.join().unwrap();
+    assert_eq!(
+        "Via: 1.0 upstream\r\nVia: 1.1 tollkeeper\r\nContent-Length: 0\r\n",
+        response.headers().to_string()
+    );
+}
+
+#[test_case("HTTP/1.0", "1.0 tollkeeper" ; "http 1.0 upstream")]
+#[test_case("HTTP/1.1", "1.1 tollkeeper" ; "http 1.1 upstream")]
+#[tokio::test]
+pub async fn proxy_request_should_add_via_with_upstream_version_to_response(
+    http_version: &str,
+    expected_via: &str,
+) {
+    // Arrange
+    let proxy_response = format!("{http_version} 200 OK\r\nContent-Length: 0\r\n\r\n");
+    let (proxy, proxy_addr) = setup_proxy(proxy_response.into_bytes());
+    let sut = setup(false, proxy_addr, None);
+    // Act
+    let mut headers = http::Headers::empty();
+    headers.insert("Host", format!("127.0.0.1:{}", proxy_addr.port()));
+    let headers = request::Headers::new(headers).unwrap();
+    let request = Request::new(Method::Get, "/", headers, http::Body::None).unwrap();
+    let response = sut
+        .proxy_request(&client_addr(), request)
+        .await
+        .expect("Expected response, got denied");
+    // Assert
+    proxy.join().unwrap();
+    assert_eq!(Some(expected_via), response.headers().extension("Via"));
+    assert_eq!(http::HttpVersion::Http11, response.http_version());
+}
+
+#[test_case(HostHeader::Preserve, "example.ascendise.ch" ; "preserve host")]
+#[test_case(HostHeader::Rewrite, "127.0.0.1:{port}" ; "rewrite host")]
+#[tokio::test]
+pub async fn proxy_request_should_send_configured_host_header(
+    host_header: HostHeader,
+    expected_host: &str,
+) {
+    // Arrange
+    let (proxy, internal_addr) = setup_recording_proxy(b"HTTP/1.1 204 No Content\r\n\r\n");
+    let public_url = url::Url::from_str("http://example.ascendise.ch:80").unwrap();
+    let mut sut = setup_with_redirect(false, internal_addr, public_url.clone(), None);
+    let mut target = Target::new(to_url(&internal_addr));
+    target.set_host_header(host_header);
+    sut.url_resolver = Box::new(UrlResolverImpl::new(indexmap::indexmap![
+        public_url => target
+    ]));
+    // Act
+    let mut headers = http::Headers::empty();
+    headers.insert("Host", "example.ascendise.ch");
+    let headers = request::Headers::new(headers).unwrap();
+    let request = Request::new(Method::Get, "/", headers, http::Body::None).unwrap();
+    sut.proxy_request(&client_addr(), request)
+        .await
+        .expect("Expected response, got denied");
+    // Assert
+    let received = proxy.join().unwrap();
+    let expected_host = expected_host.replace("{port}", &internal_addr.port().to_string());
+    let expected_header = format!("\r\nHost: {expected_host}\r\n");
+    assert!(
+        received.contains(&expected_header),
+        "Missing {expected_header} in {received}"
+    );
+    assert!(
+        received.contains("X-Forwarded-Host: example.ascendise.ch\r\n"),
+        "Public host not forwarded: {received}"
+    );
+}
+
+#[tokio::test]
+pub async fn proxy_request_should_rewrite_internal_location_to_public_url() {
+    // Arrange
+    let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let internal_addr = listener.local_addr().unwrap();
+    let proxy = thread::spawn(move || {
+        let (mut conn, _) = listener.accept().unwrap();
+        consume_request(&conn);
+        let response = format!(
+            "HTTP/1.1 302 Found\r\nLocation: http://{internal_addr}/login\r\n\
+            Set-Cookie: session=1; Domain=127.0.0.1\r\nContent-Length: 0\r\n\r\n"
+        );
+        conn.write_all(response.as_bytes()).unwrap();
+    });
+    let public_url = url::Url::from_str("http://example.ascendise.ch:80").unwrap();
+    let sut = setup_with_redirect(false, internal_addr, public_url, None);
+    // Act
+    let mut headers = http::Headers::empty();
+    headers.insert("Host", "example.ascendise.ch");
+    let headers = request::Headers::new(headers).unwrap();
+    let request = Request::new(Method::Get, "/", headers, http::Body::None).unwrap();
+    let response = sut
+        .proxy_request(&client_addr(), request)
+        .await
+        .expect("Expected response, got denied");
+    // Assert
+    proxy.join().unwrap();
+    assert_eq!(
+        Some("http://example.ascendise.ch/login"),
+        response.headers().extension("Location")
+    );
+    assert_eq!(
+        Some("session=1; Domain=example.ascendise.ch"),
+        response.headers().extension("Set-Cookie")
+    );
+}
+
+#[tokio::test]
+pub async fn proxy_request_should_forward_https_for_secure_requests() {
+    // Arrange
+    let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let internal_addr = listener.local_addr().unwrap();
+    let proxy = thread::spawn(move || {
+        let (mut conn, _) = listener.accept().unwrap();
+        let mut received = String::new();
+        let mut reader = io::BufReader::new(&conn);
+        while reader.read_line(&mut received).unwrap() > 2 {}
+        let response = format!(
+            "HTTP/1.1 302 Found\r\nLocation: http://{internal_addr}/login\r\n\
+            Content-Length: 0\r\n\r\n"
+        );
+        conn.write_all(response.as_bytes()).unwrap();
+        received
+    });
+    let public_url = url::Url::from_str("https://example.ascendise.ch").unwrap();
+    let sut = setup_with_redirect(false, internal_addr, public_url, None);
+    // Act
+    let mut headers = http::Headers::empty();
+    headers.insert("Host", "example.ascendise.ch");
+    let headers = request::Headers::new(headers).unwrap();
+    let mut request = Request::new(Method::Get, "/", headers, http::Body::None).unwrap();
+    request.set_secure(true);
+    let response = sut
+        .proxy_request(&client_addr(), request)
+        .await
+        .expect("Expected response, got denied");
+    // Assert
+    let received = proxy.join().unwrap();
+    assert!(
+        received.contains("X-Forwarded-Proto: https\r\n"),
+        "Protocol not forwarded: {received}"
+    );
+    assert_eq!(
+        Some("https://example.ascendise.ch/login"),
+        response.headers().extension("Location")
+    );
+}
+
+#[test_case(TlsSettings {
+    ca_bundle: Some(cert_path("ca.pem")),
+    server_name: Some("localhost".into()),
+    skip_verify: false,
+} ; "trusted certificate")]
+#[test_case(TlsSettings {
+    ca_bundle: None,
+    server_name: None,
+    skip_verify: true,
+} ; "skipped verification")]
+#[tokio::test]
+pub async fn proxy_request_should_send_request_to_https_target(settings: TlsSettings) {
+    // Arrange
+    let (proxy, proxy_addr) = setup_tls_proxy("127.0.0.1", b"HTTP/1.1 204 No Content\r\n\r\n");
+    let mut sut = setup(false, proxy_addr, None);
+    set_upstream_tls(&mut sut, proxy_addr, settings);
+    // Act
+    let mut headers = http::Headers::empty();
+    headers.insert("Host", format!("127.0.0.1:{}", proxy_addr.port()));
+    let headers = request::Headers::new(headers).unwrap();
+    let request = Request::new(Method::Get, "/", headers, http::Body::None).unwrap();
+    let response = sut
+        .proxy_request(&client_addr(), request)
+        .await
+        .expect("Expected response, got denied");
+    // Assert
+    assert_eq!(StatusCode::NoContent, response.status_code());
+    let received = proxy
+        .await
+        .unwrap()
+        .expect("Target did not receive request");
+    assert!(
+        received.starts_with("GET / HTTP/1.1\r\n"),
+        "Unexpected request: {received}"
+    );
+}
+
+#[tokio::test]
+pub async fn proxy_request_should_send_request_to_https_target_at_ipv6_address() {
+    // Arrange
+    let (proxy, internal_addr) = setup_tls_proxy("[::1]", b"HTTP/1.1 204 No Content\r\n\r\n");
+    let public_url = url::Url::from_str("http://example.ascendise.ch:80").unwrap();
+    let mut sut = setup_with_redirect(false, internal_addr, public_url.clone(), None);
+    let internal_url = url::Url::from_str(&format!("https://{internal_addr}")).unwrap();
+    let mut target = Target::new(internal_url);
+    let settings = TlsSettings {
+        ca_bundle: None,
+        server_name: None,
+        skip_verify: true,
+    };
+    target.set_tls(TlsConnector::new(settings).unwrap());
+    sut.url_resolver = Box::new(UrlResolverImpl::new(
+        indexmap::indexmap![public_url => target],
+    ));
+    // Act
+    let mut headers = http::Headers::empty();
+    headers.insert("Host", "example.ascendise.ch");
+    let headers = request::Headers::new(headers).unwrap();
+    let request = Request::new(Method::Get, "/", headers, http::Body::None).unwrap();
+    let response = sut
+        .proxy_request(&client_addr(), request)
+        .await
+        .expect("Expected response, got denied");
+    // Assert
+    assert_eq!(StatusCode::NoContent, response.status_code());
+    proxy
+        .await
+        .unwrap()
+        .expect("Target did not receive request");
+}
+
+#[test_case(TlsSettings::default() ; "untrusted certificate")]
+#[test_case(TlsSettings {
+    ca_bundle: Some(cert_path("ca.pem")),
+    server_name: Some("example.com".into()),
+    skip_verify: false,
+} ; "mismatched server name")]
+#[tokio::test]
+pub async fn proxy_request_should_return_connection_error_for_invalid_target_certificate(
+    settings: TlsSettings,
+) {
+    // Arrange
+    let (proxy, proxy_addr) = setup_tls_proxy("127.0.0.1", b"HTTP/1.1 204 No Content\r\n\r\n");
+    let mut sut = setup(false, proxy_addr, None);
+    set_upstream_tls(&mut sut, proxy_addr, settings);
+    // Act
+    let mut headers = http::Headers::empty();
+    headers.insert("Host", format!("127.0.0.1:{}", proxy_addr.port()));
+    let headers = request::Headers::new(headers).unwrap();
+    let request = Request::new(Method::Get, "/", headers, http::Body::None).unwrap();
+    let result = sut.proxy_request(&client_addr(), request).await;
+    // Assert
+    let expected = Err(ProxyError::Upstream(UpstreamError::ConnectionFailed));
+    assert_eq!(expected, result.map(|r| r.status_code()));
+    assert!(proxy.await.unwrap().is_err(), "Handshake did not fail");
+}
+
+#[tokio::test]
+pub async fn proxy_request_should_splice_upgraded_connection_with_target() {
+    // Arrange
+    let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let proxy_addr = listener.local_addr().unwrap();
+    let proxy = thread::spawn(move || {
+        let (mut conn, _) = listener.accept().unwrap();
+        let mut reader = io::BufReader::new(conn.try_clone().unwrap());
+        let mut head = String::new();
+        while reader.read_line(&mut head).is_ok_and(|n| n > 2) {}
+        let response = "HTTP/1.1 101 Switching Protocols\r\nConnection: Upgrade\r\n\
+            Upgrade: websocket\r\n\r\n";
+        conn.write_all(response.as_bytes()).unwrap();
+        thread::sleep(Duration::from_millis(100)); // Longer than the read timeout
+        conn.write_all(b"Hello").unwrap();
+        let mut message = [0; 5];
+        reader.read_exact(&mut message).unwrap();
+        conn.write_all(&message).unwrap();
+        head
+    });
+    let mut sut = setup(false, proxy_addr, None);
+    sut.set_timeouts(UpstreamTimeouts {
+        read: Duration::from_millis(20),
+        ..Default::default()
+    });
+    // Act
+    let mut headers = http::Headers::empty();
+    headers.insert("Host", format!("127.0.0.1:{}", proxy_addr.port()));
+    headers.insert("Connection", "keep-alive, Upgrade");
+    headers.insert("Upgrade", "websocket");
+    headers.insert("Sec-WebSocket-Key", "dGhlIHNhbXBsZSBub25jZQ==");
+    let headers = request::Headers::new(headers).unwrap();
+    let request = Request::new(Method::Get, "/chat", headers, http::Body::None).unwrap();
+    let mut response = sut
+        .proxy_request(&client_addr(), request)
+        .await
+        .expect("Expected response, got denied");
+    let upgrade = response
+        .take_upgrade()
+        .expect("Connection was not upgraded");
+    let (client, connection) = tokio::io::duplex(64);
+    let splice = tokio::spawn(async move {
+        let (mut reader, mut writer) = tokio::io::split(connection);
+        upgrade.splice(&mut reader, &mut writer).await
+    });
+    let (mut client_reader, mut client_writer) = tokio::io::split(client);
+    let mut greeting = [0; 5];
+    client_reader.read_exact(&mut greeting).await.unwrap();
+    client_writer.write_all(b"World").await.unwrap();
+    let mut echo = [0; 5];
+    client_reader.read_exact(&mut echo).await.unwrap();
+    drop((client_reader, client_writer));
+    // Assert
+    assert_eq!(StatusCode::SwitchingProtocols, response.status_code());
+    assert_eq!(Some("upgrade"), response.headers().extension("Connection"));
+    assert_eq!(Some("websocket"), response.headers().extension("Upgrade"));
+    assert_eq!(b"Hello", &greeting);
+    assert_eq!(b"World", &echo);
+    splice.await.unwrap().expect("Splicing failed");
+    let received = proxy.join().unwrap();
+    for header in [
+        "Connection: upgrade\r\n",
+        "Upgrade: websocket\r\n",
+        "Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n",
+    ] {
+        assert!(received.contains(header), "Missing {header} in {received}");
+    }
+}
+
+#[tokio::test]
+pub async fn proxy_request_should_return_invalid_response_error_for_unrequested_protocol_switch() {
+    // Arrange
+    let (proxy, internal_addr) = setup_proxy(b"HTTP/1.1 101 Switching Protocols\r\n\r\n".into());
+    let sut = setup(false, internal_addr, None);
+    // Act
+    let mut headers = http::Headers::empty();
+    headers.insert("Host", format!("127.0.0.1:{}", internal_addr.port()));
+    let headers = request::Headers::new(headers).unwrap();
+    let request = Request::new(Method::Get, "/", headers, http::Body::None).unwrap();
+    let result = sut.proxy_request(&client_addr(), request).await;
+    // Assert
+    proxy.join().unwrap();
+    let expected = Err(ProxyError::Upstream(UpstreamError::InvalidResponse));
+    assert_eq!(expected, result.map(|r| r.status_code()));
+}
+
+#[tokio::test]
+pub async fn proxy_request_should_return_error_when_payment_is_required() {
     // Arrange
     let mut target_addr = client_addr();
     target_addr.set_port(80);
@@ -244,14 +999,16 @@ pub fn proxy_request_should_return_error_when_payment_is_required() {
     headers.insert("Host", "127.0.0.1");
     let headers = request::Headers::new(headers).unwrap();
     let request = Request::new(Method::Get, "/", headers, http::Body::None).unwrap();
-    let proxy_result = sut.proxy_request(&client_addr, request);
+    let proxy_result = sut.proxy_request(&client_addr, request).await;
     // Assert
     assert!(
         proxy_result.is_err(),
         "Expected a PaymentRequiredError, but was proxied successfully!"
     );
-    let payment_required_error = proxy_result.err().unwrap();
-    let toll = payment_required_error.0;
+    let toll = match proxy_result.err().unwrap() {
+        ProxyError::PaymentRequired(e) => e.0,
+        e => panic!("Expected a PaymentRequiredError, got {e}"),
+    };
     let expected_toll = Toll {
         recipient: Recipient {
             client_ip: client_addr.ip().to_string(),
@@ -265,8 +1022,8 @@ pub fn proxy_request_should_return_error_when_payment_is_required() {
     assert_eq!(expected_toll, *toll);
 }
 
-#[test]
-pub fn proxy_request_should_return_404_response_when_trying_to_access_unknown_target() {
+#[tokio::test]
+pub async fn proxy_request_should_return_404_response_when_trying_to_access_unknown_target() {
     // Arrange
     let mut target_addr = client_addr();
     target_addr.set_port(2200);
@@ -278,7 +1035,7 @@ pub fn proxy_request_should_return_404_response_when_trying_to_access_unknown_ta
     headers.insert("Host", host);
     let headers = request::Headers::new(headers).unwrap();
     let request = Request::new(Method::Get, "/", headers, http::Body::None).unwrap();
-    let proxy_result = sut.proxy_request(&client_addr, request);
+    let proxy_result = sut.proxy_request(&client_addr, request).await;
     // Assert
     assert!(
         proxy_result.is_ok(),
@@ -289,7 +1046,8 @@ pub fn proxy_request_should_return_404_response_when_trying_to_access_unknown_ta
 }
 #[test_case(ProxyWithVisaTestCases::Header ; "read token from X-Keeper-Token header")]
 #[test_case(ProxyWithVisaTestCases::Cookie ; "read token from cookies")]
-pub fn proxy_request_should_send_request_to_target_if_positive_suspect_has_visa(
+#[tokio::test]
+pub async fn proxy_request_should_send_request_to_target_if_positive_suspect_has_visa(
     test_case: ProxyWithVisaTestCases,
 ) {
     // Arrange
@@ -299,6 +1057,113 @@ pub fn proxy_request_should_send_request_to_target_if_positive_suspect_has_visa(
     let mut headers = http::Headers::empty();
     let host = format!("127.0.0.1:{}", proxy_addr.port());
     headers.insert("Host", host.clone());
+    let token = create_token(&order_id, proxy_addr);
+    headers.insert("User-Agent", "Yo Mama");
+    add_token_header(test_case, &mut headers, token);
+    let headers = request::Headers::new(headers).unwrap();
+    let request = Request::new(Method::Get, "/", headers, http::Body::None).unwrap();
+    let response = sut
+        .proxy_request(&client_addr(), request)
+        .await
+        .expect("Expected response, got denied");
+    proxy.join().unwrap();
+    // Assert
+    assert_eq!(StatusCode::OK, response.status_code());
+}
+
+#[test_case(ProxyWithVisaTestCases::Header ; "token in X-Keeper-Token header")]
+#[test_case(ProxyWithVisaTestCases::Cookie ; "token in cookies")]
+#[tokio::test]
+pub async fn proxy_request_should_not_forward_visa_to_target(test_case: ProxyWithVisaTestCases) {
+    // Arrange
+    let (proxy, proxy_addr) = setup_recording_proxy(b"HTTP/1.1 204 No Content\r\n\r\n");
+    let (order_id, sut) = setup_and_get_id(true, proxy_addr, None, None);
+    // Act
+    let mut headers = http::Headers::empty();
+    headers.insert("Host", format!("127.0.0.1:{}", proxy_addr.port()));
+    headers.insert("User-Agent", "Yo Mama");
+    headers.insert("Cookie", "theme=dark; lang=en");
+    add_token_header(test_case, &mut headers, create_token(&order_id, proxy_addr));
+    let headers = request::Headers::new(headers).unwrap();
+    let request = Request::new(Method::Get, "/", headers, http::Body::None).unwrap();
+    sut.proxy_request(&client_addr(), request)
+        .await
+        .expect("Expected response, got denied");
+    // Assert
+    let received = proxy.join().unwrap();
+    assert!(
+        !received.contains("X-Keeper-Token"),
+        "Visa was forwarded: {received}"
+    );
+    assert!(
+        received.contains("Cookie: theme=dark; lang=en\r\n"),
+        "Other cookies were not forwarded: {received}"
+    );
+}
+
+#[tokio::test]
+pub async fn proxy_request_should_add_verified_order_if_configured() {
+    // Arrange
+    let (proxy, proxy_addr) = setup_recording_proxy(b"HTTP/1.1 204 No Content\r\n\r\n");
+    let (order_id, mut sut) = setup_and_get_id(true, proxy_addr, None, None);
+    let mut target = Target::new(to_url(&proxy_addr));
+    target.set_verified_header(true);
+    sut.url_resolver = Box::new(UrlResolverImpl::new(indexmap::indexmap![
+        to_url(&proxy_addr) => target
+    ]));
+    // Act
+    let mut headers = http::Headers::empty();
+    headers.insert("Host", format!("127.0.0.1:{}", proxy_addr.port()));
+    headers.insert("User-Agent", "Yo Mama");
+    headers.insert("X-Keeper-Token", create_token(&order_id, proxy_addr));
+    let headers = request::Headers::new(headers).unwrap();
+    let request = Request::new(Method::Get, "/", headers, http::Body::None).unwrap();
+    sut.proxy_request(&client_addr(), request)
+        .await
+        .expect("Expected response, got denied");
+    // Assert
+    let received = proxy.join().unwrap();
+    let expected_header = format!("X-Keeper-Verified: {order_id}\r\n");
+    assert!(
+        received.contains(&expected_header),
+        "Missing {expected_header} in {received}"
+    );
+}
+
+#[tokio::test]
+pub async fn proxy_request_should_remove_verified_order_sent_by_client() {
+    // Arrange
+    let (proxy, proxy_addr) = setup_recording_proxy(b"HTTP/1.1 204 No Content\r\n\r\n");
+    let mut sut = setup(false, proxy_addr, None);
+    let mut target = Target::new(to_url(&proxy_addr));
+    target.set_verified_header(true);
+    sut.url_resolver = Box::new(UrlResolverImpl::new(indexmap::indexmap![
+        to_url(&proxy_addr) => target
+    ]));
+    // Act
+    let mut headers = http::Headers::empty();
+    headers.insert("Host", format!("127.0.0.1:{}", proxy_addr.port()));
+    headers.insert("X-Keeper-Verified", "gate#order");
+    let headers = request::Headers::new(headers).unwrap();
+    let request = Request::new(Method::Get, "/", headers, http::Body::None).unwrap();
+    sut.proxy_request(&client_addr(), request)
+        .await
+        .expect("Expected response, got denied");
+    // Assert
+    let received = proxy.join().unwrap();
+    assert!(
+        !received.contains("X-Keeper-Verified"),
+        "Spoofed X-Keeper-Verified was forwarded: {received}"
+    );
+}
+
+pub enum ProxyWithVisaTestCases {
+    Header,
+    Cookie,
+}
+/// Creates a valid `X-Keeper-Token` for a client with user agent `Yo Mama` accessing `proxy_addr`
+fn create_token(order_id: &OrderId, proxy_addr: net::SocketAddr) -> String {
+    let host = format!("127.0.0.1:{}", proxy_addr.port());
     let expires = chrono::Utc::now()
         .checked_add_days(chrono::Days::new(1))
         .unwrap();
@@ -311,7 +1176,7 @@ pub fn proxy_request_should_send_request_to_target_if_positive_suspect_has_visa(
     })
     .to_string();
     let signature = tollkeeper::declarations::Visa::new(
-        tollkeeper::declarations::OrderIdentifier::new(order_id.gate_id, order_id.order_id),
+        tollkeeper::declarations::OrderIdentifier::new(order_id.gate_id(), order_id.order_id()),
         tollkeeper::descriptions::Suspect::new(
             "127.0.0.1",
             "Yo Mama",
@@ -322,23 +1187,9 @@ pub fn proxy_request_should_send_request_to_target_if_positive_suspect_has_visa(
     let signature = tollkeeper::signatures::Signed::sign(signature, b"Secret key");
     let visa = Base64::encode(visa.as_bytes());
     let signature = signature.signature().base64();
-    let token = format!("{}.{}", visa, signature);
-    headers.insert("User-Agent", "Yo Mama");
-    add_token_header(test_case, &mut headers, token);
-    let headers = request::Headers::new(headers).unwrap();
-    let request = Request::new(Method::Get, "/", headers, http::Body::None).unwrap();
-    let response = sut
-        .proxy_request(&client_addr(), request)
-        .expect("Expected response, got denied");
-    proxy.join().unwrap();
-    // Assert
-    assert_eq!(StatusCode::OK, response.status_code());
+    format!("{}.{}", visa, signature)
 }
 
-pub enum ProxyWithVisaTestCases {
-    Header,
-    Cookie,
-}
 fn add_token_header(
     test_case: ProxyWithVisaTestCases,
     headers: &mut http::Headers,
@@ -351,35 +1202,3 @@ fn add_token_header(
         }
     };
 }
-
-struct StubDescription {
-    is_match: bool,
-}
-impl tollkeeper::Description for StubDescription {
-    fn matches(&self, _: &descriptions::Suspect) -> bool {
-        self.is_match
-    }
-}
-
-struct StubTollDeclaration;
-impl tollkeeper::Declaration for StubTollDeclaration {
-    fn declare(
-        &self,
-        suspect: descriptions::Suspect,
-        order_id: declarations::OrderIdentifier,
-    ) -> declarations::Toll {
-        tollkeeper::declarations::Toll::new(
-            suspect,
-            order_id,
-            tollkeeper::declarations::Challenge::new(),
-        )
-    }
-
-    fn pay(
-        &self,
-        _: declarations::Payment,
-        _: &descriptions::Suspect,
-    ) -> Result<declarations::Visa, tollkeeper::declarations::PaymentError> {
-        todo!()
-    }
-}
@@ -0,0 +1,67 @@
+use pretty_assertions::assert_eq;
+use test_case::test_case;
+
+use crate::{
+    http::{self, response},
+    proxy::rewriting,
+};
+
+fn url(url: &str) -> url::Url {
+    url::Url::parse(url).unwrap()
+}
+
+#[test_case("http://internal:9000/login?next=%2F", Some("http://example.com/login?next=%2F") ; "internal origin")]
+#[test_case("http://internal:9000", Some("http://example.com/") ; "internal origin without path")]
+#[test_case("http://internal:9001/login", None ; "other port")]
+#[test_case("http://other.com/login", None ; "other origin")]
+#[test_case("/login", None ; "relative location")]
+pub fn rewrite_location_should_replace_internal_origin(location: &str, expected: Option<&str>) {
+    // Arrange
+    let internal = url("http://internal:9000/");
+    let public = url("http://example.com/");
+    // Act
+    let location = rewriting::rewrite_location(location, &internal, &public);
+    // Assert
+    assert_eq!(expected.map(String::from), location);
+}
+
+#[test_case("id=1; Domain=internal; Path=/", "id=1; Domain=example.com; Path=/" ; "internal domain")]
+#[test_case("id=1; domain=.INTERNAL", "id=1; domain=example.com" ; "case insensitive domain with leading dot")]
+#[test_case("id=1; Domain=other.com", "id=1; Domain=other.com" ; "other domain")]
+#[test_case("id=1; Path=/", "id=1; Path=/" ; "no domain")]
+pub fn rewrite_cookie_domain_should_replace_internal_domain(set_cookie: &str, expected: &str) {
+    // Act
+    let set_cookie = rewriting::rewrite_cookie_domain(set_cookie, "internal", "example.com");
+    // Assert
+    assert_eq!(expected, set_cookie);
+}
+
+#[test_case("http://internal:9000/", "internal:9000" ; "custom port")]
+#[test_case("http://internal:80/", "internal" ; "default port")]
+pub fn host_header_should_omit_default_port(internal: &str, expected: &str) {
+    // Act
+    let host = rewriting::host_header(&url(internal));
+    // Assert
+    assert_eq!(expected, host);
+}
+
+#[test]
+pub fn rewrite_response_headers_should_rewrite_all_set_cookie_headers() {
+    // Arrange
+    let mut headers = http::Headers::empty();
+    headers.insert("Set-Cookie", "a=1; Domain=internal");
+    headers.insert("Set-Cookie", "b=2");
+    headers.insert("Location", "http://internal:9000/home");
+    let mut headers = response::Headers::new(headers);
+    // Act
+    rewriting::rewrite_response_headers(
+        &mut headers,
+        &url("http://internal:9000/"),
+        &url("http://example.com/"),
+    );
+    // Assert
+    assert_eq!(
+        "Location: http://example.com/home\r\nSet-Cookie: a=1; Domain=example.com\r\nSet-Cookie: b=2\r\n",
+        headers.to_string()
+    );
+}
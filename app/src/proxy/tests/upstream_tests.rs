@@ -0,0 +1,101 @@
+use std::{net, time::Duration};
+
+use pretty_assertions::assert_eq;
+
+use crate::proxy::upstream::{self, UpstreamTimeouts};
+
+fn unreachable_addr(ip: &str) -> net::SocketAddr {
+    let listener = net::TcpListener::bind(format!("{ip}:0")).unwrap();
+    listener.local_addr().unwrap()
+}
+
+fn addr(addr: &str) -> net::SocketAddr {
+    addr.parse().unwrap()
+}
+
+#[tokio::test]
+pub async fn connect_should_connect_to_ipv6_address() {
+    // Arrange
+    let listener = net::TcpListener::bind("[::1]:0").unwrap();
+    let listener_addr = listener.local_addr().unwrap();
+    // Act
+    let conn = upstream::connect(
+        &[listener_addr],
+        upstream::CONNECTION_ATTEMPT_DELAY,
+        UpstreamTimeouts::CONNECT_DEFAULT,
+    )
+    .await
+    .expect("Failed to connect to IPv6 address");
+    // Assert
+    assert_eq!(listener_addr, conn.peer_addr().unwrap());
+}
+
+#[tokio::test]
+pub async fn connect_should_fall_back_to_next_address_when_attempt_fails() {
+    // Arrange
+    let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let listener_addr = listener.local_addr().unwrap();
+    let addrs = [unreachable_addr("[::1]"), listener_addr];
+    // Act
+    let conn = upstream::connect(
+        &addrs,
+        Duration::from_secs(10),
+        UpstreamTimeouts::CONNECT_DEFAULT,
+    )
+    .await
+    .expect("Failed to connect to fallback address");
+    // Assert
+    assert_eq!(listener_addr, conn.peer_addr().unwrap());
+}
+
+#[tokio::test]
+pub async fn connect_should_return_error_when_no_address_is_reachable() {
+    // Arrange
+    let addrs = [unreachable_addr("[::1]"), unreachable_addr("127.0.0.1")];
+    // Act
+    let result = upstream::connect(
+        &addrs,
+        upstream::CONNECTION_ATTEMPT_DELAY,
+        UpstreamTimeouts::CONNECT_DEFAULT,
+    )
+    .await;
+    // Assert
+    assert!(result.is_err(), "Expected error, but got a connection");
+}
+
+#[tokio::test]
+pub async fn connect_should_return_error_when_there_are_no_addresses() {
+    // Arrange
+    // Act
+    let result = upstream::connect(
+        &[],
+        upstream::CONNECTION_ATTEMPT_DELAY,
+        UpstreamTimeouts::CONNECT_DEFAULT,
+    )
+    .await;
+    // Assert
+    assert!(result.is_err(), "Expected error, but got a connection");
+}
+
+#[test]
+pub fn interleave_address_families_should_alternate_starting_with_preferred_family() {
+    // Arrange
+    let addrs = [
+        addr("[2001:db8::1]:80"),
+        addr("[2001:db8::2]:80"),
+        addr("[2001:db8::3]:80"),
+        addr("10.0.0.1:80"),
+        addr("10.0.0.2:80"),
+    ];
+    // Act
+    let interleaved = upstream::interleave_address_families(&addrs);
+    // Assert
+    let expected = vec![
+        addr("[2001:db8::1]:80"),
+        addr("10.0.0.1:80"),
+        addr("[2001:db8::2]:80"),
+        addr("10.0.0.2:80"),
+        addr("[2001:db8::3]:80"),
+    ];
+    assert_eq!(expected, interleaved);
+}
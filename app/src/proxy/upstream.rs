@@ -0,0 +1,408 @@
+use std::{
+    fmt,
+    future::Future,
+    io, net,
+    path::PathBuf,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    net::TcpStream,
+    task::JoinSet,
+    time::{self, Instant, Sleep},
+};
+use tokio_rustls::rustls::{
+    self,
+    client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+    crypto::CryptoProvider,
+    pki_types::{pem::PemObject, CertificateDer, ServerName, UnixTime},
+    DigitallySignedStruct, SignatureScheme,
+};
+
+use super::{forwarding::ForwardedHeaders, rewriting::HostHeader};
+use crate::http::server::tls::TlsError;
+
+/// Delay before starting a connection attempt to the next address while the previous
+/// attempt is still pending ([RFC 8305](https://www.rfc-editor.org/rfc/rfc8305#section-5))
+pub const CONNECTION_ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+/// Limits how long the proxy waits for an upstream target
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct UpstreamTimeouts {
+    /// Time a single connection attempt may take
+    pub connect: Duration,
+    /// Time the upstream may stay silent while sending its response
+    pub read: Duration,
+}
+impl UpstreamTimeouts {
+    pub const CONNECT_DEFAULT: Duration = Duration::from_secs(5);
+    pub const READ_DEFAULT: Duration = Duration::from_secs(30);
+}
+impl Default for UpstreamTimeouts {
+    fn default() -> Self {
+        Self {
+            connect: Self::CONNECT_DEFAULT,
+            read: Self::READ_DEFAULT,
+        }
+    }
+}
+
+/// Internal url a gate forwards requests to, together with how they get forwarded
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Target {
+    url: url::Url,
+    forwarded_headers: ForwardedHeaders,
+    verified_header: bool,
+    host_header: HostHeader,
+    tls: Option<TlsConnector>,
+}
+impl Target {
+    /// Creates a target for `url`. `https` urls are connected to over TLS, verifying the
+    /// certificate against the built-in root certificates
+    pub fn new(url: url::Url) -> Self {
+        let tls = (url.scheme() == "https").then(TlsConnector::default);
+        Self {
+            url,
+            forwarded_headers: ForwardedHeaders::default(),
+            verified_header: false,
+            host_header: HostHeader::default(),
+            tls,
+        }
+    }
+
+    pub fn url(&self) -> &url::Url {
+        &self.url
+    }
+
+    pub fn forwarded_headers(&self) -> ForwardedHeaders {
+        self.forwarded_headers
+    }
+
+    pub fn set_forwarded_headers(&mut self, forwarded_headers: ForwardedHeaders) {
+        self.forwarded_headers = forwarded_headers;
+    }
+
+    /// Returns `true` if requests with a valid visa get the id of the order that issued it in an
+    /// `X-Keeper-Verified` header
+    pub fn verified_header(&self) -> bool {
+        self.verified_header
+    }
+
+    pub fn set_verified_header(&mut self, verified_header: bool) {
+        self.verified_header = verified_header;
+    }
+
+    pub fn host_header(&self) -> HostHeader {
+        self.host_header
+    }
+
+    pub fn set_host_header(&mut self, host_header: HostHeader) {
+        self.host_header = host_header;
+    }
+
+    /// Returns [Some] if the target is connected to over TLS
+    pub fn tls(&self) -> Option<&TlsConnector> {
+        self.tls.as_ref()
+    }
+
+    pub fn set_tls(&mut self, tls: TlsConnector) {
+        self.tls = Some(tls);
+    }
+}
+
+/// Connection to an upstream target, either plain or secured with TLS
+pub trait UpstreamStream: AsyncRead + AsyncWrite + Unpin + Send {}
+impl<S: AsyncRead + AsyncWrite + Unpin + Send> UpstreamStream for S {}
+
+/// How the certificate of an `https` target gets verified
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct TlsSettings {
+    /// PEM file with the CA certificates to trust instead of the built-in root certificates
+    pub ca_bundle: Option<PathBuf>,
+    /// Name sent via SNI and expected in the certificate instead of the host of the url
+    pub server_name: Option<String>,
+    /// Accepts any certificate. Only meant for self-signed certificates during development
+    pub skip_verify: bool,
+}
+
+/// Performs the client side of TLS handshakes with an upstream target
+#[derive(Clone)]
+pub struct TlsConnector {
+    settings: TlsSettings,
+    connector: tokio_rustls::TlsConnector,
+}
+impl TlsConnector {
+    pub fn new(settings: TlsSettings) -> Result<Self, TlsError> {
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let builder = rustls::ClientConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()
+            .map_err(|e| TlsError::Config(e.to_string()))?;
+        let builder = if settings.skip_verify {
+            let verifier = Arc::new(NoVerification(provider));
+            builder
+                .dangerous()
+                .with_custom_certificate_verifier(verifier)
+        } else {
+            builder.with_root_certificates(Self::root_certificates(&settings)?)
+        };
+        let mut config = builder.with_no_client_auth();
+        config.alpn_protocols = vec![b"http/1.1".to_vec()];
+        Ok(Self {
+            settings,
+            connector: tokio_rustls::TlsConnector::from(Arc::new(config)),
+        })
+    }
+
+    fn root_certificates(settings: &TlsSettings) -> Result<rustls::RootCertStore, TlsError> {
+        let Some(ca_bundle) = &settings.ca_bundle else {
+            return Ok(rustls::RootCertStore {
+                roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+            });
+        };
+        let invalid = |e: String| TlsError::InvalidCertificate(ca_bundle.clone(), e);
+        let certificates = CertificateDer::pem_file_iter(ca_bundle)
+            .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+            .map_err(|e| invalid(e.to_string()))?;
+        let mut roots = rustls::RootCertStore::empty();
+        let (_, ignored) = roots.add_parsable_certificates(certificates);
+        if roots.is_empty() || ignored > 0 {
+            return Err(invalid("contains invalid CA certificates".into()));
+        }
+        Ok(roots)
+    }
+
+    /// Performs the handshake with the target at `host`, unless the [TlsSettings] override the
+    /// server name
+    pub async fn connect(
+        &self,
+        host: url::Host<&str>,
+        stream: TcpStream,
+    ) -> io::Result<tokio_rustls::client::TlsStream<TcpStream>> {
+        let server_name = self.server_name(host)?;
+        self.connector.connect(server_name, stream).await
+    }
+
+    /// IP hosts are verified against the IP addresses of the certificate, as their brackets
+    /// are not part of any server name
+    fn server_name(&self, host: url::Host<&str>) -> io::Result<ServerName<'static>> {
+        let server_name = match (&self.settings.server_name, host) {
+            (Some(server_name), _) => ServerName::try_from(server_name.clone()),
+            (None, url::Host::Domain(domain)) => ServerName::try_from(domain.to_string()),
+            (None, url::Host::Ipv4(ip)) => Ok(ServerName::IpAddress(net::IpAddr::V4(ip).into())),
+            (None, url::Host::Ipv6(ip)) => Ok(ServerName::IpAddress(net::IpAddr::V6(ip).into())),
+        };
+        server_name.map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+    }
+}
+impl Default for TlsConnector {
+    fn default() -> Self {
+        Self::new(TlsSettings::default()).expect("Built-in TLS configuration is invalid")
+    }
+}
+impl PartialEq for TlsConnector {
+    fn eq(&self, other: &Self) -> bool {
+        self.settings == other.settings
+    }
+}
+impl Eq for TlsConnector {}
+impl fmt::Debug for TlsConnector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TlsConnector")
+            .field("settings", &self.settings)
+            .finish()
+    }
+}
+
+/// Accepts any certificate, only checking that the handshake is signed by its key
+#[derive(Debug)]
+struct NoVerification(Arc<CryptoProvider>);
+impl ServerCertVerifier for NoVerification {
+    fn verify_server_cert(
+        &self,
+        _: &CertificateDer<'_>,
+        _: &[CertificateDer<'_>],
+        _: &ServerName<'_>,
+        _: &[u8],
+        _: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        let algorithms = &self.0.signature_verification_algorithms;
+        rustls::crypto::verify_tls12_signature(message, cert, dss, algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        let algorithms = &self.0.signature_verification_algorithms;
+        rustls::crypto::verify_tls13_signature(message, cert, dss, algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+/// Connects to the first reachable address, racing IPv6 and IPv4 addresses Happy Eyeballs style.
+///
+/// Attempts are started in interleaved address family order, each one [CONNECTION_ATTEMPT_DELAY]
+/// after the previous one or immediately if the previous one failed. Every attempt gives up
+/// after `connect_timeout`. The first established connection wins, the rest get aborted.
+pub async fn connect(
+    addrs: &[net::SocketAddr],
+    attempt_delay: Duration,
+    connect_timeout: Duration,
+) -> io::Result<TcpStream> {
+    let mut addrs = interleave_address_families(addrs).into_iter();
+    let mut attempts = JoinSet::new();
+    let mut last_error = None;
+    loop {
+        if let Some(addr) = addrs.next() {
+            attempts.spawn(attempt(addr, connect_timeout));
+        } else if attempts.is_empty() {
+            break;
+        }
+        let result = if addrs.len() > 0 {
+            time::timeout(attempt_delay, attempts.join_next())
+                .await
+                .ok()
+                .flatten()
+        } else {
+            attempts.join_next().await
+        };
+        match result {
+            Some(Ok(Ok(stream))) => return Ok(stream),
+            Some(Ok(Err(e))) => last_error = Some(e),
+            Some(Err(e)) => last_error = Some(io::Error::other(e)),
+            None => (), // Attempt takes too long, start the next one in parallel
+        }
+    }
+    let error = last_error.unwrap_or(io::Error::new(
+        io::ErrorKind::AddrNotAvailable,
+        "No addresses to connect to",
+    ));
+    Err(error)
+}
+
+async fn attempt(addr: net::SocketAddr, connect_timeout: Duration) -> io::Result<TcpStream> {
+    let result = match time::timeout(connect_timeout, TcpStream::connect(addr)).await {
+        Ok(result) => result,
+        Err(_) => Err(io::ErrorKind::TimedOut.into()),
+    };
+    if let Err(e) = &result {
+        tracing::debug!("Failed to connect to upstream address {addr}: {e}");
+    }
+    result
+}
+
+/// Orders addresses by alternating between address families, starting with the family of the
+/// first (most preferred) address
+pub fn interleave_address_families(addrs: &[net::SocketAddr]) -> Vec<net::SocketAddr> {
+    let prefers_ipv6 = addrs.first().map(|a| a.is_ipv6()).unwrap_or(true);
+    let (mut preferred, mut fallback): (Vec<net::SocketAddr>, Vec<net::SocketAddr>) = addrs
+        .iter()
+        .copied()
+        .partition(|a| a.is_ipv6() == prefers_ipv6);
+    preferred.reverse();
+    fallback.reverse();
+    let mut interleaved = Vec::with_capacity(addrs.len());
+    while !preferred.is_empty() || !fallback.is_empty() {
+        interleaved.extend(preferred.pop());
+        interleaved.extend(fallback.pop());
+    }
+    interleaved
+}
+
+/// Stream whose reads fail with [io::ErrorKind::TimedOut] once the peer stayed silent for longer
+/// than the timeout
+pub struct ReadTimeoutStream<S> {
+    stream: S,
+    timeout: Duration,
+    deadline: Pin<Box<Sleep>>,
+    is_waiting: bool,
+    is_enabled: Arc<AtomicBool>,
+}
+impl<S> ReadTimeoutStream<S> {
+    pub fn new(stream: S, timeout: Duration) -> Self {
+        Self {
+            stream,
+            timeout,
+            deadline: Box::pin(time::sleep(timeout)),
+            is_waiting: false,
+            is_enabled: Arc::new(AtomicBool::new(true)),
+        }
+    }
+
+    /// Returns a switch turning the timeout off after the stream was moved
+    pub fn switch(&self) -> ReadTimeoutSwitch {
+        ReadTimeoutSwitch(self.is_enabled.clone())
+    }
+}
+
+/// Turns off the timeout of a [ReadTimeoutStream], e.g. once the connection switched to a
+/// protocol that may stay silent for longer
+#[derive(Clone)]
+pub struct ReadTimeoutSwitch(Arc<AtomicBool>);
+impl ReadTimeoutSwitch {
+    pub fn disable(&self) {
+        self.0.store(false, Ordering::Relaxed);
+    }
+}
+impl<S: AsyncRead + Unpin> AsyncRead for ReadTimeoutStream<S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        if !this.is_enabled.load(Ordering::Relaxed) {
+            return Pin::new(&mut this.stream).poll_read(cx, buf);
+        }
+        if let Poll::Ready(result) = Pin::new(&mut this.stream).poll_read(cx, buf) {
+            this.is_waiting = false;
+            return Poll::Ready(result);
+        }
+        if !this.is_waiting {
+            this.is_waiting = true;
+            this.deadline.as_mut().reset(Instant::now() + this.timeout);
+        }
+        match this.deadline.as_mut().poll(cx) {
+            Poll::Ready(()) => Poll::Ready(Err(io::ErrorKind::TimedOut.into())),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+impl<S: AsyncWrite + Unpin> AsyncWrite for ReadTimeoutStream<S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.stream).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_shutdown(cx)
+    }
+}
@@ -4,16 +4,17 @@ use crate::templates::*;
 
 pub struct HandlebarTemplateRenderer {
     template_store: Box<dyn TemplateStore + Send + Sync>,
-    asset_base_url: url::Url,
+    asset_base_url: String,
 }
 impl HandlebarTemplateRenderer {
+    /// Assets are linked relative to `asset_base_url`, which may omit the origin
     pub fn new(
         template_store: Box<dyn TemplateStore + Send + Sync>,
-        asset_base_url: url::Url,
+        asset_base_url: impl Into<String>,
     ) -> Self {
         Self {
             template_store,
-            asset_base_url,
+            asset_base_url: asset_base_url.into(),
         }
     }
 }
@@ -75,7 +76,7 @@ impl HelperDef for JsonHelper {
 }
 
 struct AssetHelper {
-    base_url: url::Url,
+    base_url: String,
 }
 impl HelperDef for AssetHelper {
     fn call<'reg: 'rc, 'rc>(
@@ -101,8 +102,8 @@ impl HelperDef for AssetHelper {
             let version_query = format!("?v={version}");
             path.push_str(&version_query);
         }
-        let asset_url = self.base_url.join(&path).unwrap();
-        out.write(asset_url.as_str())?;
+        let asset_url = format!("{}{path}", self.base_url);
+        out.write(&asset_url)?;
         Ok(())
     }
 }
@@ -2,6 +2,7 @@ use pretty_assertions::assert_eq;
 use std::collections::HashMap;
 
 use serde_json::json;
+use test_case::test_case;
 
 use crate::templates::{
     handlebars::HandlebarTemplateRenderer, InMemoryTemplateStore, SerializedData, TemplateError,
@@ -77,3 +78,21 @@ pub fn render_should_handle_helper_for_js_object_parsing() {
     let expected = String::from(r#"{"Hello":{"Planet":"Earth"}}"#);
     assert_eq!(Ok(expected), result);
 }
+
+#[test_case("http://localhost:9100/", "http://localhost:9100/challenge.css" ; "api url")]
+#[test_case("/.tollkeeper/", "/.tollkeeper/challenge.css" ; "same origin path")]
+pub fn render_should_link_assets_relative_to_base(asset_base_url: &str, expected_url: &str) {
+    // Arrange
+    let mut templates = HashMap::new();
+    templates.insert(
+        "html/my_template.html".into(),
+        "{{asset challenge.css const}}".into(),
+    );
+    let template_store = InMemoryTemplateStore::new(templates);
+    let sut = HandlebarTemplateRenderer::new(Box::new(template_store), asset_base_url);
+    let data = SerializedData::new(json!({}));
+    // Act
+    let result = sut.render("html/my_template.html", &data);
+    // Assert
+    assert_eq!(Ok(expected_url.into()), result);
+}
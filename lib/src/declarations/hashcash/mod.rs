@@ -93,7 +93,10 @@ impl HashcashDeclaration {
         challenge.insert("width".into(), Timestamp::width().to_string());
         let resource = Resource(suspect.destination().clone());
         challenge.insert("resource".into(), resource.to_string());
-        challenge.insert("ext".into(), format!("suspect.ip={}", suspect.client_ip()));
+        let ext = Extension(indexmap::indexmap![
+            "suspect.ip".into() => suspect.client_ip().into(),
+        ]);
+        challenge.insert("ext".into(), ext.to_string());
         challenge
     }
 
@@ -331,6 +334,42 @@ impl Display for Timestamp {
     }
 }
 
+/// Escapes characters reserved by the stamp format (`:`) and the extension field (`;`, `=`)
+/// using percent-encoding, so values like IPv6 addresses can be embedded into a stamp
+fn encode_field(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '%' => encoded.push_str("%25"),
+            ':' => encoded.push_str("%3A"),
+            ';' => encoded.push_str("%3B"),
+            '=' => encoded.push_str("%3D"),
+            _ => encoded.push(c),
+        }
+    }
+    encoded
+}
+
+/// Reverses [encode_field]. Fails on malformed escape sequences
+fn decode_field(value: &str) -> Result<String, ParseStampError> {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = bytes.get(i + 1..i + 3).ok_or(ParseStampError)?;
+            let hex = std::str::from_utf8(hex).or(Err(ParseStampError))?;
+            let byte = u8::from_str_radix(hex, 16).or(Err(ParseStampError))?;
+            decoded.push(byte);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).or(Err(ParseStampError))
+}
+
 #[derive(Debug, PartialEq, Eq)]
 struct Resource(Destination);
 impl Display for Resource {
@@ -338,9 +377,9 @@ impl Display for Resource {
         write!(
             f,
             "{}({}){}",
-            self.0.base_url(),
+            encode_field(self.0.base_url()),
             self.0.port(),
-            self.0.path()
+            encode_field(self.0.path())
         )
     }
 }
@@ -354,7 +393,7 @@ impl FromStr for Resource {
             .map(|c| c.extract())
             .ok_or(ParseStampError)?;
         let port = port.parse().or(Err(ParseStampError))?;
-        let destination = Destination::new(host, port, path);
+        let destination = Destination::new(decode_field(host)?, port, decode_field(path)?);
         Ok(Resource(destination))
     }
 }
@@ -371,7 +410,7 @@ impl Display for Extension {
         let ext_str = self
             .0
             .iter()
-            .map(|e| format!("{}={}", e.0, e.1))
+            .map(|e| format!("{}={}", encode_field(e.0), encode_field(e.1)))
             .collect::<Vec<String>>()
             .join(";");
         write!(f, "{ext_str}")
@@ -391,7 +430,7 @@ impl FromStr for Extension {
             if kv.len() != 2 {
                 return Err(ParseStampError);
             }
-            ext.insert(kv[0].into(), kv[1].into());
+            ext.insert(decode_field(kv[0])?, decode_field(kv[1])?);
         }
         let ext = Extension(ext);
         Ok(ext)
@@ -135,6 +135,68 @@ pub fn pay_with_valid_payment_should_return_visa() {
     assert!(sut.double_spent_db.stamps().contains(stamp));
 }
 
+#[test]
+pub fn declare_should_escape_ipv6_suspect_ip_in_challenge() {
+    // Arrange
+    let sut = setup();
+    // Act
+    let suspect = Suspect::new(
+        "2001:db8::1",
+        "Bot",
+        Destination::new("example.com", 8888, "/hello"),
+    );
+    let order_id = OrderIdentifier::new("gate", "order");
+    let toll = sut.declare(suspect.clone(), order_id.clone());
+    // Assert
+    assert_eq!(
+        Some(&String::from("suspect.ip=2001%3Adb8%3A%3A1")),
+        toll.challenge().get("ext")
+    );
+}
+
+#[test]
+pub fn pay_with_valid_payment_from_ipv6_suspect_should_return_visa() {
+    // Arrange
+    let sut = setup();
+    let suspect = Suspect::new(
+        "2001:db8::1",
+        "Bot",
+        Destination::new("example.com", 8888, "/hello"),
+    );
+    // Act
+    let order_id = OrderIdentifier::new("gate", "order");
+    let toll = sut.declare(suspect.clone(), order_id.clone());
+    let stamp = "1:4:250506202406:example.com(8888)/hello:suspect.ip=2001%3Adb8%3A%3A1:VM81iAlX9M94FSXy:0000000000000000034";
+    let payment = Payment::new(toll, stamp);
+    let visa = sut
+        .pay(payment, &suspect)
+        .expect("Expected Visa, got InvalidPaymentError");
+    // Assert
+    assert_eq!(&suspect, visa.suspect());
+    assert_eq!(&order_id, visa.order_id());
+}
+
+#[test]
+pub fn pay_with_ipv6_stamp_minted_for_other_suspect_should_return_error() {
+    // Arrange
+    let sut = setup();
+    let suspect = Suspect::new(
+        "2001:db8::2",
+        "Bot",
+        Destination::new("example.com", 8888, "/hello"),
+    );
+    // Act
+    let order_id = OrderIdentifier::new("gate", "order");
+    let toll = sut.declare(suspect.clone(), order_id.clone());
+    let stamp = "1:4:250506202406:example.com(8888)/hello:suspect.ip=2001%3Adb8%3A%3A1:VM81iAlX9M94FSXy:0000000000000000034";
+    let payment = Payment::new(toll, stamp);
+    let error = sut
+        .pay(payment.clone(), &suspect)
+        .expect_err("Expected InvalidPaymentError, got Visa");
+    // Assert
+    assert_eq!(&payment, error.payment());
+}
+
 #[test]
 pub fn pay_with_invalid_stamp_should_return_error() {
     // Arrange
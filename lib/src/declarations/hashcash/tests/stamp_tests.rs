@@ -1,5 +1,6 @@
 use chrono::TimeZone;
 use pretty_assertions::assert_eq;
+use test_case::test_case;
 
 use crate::declarations::hashcash::*;
 
@@ -114,3 +115,58 @@ pub fn check_hash_should_return_false_if_hash_is_invalid() {
     // Assert
     assert!(!is_valid_hash);
 }
+
+#[test]
+pub fn to_string_should_escape_reserved_characters_in_resource_and_extension() {
+    // Arrange
+    let date = chrono::Utc
+        .with_ymd_and_hms(2025, 5, 7, 22, 24, 6)
+        .unwrap()
+        .to_utc();
+    let date = Timestamp(date);
+    let ext = indexmap::indexmap![
+        "suspect.ip".into() => "2001:db8::1".into(),
+        "weird=key".into() => "100%;done".into(),
+    ];
+    let ext = Extension(ext);
+    let res = Resource(Destination::new("::1", 8080, "/a:b"));
+    let stamp = Stamp::new(1, 3, date, res, ext, "veryrandomstring", "123");
+    // Act
+    let stamp_str = stamp.to_string();
+    // Assert
+    let expected = "1:3:250507222406:%3A%3A1(8080)/a%3Ab:suspect.ip=2001%3Adb8%3A%3A1;weird%3Dkey=100%25%3Bdone:veryrandomstring:123";
+    assert_eq!(expected, stamp_str);
+}
+
+#[test]
+pub fn from_string_should_return_stamp_with_decoded_ipv6_fields() {
+    // Arrange
+    let stamp = "1:3:250507222406:%3A%3A1(8080)/a%3Ab:suspect.ip=2001%3Adb8%3A%3A1;weird%3Dkey=100%25%3Bdone:veryrandomstring:123";
+    // Act
+    let stamp = Stamp::from_str(stamp).expect("Failed to parse valid stamp!");
+    // Assert
+    let date = chrono::Utc
+        .with_ymd_and_hms(2025, 5, 7, 22, 24, 6)
+        .unwrap()
+        .to_utc();
+    let date = Timestamp(date);
+    let ext = indexmap::indexmap![
+        "suspect.ip".into() => "2001:db8::1".into(),
+        "weird=key".into() => "100%;done".into(),
+    ];
+    let ext = Extension(ext);
+    let res = Resource(Destination::new("::1", 8080, "/a:b"));
+    let expected_stamp = Stamp::new(1, 3, date, res, ext, "veryrandomstring", "123");
+    assert_eq!(expected_stamp, stamp);
+}
+
+#[test_case("1:3:250507222406:localhost(80)/:suspect.ip=%3:rand:123" ; "truncated escape")]
+#[test_case("1:3:250507222406:localhost(80)/:suspect.ip=%zz:rand:123" ; "non hex escape")]
+#[test_case("1:3:250507222406:localhost(80)/:suspect.ip=2001:db8::1:rand:123" ; "unescaped ipv6")]
+pub fn from_string_should_fail_on_malformed_fields(stamp: &str) {
+    // Arrange
+    // Act
+    let result = Stamp::from_str(stamp);
+    // Assert
+    assert!(result.is_err());
+}
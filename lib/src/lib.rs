@@ -98,6 +98,21 @@ impl Tollkeeper {
         }
     }
 
+    /// Returns the [OrderIdentifier] of the `visa` if it grants the [Suspect] access: Signed by
+    /// this [Tollkeeper], not expired and issued by an [Order] of the gate guarding the destination
+    pub fn verify_visa(&self, suspect: &Suspect, visa: &Signed<Visa>) -> Option<OrderIdentifier> {
+        let gate = self.find_matching_gate(suspect).ok()?;
+        let visa = self.validate_signature(Some(visa));
+        let visa = self.validate_expiry_date(visa);
+        let visa = gate.check_visa(visa)?;
+        let is_gate_order = visa.order_id().gate_id() == gate.id
+            && gate
+                .orders
+                .iter()
+                .any(|o| o.has_valid_visa(suspect, Some(visa)));
+        is_gate_order.then(|| visa.order_id().clone())
+    }
+
     fn validate_signature<'a>(&self, visa: Option<&'a Signed<Visa>>) -> Option<&'a Visa> {
         let secret_key = self.secret_key_provider.read_secret_key();
         match visa {
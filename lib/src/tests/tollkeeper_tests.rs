@@ -400,6 +400,40 @@ pub fn passing_gate_with_visa_with_invalid_signature_should_reject_with_new_toll
     let _ = assert_is_denied(&access_result);
 }
 
+#[test]
+pub fn verify_visa_should_return_order_id_of_valid_visa() {
+    // Arrange
+    let (sut, order_id) = setup(None);
+    let suspect = Suspect::new("1.2.3.4", "Bot", Destination::new_base("localhost"));
+    let visa = Visa::new(order_id.clone(), suspect.clone(), expires_from_now(1));
+    let visa = Signed::sign(visa, b"Secret key");
+    // Act
+    let verified = sut.verify_visa(&suspect, &visa);
+    // Assert
+    assert_eq!(Some(order_id), verified);
+}
+
+#[test_case(b"Secret key", "1.2.3.4", -1 ; "expired visa")]
+#[test_case(b"Wrong key!", "1.2.3.4", 1 ; "visa with invalid signature")]
+#[test_case(b"Secret key", "11.22.33.44", 1 ; "visa of other suspect")]
+pub fn verify_visa_should_return_none_for_invalid_visa(
+    secret_key: &[u8],
+    visa_ip: &str,
+    expires_in_days: i64,
+) {
+    // Arrange
+    let (sut, order_id) = setup(None);
+    let suspect = Suspect::new("1.2.3.4", "Bot", Destination::new_base("localhost"));
+    let visa_suspect = Suspect::new(visa_ip, "Bot", Destination::new_base("localhost"));
+    let expires = chrono::Utc::now() + chrono::Duration::days(expires_in_days);
+    let visa = Visa::new(order_id, visa_suspect, expires);
+    let visa = Signed::sign(visa, secret_key);
+    // Act
+    let verified = sut.verify_visa(&suspect, &visa);
+    // Assert
+    assert_eq!(None, verified);
+}
+
 #[test]
 pub fn paying_toll_for_valid_order_with_valid_payment_should_return_visa() {
     // Arrange
@@ -549,3 +583,94 @@ pub fn paying_toll_with_forged_toll_should_return_error_without_new_toll() {
         Err(PaymentDeniedError::InvalidSignature);
     assert_eq!(expected, result);
 }
+
+fn setup_with_hashcash(current_time: chrono::DateTime<chrono::Utc>) -> Tollkeeper {
+    let declaration = declarations::hashcash::HashcashDeclaration::new(
+        4,
+        chrono::Duration::days(1),
+        Box::new(FakeDateTimeProvider(current_time)),
+        Box::new(declarations::hashcash::DoubleSpentDatabaseImpl::new(None)),
+    );
+    let order = Order::new(
+        vec![Box::new(StubDescription::new(true))],
+        AccessPolicy::Blacklist,
+        Box::new(declaration),
+    );
+    let gate = Gate::new(Destination::new_base("localhost"), vec![order]).unwrap();
+    setup_with_gates(vec![gate], Some(current_time))
+}
+
+/// Brute-forces a stamp for the challenge, the same way the challenge page does
+fn mint_stamp(challenge: &Challenge, date: &str) -> String {
+    use sha1::Digest;
+    let prefix = format!(
+        "{}:{}:{date}:{}:{}:rand",
+        challenge["ver"], challenge["bits"], challenge["resource"], challenge["ext"]
+    );
+    let bits: u32 = challenge["bits"].parse().unwrap();
+    (0u64..)
+        .map(|counter| format!("{prefix}:{counter}"))
+        .find(|stamp| {
+            let hash = sha1::Sha1::digest(stamp.as_bytes());
+            let leading_zeroes = u32::from_be_bytes([hash[0], hash[1], hash[2], hash[3]]);
+            leading_zeroes.leading_zeros() >= bits
+        })
+        .unwrap()
+}
+
+#[test_case("2001:db8::1" ; "ipv6")]
+#[test_case("::1" ; "ipv6 loopback")]
+#[test_case("::ffff:1.2.3.4" ; "ipv4 mapped ipv6")]
+#[test_case("1.2.3.4" ; "ipv4")]
+pub fn paying_hashcash_toll_should_grant_access_to_suspect(client_ip: &str) {
+    // Arrange
+    let current_time = chrono::Utc
+        .with_ymd_and_hms(2025, 5, 6, 20, 24, 6)
+        .unwrap()
+        .to_utc();
+    let sut = setup_with_hashcash(current_time);
+    let suspect = Suspect::new(client_ip, "Bot", Destination::new_base("localhost"));
+    // Act
+    let access_result = sut.check_access(&suspect, None);
+    let toll = assert_is_denied(&access_result).clone();
+    let challenge = toll.verify(b"Secret key").unwrap().challenge().clone();
+    let stamp = mint_stamp(&challenge, "250506202406");
+    let payment = SignedPayment::new(toll, stamp);
+    let visa = sut
+        .pay_toll(&suspect, payment)
+        .expect("Expected visa for valid stamp");
+    let access_result = sut.check_access(&suspect, Some(visa));
+    // Assert
+    assert_is_allowed(&access_result);
+}
+
+#[test]
+pub fn paying_hashcash_toll_with_stamp_minted_for_other_ipv6_suspect_should_return_error() {
+    // Arrange
+    let current_time = chrono::Utc
+        .with_ymd_and_hms(2025, 5, 6, 20, 24, 6)
+        .unwrap()
+        .to_utc();
+    let sut = setup_with_hashcash(current_time);
+    let alice = Suspect::new("2001:db8::1", "Bot", Destination::new_base("localhost"));
+    let mallory = Suspect::new("2001:db8::2", "Bot", Destination::new_base("localhost"));
+    // Act
+    let alice_access = sut.check_access(&alice, None);
+    let alice_toll = assert_is_denied(&alice_access).clone();
+    let challenge = alice_toll
+        .verify(b"Secret key")
+        .unwrap()
+        .challenge()
+        .clone();
+    let stamp = mint_stamp(&challenge, "250506202406");
+    let mallory_access = sut.check_access(&mallory, None);
+    let mallory_toll = assert_is_denied(&mallory_access).clone();
+    let payment = SignedPayment::new(mallory_toll, stamp);
+    let result = sut.pay_toll(&mallory, payment);
+    // Assert
+    match result {
+        Err(PaymentDeniedError::InvalidPayment(_)) => (),
+        Err(e) => panic!("Expected invalid payment error, got: {e}"),
+        Ok(_) => panic!("Expected invalid payment error, got visa!"),
+    }
+}